@@ -2,6 +2,15 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    // FreeBSD has its own headers, without any of Linux's
+    let header = match env::var("CARGO_CFG_TARGET_OS").as_deref() {
+        Ok("freebsd") => "./src/wrapper_freebsd.h",
+        _ => "./src/wrapper.h"
+    };
+
+    // Capabilities backends which aren't written for a specific architecture take its name from here
+    println!("cargo:rustc-env=HWCAPS_TARGET_ARCH={}", env::var("CARGO_CFG_TARGET_ARCH").unwrap());
+
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
     // the resulting bindings.
@@ -9,7 +18,7 @@ fn main() {
         .use_core()
         // The input header we would like to generate
         // bindings for.
-        .header("./src/wrapper.h")
+        .header(header)
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
@@ -0,0 +1,83 @@
+#!/bin/sh
+# Parity check between the hosted (glibc, musl) and freestanding flavors.
+# Usage: helpers/check_flavors.sh [features]
+# Builds hwcaps-loader for every flavor with the same features (a comma-separated list, on top of
+# the default ones), so one which only builds for some of them is caught before release. Every
+# build is then installed in a fixture /usr, an overlayfs over the real one in a private user and
+# mount namespace (so no root is needed, only unprivileged user namespaces and Linux 5.11 or
+# later). There, each flavor must dispatch an alias to its target, resolve it with "which" (with
+# maintenance), and refuse to run from the target directory, all exactly like the others.
+# Set TARGETS to check fewer flavors (e.g. TARGETS=x86_64-unknown-linux-gnu), and LEVEL to the
+# baseline level's directory on other architectures (e.g. LEVEL=aarch64).
+
+set -e
+
+FEATURES=${1:-}
+TARGETS=${TARGETS:-"x86_64-unknown-linux-gnu x86_64-unknown-linux-musl x86_64-unknown-none"}
+LEVEL=${LEVEL:-x86-64-v1}
+SCRIPT=$(realpath "$0")
+
+cd "$(dirname "$SCRIPT")/.."
+
+if [ -z "$HWCAPS_FLAVORS_INNER" ]; then
+    for TARGET in $TARGETS; do
+        case $TARGET in
+        *-none)
+            cargo +nightly build -p hwcaps-loader --release --target "$TARGET" --features "$FEATURES"
+            ;;
+        *)
+            cargo build -p hwcaps-loader --release --target "$TARGET" --features "$FEATURES"
+            ;;
+        esac
+    done
+
+    exec unshare --user --map-root-user --mount env HWCAPS_FLAVORS_INNER=1 TARGETS="$TARGETS" LEVEL="$LEVEL" "$SCRIPT" "$FEATURES"
+fi
+
+SCRATCH=$(mktemp -d)
+trap 'rm -rf --one-file-system "$SCRATCH"' EXIT
+
+# The fixture is the lower layer on top of the real /usr, so the rest of the system keeps working.
+# Flavors are installed one after another, in the upper layer.
+mkdir -p "$SCRATCH/fixture/bin" "$SCRATCH/fixture/hwcaps/$LEVEL/bin" "$SCRATCH/upper" "$SCRATCH/work"
+ln -s hwcaps-loader "$SCRATCH/fixture/bin/flavor-check"
+printf '#!/bin/sh\necho "dispatched $*"\n' > "$SCRATCH/fixture/hwcaps/$LEVEL/bin/flavor-check"
+chmod +x "$SCRATCH/fixture/hwcaps/$LEVEL/bin/flavor-check"
+
+mount -t overlay overlay -o "lowerdir=$SCRATCH/fixture:/usr,upperdir=$SCRATCH/upper,workdir=$SCRATCH/work" /usr
+
+# Prints what running a command printed, and its exit code
+run() {
+    OUTPUT=$("$@" 2>&1) && CODE=0 || CODE=$?
+    echo "$OUTPUT ($CODE)"
+}
+
+EXPECTED=""
+for TARGET in $TARGETS; do
+    cp "target/$TARGET/release/hwcaps-loader" /usr/bin/hwcaps-loader
+
+    RESULT="dispatch: $(run /usr/bin/flavor-check one two)"
+    case ",$FEATURES," in
+    *,maintenance,*)
+        RESULT="$RESULT; which: $(run /usr/bin/hwcaps-loader which flavor-check)"
+        ;;
+    esac
+    # Outside of /usr/bin, the loader stops before dispatching anything
+    RESULT="$RESULT; outside /usr/bin: $(run "target/$TARGET/release/hwcaps-loader" | sed 's/.*(/(/')"
+    echo "hwcaps-loader ($TARGET): $RESULT"
+
+    case $RESULT in
+    "dispatch: dispatched one two (0)"*) ;;
+    *)
+        echo "$TARGET didn't dispatch the alias to its target!" >&2
+        exit 1
+        ;;
+    esac
+
+    if [ -z "$EXPECTED" ]; then
+        EXPECTED=$RESULT
+    elif [ "$RESULT" != "$EXPECTED" ]; then
+        echo "Flavor mismatch! $TARGET behaved differently from $(echo "$TARGETS" | cut -d' ' -f1)." >&2
+        exit 1
+    fi
+done
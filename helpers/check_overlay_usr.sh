@@ -0,0 +1,63 @@
+#!/bin/sh
+# Checks mount_aware against an overlayfs /usr, like container images have.
+# Usage: helpers/check_overlay_usr.sh [loader]
+# Builds hwcaps-loader with mount_aware (unless a loader built with it is given), and mounts an
+# overlayfs over /usr with the loader, an alias and its target added, in a private user and mount
+# namespace. No root is needed, only unprivileged user namespaces (and Linux 5.11 or later, for
+# overlayfs in them). The alias must run from /usr/bin and through a bind mount of /usr/bin, and
+# must be refused from a copy of /usr/bin.
+# Set LEVEL to the baseline level's directory on other architectures (e.g. LEVEL=aarch64).
+
+set -e
+
+LEVEL=${LEVEL:-x86-64-v1}
+SCRIPT=$(realpath "$0")
+
+if [ -z "$HWCAPS_OVERLAY_INNER" ]; then
+    cd "$(dirname "$SCRIPT")/.."
+
+    LOADER=${1:-}
+    if [ -z "$LOADER" ]; then
+        cargo build -p hwcaps-loader --release --features mount_aware
+        LOADER=target/release/hwcaps-loader
+    fi
+
+    LOADER=$(realpath "$LOADER")
+    exec unshare --user --map-root-user --mount env HWCAPS_OVERLAY_INNER=1 "$SCRIPT" "$LOADER"
+fi
+
+LOADER=$1
+SCRATCH=$(mktemp -d)
+# The bind mount must go first, or removing it would remove /usr/bin's files. The overlay goes
+# away with the namespace (it can't be unmounted while this shell runs from it).
+trap 'umount "$SCRATCH/bind" 2>/dev/null || true; rm -rf --one-file-system "$SCRATCH"' EXIT
+
+# The fixture is the lower layer on top of the real /usr, so the rest of the system keeps working
+mkdir -p "$SCRATCH/fixture/bin" "$SCRATCH/fixture/hwcaps/$LEVEL/bin" "$SCRATCH/upper" "$SCRATCH/work"
+cp "$LOADER" "$SCRATCH/fixture/bin/hwcaps-loader"
+ln -s hwcaps-loader "$SCRATCH/fixture/bin/overlay-check"
+printf '#!/bin/sh\necho dispatched\n' > "$SCRATCH/fixture/hwcaps/$LEVEL/bin/overlay-check"
+chmod +x "$SCRATCH/fixture/hwcaps/$LEVEL/bin/overlay-check"
+
+mount -t overlay overlay -o "lowerdir=$SCRATCH/fixture:/usr,upperdir=$SCRATCH/upper,workdir=$SCRATCH/work" /usr
+
+mkdir "$SCRATCH/bind" "$SCRATCH/copy"
+mount --bind /usr/bin "$SCRATCH/bind"
+cp -P /usr/bin/hwcaps-loader /usr/bin/overlay-check "$SCRATCH/copy/"
+
+check() {
+    OUTPUT=$("$2" 2>&1) && CODE=0 || CODE=$?
+    echo "$2: exited with $CODE"
+
+    if [ "$1" = run ] && [ "$OUTPUT" != dispatched ]; then
+        echo "Expected $2 to run the target, got: $OUTPUT" >&2
+        exit 1
+    elif [ "$1" = refuse ] && [ "$CODE" = 0 ]; then
+        echo "Expected $2 to be refused" >&2
+        exit 1
+    fi
+}
+
+check run /usr/bin/overlay-check
+check run "$SCRATCH/bind/overlay-check"
+check refuse "$SCRATCH/copy/overlay-check"
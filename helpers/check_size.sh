@@ -0,0 +1,23 @@
+#!/bin/sh
+# Size regression check for the minimal freestanding build, run by CI.
+# Usage: helpers/check_size.sh [max_size_in_bytes]
+
+set -e
+
+MAX_SIZE=${1:-6144}
+TARGET=x86_64-unknown-none
+
+cd "$(dirname "$0")/.."
+
+RUSTFLAGS="-Z unstable-options -C panic=immediate-abort" \
+    cargo +nightly build -p hwcaps-loader --profile minimal --no-default-features --target $TARGET \
+    -Z build-std=core,panic_abort
+
+SIZE=$(wc -c < target/$TARGET/minimal/hwcaps-loader)
+
+echo "hwcaps-loader ($TARGET, minimal): $SIZE bytes (limit: $MAX_SIZE bytes)"
+
+if [ "$SIZE" -gt "$MAX_SIZE" ]; then
+    echo "Size regression! The minimal build is larger than $MAX_SIZE bytes." >&2
+    exit 1
+fi
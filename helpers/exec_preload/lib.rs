@@ -0,0 +1,196 @@
+/*
+   exec() interposer for incremental migrations.
+   Loaded with LD_PRELOAD, it intercepts execve(), execv() and execvp() and executes the best build of
+   programs under /usr which have candidates in the hwcaps directory, even when callers bypass
+   their aliases (e.g. hardcoded paths into /usr/lib, or programs which haven't been turned into
+   aliases yet). Aliases themselves are left to hwcaps-loader, so its policies still apply to them.
+   Anything else, or a candidate which fails to execute, goes to the real function untouched.
+   Candidates skipped by .hwcaps-ignore markers are skipped here too. The loader's checks can't be
+   applied without its configuration, so candidates with a signature or a build-ID manifest are
+   left alone, and so is everything in secure-execution mode (e.g. setuid programs).
+   It runs between fork() and exec(), so it mustn't allocate or take locks.
+*/
+
+#![no_std]
+
+use core::ffi::{c_char, c_int, c_ulong, c_void, CStr};
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use hwcaps_loader::{capabilities, ignore, path, resolve, sys, HWCAPS_PATH, USR_PATH};
+use hwcaps_loader::resolve::CandidatePath;
+
+const LOADER_NAME: &[u8] = b"hwcaps-loader";
+// Used by execvp() when PATH isn't set, same as glibc
+const DEFAULT_PATH: &[u8] = b"/bin:/usr/bin";
+// Where signatures and build-ID manifests are, same as the loader's signatures and build_ids
+const SIGNATURE_SUFFIX: &[u8] = b".sig";
+const BUILD_IDS_DIR: &[u8] = b".build-ids";
+
+type ExecveFn = unsafe extern "C" fn(*const c_char, *const *const c_char, *const *const c_char) -> c_int;
+type ExecvpFn = unsafe extern "C" fn(*const c_char, *const *const c_char) -> c_int;
+
+const RTLD_NEXT: *mut c_void = -1isize as *mut c_void;
+
+#[link(name = "c")]
+#[link(name = "dl")]
+extern "C" {
+    static environ: *const *const c_char;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn getenv(name: *const c_char) -> *const c_char;
+    fn getauxval(kind: c_ulong) -> c_ulong;
+    fn __errno_location() -> *mut c_int;
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    sys::exit(sys::ExitCode::RustPanic as u8)
+}
+
+// The functions we're hiding, from the next library in the search order (usually libc).
+// dlsym() can allocate and take locks, so they're looked up once, when we're loaded.
+static REAL_EXECVE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+static REAL_EXECVP: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+#[used]
+#[link_section = ".init_array"]
+static INIT: unsafe extern "C" fn() = init;
+
+unsafe extern "C" fn init() {
+    REAL_EXECVE.store(dlsym(RTLD_NEXT, c"execve".as_ptr()), Ordering::Relaxed);
+    REAL_EXECVP.store(dlsym(RTLD_NEXT, c"execvp".as_ptr()), Ordering::Relaxed);
+}
+
+// Safety: T must be the type of the function in real.
+unsafe fn real<T>(real: &AtomicPtr<c_void>) -> Option<T> {
+    let function = real.load(Ordering::Relaxed);
+    match function.is_null() {
+        true => None,
+        false => Some(core::mem::transmute_copy(&function))
+    }
+}
+
+// What the functions return when the real ones are missing
+unsafe fn missing() -> c_int {
+    *__errno_location() = sys::ENOSYS as c_int;
+    -1
+}
+
+// Whether the path made of parts exists. Paths which don't fit are assumed to.
+fn exists(parts: &[&[u8]]) -> bool {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    match path::join(&mut buffer, parts) {
+        Some(len) => sys::faccessat(sys::AT_FDCWD, unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) }, sys::F_OK).is_ok(),
+        None => true
+    }
+}
+
+// Whether the loader may have to check candidate before executing it (signatures and build_ids)
+fn is_checked(candidate: &[u8]) -> bool {
+    // Part of the path after the level's directory (e.g. "/bin/foo")
+    let program = match candidate[HWCAPS_PATH.len()..].iter().position(|c| *c == b'/') {
+        Some(i) => &candidate[HWCAPS_PATH.len() + i..],
+        None => return true
+    };
+    exists(&[candidate, SIGNATURE_SUFFIX]) || exists(&[HWCAPS_PATH, BUILD_IDS_DIR, program])
+}
+
+// Returns the best candidate of path, if it's a program under /usr with candidates
+fn reroute(path: &[u8]) -> Option<CandidatePath> {
+    // Whatever picks what privileged programs run is up to the loader's policies
+    if !path.starts_with(USR_PATH) || unsafe { getauxval(sys::AT_SECURE as c_ulong) } != 0 {
+        return None
+    }
+
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    if path.len() >= buffer.len() {
+        return None
+    }
+    let len = path::normalize(&mut buffer, path);
+    let program = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len]) };
+
+    // Aliases are hwcaps-loader's business
+    let mut target = [0u8; sys::PATH_MAX as usize];
+    if let Ok(target_len) = sys::readlinkat(sys::AT_FDCWD, program, &mut target) {
+        if path::file_name(&target[..target_len]) == LOADER_NAME {
+            return None
+        }
+    }
+
+    let max_level = capabilities::get_max_feature_level();
+    let candidate = resolve::candidates(program.to_bytes(), max_level).ok()?
+        .filter(|candidate| !ignore::is_ignored(HWCAPS_PATH, &candidate.as_bytes()[HWCAPS_PATH.len()..]))
+        .find(|candidate| sys::faccessat(sys::AT_FDCWD, candidate.as_c_str(), sys::X_OK).is_ok())?;
+
+    match is_checked(candidate.as_bytes()) {
+        true => None,
+        false => Some(candidate)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execve(path: *const c_char, argv: *const *const c_char, envp: *const *const c_char) -> c_int {
+    let real_execve = match real::<ExecveFn>(&REAL_EXECVE) {
+        Some(f) => f,
+        None => return missing()
+    };
+
+    if !path.is_null() {
+        if let Some(candidate) = reroute(CStr::from_ptr(path).to_bytes()) {
+            real_execve(candidate.as_c_str().as_ptr(), argv, envp);
+        }
+    }
+    real_execve(path, argv, envp)
+}
+
+// libc's execv() doesn't go through execve()'s symbol
+#[no_mangle]
+pub unsafe extern "C" fn execv(path: *const c_char, argv: *const *const c_char) -> c_int {
+    execve(path, argv, environ)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execvp(file: *const c_char, argv: *const *const c_char) -> c_int {
+    let real_execvp = match real::<ExecvpFn>(&REAL_EXECVP) {
+        Some(f) => f,
+        None => return missing()
+    };
+
+    if file.is_null() {
+        return real_execvp(file, argv)
+    }
+
+    let name = CStr::from_ptr(file).to_bytes();
+    if name.contains(&b'/') {
+        return execve(file, argv, environ)
+    }
+
+    let search_path = match getenv(c"PATH".as_ptr()) {
+        p if p.is_null() => DEFAULT_PATH,
+        p => CStr::from_ptr(p).to_bytes()
+    };
+
+    // Only the directory execvp() would pick matters
+    for directory in search_path.split(|c| *c == b':') {
+        // Empty entries stand for the current directory
+        let directory = if directory.is_empty() { b"." } else { directory };
+
+        let mut buffer = [0u8; sys::PATH_MAX as usize];
+        let len = match path::join(&mut buffer, &[directory, b"/", name]) {
+            Some(l) => l,
+            None => continue
+        };
+        let program = CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]);
+
+        if sys::faccessat(sys::AT_FDCWD, program, sys::X_OK).is_ok() {
+            if let Some(candidate) = reroute(program.to_bytes()) {
+                if let Some(real_execve) = real::<ExecveFn>(&REAL_EXECVE) {
+                    real_execve(candidate.as_c_str().as_ptr(), argv, environ);
+                }
+            }
+            break
+        }
+    }
+    real_execvp(file, argv)
+}
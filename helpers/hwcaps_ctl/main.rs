@@ -0,0 +1,178 @@
+/*
+   hwcaps-ctl report
+   hwcaps-ctl diff <report> [<report>]
+   Helps fleet operators understand why the same image behaves differently across hardware
+   generations. "report" prints what the loader detects on this machine as JSON: the CPU, the
+   hypervisor, every feature CPUID reports (by name, as in /proc/cpuinfo), the ones quirks rule
+   out, and the highest feature level which is left. "diff" compares a report from another
+   machine against this one (or two reports against each other), and explains every feature
+   level only one of them supports by the features the other one is missing.
+   Levels are worked out like a loader built with the quirks feature would, before any
+   configured limits.
+   Exits with 0 if the machines would use the same level, 1 if they wouldn't, and 2 on errors.
+*/
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+use hwcaps_loader::capabilities::{self, Features, FEATURE_LEVELS, FEATURE_NAMES};
+use serde::{Deserialize, Serialize};
+
+const USAGE: &str = "Usage: hwcaps-ctl report\n       hwcaps-ctl diff <report> [<report>]";
+
+#[derive(Serialize, Deserialize)]
+struct Cpu {
+    vendor: String,
+    family: u32,
+    model: u32,
+    stepping: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Report {
+    arch: String,
+    cpu: Option<Cpu>,
+    hypervisor: Option<String>,
+    features: BTreeSet<String>,
+    quirks: BTreeSet<String>,
+    max_level: String,
+}
+
+fn name(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+// Names of every feature in features
+fn feature_names(features: &Features) -> BTreeSet<String> {
+    FEATURE_NAMES.iter().filter(|(_, f)| features.contains(f)).map(|(n, _)| name(n)).collect()
+}
+
+// Only names this build knows about count, so reports from other architectures are compared by name alone
+fn features_of(names: &BTreeSet<String>) -> Features {
+    FEATURE_NAMES.iter()
+        .filter(|(n, _)| names.contains(&name(n)))
+        .fold(Features::NONE, |features, (_, f)| features.union(f))
+}
+
+fn local_report() -> Report {
+    let features = capabilities::get_features();
+    let quirks = capabilities::get_quirks();
+    let max_level = capabilities::get_feature_level(&features.without(&quirks));
+
+    Report {
+        arch: name(capabilities::ARCH_NAME),
+        cpu: capabilities::get_signature().map(|s| Cpu {
+            vendor: name(&s.vendor),
+            family: s.family,
+            model: s.model,
+            stepping: s.stepping,
+        }),
+        hypervisor: capabilities::get_hypervisor().map(name),
+        features: feature_names(&features),
+        // Only the ones the CPU reports matter
+        quirks: feature_names(&features).intersection(&feature_names(&quirks)).cloned().collect(),
+        max_level: name(FEATURE_LEVELS[max_level as usize].name),
+    }
+}
+
+fn read_report(path: &str) -> Result<Report, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+    serde_json::from_str(&contents).map_err(|e| format!("{path} isn't a valid report: {e}"))
+}
+
+fn describe_cpu(cpu: &Option<Cpu>) -> String {
+    match cpu {
+        Some(c) => format!("{} {:02x}-{:02x}-{:02x}", c.vendor, c.family, c.model, c.stepping),
+        None => "unknown".into()
+    }
+}
+
+fn join(names: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    names.into_iter().map(|n| n.as_ref().to_string()).collect::<Vec<_>>().join(" ")
+}
+
+// Prints "<key>: <a> | <b>", if they differ
+fn compare(key: &str, a: &str, b: &str) {
+    if a != b {
+        println!("{key}: {a} | {b}");
+    }
+}
+
+// Prints the names only in a and only in b
+fn compare_sets(key: &str, a: &BTreeSet<String>, b: &BTreeSet<String>, labels: (&str, &str)) {
+    let only_a: Vec<_> = a.difference(b).collect();
+    let only_b: Vec<_> = b.difference(a).collect();
+    if !only_a.is_empty() {
+        println!("{key} only {}: {}", labels.0, join(only_a));
+    }
+    if !only_b.is_empty() {
+        println!("{key} only {}: {}", labels.1, join(only_b));
+    }
+}
+
+// Why the machine described by report doesn't support level
+fn missing(report: &Report, level: &Features) -> String {
+    let usable = |n: &str| report.features.contains(n) && !report.quirks.contains(n);
+    let names = FEATURE_NAMES.iter()
+        .filter(|(n, f)| level.contains(f) && !usable(&name(n)))
+        .map(|(n, _)| match report.features.contains(&name(n)) {
+            true => format!("{} (ruled out by a quirk)", name(n)),
+            false => name(n)
+        });
+    join(names)
+}
+
+fn diff(a: &Report, b: &Report, labels: (&str, &str)) -> ExitCode {
+    println!("max_level: {} {} | {} {}", labels.0, a.max_level, labels.1, b.max_level);
+
+    compare("arch", &a.arch, &b.arch);
+    compare("cpu", &describe_cpu(&a.cpu), &describe_cpu(&b.cpu));
+    compare("hypervisor", a.hypervisor.as_deref().unwrap_or("none"), b.hypervisor.as_deref().unwrap_or("none"));
+    compare_sets("features", &a.features, &b.features, labels);
+    compare_sets("quirks", &a.quirks, &b.quirks, labels);
+
+    // Other architectures' levels can't be worked out from this build's table
+    if a.arch == b.arch && a.arch == name(capabilities::ARCH_NAME) {
+        let effective = |r: &Report| features_of(&r.features).without(&features_of(&r.quirks));
+        let (features_a, features_b) = (effective(a), effective(b));
+
+        for level in FEATURE_LEVELS {
+            match (features_a.contains(&level.features), features_b.contains(&level.features)) {
+                (true, false) => println!("{} only {}: {} is missing {}", name(level.name), labels.0, labels.1, missing(b, &level.features)),
+                (false, true) => println!("{} only {}: {} is missing {}", name(level.name), labels.1, labels.0, missing(a, &level.features)),
+                _ => ()
+            }
+        }
+    }
+
+    match a.max_level == b.max_level && a.arch == b.arch {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(1)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args[..] {
+        ["report"] => serde_json::to_string_pretty(&local_report())
+            .map(|json| {
+                println!("{json}");
+                ExitCode::SUCCESS
+            })
+            .map_err(|e| format!("Failed to serialize the report: {e}")),
+        ["diff", there] => read_report(there).map(|b| diff(&local_report(), &b, ("here", "there"))),
+        ["diff", first, second] => read_report(first)
+            .and_then(|a| Ok((a, read_report(second)?)))
+            .map(|(a, b)| diff(&a, &b, (first, second))),
+        _ => Err(USAGE.into())
+    };
+
+    result.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        ExitCode::from(2)
+    })
+}
@@ -0,0 +1,69 @@
+/*
+   hwcaps-exec <program> [args...]
+   Executes the best build of program for this machine, for places where installing aliases isn't
+   possible (shell scripts, systemd ExecStart= lines, container entrypoints). program is a name
+   (looked up like aliases in /usr/bin) or a path under /usr, and is resolved through the
+   hwcaps-loader library, trying candidates in the same order as the loader.
+*/
+
+#![no_std]
+#![no_main]
+
+use core::ffi::{c_char, CStr};
+
+use hwcaps_loader::{arg_size, capabilities, ignore, resolve, sys, HWCAPS_PATH};
+use hwcaps_loader::sys::ExitCode;
+use hwcaps_loader::output::{abort, Fatal};
+
+#[cfg(target_os = "none")]
+compile_error!("hwcaps-exec must be built for a hosted target!");
+
+#[link(name = "c")]
+extern "C" {}
+
+//Workarounds for https://github.com/rust-lang/rust/issues/106864
+#[no_mangle]
+extern "C" fn rust_eh_personality() {}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    sys::exit(ExitCode::RustPanic as u8)
+}
+
+#[no_mangle]
+pub extern "C" fn main(argc: i32, argv: *const *const c_char, envp: *const *const c_char) -> ! {
+    if argc < 2 {
+        abort(&Fatal(ExitCode::CommandPathInvalid, "Usage: hwcaps-exec <program> [args...]"), 0, None)
+    }
+
+    // The target's argv starts at the program
+    let argv = unsafe { argv.add(1) };
+    let program = unsafe { CStr::from_ptr(*argv).to_bytes() };
+
+    let max_level = capabilities::get_max_feature_level();
+    let candidates = match resolve::candidates(program, max_level) {
+        Ok(c) => c,
+        Err(resolve::Error::TooLong) => abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, Some(program)),
+        Err(_) => abort(&Fatal(ExitCode::TargetPathInvalid, "Invalid target location!"), 0, Some(program))
+    };
+
+    // Like the loader, only move on to the next candidate if this one doesn't exist
+    for candidate in candidates {
+        // Skipped candidates are handled like missing ones
+        if ignore::is_ignored(HWCAPS_PATH, &candidate.as_bytes()[HWCAPS_PATH.len()..]) {
+            continue
+        }
+
+        let errno = sys::execve(candidate.as_c_str(), argv, envp);
+
+        if errno.into_raw() as u32 == sys::E2BIG {
+            arg_size::abort_too_large(argv, envp, candidate.as_bytes())
+        }
+        if errno.into_raw() as u32 != sys::ENOENT {
+            abort(&Fatal(ExitCode::TargetExecutionError, "Failed to execute target binary!"), errno.into_raw() as u32, Some(candidate.as_bytes()))
+        }
+    }
+
+    abort(&Fatal(ExitCode::TargetNoViableBinaries, "Program has no supported binaries available. Is it installed properly?"), 0, Some(program))
+}
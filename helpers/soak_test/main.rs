@@ -0,0 +1,254 @@
+/*
+   hwcaps-soak [--iterations <n>] [--jobs <n>] [--timeout-ms <n>] <program>[:<exit code>]...
+   Release qualification on real hardware: executes every program (a path, or the name of an
+   alias in /usr/bin) <iterations> times from <jobs> concurrent workers, and reports runs which
+   exit with an unexpected code (0 by default), are killed by a signal, or get stuck for longer
+   than the timeout.
+   hwcaps-soak is its own fixture: installed as a candidate (e.g.
+   /usr/hwcaps/x86-64-v2/bin/hwcaps-soak-fixture) behind an alias, every run through the loader
+   reports the candidate it ended up at and any file descriptors the loader leaked into it.
+   Fixture runs are recognized by HWCAPS_SOAK_FIXTURE, which is set for every program executed.
+*/
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::process::{Command, ExitCode, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: hwcaps-soak [--iterations <n>] [--jobs <n>] [--timeout-ms <n>] <program>[:<exit code>]...";
+const FIXTURE_VAR: &str = "HWCAPS_SOAK_FIXTURE";
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Program {
+    path: String,
+    expected: i32,
+}
+
+// Outcomes of one program's runs
+#[derive(Default)]
+struct Report {
+    runs: usize,
+    // Unexpected exit codes, and how often they happened
+    exit_codes: BTreeMap<i32, usize>,
+    signals: usize,
+    stuck: usize,
+    spawn_errors: usize,
+    // Descriptors fixtures found open, besides stdin, stdout and stderr
+    leaks: BTreeMap<String, usize>,
+    // Candidates fixtures were executed from
+    candidates: BTreeMap<String, usize>,
+}
+
+impl Report {
+    fn failures(&self) -> usize {
+        self.exit_codes.values().sum::<usize>() + self.signals + self.stuck + self.spawn_errors + self.leaks.values().sum::<usize>()
+    }
+}
+
+// Reports where this fixture is and which descriptors it inherited, one per line
+fn fixture() -> ExitCode {
+    if let Ok(exe) = fs::read_link("/proc/self/exe") {
+        println!("candidate {}", exe.display());
+    }
+
+    let entries = match fs::read_dir("/proc/self/fd") {
+        Ok(e) => e,
+        Err(_) => return ExitCode::FAILURE
+    };
+    for entry in entries.flatten() {
+        let fd = entry.file_name().to_string_lossy().into_owned();
+        if matches!(fd.as_str(), "0" | "1" | "2") {
+            continue
+        }
+
+        let target = fs::read_link(entry.path()).map(|t| t.display().to_string()).unwrap_or_default();
+        // The directory being read
+        if target.starts_with("/proc/") && target.ends_with("/fd") {
+            continue
+        }
+        println!("leak {fd} -> {target}");
+    }
+    ExitCode::SUCCESS
+}
+
+fn run_once(program: &Program, timeout: Duration, report: &mut Report) {
+    report.runs += 1;
+
+    let child = Command::new(&program.path)
+        .env(FIXTURE_VAR, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(_) => {
+            report.spawn_errors += 1;
+            return
+        }
+    };
+
+    // Fixtures' output is small enough to never fill the pipe
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if start.elapsed() > timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None
+            },
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(_) => break None
+        }
+    };
+
+    let status = match status {
+        Some(s) => s,
+        None => {
+            report.stuck += 1;
+            return
+        }
+    };
+
+    match status.code() {
+        Some(code) if code == program.expected => (),
+        Some(code) => *report.exit_codes.entry(code).or_default() += 1,
+        None => report.signals += 1
+    }
+
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut output);
+    }
+    for line in output.lines() {
+        if let Some(candidate) = line.strip_prefix("candidate ") {
+            *report.candidates.entry(candidate.to_string()).or_default() += 1;
+        } else if let Some(leak) = line.strip_prefix("leak ") {
+            *report.leaks.entry(leak.to_string()).or_default() += 1;
+        }
+    }
+}
+
+fn parse_program(arg: &str) -> Option<Program> {
+    let (name, expected) = match arg.rsplit_once(':') {
+        Some((name, code)) => (name, code.parse().ok()?),
+        None => (arg, 0)
+    };
+    if name.is_empty() {
+        return None
+    }
+
+    // Names are aliases, like in a shell
+    let path = match name.contains('/') {
+        true => name.to_string(),
+        false => format!("/usr/bin/{name}")
+    };
+    Some(Program { path, expected })
+}
+
+fn main() -> ExitCode {
+    if env::var_os(FIXTURE_VAR).is_some() {
+        return fixture()
+    }
+
+    let mut iterations = 1000;
+    let mut jobs = thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let mut timeout = Duration::from_secs(5);
+    let mut programs = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--iterations" => args.next().and_then(|n| n.parse().ok()).map(|n| iterations = n),
+            "--jobs" => args.next().and_then(|n| n.parse().ok()).filter(|n| *n > 0).map(|n| jobs = n),
+            "--timeout-ms" => args.next().and_then(|n| n.parse().ok()).map(|n| timeout = Duration::from_millis(n)),
+            _ => parse_program(&arg).map(|p| programs.push(p))
+        };
+        if parsed.is_none() {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE
+        }
+    }
+    if programs.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE
+    }
+
+    // Workers take the next iteration until there are none left, so they stay busy
+    let next = AtomicUsize::new(0);
+    let reports: Mutex<Vec<Report>> = Mutex::new(programs.iter().map(|_| Report::default()).collect());
+    let start = Instant::now();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                let mut local: Vec<Report> = programs.iter().map(|_| Report::default()).collect();
+                while next.fetch_add(1, Ordering::Relaxed) < iterations {
+                    for (program, report) in programs.iter().zip(&mut local) {
+                        run_once(program, timeout, report);
+                    }
+                }
+
+                let mut reports = reports.lock().unwrap();
+                for (total, report) in reports.iter_mut().zip(local) {
+                    total.runs += report.runs;
+                    total.signals += report.signals;
+                    total.stuck += report.stuck;
+                    total.spawn_errors += report.spawn_errors;
+                    for (code, n) in report.exit_codes {
+                        *total.exit_codes.entry(code).or_default() += n;
+                    }
+                    for (leak, n) in report.leaks {
+                        *total.leaks.entry(leak).or_default() += n;
+                    }
+                    for (candidate, n) in report.candidates {
+                        *total.candidates.entry(candidate).or_default() += n;
+                    }
+                }
+            });
+        }
+    });
+
+    let elapsed = start.elapsed();
+    let reports = reports.into_inner().unwrap();
+    let mut failed = false;
+
+    for (program, report) in programs.iter().zip(&reports) {
+        println!("{} (expecting {}): {} runs, {} failures", program.path, program.expected, report.runs, report.failures());
+        for (code, n) in &report.exit_codes {
+            println!("  exited with {code}: {n}");
+        }
+        if report.signals != 0 {
+            println!("  killed by a signal: {}", report.signals);
+        }
+        if report.stuck != 0 {
+            println!("  stuck for over {}ms: {}", timeout.as_millis(), report.stuck);
+        }
+        if report.spawn_errors != 0 {
+            println!("  failed to start: {}", report.spawn_errors);
+        }
+        for (leak, n) in &report.leaks {
+            println!("  leaked fd {leak}: {n}");
+        }
+        // The loader should pick the same candidate every time
+        for (candidate, n) in &report.candidates {
+            println!("  executed {candidate}: {n}");
+        }
+
+        failed |= report.failures() != 0 || report.candidates.len() > 1;
+    }
+
+    let runs: usize = reports.iter().map(|r| r.runs).sum();
+    println!("{runs} runs from {jobs} workers in {:.1}s", elapsed.as_secs_f64());
+
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,276 @@
+/*
+   cargo xtask packaging [--features <list>] [--no-default-features] [--binary <path>] [--out <dir>]
+   Generates packaging fragments for hwcaps-loader from a single description of what it installs
+   and which maintenance subcommands package managers must run, so Debian and RPM packagers
+   don't each re-derive them from FOR_DISTRIBUTORS.md:
+   - debian/ -> hwcaps-loader.{install,dirs,postinst,triggers} and units for debhelper, plus
+                hwcaps-alias.{postinst,prerm} snippets for packages which ship programs
+   - rpm/    -> install.inc, files.inc and scriptlets.inc to %include in a spec file, and units
+                to list as sources, plus macros.hwcaps-loader for packages which ship programs
+   Pass the same features the loader is built with: steps which need optional features
+   (e.g. "hwcaps-loader reindex" needs maintenance and level_index) are left out otherwise.
+   The binary defaults to target/release/hwcaps-loader and the output to target/packaging.
+*/
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: cargo xtask packaging [--features <list>] [--no-default-features] [--binary <path>] [--out <dir>]";
+
+const LOADER_PATH: &str = "/usr/bin/hwcaps-loader";
+// Same as the loader's Cargo.toml
+const DEFAULT_FEATURES: &[&str] = &["self_execution_check", "error_output"];
+
+// Directories owned by the loader's package
+struct Dir {
+    path: &'static str,
+    features: &'static [&'static str],
+}
+
+const DIRS: &[Dir] = &[
+    Dir { path: "/usr/hwcaps", features: &[] },
+    Dir { path: "/usr/lib/hwcaps-loader.conf.d", features: &["config"] },
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum When {
+    // After the loader is installed or upgraded
+    Installed,
+    // After any package adds or removes files in /usr/hwcaps
+    HwcapsChanged,
+}
+
+// Commands the loader's package runs
+struct Step {
+    when: When,
+    command: &'static str,
+    features: &'static [&'static str],
+}
+
+const STEPS: &[Step] = &[
+    Step { when: When::Installed, command: "hwcaps-loader reindex", features: &["maintenance", "level_index"] },
+    Step { when: When::HwcapsChanged, command: "hwcaps-loader reindex", features: &["maintenance", "level_index"] },
+];
+
+// systemd units shipped with the loader
+struct Unit {
+    name: &'static str,
+    features: &'static [&'static str],
+    contents: &'static str,
+}
+
+const UNITS: &[Unit] = &[
+    Unit {
+        name: "hwcaps-loader-resolve.service",
+        features: &["resolve_daemon"],
+        contents: "[Unit]\n\
+                   Description=hwcaps-loader resolution daemon\n\
+                   \n\
+                   [Service]\n\
+                   ExecStart=/usr/bin/hwcaps-loader --daemon\n\
+                   DynamicUser=yes\n\
+                   RuntimeDirectory=hwcaps-loader\n\
+                   RuntimeDirectoryPreserve=yes\n\
+                   \n\
+                   [Install]\n\
+                   WantedBy=multi-user.target\n",
+    },
+];
+
+// Programs' aliases are managed through maintenance subcommands
+const ALIAS_FEATURES: &[&str] = &["maintenance"];
+
+struct Options {
+    features: Vec<String>,
+    binary: String,
+    out: PathBuf,
+}
+
+impl Options {
+    fn has(&self, features: &[&str]) -> bool {
+        features.iter().all(|f| self.features.iter().any(|enabled| enabled == f))
+    }
+
+    fn header(&self, comment: &str) -> String {
+        format!("{comment} Generated by \"cargo xtask packaging\" for features: {}\n", self.features.join(","))
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Option<Options> {
+    // helpers/xtask, in the workspace
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).ancestors().nth(2)?;
+    let mut features: Vec<String> = DEFAULT_FEATURES.iter().map(|f| f.to_string()).collect();
+    let mut extra = Vec::new();
+    let mut binary = String::from("target/release/hwcaps-loader");
+    let mut out = root.join("target/packaging");
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--features" => extra.extend(args.next()?.split([',', ' ']).filter(|f| !f.is_empty()).map(String::from)),
+            "--no-default-features" => features.clear(),
+            "--binary" => binary = args.next()?,
+            "--out" => out = PathBuf::from(args.next()?),
+            _ => return None
+        }
+    }
+
+    features.extend(extra);
+    features.sort();
+    features.dedup();
+    Some(Options { features, binary, out })
+}
+
+// Shell commands for a "case" arm, or None if there aren't any
+fn commands(options: &Options, when: When, indent: &str) -> Option<String> {
+    let mut script = String::new();
+    for step in STEPS.iter().filter(|s| s.when == when && options.has(s.features)) {
+        let _ = writeln!(script, "{indent}{}", step.command);
+    }
+    (!script.is_empty()).then_some(script)
+}
+
+fn debian(options: &Options) -> Vec<(String, String)> {
+    let mut files = Vec::new();
+
+    let install = format!("{}{} usr/bin/\n", options.header("#"), options.binary);
+    files.push(("hwcaps-loader.install".into(), install));
+
+    let mut dirs = options.header("#");
+    for dir in DIRS.iter().filter(|d| options.has(d.features)) {
+        let _ = writeln!(dirs, "{}", &dir.path[1..]);
+    }
+    files.push(("hwcaps-loader.dirs".into(), dirs));
+
+    let installed = commands(options, When::Installed, "        ");
+    let changed = commands(options, When::HwcapsChanged, "        ");
+
+    let mut postinst = format!("#!/bin/sh\n{}\nset -e\n\n", options.header("#"));
+    if installed.is_some() || changed.is_some() {
+        postinst.push_str("case \"$1\" in\n");
+        if let Some(commands) = &installed {
+            let _ = write!(postinst, "    configure)\n{commands}        ;;\n");
+        }
+        if let Some(commands) = &changed {
+            let _ = write!(postinst, "    triggered)\n{commands}        ;;\n");
+        }
+        postinst.push_str("esac\n\n");
+    }
+    postinst.push_str("#DEBHELPER#\n\nexit 0\n");
+    files.push(("hwcaps-loader.postinst".into(), postinst));
+
+    // Noticing packages which change the hwcaps directory is only needed if something runs then
+    if changed.is_some() {
+        let triggers = format!("{}interest-noawait /usr/hwcaps\n", options.header("#"));
+        files.push(("hwcaps-loader.triggers".into(), triggers));
+    }
+
+    // Named units are installed with "dh_installsystemd --name=<unit>"
+    for unit in UNITS.iter().filter(|u| options.has(u.features)) {
+        files.push((format!("hwcaps-loader.{}", unit.name), options.header("#") + unit.contents));
+    }
+
+    // For packages which ship programs: replace #NAMES# with their names
+    if options.has(ALIAS_FEATURES) {
+        let postinst = format!("{}# Add to the package's postinst, replacing #NAMES# with its programs\n\
+            if [ \"$1\" = configure ]; then\n    for name in #NAMES#; do\n        hwcaps-loader install-alias \"$name\"\n    done\nfi\n",
+            options.header("#"));
+        let prerm = format!("{}# Add to the package's prerm, replacing #NAMES# with its programs\n\
+            if [ \"$1\" = remove ]; then\n    for name in #NAMES#; do\n        hwcaps-loader remove-alias \"$name\"\n    done\nfi\n",
+            options.header("#"));
+        files.push(("hwcaps-alias.postinst".into(), postinst));
+        files.push(("hwcaps-alias.prerm".into(), prerm));
+    }
+
+    files
+}
+
+fn rpm(options: &Options) -> Vec<(String, String)> {
+    let mut files = Vec::new();
+    let units: Vec<&Unit> = UNITS.iter().filter(|u| options.has(u.features)).collect();
+
+    // Units are written next to the fragments, to be listed as the spec file's sources
+    let mut install = format!("{}install -Dm755 {} %{{buildroot}}{LOADER_PATH}\n", options.header("#"), options.binary);
+    for dir in DIRS.iter().filter(|d| options.has(d.features)) {
+        let _ = writeln!(install, "install -dm755 %{{buildroot}}{}", dir.path);
+    }
+    for unit in &units {
+        let _ = writeln!(install, "install -Dm644 %{{_sourcedir}}/{0} %{{buildroot}}%{{_unitdir}}/{0}", unit.name);
+    }
+    files.push(("install.inc".into(), install));
+    for unit in &units {
+        files.push((unit.name.into(), options.header("#") + unit.contents));
+    }
+
+    let mut list = format!("{}{LOADER_PATH}\n", options.header("#"));
+    for dir in DIRS.iter().filter(|d| options.has(d.features)) {
+        let _ = writeln!(list, "%dir {}", dir.path);
+    }
+    for unit in &units {
+        let _ = writeln!(list, "%{{_unitdir}}/{}", unit.name);
+    }
+    files.push(("files.inc".into(), list));
+
+    let mut scriptlets = options.header("#");
+    let names: Vec<&str> = units.iter().map(|u| u.name).collect();
+    let mut post = commands(options, When::Installed, "").unwrap_or_default();
+    if !names.is_empty() {
+        let _ = writeln!(post, "%systemd_post {}", names.join(" "));
+        let _ = write!(scriptlets, "\n%preun\n%systemd_preun {0}\n\n%postun\n%systemd_postun_with_restart {0}\n", names.join(" "));
+    }
+    if !post.is_empty() {
+        let _ = write!(scriptlets, "\n%post\n{post}");
+    }
+    // File triggers fire once per transaction, for every package touching the hwcaps directory
+    if let Some(commands) = commands(options, When::HwcapsChanged, "") {
+        let _ = write!(scriptlets, "\n%transfiletriggerin -- /usr/hwcaps\n{commands}\n%transfiletriggerpostun -- /usr/hwcaps\n{commands}");
+    }
+    files.push(("scriptlets.inc".into(), scriptlets));
+
+    if options.has(ALIAS_FEATURES) {
+        let macros = format!("{}# In packages which ship programs: \"%post\" followed by \"%hwcaps_alias_post foo bar\", and\n\
+            # \"%preun\" followed by \"%hwcaps_alias_preun foo bar\"\n\
+            %hwcaps_alias_post() for name in %{{*}}; do hwcaps-loader install-alias \"$name\"; done\n\
+            %hwcaps_alias_preun() if [ $1 -eq 0 ]; then for name in %{{*}}; do hwcaps-loader remove-alias \"$name\"; done; fi\n",
+            options.header("#"));
+        files.push(("macros.hwcaps-loader".into(), macros));
+    }
+
+    files
+}
+
+fn write(out: &Path, directory: &str, files: Vec<(String, String)>) -> std::io::Result<()> {
+    let directory = out.join(directory);
+    // Fragments from a previous run with other features mustn't linger
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory)?;
+
+    for (name, contents) in files {
+        let path = directory.join(name);
+        fs::write(&path, contents)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let options = match (args.next().as_deref(), parse_options(args)) {
+        (Some("packaging"), Some(options)) => options,
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE
+        }
+    };
+
+    let result = write(&options.out, "debian", debian(&options))
+        .and_then(|_| write(&options.out, "rpm", rpm(&options)));
+
+    if let Err(e) = result {
+        eprintln!("Failed to write packaging fragments to {}: {e}", options.out.display());
+        return ExitCode::FAILURE
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,106 @@
+/*
+   CPU affinity pinning for hybrid systems.
+   Feature levels are detected on whichever core we happen to run on. If some cores don't
+   support every feature of the chosen level (misconfigured kernels on hybrid systems, for
+   example), the target could fault with illegal instructions once it's migrated to them.
+   To prevent that, every allowed core is visited to find out which levels it supports, and the
+   target is pinned to the cores which support the level it was built for. Alternatively, only
+   levels every allowed core supports are considered, so the target keeps every core.
+*/
+
+use core::cell::Cell;
+
+use crate::sys;
+use crate::capabilities;
+
+const MAX_CPUS: usize = 1024;
+const WORD_BITS: usize = u64::BITS as usize;
+
+type CpuSet = [u64; MAX_CPUS / WORD_BITS];
+
+fn contains(set: &CpuSet, cpu: usize) -> bool {
+    set[cpu / WORD_BITS] >> (cpu % WORD_BITS) & 1 == 1
+}
+
+fn insert(set: &mut CpuSet, cpu: usize) {
+    set[cpu / WORD_BITS] |= 1 << (cpu % WORD_BITS);
+}
+
+// Returns the highest level CPU number cpu supports, or None if it's offline
+fn get_level(cpu: usize) -> Option<u32> {
+    // On aarch64, CPUs only differ by their quirks, which can be read without visiting them
+    #[cfg(target_arch = "aarch64")]
+    if let Some(level) = capabilities::get_cpu_feature_level(cpu as u32) {
+        return Some(level)
+    }
+
+    let mut single: CpuSet = [0; MAX_CPUS / WORD_BITS];
+    insert(&mut single, cpu);
+
+    // The kernel migrates us before returning
+    sys::sched_setaffinity(0, &single).ok()?;
+    Some(capabilities::get_max_feature_level())
+}
+
+pub struct CpuLevels {
+    // CPUs we were allowed to run on to begin with
+    allowed: CpuSet,
+    // Highest feature level supported by each allowed CPU
+    levels: [u8; MAX_CPUS],
+    // Lowest of those levels. Levels up to this one are supported everywhere.
+    min_level: u32,
+    // Whether we're currently restricted to some of the allowed CPUs
+    pinned: Cell<bool>,
+}
+
+impl CpuLevels {
+    // Returns None if the allowed CPUs can't be determined
+    pub fn detect() -> Option<Self> {
+        let mut allowed: CpuSet = [0; MAX_CPUS / WORD_BITS];
+        sys::sched_getaffinity(0, &mut allowed).ok()?;
+
+        let mut cpu_levels = CpuLevels { allowed, levels: [0; MAX_CPUS], min_level: u32::MAX, pinned: Cell::new(false) };
+
+        for cpu in (0..MAX_CPUS).filter(|cpu| contains(&allowed, *cpu)) {
+            // CPUs which went offline in the meantime
+            let level = match get_level(cpu) {
+                Some(level) => level,
+                None => continue
+            };
+
+            cpu_levels.levels[cpu] = level as u8;
+            cpu_levels.min_level = core::cmp::min(cpu_levels.min_level, level);
+        }
+
+        let _ = sys::sched_setaffinity(0, &allowed);
+        Some(cpu_levels)
+    }
+
+    // Highest level supported by every allowed CPU
+    pub fn min_level(&self) -> u32 {
+        self.min_level
+    }
+
+    // Restricts us (and the target we'll execute) to the CPUs which support level
+    pub fn pin(&self, level: u32) {
+        // Levels supported by every CPU don't need any pinning
+        if level <= self.min_level {
+            if self.pinned.get() {
+                let _ = sys::sched_setaffinity(0, &self.allowed);
+                self.pinned.set(false);
+            }
+            return
+        }
+
+        let mut supported: CpuSet = [0; MAX_CPUS / WORD_BITS];
+        for cpu in (0..MAX_CPUS).filter(|cpu| contains(&self.allowed, *cpu)) {
+            if self.levels[cpu] as u32 >= level {
+                insert(&mut supported, cpu);
+            }
+        }
+
+        if sys::sched_setaffinity(0, &supported).is_ok() {
+            self.pinned.set(true);
+        }
+    }
+}
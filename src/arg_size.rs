@@ -0,0 +1,107 @@
+/*
+   Oversized arguments and environment.
+   execve() fails with E2BIG when the target's arguments and environment don't fit in the space
+   the kernel sets aside for them (a quarter of the stack size limit, usually 2 MiB), or when a
+   single one of them is longer than 128 KiB. The target is fine either way, so instead of a
+   generic execution error, this reports how large they are and which one is to blame.
+*/
+
+use core::ffi::c_char;
+#[cfg(feature = "error_output")]
+use core::ffi::CStr;
+
+use crate::sys::{self, ExitCode};
+use crate::output::{abort, Fatal};
+#[cfg(feature = "error_output")]
+use crate::{output, path};
+
+// MAX_ARG_STRLEN, the longest string the kernel copies
+#[cfg(feature = "error_output")]
+const MAX_STRING_LEN: usize = 32 * 4096;
+// Long enough for any variable name worth printing
+#[cfg(feature = "error_output")]
+const NAME_MAX: usize = 64;
+
+// What the kernel counts against the limit
+#[cfg(feature = "error_output")]
+struct Usage {
+    // Strings (with their terminators) and the pointers to them
+    total: usize,
+    // Length of the longest string, and where it is
+    longest: usize,
+    longest_in_envp: bool,
+    longest_index: usize,
+    longest_string: &'static [u8],
+}
+
+#[cfg(feature = "error_output")]
+fn measure(list: *const *const c_char, in_envp: bool, usage: &mut Usage) {
+    if list.is_null() {
+        return
+    }
+
+    let mut i = 0;
+    loop {
+        let string = unsafe { *list.add(i) };
+        if string.is_null() {
+            break
+        }
+        let string = unsafe { CStr::from_ptr(string).to_bytes() };
+
+        usage.total += string.len() + 1 + size_of::<*const c_char>();
+        if string.len() > usage.longest {
+            usage.longest = string.len();
+            usage.longest_in_envp = in_envp;
+            usage.longest_index = i;
+            usage.longest_string = string;
+        }
+        i += 1;
+    }
+    // Both lists end with a null pointer
+    usage.total += size_of::<*const c_char>();
+}
+
+#[cfg(feature = "error_output")]
+fn explain(argv: *const *const c_char, envp: *const *const c_char) {
+    let mut usage = Usage { total: 0, longest: 0, longest_in_envp: false, longest_index: 0, longest_string: b"" };
+    measure(argv, false, &mut usage);
+    measure(envp, true, &mut usage);
+
+    let mut digits = [0u8; 16];
+    let mut line = [0u8; 320];
+
+    let len = match usage.longest > MAX_STRING_LEN {
+        // Variables are more useful by name, arguments by position
+        true if usage.longest_in_envp => {
+            let end = usage.longest_string.iter().position(|c| *c == b'=').unwrap_or(usage.longest_string.len());
+            let name = &usage.longest_string[..core::cmp::min(end, NAME_MAX)];
+            let name = if name.iter().all(|c| c.is_ascii_graphic()) { name } else { b"(unprintable)" };
+            path::join(&mut line, &[b"hwcaps-loader: Environment variable ", name,
+                b" is longer than the 128 KiB the kernel allows for a single variable."])
+        },
+        true => {
+            let digits_len = path::itoa(usage.longest_index as u32, &mut digits);
+            path::join(&mut line, &[b"hwcaps-loader: Argument ", &digits[..digits_len],
+                b" is longer than the 128 KiB the kernel allows for a single argument."])
+        },
+        false => {
+            let digits_len = path::itoa(core::cmp::min(usage.total, u32::MAX as usize) as u32, &mut digits);
+            path::join(&mut line, &[b"hwcaps-loader: Arguments and environment take ", &digits[..digits_len],
+                b" bytes, more than the kernel allows (a quarter of \"ulimit -s\", usually 2 MiB).",
+                b" Check for oversized environment variables, or pass long argument lists through xargs."])
+        }
+    };
+
+    if let Some(len) = len {
+        output::print_line(&line[..len]);
+    }
+}
+
+// Reports why execve() failed with E2BIG while executing path, then aborts
+#[cold]
+pub fn abort_too_large(_argv: *const *const c_char, _envp: *const *const c_char, path: &[u8]) -> ! {
+    #[cfg(feature = "error_output")]
+    explain(_argv, _envp);
+
+    abort(&Fatal(ExitCode::TargetArgumentsTooLarge, "Arguments and environment are too large to execute target!"), sys::E2BIG, Some(path))
+}
@@ -0,0 +1,209 @@
+/*
+   Build-ID consistency checking.
+   A level directory can be left with a stale binary from a previous version of a package (e.g.
+   when a package stops shipping a level, or an upgrade is interrupted), which the loader would
+   happily execute. Packages can ship a manifest of the GNU build-IDs they installed, generated
+   at package build, for each program:
+   /usr/hwcaps/.build-ids/<path>   (e.g. /usr/hwcaps/.build-ids/bin/foo)
+   made of "<directory> <build-id>" lines, where <directory> is a level directory (with its
+   variant suffix, if any) and <build-id> is in hex, as readelf prints it:
+   x86-64-v1 0e5a9bb4c3d1f07a8c0f1b2d3e4f5a6b7c8d9e0f
+   x86-64-v3 7d2c4e6f8a0b1c3d5e7f9a1b2c3d4e5f6a7b8c9d
+   Before a candidate is executed, its build-ID note is read and compared with the manifest's,
+   which aborts with TargetBuildIdMismatch if they differ (or if the candidate has none), or only
+   warns with "build_id_mismatch = warn" in the configuration. Programs without a manifest, and
+   directories it doesn't list, aren't checked. Neither are candidates outside the hwcaps
+   directory (extra_dir, force_target).
+*/
+
+use core::ffi::CStr;
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::output::{abort, Fatal};
+use crate::HWCAPS_PATH;
+
+const MANIFEST_DIR: &[u8] = b".build-ids";
+const MANIFEST_MAX: usize = 4096;
+// SHA-1 build-IDs take 20 bytes, but other lengths are allowed
+const BUILD_ID_MAX: usize = 64;
+// Enough for the program headers of any reasonable binary
+const HEADERS_MAX: usize = 4096;
+// Enough for the notes of any reasonable binary (ABI tag, build-ID, properties...)
+const NOTES_MAX: usize = 1024;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+#[cfg(target_endian = "little")]
+const ELFDATA_NATIVE: u8 = 1;
+#[cfg(target_endian = "big")]
+const ELFDATA_NATIVE: u8 = 2;
+const PT_NOTE: u32 = 4;
+const NT_GNU_BUILD_ID: u32 = 3;
+const GNU_NAME: &[u8] = b"GNU\0";
+
+fn read_exact(fd: i32, buffer: &mut [u8], offset: u64) -> Option<()> {
+    match sys::pread64(fd, buffer, offset) {
+        Ok(n) if n == buffer.len() => Some(()),
+        _ => None
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> usize {
+    u16::from_ne_bytes([bytes[offset], bytes[offset+1]]) as usize
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u64 {
+    u32::from_ne_bytes(bytes[offset..offset+4].try_into().unwrap()) as u64
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_ne_bytes(bytes[offset..offset+8].try_into().unwrap())
+}
+
+// Returns the build-ID in notes (a PT_NOTE segment, whose entries are aligned to align)
+fn find_note(notes: &[u8], align: usize) -> Option<&[u8]> {
+    let pad = |len: usize| len.div_ceil(align) * align;
+    let mut offset = 0;
+
+    while offset + 12 <= notes.len() {
+        let name_len = read_u32(notes, offset) as usize;
+        let desc_len = read_u32(notes, offset + 4) as usize;
+        let kind = read_u32(notes, offset + 8) as u32;
+
+        let name_start = offset + 12;
+        let desc_start = name_start.checked_add(pad(name_len))?;
+        let desc_end = desc_start.checked_add(desc_len)?;
+        if desc_end > notes.len() {
+            return None
+        }
+
+        if kind == NT_GNU_BUILD_ID && notes[name_start..name_start + name_len] == *GNU_NAME {
+            return Some(&notes[desc_start..desc_end])
+        }
+        offset = desc_start.checked_add(pad(desc_len))?;
+    }
+    None
+}
+
+// Reads the build-ID of the binary open as fd into buffer, returning its length.
+// Returns None if there's none (including files which aren't ELF binaries, or malformed ones).
+fn read_build_id(fd: i32, buffer: &mut [u8; BUILD_ID_MAX]) -> Option<usize> {
+    let mut header = [0u8; 64];
+    let header_len = sys::pread64(fd, &mut header, 0).ok()?;
+    if header_len < 52 || header[..4] != *ELF_MAGIC || header[5] != ELFDATA_NATIVE {
+        return None
+    }
+
+    // Offsets of e_phoff, e_phentsize and e_phnum, and of p_offset, p_filesz and p_align
+    let (phoff, phentsize, phnum, p_offset, p_filesz, p_align) = match header[4] {
+        ELFCLASS64 if header_len == header.len() => (read_u64(&header, 32), read_u16(&header, 54), read_u16(&header, 56), 8, 32, 48),
+        ELFCLASS32 => (read_u32(&header, 28), read_u16(&header, 42), read_u16(&header, 44), 4, 16, 28),
+        _ => return None
+    };
+    let is_64 = header[4] == ELFCLASS64;
+
+    let size = phentsize * phnum;
+    if phentsize < p_align + 8 || size > HEADERS_MAX {
+        return None
+    }
+
+    let mut headers = [0u8; HEADERS_MAX];
+    read_exact(fd, &mut headers[..size], phoff)?;
+
+    for entry in headers[..size].chunks_exact(phentsize) {
+        if read_u32(entry, 0) as u32 != PT_NOTE {
+            continue
+        }
+
+        let (offset, len, align) = match is_64 {
+            true => (read_u64(entry, p_offset), read_u64(entry, p_filesz), read_u64(entry, p_align)),
+            false => (read_u32(entry, p_offset), read_u32(entry, p_filesz), read_u32(entry, p_align))
+        };
+        if len as usize > NOTES_MAX {
+            continue
+        }
+
+        let mut notes = [0u8; NOTES_MAX];
+        read_exact(fd, &mut notes[..len as usize], offset)?;
+
+        // Notes are aligned to 4 bytes, except in segments aligned to 8 (e.g. GNU properties)
+        let align = if align == 8 { 8 } else { 4 };
+        if let Some(build_id) = find_note(&notes[..len as usize], align) {
+            if build_id.len() > BUILD_ID_MAX {
+                return None
+            }
+            buffer[..build_id.len()].copy_from_slice(build_id);
+            return Some(build_id.len())
+        }
+    }
+    None
+}
+
+fn from_hex(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|d| d as u8)
+}
+
+// Whether hex (as in the manifest) is build_id
+fn is_same(hex: &[u8], build_id: &[u8]) -> bool {
+    hex.len() == build_id.len() * 2 && hex.chunks_exact(2).zip(build_id).all(|(pair, byte)| {
+        matches!((from_hex(pair[0]), from_hex(pair[1])), (Some(high), Some(low)) if high << 4 | low == *byte)
+    })
+}
+
+// Aborts (or warns, if warn_only is set) if the candidate at path (open as fd) doesn't have the
+// build-ID its program's manifest expects
+pub fn check(fd: i32, path: &CStr, warn_only: bool) {
+    // Part of the path after the hwcaps directory (e.g. "x86-64-v3/bin/foo")
+    let relative = match path.to_bytes().strip_prefix(HWCAPS_PATH) {
+        Some(r) => r,
+        None => return
+    };
+    let (directory, program) = match relative.iter().position(|c| *c == b'/') {
+        Some(i) => relative.split_at(i),
+        None => return
+    };
+
+    let mut manifest_path = [0u8; sys::PATH_MAX as usize];
+    let len = match path::join(&mut manifest_path, &[HWCAPS_PATH, MANIFEST_DIR, program]) {
+        Some(l) => l,
+        None => return
+    };
+    let manifest_path = unsafe { CStr::from_bytes_with_nul_unchecked(&manifest_path[..len+1]) };
+
+    // Packages which don't ship a manifest aren't checked
+    let mut manifest = [0u8; MANIFEST_MAX];
+    let manifest = match sys::read_file(manifest_path, &mut manifest) {
+        Ok(len) => &manifest[..len],
+        Err(_) => return
+    };
+
+    let expected = manifest.split(|c| *c == b'\n')
+        .filter_map(|line| {
+            let line = line.trim_ascii();
+            let separator = line.iter().position(|c| c.is_ascii_whitespace())?;
+            Some((&line[..separator], line[separator..].trim_ascii()))
+        })
+        .find(|(d, _)| *d == directory);
+    let expected = match expected {
+        Some((_, hex)) => hex,
+        None => return
+    };
+
+    let mut build_id = [0u8; BUILD_ID_MAX];
+    let matches = match read_build_id(fd, &mut build_id) {
+        Some(len) => is_same(expected, &build_id[..len]),
+        None => false
+    };
+    if matches {
+        return
+    }
+
+    if !warn_only {
+        abort(&Fatal(ExitCode::TargetBuildIdMismatch, "Target binary's build-ID doesn't match its manifest!"), 0, Some(path.to_bytes()))
+    }
+
+    #[cfg(feature = "error_output")]
+    crate::output::hint("Warning: target binary's build-ID doesn't match its manifest.", Some(path.to_bytes()));
+}
@@ -0,0 +1,93 @@
+/*
+   Canary rollout of feature levels.
+   Distributions can roll out builds for a newly shipped level gradually, with
+   "canary = <level>:<percent>" in the configuration: candidates for <level> (and its variants)
+   are only used by <percent> of launches, and the rest behave as if they didn't exist. Which
+   launches those are is decided by a hash of the boot ID and the program's name, so a program
+   consistently uses the same builds until the next boot, and no random numbers are needed.
+   Fallback and error counters (metrics, fallback_counters, error_file) can be watched before
+   raising the percentage to 100 or removing the line. Launches kept off a level which is the
+   best this machine supports don't count as fallbacks, since nothing is missing.
+   If the boot ID can't be read, launches stay off canary levels.
+*/
+
+use crate::sys;
+use crate::capabilities;
+
+const BOOT_ID_PATH: &core::ffi::CStr = c"/proc/sys/kernel/random/boot_id";
+const BOOT_ID_LEN: usize = 36;
+const MAX_RULES: usize = 8;
+
+#[derive(Clone, Copy)]
+pub struct Rules {
+    // Level and percentage of launches which use it
+    rules: [Option<(u32, u32)>; MAX_RULES],
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules { rules: [None; MAX_RULES] }
+    }
+}
+
+impl Rules {
+    // Parses "<level>:<percent>" (e.g. "x86-64-v4:10"). Returns None if it's invalid, or there
+    // are too many rules.
+    pub fn push(&mut self, value: &[u8]) -> Option<()> {
+        let separator = value.iter().position(|c| *c == b':')?;
+        let level = capabilities::find_feature_level(&value[..separator])?;
+        let percent = crate::path::atoi(&value[separator+1..]).filter(|p| *p <= 100)?;
+
+        // A later rule for the same level replaces the earlier one
+        let slot = match self.rules.iter().position(|r| r.is_some_and(|(l, _)| l == level)) {
+            Some(i) => &mut self.rules[i],
+            None => self.rules.iter_mut().find(|r| r.is_none())?
+        };
+        *slot = Some((level, percent));
+        Some(())
+    }
+}
+
+// FNV-1a
+fn hash(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, c| (hash ^ *c as u64).wrapping_mul(0x100000001b3))
+}
+
+// Which launches use which canary levels
+pub struct Canary<'a> {
+    rules: &'a Rules,
+    // Between 0 and 99, or None if the boot ID couldn't be read
+    bucket: Option<u32>,
+}
+
+impl<'a> Canary<'a> {
+    // program is the name of the program being executed (e.g. "foo")
+    pub fn new(rules: &'a Rules, program: &[u8]) -> Self {
+        // Only read when it's needed
+        if rules.rules.iter().all(|r| r.is_none()) {
+            return Canary { rules, bucket: None }
+        }
+
+        let mut boot_id = [0u8; BOOT_ID_LEN];
+        let bucket = match sys::read_file(BOOT_ID_PATH, &mut boot_id) {
+            Ok(BOOT_ID_LEN) => Some((hash(hash(0xcbf29ce484222325, &boot_id), program) % 100) as u32),
+            _ => None
+        };
+
+        Canary { rules, bucket }
+    }
+
+    // Whether candidates for level must be skipped by this launch
+    pub fn skips(&self, level: u32) -> bool {
+        match (self.rules.rules.iter().flatten().find(|(l, _)| *l == level), self.bucket) {
+            (Some((_, percent)), Some(bucket)) => bucket >= *percent,
+            (Some(_), None) => true,
+            (None, _) => false
+        }
+    }
+
+    // The highest level up to max_level which this launch doesn't skip
+    pub fn limit(&self, max_level: u32) -> u32 {
+        (0..=max_level).rev().find(|level| !self.skips(*level)).unwrap_or(0)
+    }
+}
@@ -0,0 +1,364 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+use core::arch::asm;
+use core::ffi::CStr;
+
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+bitflags! {
+    // AT_HWCAP, as in the kernel's arch/arm64/include/uapi/asm/hwcap.h
+    pub struct Aarch64Hwcap: u64 {
+        // ARMv8.0
+        const FP       = 1 << 0;
+        const ASIMD    = 1 << 1;
+        const AES      = 1 << 3;
+        const PMULL    = 1 << 4;
+        const SHA1     = 1 << 5;
+        const SHA2     = 1 << 6;
+        const CRC32    = 1 << 7;
+        // ARMv8.1 (LSE)
+        const ATOMICS  = 1 << 8;
+        const ASIMDRDM = 1 << 12;
+        // ARMv8.2
+        const FPHP     = 1 << 9;
+        const ASIMDHP  = 1 << 10;
+        const DCPOP    = 1 << 16;
+        const SVE      = 1 << 22;
+        // MIDR_EL1 and friends can be read (the kernel emulates them)
+        const CPUID    = 1 << 11;
+        // ARMv8.3
+        const JSCVT    = 1 << 13;
+        const FCMA     = 1 << 14;
+        const LRCPC    = 1 << 15;
+        const PACA     = 1 << 30;
+        const PACG     = 1 << 31;
+        // ARMv8.4
+        const ASIMDDP  = 1 << 20;
+        const ASIMDFHM = 1 << 23;
+        const DIT      = 1 << 24;
+        const USCAT    = 1 << 25;
+        const ILRCPC   = 1 << 26;
+        const FLAGM    = 1 << 27;
+        const SHA3     = 1 << 17;
+        const SHA512   = 1 << 21;
+        // ARMv8.5
+        const SSBS     = 1 << 28;
+        const SB       = 1 << 29;
+    }
+
+    // AT_HWCAP2
+    pub struct Aarch64Hwcap2: u64 {
+        const DCPODP = 1 << 0;
+        const SVE2   = 1 << 1;
+        const FLAGM2 = 1 << 7;
+        const FRINT  = 1 << 8;
+        const I8MM   = 1 << 13;
+        const BF16   = 1 << 14;
+        const RNG    = 1 << 16;
+        const BTI    = 1 << 17;
+        const MTE    = 1 << 18;
+    }
+}
+
+// Names of the features levels are made of, as in /proc/cpuinfo
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"fp", Features { hwcap: Aarch64Hwcap::FP.bits(), ..Features::NONE }),
+    (b"asimd", Features { hwcap: Aarch64Hwcap::ASIMD.bits(), ..Features::NONE }),
+    (b"aes", Features { hwcap: Aarch64Hwcap::AES.bits(), ..Features::NONE }),
+    (b"pmull", Features { hwcap: Aarch64Hwcap::PMULL.bits(), ..Features::NONE }),
+    (b"sha1", Features { hwcap: Aarch64Hwcap::SHA1.bits(), ..Features::NONE }),
+    (b"sha2", Features { hwcap: Aarch64Hwcap::SHA2.bits(), ..Features::NONE }),
+    (b"crc32", Features { hwcap: Aarch64Hwcap::CRC32.bits(), ..Features::NONE }),
+    (b"atomics", Features { hwcap: Aarch64Hwcap::ATOMICS.bits(), ..Features::NONE }),
+    (b"fphp", Features { hwcap: Aarch64Hwcap::FPHP.bits(), ..Features::NONE }),
+    (b"asimdhp", Features { hwcap: Aarch64Hwcap::ASIMDHP.bits(), ..Features::NONE }),
+    (b"cpuid", Features { hwcap: Aarch64Hwcap::CPUID.bits(), ..Features::NONE }),
+    (b"asimdrdm", Features { hwcap: Aarch64Hwcap::ASIMDRDM.bits(), ..Features::NONE }),
+    (b"jscvt", Features { hwcap: Aarch64Hwcap::JSCVT.bits(), ..Features::NONE }),
+    (b"fcma", Features { hwcap: Aarch64Hwcap::FCMA.bits(), ..Features::NONE }),
+    (b"lrcpc", Features { hwcap: Aarch64Hwcap::LRCPC.bits(), ..Features::NONE }),
+    (b"dcpop", Features { hwcap: Aarch64Hwcap::DCPOP.bits(), ..Features::NONE }),
+    (b"sha3", Features { hwcap: Aarch64Hwcap::SHA3.bits(), ..Features::NONE }),
+    (b"asimddp", Features { hwcap: Aarch64Hwcap::ASIMDDP.bits(), ..Features::NONE }),
+    (b"sha512", Features { hwcap: Aarch64Hwcap::SHA512.bits(), ..Features::NONE }),
+    (b"sve", Features { hwcap: Aarch64Hwcap::SVE.bits(), ..Features::NONE }),
+    (b"asimdfhm", Features { hwcap: Aarch64Hwcap::ASIMDFHM.bits(), ..Features::NONE }),
+    (b"dit", Features { hwcap: Aarch64Hwcap::DIT.bits(), ..Features::NONE }),
+    (b"uscat", Features { hwcap: Aarch64Hwcap::USCAT.bits(), ..Features::NONE }),
+    (b"ilrcpc", Features { hwcap: Aarch64Hwcap::ILRCPC.bits(), ..Features::NONE }),
+    (b"flagm", Features { hwcap: Aarch64Hwcap::FLAGM.bits(), ..Features::NONE }),
+    (b"ssbs", Features { hwcap: Aarch64Hwcap::SSBS.bits(), ..Features::NONE }),
+    (b"sb", Features { hwcap: Aarch64Hwcap::SB.bits(), ..Features::NONE }),
+    (b"paca", Features { hwcap: Aarch64Hwcap::PACA.bits(), ..Features::NONE }),
+    (b"pacg", Features { hwcap: Aarch64Hwcap::PACG.bits(), ..Features::NONE }),
+    (b"dcpodp", Features { hwcap2: Aarch64Hwcap2::DCPODP.bits(), ..Features::NONE }),
+    (b"sve2", Features { hwcap2: Aarch64Hwcap2::SVE2.bits(), ..Features::NONE }),
+    (b"flagm2", Features { hwcap2: Aarch64Hwcap2::FLAGM2.bits(), ..Features::NONE }),
+    (b"frint", Features { hwcap2: Aarch64Hwcap2::FRINT.bits(), ..Features::NONE }),
+    (b"i8mm", Features { hwcap2: Aarch64Hwcap2::I8MM.bits(), ..Features::NONE }),
+    (b"bf16", Features { hwcap2: Aarch64Hwcap2::BF16.bits(), ..Features::NONE }),
+    (b"rng", Features { hwcap2: Aarch64Hwcap2::RNG.bits(), ..Features::NONE }),
+    (b"bti", Features { hwcap2: Aarch64Hwcap2::BTI.bits(), ..Features::NONE }),
+    (b"mte", Features { hwcap2: Aarch64Hwcap2::MTE.bits(), ..Features::NONE }),
+];
+
+// Architecture versions, named like GCC's and Clang's -march (e.g. "armv8.2-a"). Each one only
+// requires the features its version makes mandatory (and the kernel reports), so it's the baseline
+// a compiler targets for it. Optional features (crypto, FP16, SVE on ARMv8) don't make a level.
+const ARMV8_HWCAPS: u64 = Aarch64Hwcap::FP.bits() | Aarch64Hwcap::ASIMD.bits();
+// Large System Extensions, whose atomics matter the most for contended workloads
+const ARMV8_1_HWCAPS: u64 = ARMV8_HWCAPS | Aarch64Hwcap::CRC32.bits() | Aarch64Hwcap::ATOMICS.bits() | Aarch64Hwcap::ASIMDRDM.bits();
+const ARMV8_2_HWCAPS: u64 = ARMV8_1_HWCAPS | Aarch64Hwcap::DCPOP.bits();
+const ARMV8_3_HWCAPS: u64 = ARMV8_2_HWCAPS | Aarch64Hwcap::JSCVT.bits() | Aarch64Hwcap::FCMA.bits() | Aarch64Hwcap::LRCPC.bits()
+                          | Aarch64Hwcap::PACA.bits() | Aarch64Hwcap::PACG.bits();
+const ARMV8_4_HWCAPS: u64 = ARMV8_3_HWCAPS | Aarch64Hwcap::ASIMDDP.bits() | Aarch64Hwcap::DIT.bits() | Aarch64Hwcap::USCAT.bits()
+                          | Aarch64Hwcap::ILRCPC.bits() | Aarch64Hwcap::FLAGM.bits();
+const ARMV8_5_HWCAPS: u64 = ARMV8_4_HWCAPS | Aarch64Hwcap::SSBS.bits() | Aarch64Hwcap::SB.bits();
+const ARMV8_5_HWCAPS2: u64 = Aarch64Hwcap2::FLAGM2.bits() | Aarch64Hwcap2::FRINT.bits();
+// ARMv9.0 is ARMv8.5 with SVE2, which ARMv8 CPUs (even those with SVE) don't have. Later ARMv8
+// versions (e.g. ARMv8.6's I8MM and BF16) aren't part of it, so they can't be levels below it.
+const ARMV9_HWCAPS: u64 = ARMV8_5_HWCAPS | Aarch64Hwcap::SVE.bits();
+const ARMV9_HWCAPS2: u64 = ARMV8_5_HWCAPS2 | Aarch64Hwcap2::SVE2.bits();
+
+// Auxiliary vector entries which hold the features we're interested in
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub hwcap: u64,
+    pub hwcap2: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        hwcap: 0,
+        hwcap2: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap | other.hwcap,
+            hwcap2: self.hwcap2 | other.hwcap2,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap & !other.hwcap,
+            hwcap2: self.hwcap2 & !other.hwcap2,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.hwcap & other.hwcap == other.hwcap
+        && self.hwcap2 & other.hwcap2 == other.hwcap2
+    }
+}
+
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv8-a",
+        directory: b"armv8-a",
+        features: Features { hwcap: ARMV8_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv8.1-a",
+        directory: b"armv8.1-a",
+        features: Features { hwcap: ARMV8_1_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv8.2-a",
+        directory: b"armv8.2-a",
+        features: Features { hwcap: ARMV8_2_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv8.3-a",
+        directory: b"armv8.3-a",
+        features: Features { hwcap: ARMV8_3_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv8.4-a",
+        directory: b"armv8.4-a",
+        features: Features { hwcap: ARMV8_4_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv8.5-a",
+        directory: b"armv8.5-a",
+        features: Features { hwcap: ARMV8_5_HWCAPS, hwcap2: ARMV8_5_HWCAPS2 },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv9-a",
+        directory: b"armv9-a",
+        features: Features { hwcap: ARMV9_HWCAPS, hwcap2: ARMV9_HWCAPS2 },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+pub const ARCH_NAME: &[u8] = b"aarch64";
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Features come from the kernel rather than from the CPU, since most of the ID registers can't be
+// read from userspace (and the kernel knows which features it has enabled). If the auxiliary
+// vector can't be found, only the baseline level is supported.
+#[inline]
+pub fn get_features() -> Features {
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+    let hwcap2 = sys::auxval(sys::AT_HWCAP2 as _).unwrap_or(0);
+
+    Features { hwcap, hwcap2 }
+}
+
+// Reads MIDR_EL1, if the kernel emulates it for us
+fn get_midr(features: &Features) -> Option<u64> {
+    if features.hwcap & Aarch64Hwcap::CPUID.bits() == 0 {
+        return None
+    }
+
+    let midr: u64;
+    unsafe { asm!("mrs {}, midr_el1", out(reg) midr, options(nomem, nostack, preserves_flags)) };
+    Some(midr)
+}
+
+// Passes the auxiliary vector entries (and MIDR_EL1, if it can be read) detection reads to
+// report, as the kernel returns them
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    let features = get_features();
+    report(&Raw { source: b"auxv", fields: &[
+        (b"hwcap", features.hwcap),
+        (b"hwcap2", features.hwcap2),
+    ]});
+
+    if let Some(midr) = get_midr(&features) {
+        report(&Raw { source: b"midr_el1", fields: &[(b"value", midr)] });
+    }
+}
+
+// Hypervisors can't be told apart from bare metal without help from the firmware, which
+// userspace can't ask, so we always assume bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    None
+}
+
+// Implementer codes (MIDR_EL1's top byte) of well known vendors
+const IMPLEMENTERS: [(u32, &[u8]); 11] = [
+    (0x41, b"ARM"),
+    (0x42, b"Broadcom"),
+    (0x43, b"Cavium"),
+    (0x46, b"Fujitsu"),
+    (0x48, b"HiSilicon"),
+    (0x4e, b"NVIDIA"),
+    (0x50, b"APM"),
+    (0x51, b"Qualcomm"),
+    (0x61, b"Apple"),
+    (0x6d, b"Microsoft"),
+    (0xc0, b"Ampere"),
+];
+
+// CPUs whose implementation of some features is broken or too slow to be worth using
+struct Quirk {
+    implementer: u32,
+    part: u32,
+    // Inclusive, as (variant << 4) | revision
+    revisions: (u32, u32),
+    // Features which are ignored on matching CPUs
+    ignored: Features,
+}
+
+// Adding a CPU only takes an entry here
+const QUIRKS: &[Quirk] = &[];
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // Implementer's name, padded with NULs (e.g. "ARM"), or NULs if it isn't a well known one
+    pub vendor: [u8; 12],
+    // Implementer code (e.g. 0x41)
+    pub family: u32,
+    // Part number (e.g. 0xd0c, Neoverse N1)
+    pub model: u32,
+    // Variant and revision, as (variant << 4) | revision
+    pub stepping: u32,
+}
+
+fn decode_signature(midr: u64) -> Signature {
+    let family = ((midr >> 24) & 0xFF) as u32;
+    let model = ((midr >> 4) & 0xFFF) as u32;
+    let stepping = (((midr >> 20) & 0xF) << 4 | (midr & 0xF)) as u32;
+
+    let mut vendor = [0u8; 12];
+    if let Some((_, name)) = IMPLEMENTERS.iter().find(|(code, _)| *code == family) {
+        vendor[..name.len()].copy_from_slice(name);
+    }
+
+    Signature { vendor, family, model, stepping }
+}
+
+// Returns None if the kernel doesn't let us read MIDR_EL1. Only the CPU we're running on is read,
+// which may be a different model from the others on big.LITTLE systems.
+pub fn get_signature() -> Option<Signature> {
+    Some(decode_signature(get_midr(&get_features())?))
+}
+
+// Reads the MIDR_EL1 of CPU number cpu from sysfs (e.g. "0x00000000410fd0c0"), which has every
+// online CPU's, even if the kernel doesn't emulate the register
+fn get_cpu_midr(cpu: u32) -> Option<u64> {
+    const PREFIX: &[u8] = b"/sys/devices/system/cpu/cpu";
+    const SUFFIX: &[u8] = b"/regs/identification/midr_el1\0";
+
+    let mut path = [0u8; PREFIX.len() + 10 + SUFFIX.len()];
+    path[..PREFIX.len()].copy_from_slice(PREFIX);
+    let mut len = PREFIX.len();
+    len += crate::path::itoa(cpu, &mut path[len..]);
+    path[len..len + SUFFIX.len()].copy_from_slice(SUFFIX);
+
+    let mut buffer = [0u8; 32];
+    let read = sys::read_file(CStr::from_bytes_until_nul(&path).ok()?, &mut buffer).ok()?;
+    let digits = buffer[..read].trim_ascii().strip_prefix(b"0x")?;
+
+    u64::from_str_radix(core::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+fn match_quirks(signature: &Signature) -> Features {
+    let mut ignored = Features::NONE;
+    for quirk in QUIRKS {
+        if quirk.implementer == signature.family && quirk.part == signature.model
+            && (quirk.revisions.0..=quirk.revisions.1).contains(&signature.stepping) {
+            ignored = ignored.union(&quirk.ignored);
+        }
+    }
+    ignored
+}
+
+// Returns the features which this CPU's quirks rule out
+pub fn get_quirks() -> Features {
+    match get_signature() {
+        Some(signature) => match_quirks(&signature),
+        None => Features::NONE
+    }
+}
+
+// Returns the features which the quirks of CPU number cpu rule out, without running on it, or None
+// if its MIDR_EL1 can't be read. The kernel only reports features every CPU has, so on big.LITTLE
+// systems, CPUs only differ by their quirks.
+pub fn get_cpu_quirks(cpu: u32) -> Option<Features> {
+    Some(match_quirks(&decode_signature(get_cpu_midr(cpu)?)))
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
@@ -0,0 +1,285 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+bitflags! {
+    // AT_HWCAP, as in the kernel's arch/arm/include/uapi/asm/hwcap.h
+    pub struct ArmHwcap: u64 {
+        const SWP       = 1 << 0;
+        const HALF      = 1 << 1;
+        const THUMB     = 1 << 2;
+        const BIT26     = 1 << 3;
+        const FAST_MULT = 1 << 4;
+        const FPA       = 1 << 5;
+        const VFP       = 1 << 6;
+        const EDSP      = 1 << 7;
+        const JAVA      = 1 << 8;
+        const IWMMXT    = 1 << 9;
+        const CRUNCH    = 1 << 10;
+        const THUMBEE   = 1 << 11;
+        const NEON      = 1 << 12;
+        // Also set for VFPv3-D16, which only has 16 double-precision registers
+        const VFPV3     = 1 << 13;
+        const VFPV3D16  = 1 << 14;
+        const TLS       = 1 << 15;
+        const VFPV4     = 1 << 16;
+        // Integer division in ARM and Thumb code
+        const IDIVA     = 1 << 17;
+        const IDIVT     = 1 << 18;
+        const VFPD32    = 1 << 19;
+        const LPAE      = 1 << 20;
+        const EVTSTRM   = 1 << 21;
+        const FPHP      = 1 << 22;
+        const ASIMDHP   = 1 << 23;
+        const ASIMDDP   = 1 << 24;
+        const ASIMDFHM  = 1 << 25;
+        const ASIMDBF16 = 1 << 26;
+        const I8MM      = 1 << 27;
+    }
+
+    // AT_HWCAP2, for ARMv8 CPUs running 32-bit code
+    pub struct ArmHwcap2: u64 {
+        const AES   = 1 << 0;
+        const PMULL = 1 << 1;
+        const SHA1  = 1 << 2;
+        const SHA2  = 1 << 3;
+        const CRC32 = 1 << 4;
+        const SB    = 1 << 5;
+        const SSBS  = 1 << 6;
+    }
+}
+
+// Names of the features levels are made of, as in /proc/cpuinfo
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"swp", Features { hwcap: ArmHwcap::SWP.bits(), ..Features::NONE }),
+    (b"half", Features { hwcap: ArmHwcap::HALF.bits(), ..Features::NONE }),
+    (b"thumb", Features { hwcap: ArmHwcap::THUMB.bits(), ..Features::NONE }),
+    (b"26bit", Features { hwcap: ArmHwcap::BIT26.bits(), ..Features::NONE }),
+    (b"fastmult", Features { hwcap: ArmHwcap::FAST_MULT.bits(), ..Features::NONE }),
+    (b"fpa", Features { hwcap: ArmHwcap::FPA.bits(), ..Features::NONE }),
+    (b"vfp", Features { hwcap: ArmHwcap::VFP.bits(), ..Features::NONE }),
+    (b"edsp", Features { hwcap: ArmHwcap::EDSP.bits(), ..Features::NONE }),
+    (b"java", Features { hwcap: ArmHwcap::JAVA.bits(), ..Features::NONE }),
+    (b"iwmmxt", Features { hwcap: ArmHwcap::IWMMXT.bits(), ..Features::NONE }),
+    (b"crunch", Features { hwcap: ArmHwcap::CRUNCH.bits(), ..Features::NONE }),
+    (b"thumbee", Features { hwcap: ArmHwcap::THUMBEE.bits(), ..Features::NONE }),
+    (b"neon", Features { hwcap: ArmHwcap::NEON.bits(), ..Features::NONE }),
+    (b"vfpv3", Features { hwcap: ArmHwcap::VFPV3.bits(), ..Features::NONE }),
+    (b"vfpv3d16", Features { hwcap: ArmHwcap::VFPV3D16.bits(), ..Features::NONE }),
+    (b"tls", Features { hwcap: ArmHwcap::TLS.bits(), ..Features::NONE }),
+    (b"vfpv4", Features { hwcap: ArmHwcap::VFPV4.bits(), ..Features::NONE }),
+    (b"idiva", Features { hwcap: ArmHwcap::IDIVA.bits(), ..Features::NONE }),
+    (b"idivt", Features { hwcap: ArmHwcap::IDIVT.bits(), ..Features::NONE }),
+    (b"vfpd32", Features { hwcap: ArmHwcap::VFPD32.bits(), ..Features::NONE }),
+    (b"lpae", Features { hwcap: ArmHwcap::LPAE.bits(), ..Features::NONE }),
+    (b"evtstrm", Features { hwcap: ArmHwcap::EVTSTRM.bits(), ..Features::NONE }),
+    (b"fphp", Features { hwcap: ArmHwcap::FPHP.bits(), ..Features::NONE }),
+    (b"asimdhp", Features { hwcap: ArmHwcap::ASIMDHP.bits(), ..Features::NONE }),
+    (b"asimddp", Features { hwcap: ArmHwcap::ASIMDDP.bits(), ..Features::NONE }),
+    (b"asimdfhm", Features { hwcap: ArmHwcap::ASIMDFHM.bits(), ..Features::NONE }),
+    (b"asimdbf16", Features { hwcap: ArmHwcap::ASIMDBF16.bits(), ..Features::NONE }),
+    (b"i8mm", Features { hwcap: ArmHwcap::I8MM.bits(), ..Features::NONE }),
+    (b"aes", Features { hwcap2: ArmHwcap2::AES.bits(), ..Features::NONE }),
+    (b"pmull", Features { hwcap2: ArmHwcap2::PMULL.bits(), ..Features::NONE }),
+    (b"sha1", Features { hwcap2: ArmHwcap2::SHA1.bits(), ..Features::NONE }),
+    (b"sha2", Features { hwcap2: ArmHwcap2::SHA2.bits(), ..Features::NONE }),
+    (b"crc32", Features { hwcap2: ArmHwcap2::CRC32.bits(), ..Features::NONE }),
+    (b"sb", Features { hwcap2: ArmHwcap2::SB.bits(), ..Features::NONE }),
+    (b"ssbs", Features { hwcap2: ArmHwcap2::SSBS.bits(), ..Features::NONE }),
+];
+
+// Levels are named like the hard-float ABI's (armv7hl), and add what -mfpu options assume. The
+// baseline is ARMv7 with VFPv3-D16, which every armhf distribution requires.
+const ARMV7HL_HWCAPS: u64 = ArmHwcap::VFP.bits() | ArmHwcap::VFPV3.bits();
+// -mfpu=neon, which also assumes 32 double-precision registers
+const NEON_HWCAPS: u64 = ARMV7HL_HWCAPS | ArmHwcap::NEON.bits() | ArmHwcap::VFPD32.bits();
+// -mfpu=neon-vfpv4, with fused multiply-add. Integer division is required too, since it comes with
+// every such CPU (such as the Cortex-A7 and A15) but the Cortex-A5.
+const NEON_VFPV4_HWCAPS: u64 = NEON_HWCAPS | ArmHwcap::VFPV4.bits() | ArmHwcap::IDIVA.bits();
+
+// Auxiliary vector entries which hold the features we're interested in
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub hwcap: u64,
+    pub hwcap2: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        hwcap: 0,
+        hwcap2: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap | other.hwcap,
+            hwcap2: self.hwcap2 | other.hwcap2,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap & !other.hwcap,
+            hwcap2: self.hwcap2 & !other.hwcap2,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.hwcap & other.hwcap == other.hwcap
+        && self.hwcap2 & other.hwcap2 == other.hwcap2
+    }
+}
+
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv7hl",
+        directory: b"armv7hl",
+        features: Features { hwcap: ARMV7HL_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv7hl-neon",
+        directory: b"armv7hl-neon",
+        features: Features { hwcap: NEON_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"armv7hl-neon-vfpv4",
+        directory: b"armv7hl-neon-vfpv4",
+        features: Features { hwcap: NEON_VFPV4_HWCAPS, ..Features::NONE },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+pub const ARCH_NAME: &[u8] = b"armhf";
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Enough for the lines of the first few processors
+const CPUINFO_MAX: usize = 4096;
+
+// Features come from the kernel, which knows which ones it has enabled (e.g. NEON, which it may
+// not save the registers of). If the auxiliary vector can't be found, only the baseline level is
+// supported.
+#[inline]
+pub fn get_features() -> Features {
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0) as u64;
+    let hwcap2 = sys::auxval(sys::AT_HWCAP2 as _).unwrap_or(0) as u64;
+
+    Features { hwcap, hwcap2 }
+}
+
+// Passes the auxiliary vector entries detection reads to report, as the kernel returns them
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    let features = get_features();
+    report(&Raw { source: b"auxv", fields: &[
+        (b"hwcap", features.hwcap),
+        (b"hwcap2", features.hwcap2),
+    ]});
+}
+
+// Hypervisors can't be told apart from bare metal without help from the firmware, which
+// userspace can't ask, so we always assume bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    None
+}
+
+// Implementer codes (MIDR's top byte) of well known vendors
+const IMPLEMENTERS: [(u32, &[u8]); 5] = [
+    (0x41, b"ARM"),
+    (0x42, b"Broadcom"),
+    (0x51, b"Qualcomm"),
+    (0x56, b"Marvell"),
+    (0x69, b"Intel"),
+];
+
+// CPUs whose implementation of some features is broken or too slow to be worth using
+struct Quirk {
+    implementer: u32,
+    part: u32,
+    // Inclusive, as (variant << 4) | revision
+    revisions: (u32, u32),
+    // Features which are ignored on matching CPUs
+    ignored: Features,
+}
+
+// Adding a CPU only takes an entry here
+const QUIRKS: &[Quirk] = &[];
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // Implementer's name, padded with NULs (e.g. "ARM"), or NULs if it isn't a well known one
+    pub vendor: [u8; 12],
+    // Implementer code (e.g. 0x41)
+    pub family: u32,
+    // Part number (e.g. 0xc07, Cortex-A7)
+    pub model: u32,
+    // Variant and revision, as (variant << 4) | revision
+    pub stepping: u32,
+}
+
+// Returns the value of the first line of /proc/cpuinfo called name (e.g. "CPU part\t: 0xc07"),
+// which is hexadecimal if it starts with 0x
+fn cpuinfo_field(cpuinfo: &[u8], name: &[u8]) -> Option<u32> {
+    let line = cpuinfo.split(|c| *c == b'\n').find(|l| l.starts_with(name))?;
+    let (_, value) = line.split_at(line.iter().position(|c| *c == b':')? + 1);
+    let value = value.trim_ascii();
+
+    match value.strip_prefix(b"0x") {
+        Some(hex) => u32::from_str_radix(core::str::from_utf8(hex).ok()?, 16).ok(),
+        None => core::str::from_utf8(value).ok()?.parse().ok()
+    }
+}
+
+// MIDR is privileged, and the kernel only emulates reading it for 64-bit code, so it comes from
+// /proc/cpuinfo's first processor instead, which may be a different model from the others on
+// big.LITTLE systems. Returns None if procfs is unavailable.
+pub fn get_signature() -> Option<Signature> {
+    let mut buffer = [0u8; CPUINFO_MAX];
+    let len = sys::read_file(c"/proc/cpuinfo", &mut buffer).ok()?;
+    let cpuinfo = &buffer[..len];
+
+    let family = cpuinfo_field(cpuinfo, b"CPU implementer")?;
+    let model = cpuinfo_field(cpuinfo, b"CPU part")?;
+    let stepping = cpuinfo_field(cpuinfo, b"CPU variant")? << 4 | cpuinfo_field(cpuinfo, b"CPU revision")?;
+
+    let mut vendor = [0u8; 12];
+    if let Some((_, name)) = IMPLEMENTERS.iter().find(|(code, _)| *code == family) {
+        vendor[..name.len()].copy_from_slice(name);
+    }
+
+    Some(Signature { vendor, family, model, stepping })
+}
+
+// Returns the features which this CPU's quirks rule out
+pub fn get_quirks() -> Features {
+    let signature = match get_signature() {
+        Some(s) => s,
+        None => return Features::NONE
+    };
+
+    let mut ignored = Features::NONE;
+    for quirk in QUIRKS {
+        if quirk.implementer == signature.family && quirk.part == signature.model
+            && (quirk.revisions.0..=quirk.revisions.1).contains(&signature.stepping) {
+            ignored = ignored.union(&quirk.ignored);
+        }
+    }
+    ignored
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
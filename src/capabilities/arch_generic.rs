@@ -0,0 +1,142 @@
+#![allow(dead_code)]
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+/*
+   Detection for architectures without a backend of their own.
+   Features are the words of /proc/cpuinfo's feature lines, so supporting levels on a new
+   architecture only takes naming them here, without reading any registers. Until then, every
+   machine gets the baseline level, named after the architecture (e.g. "sparc64").
+*/
+
+// Names of the features levels are made of, as in /proc/cpuinfo. Each one is a bit of Features.
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[];
+
+// Lines of /proc/cpuinfo which list features, as the architectures which have one call it
+const FEATURE_KEYS: &[&[u8]] = &[b"flags", b"Features", b"features", b"isa", b"ASEs implemented"];
+
+// Bits of the names in FEATURE_NAMES which /proc/cpuinfo listed
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub flags: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        flags: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            flags: self.flags | other.flags,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            flags: self.flags & !other.flags,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.flags & other.flags == other.flags
+    }
+}
+
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: ARCH_NAME,
+        directory: ARCH_NAME,
+        features: Features::NONE,
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+pub const ARCH_NAME: &[u8] = env!("HWCAPS_TARGET_ARCH").as_bytes();
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Enough for the lines of the first processor
+const CPUINFO_MAX: usize = 4096;
+
+// Reads the feature lines of /proc/cpuinfo's first block, which is either the first processor's
+// or, on architectures which only list features once, the lines before every processor's.
+// Returns None if procfs is unavailable.
+fn get_cpuinfo_features() -> Option<Features> {
+    let mut buffer = [0u8; CPUINFO_MAX];
+    let len = sys::read_file(c"/proc/cpuinfo", &mut buffer).ok()?;
+
+    let mut features = Features::NONE;
+    for line in buffer[..len].split(|c| *c == b'\n') {
+        // Blocks are separated by empty lines
+        if line.trim_ascii().is_empty() {
+            break
+        }
+
+        let (key, value) = match line.iter().position(|c| *c == b':') {
+            Some(i) => (line[..i].trim_ascii(), &line[i + 1..]),
+            None => continue
+        };
+        if !FEATURE_KEYS.contains(&key) {
+            continue
+        }
+
+        for word in value.split(|c| c.is_ascii_whitespace()).filter(|w| !w.is_empty()) {
+            if let Some((_, feature)) = FEATURE_NAMES.iter().find(|(name, _)| *name == word) {
+                features = features.union(feature);
+            }
+        }
+    }
+    Some(features)
+}
+
+// Without procfs, only the baseline level is supported
+#[inline]
+pub fn get_features() -> Features {
+    get_cpuinfo_features().unwrap_or(Features::NONE)
+}
+
+// Passes the features detection found to report, since /proc/cpuinfo's words aren't numbers
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    report(&Raw { source: b"cpuinfo", fields: &[(b"flags", get_features().flags)] });
+}
+
+// Hypervisors can't be told apart from bare metal without knowing the architecture, so we always
+// assume bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    None
+}
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // Always NULs
+    pub vendor: [u8; 12],
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+// /proc/cpuinfo's model lines are different on every architecture, so the CPU model is always
+// unknown
+pub fn get_signature() -> Option<Signature> {
+    None
+}
+
+// Without a signature, no CPU can be matched
+pub fn get_quirks() -> Features {
+    Features::NONE
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
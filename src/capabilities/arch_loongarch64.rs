@@ -0,0 +1,247 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+use core::arch::asm;
+
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+bitflags! {
+    // AT_HWCAP, as in the kernel's arch/loongarch/include/uapi/asm/hwcap.h
+    pub struct LoongHwcap: u64 {
+        const CPUCFG   = 1 << 0;
+        const LAM      = 1 << 1;
+        const UAL      = 1 << 2;
+        const FPU      = 1 << 3;
+        // 128-bit and 256-bit vectors, which the kernel only reports if it saves their registers
+        const LSX      = 1 << 4;
+        const LASX     = 1 << 5;
+        const CRC32    = 1 << 6;
+        const COMPLEX  = 1 << 7;
+        const CRYPTO   = 1 << 8;
+        const LVZ      = 1 << 9;
+        const LBT_X86  = 1 << 10;
+        const LBT_ARM  = 1 << 11;
+        const LBT_MIPS = 1 << 12;
+        const PTW      = 1 << 13;
+        const LSPW     = 1 << 14;
+    }
+
+    // CPUCFG word 2, for LoongArch 1.1's instructions, which AT_HWCAP doesn't report
+    pub struct LoongCpucfg2: u64 {
+        const FRECIPE  = 1 << 25;
+        const DIV32    = 1 << 26;
+        const LAM_BH   = 1 << 27;
+        const LAMCAS   = 1 << 28;
+        const SCQ      = 1 << 30;
+    }
+}
+
+// CPUCFG words
+const CPUCFG_PRID: u32 = 0;
+const CPUCFG_FEATURES: u32 = 2;
+// Where KVM puts its signature ("KVM\0"). Nothing else uses it, so it's 0 on bare metal.
+const CPUCFG_KVM_SIGNATURE: u32 = 0x40000000;
+const KVM_SIGNATURE: &[u8; 4] = b"KVM\0";
+
+// Names of the features levels are made of, as in /proc/cpuinfo
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"cpucfg", Features { hwcap: LoongHwcap::CPUCFG.bits(), ..Features::NONE }),
+    (b"lam", Features { hwcap: LoongHwcap::LAM.bits(), ..Features::NONE }),
+    (b"ual", Features { hwcap: LoongHwcap::UAL.bits(), ..Features::NONE }),
+    (b"fpu", Features { hwcap: LoongHwcap::FPU.bits(), ..Features::NONE }),
+    (b"lsx", Features { hwcap: LoongHwcap::LSX.bits(), ..Features::NONE }),
+    (b"lasx", Features { hwcap: LoongHwcap::LASX.bits(), ..Features::NONE }),
+    (b"crc32", Features { hwcap: LoongHwcap::CRC32.bits(), ..Features::NONE }),
+    (b"complex", Features { hwcap: LoongHwcap::COMPLEX.bits(), ..Features::NONE }),
+    (b"crypto", Features { hwcap: LoongHwcap::CRYPTO.bits(), ..Features::NONE }),
+    (b"lvz", Features { hwcap: LoongHwcap::LVZ.bits(), ..Features::NONE }),
+    (b"lbt_x86", Features { hwcap: LoongHwcap::LBT_X86.bits(), ..Features::NONE }),
+    (b"lbt_arm", Features { hwcap: LoongHwcap::LBT_ARM.bits(), ..Features::NONE }),
+    (b"lbt_mips", Features { hwcap: LoongHwcap::LBT_MIPS.bits(), ..Features::NONE }),
+    (b"ptw", Features { hwcap: LoongHwcap::PTW.bits(), ..Features::NONE }),
+    (b"lspw", Features { hwcap: LoongHwcap::LSPW.bits(), ..Features::NONE }),
+    (b"frecipe", Features { cpucfg2: LoongCpucfg2::FRECIPE.bits(), ..Features::NONE }),
+    (b"div32", Features { cpucfg2: LoongCpucfg2::DIV32.bits(), ..Features::NONE }),
+    (b"lam_bh", Features { cpucfg2: LoongCpucfg2::LAM_BH.bits(), ..Features::NONE }),
+    (b"lamcas", Features { cpucfg2: LoongCpucfg2::LAMCAS.bits(), ..Features::NONE }),
+    (b"scq", Features { cpucfg2: LoongCpucfg2::SCQ.bits(), ..Features::NONE }),
+];
+
+// Levels are the vector units (e.g. "loongarch64-lasx" for a build with -mlasx). LASX CPUs
+// always have LSX, which LASX's registers extend.
+const BASE_HWCAPS: u64 = LoongHwcap::CPUCFG.bits() | LoongHwcap::FPU.bits();
+const LSX_HWCAPS: u64 = BASE_HWCAPS | LoongHwcap::LSX.bits();
+const LASX_HWCAPS: u64 = LSX_HWCAPS | LoongHwcap::LASX.bits();
+
+// AT_HWCAP, and the CPUCFG word with the features it doesn't report
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub hwcap: u64,
+    pub cpucfg2: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        hwcap: 0,
+        cpucfg2: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap | other.hwcap,
+            cpucfg2: self.cpucfg2 | other.cpucfg2,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap & !other.hwcap,
+            cpucfg2: self.cpucfg2 & !other.cpucfg2,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.hwcap & other.hwcap == other.hwcap
+        && self.cpucfg2 & other.cpucfg2 == other.cpucfg2
+    }
+}
+
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"loongarch64",
+        directory: b"loongarch64",
+        features: Features { hwcap: BASE_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"loongarch64-lsx",
+        directory: b"loongarch64-lsx",
+        features: Features { hwcap: LSX_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"loongarch64-lasx",
+        directory: b"loongarch64-lasx",
+        features: Features { hwcap: LASX_HWCAPS, ..Features::NONE },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+pub const ARCH_NAME: &[u8] = b"loongarch64";
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Reads a CPUCFG word, which userspace can always do
+#[inline]
+fn cpucfg(word: u32) -> u64 {
+    let value: u64;
+    unsafe { asm!("cpucfg {}, {}", out(reg) value, in(reg) word as u64, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+// CPUCFG tells what the CPU has, but LSX and LASX also need the kernel to save their registers,
+// which only AT_HWCAP tells. If the auxiliary vector can't be found, only the baseline level is
+// supported.
+#[inline]
+pub fn get_features() -> Features {
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+
+    Features { hwcap, cpucfg2: cpucfg(CPUCFG_FEATURES) }
+}
+
+// Passes the auxiliary vector entry and the CPUCFG words detection reads to report, as they are
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+    report(&Raw { source: b"auxv", fields: &[(b"hwcap", hwcap)] });
+
+    for word in [CPUCFG_PRID, CPUCFG_FEATURES, CPUCFG_KVM_SIGNATURE] {
+        report(&Raw { source: b"cpucfg", fields: &[(b"word", word as u64), (b"value", cpucfg(word))] });
+    }
+}
+
+// Only KVM has a signature. Returns "unknown" for other hypervisors which put something there,
+// or None on bare metal.
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    match cpucfg(CPUCFG_KVM_SIGNATURE) as u32 {
+        0 => None,
+        s if s.to_le_bytes() == *KVM_SIGNATURE => Some(b"kvm"),
+        _ => Some(b"unknown")
+    }
+}
+
+// Company IDs (PRID's third byte) of well known vendors
+const VENDORS: [(u32, &[u8]); 1] = [
+    (0x14, b"Loongson"),
+];
+
+// CPUs whose implementation of some features is broken or too slow to be worth using
+struct Quirk {
+    company: u32,
+    processor: u32,
+    // Inclusive
+    revisions: (u32, u32),
+    // Features which are ignored on matching CPUs
+    ignored: Features,
+}
+
+// Adding a CPU only takes an entry here
+const QUIRKS: &[Quirk] = &[];
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // Company's name, padded with NULs (e.g. "Loongson"), or NULs if it isn't a well known one
+    pub vendor: [u8; 12],
+    // Company ID (e.g. 0x14)
+    pub family: u32,
+    // Processor ID (e.g. 0xc0, LA464)
+    pub model: u32,
+    // Revision
+    pub stepping: u32,
+}
+
+// Only the CPU we're running on is read
+pub fn get_signature() -> Option<Signature> {
+    let prid = cpucfg(CPUCFG_PRID);
+
+    let family = ((prid >> 16) & 0xFF) as u32;
+    let model = ((prid >> 8) & 0xFF) as u32;
+    let stepping = (prid & 0xFF) as u32;
+
+    let mut vendor = [0u8; 12];
+    if let Some((_, name)) = VENDORS.iter().find(|(code, _)| *code == family) {
+        vendor[..name.len()].copy_from_slice(name);
+    }
+
+    Some(Signature { vendor, family, model, stepping })
+}
+
+// Returns the features which this CPU's quirks rule out
+pub fn get_quirks() -> Features {
+    let signature = match get_signature() {
+        Some(s) => s,
+        None => return Features::NONE
+    };
+
+    let mut ignored = Features::NONE;
+    for quirk in QUIRKS {
+        if quirk.company == signature.family && quirk.processor == signature.model
+            && (quirk.revisions.0..=quirk.revisions.1).contains(&signature.stepping) {
+            ignored = ignored.union(&quirk.ignored);
+        }
+    }
+    ignored
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
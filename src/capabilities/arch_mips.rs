@@ -0,0 +1,233 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+bitflags! {
+    // AT_HWCAP, as in the kernel's arch/mips/include/uapi/asm/hwcap.h
+    pub struct MipsHwcap: u64 {
+        // Release 6, which isn't compatible with the releases before it
+        const R6              = 1 << 0;
+        const MSA             = 1 << 1;
+        const CRC32           = 1 << 2;
+        const MIPS16          = 1 << 3;
+        const MDMX            = 1 << 4;
+        const MIPS3D          = 1 << 5;
+        const SMARTMIPS       = 1 << 6;
+        const DSP             = 1 << 7;
+        const DSP2            = 1 << 8;
+        const DSP3            = 1 << 9;
+        const MIPS16E2        = 1 << 10;
+        const LOONGSON_MMI    = 1 << 11;
+        const LOONGSON_EXT    = 1 << 12;
+        const LOONGSON_EXT2   = 1 << 13;
+        const LOONGSON_CPUCFG = 1 << 14;
+    }
+}
+
+// Names of the features levels are made of, as in /proc/cpuinfo's "ASEs implemented" line, and
+// r6 for Release 6
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"r6", Features { hwcap: MipsHwcap::R6.bits() }),
+    (b"msa", Features { hwcap: MipsHwcap::MSA.bits() }),
+    (b"crc32", Features { hwcap: MipsHwcap::CRC32.bits() }),
+    (b"mips16", Features { hwcap: MipsHwcap::MIPS16.bits() }),
+    (b"mdmx", Features { hwcap: MipsHwcap::MDMX.bits() }),
+    (b"mips3d", Features { hwcap: MipsHwcap::MIPS3D.bits() }),
+    (b"smartmips", Features { hwcap: MipsHwcap::SMARTMIPS.bits() }),
+    (b"dsp", Features { hwcap: MipsHwcap::DSP.bits() }),
+    (b"dsp2", Features { hwcap: MipsHwcap::DSP2.bits() }),
+    (b"dsp3", Features { hwcap: MipsHwcap::DSP3.bits() }),
+    (b"mips16e2", Features { hwcap: MipsHwcap::MIPS16E2.bits() }),
+    (b"loongson-mmi", Features { hwcap: MipsHwcap::LOONGSON_MMI.bits() }),
+    (b"loongson-ext", Features { hwcap: MipsHwcap::LOONGSON_EXT.bits() }),
+    (b"loongson-ext2", Features { hwcap: MipsHwcap::LOONGSON_EXT2.bits() }),
+    (b"loongson-cpucfg", Features { hwcap: MipsHwcap::LOONGSON_CPUCFG.bits() }),
+];
+
+// Levels are ISA releases (e.g. "mips64r6" for a build with -march=mips64r6), with MSA (-mmsa)
+// in between. Release 2 is the baseline every mips and mips64 distribution requires. Release 6
+// binaries don't run on older CPUs, and R6 CPUs only run older binaries (including this loader)
+// through the kernel's emulation, so they're best served by their own level. Every R6 CPU Linux
+// runs on has MSA, which the level requires too, so levels stay cumulative.
+const MSA_HWCAPS: u64 = MipsHwcap::MSA.bits();
+const R6_HWCAPS: u64 = MSA_HWCAPS | MipsHwcap::R6.bits();
+
+// AT_HWCAP, which holds every feature we're interested in
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub hwcap: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        hwcap: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap | other.hwcap,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap & !other.hwcap,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.hwcap & other.hwcap == other.hwcap
+    }
+}
+
+#[cfg(target_arch = "mips")]
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"mips32r2",
+        directory: b"mips32r2",
+        features: Features::NONE,
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"mips32r2-msa",
+        directory: b"mips32r2-msa",
+        features: Features { hwcap: MSA_HWCAPS },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"mips32r6",
+        directory: b"mips32r6",
+        features: Features { hwcap: R6_HWCAPS },
+    },
+];
+
+#[cfg(target_arch = "mips64")]
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"mips64r2",
+        directory: b"mips64r2",
+        features: Features::NONE,
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"mips64r2-msa",
+        directory: b"mips64r2-msa",
+        features: Features { hwcap: MSA_HWCAPS },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"mips64r6",
+        directory: b"mips64r6",
+        features: Features { hwcap: R6_HWCAPS },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+#[cfg(target_arch = "mips")]
+pub const ARCH_NAME: &[u8] = b"mips";
+#[cfg(target_arch = "mips64")]
+pub const ARCH_NAME: &[u8] = b"mips64";
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Enough for the lines of the first processor
+const CPUINFO_MAX: usize = 4096;
+
+// Reads the ISA and ASEs of the first processor in /proc/cpuinfo (e.g. "isa : mips1 mips2 mips32r1
+// mips32r2" and "ASEs implemented : mips16 dsp msa"), for kernels which don't report them in
+// AT_HWCAP. Returns None if procfs is unavailable.
+fn get_cpuinfo_features() -> Option<Features> {
+    let mut buffer = [0u8; CPUINFO_MAX];
+    let len = sys::read_file(c"/proc/cpuinfo", &mut buffer).ok()?;
+
+    let mut features = Features::NONE;
+    for line in buffer[..len].split(|c| *c == b'\n') {
+        let (key, value) = match line.iter().position(|c| *c == b':') {
+            Some(i) => (line[..i].trim_ascii(), &line[i + 1..]),
+            None => continue
+        };
+
+        for word in value.split(|c| c.is_ascii_whitespace()).filter(|w| !w.is_empty()) {
+            match key {
+                b"isa" if word == b"mips32r6" || word == b"mips64r6" => features.hwcap |= MipsHwcap::R6.bits(),
+                b"ASEs implemented" => if let Some((_, ase)) = FEATURE_NAMES.iter().find(|(name, _)| *name == word) {
+                    features = features.union(ase);
+                },
+                _ => {}
+            }
+        }
+
+        // The second processor's lines would only repeat the first's
+        if key == b"ASEs implemented" {
+            break
+        }
+    }
+    Some(features)
+}
+
+// Features come from the kernel, which knows which ones it has enabled (e.g. MSA, which it may not
+// save the registers of). Older kernels leave AT_HWCAP empty, so if it is, they're read from
+// /proc/cpuinfo instead. Without either, only the baseline level is supported.
+#[inline]
+pub fn get_features() -> Features {
+    #[allow(clippy::unnecessary_cast)] // 32-bit on mips
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0) as u64;
+    if hwcap != 0 {
+        return Features { hwcap }
+    }
+
+    get_cpuinfo_features().unwrap_or(Features::NONE)
+}
+
+// Passes the auxiliary vector entry detection reads to report, as the kernel returns it
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    #[allow(clippy::unnecessary_cast)] // 32-bit on mips
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0) as u64;
+    report(&Raw { source: b"auxv", fields: &[(b"hwcap", hwcap)] });
+}
+
+// Hypervisors can't be told apart from bare metal without help from the firmware, which
+// userspace can't ask, so we always assume bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    None
+}
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // Company's name, padded with NULs
+    pub vendor: [u8; 12],
+    // Company ID, PRId's third byte (e.g. 0x01, MIPS)
+    pub family: u32,
+    // Processor ID, PRId's second byte
+    pub model: u32,
+    // Revision, PRId's lowest byte
+    pub stepping: u32,
+}
+
+// PRId is privileged, and neither the kernel nor /proc/cpuinfo pass it on, so the CPU model is
+// always unknown
+pub fn get_signature() -> Option<Signature> {
+    None
+}
+
+// Without a signature, no CPU can be matched
+pub fn get_quirks() -> Features {
+    Features::NONE
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
@@ -0,0 +1,239 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+use core::arch::asm;
+
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+bitflags! {
+    // AT_HWCAP, as in the kernel's arch/powerpc/include/uapi/asm/cputable.h
+    pub struct PowerHwcap: u64 {
+        const PPC64     = 0x40000000;
+        const ALTIVEC   = 0x10000000;
+        const FPU       = 0x08000000;
+        const MMU       = 0x04000000;
+        const SMT       = 0x00004000;
+        const ARCH_2_05 = 0x00001000;
+        const DFP       = 0x00000400;
+        const ARCH_2_06 = 0x00000100;
+        const VSX       = 0x00000080;
+        const TRUE_LE   = 0x00000002;
+    }
+
+    // AT_HWCAP2
+    pub struct PowerHwcap2: u64 {
+        const ARCH_2_07 = 0x80000000;
+        const HTM       = 0x40000000;
+        const DSCR      = 0x20000000;
+        const EBB       = 0x10000000;
+        const ISEL      = 0x08000000;
+        const TAR       = 0x04000000;
+        const VCRYPTO   = 0x02000000;
+        const ARCH_3_00 = 0x00800000;
+        const IEEE128   = 0x00400000;
+        const DARN      = 0x00200000;
+        const SCV       = 0x00100000;
+        const ARCH_3_1  = 0x00040000;
+        const MMA       = 0x00020000;
+    }
+}
+
+// Names of the features levels are made of, as LD_SHOW_AUXV=1 prints them
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"ppc64", Features { hwcap: PowerHwcap::PPC64.bits(), ..Features::NONE }),
+    (b"altivec", Features { hwcap: PowerHwcap::ALTIVEC.bits(), ..Features::NONE }),
+    (b"fpu", Features { hwcap: PowerHwcap::FPU.bits(), ..Features::NONE }),
+    (b"mmu", Features { hwcap: PowerHwcap::MMU.bits(), ..Features::NONE }),
+    (b"smt", Features { hwcap: PowerHwcap::SMT.bits(), ..Features::NONE }),
+    (b"arch_2_05", Features { hwcap: PowerHwcap::ARCH_2_05.bits(), ..Features::NONE }),
+    (b"dfp", Features { hwcap: PowerHwcap::DFP.bits(), ..Features::NONE }),
+    (b"arch_2_06", Features { hwcap: PowerHwcap::ARCH_2_06.bits(), ..Features::NONE }),
+    (b"vsx", Features { hwcap: PowerHwcap::VSX.bits(), ..Features::NONE }),
+    (b"true_le", Features { hwcap: PowerHwcap::TRUE_LE.bits(), ..Features::NONE }),
+    (b"arch_2_07", Features { hwcap2: PowerHwcap2::ARCH_2_07.bits(), ..Features::NONE }),
+    (b"htm", Features { hwcap2: PowerHwcap2::HTM.bits(), ..Features::NONE }),
+    (b"dscr", Features { hwcap2: PowerHwcap2::DSCR.bits(), ..Features::NONE }),
+    (b"ebb", Features { hwcap2: PowerHwcap2::EBB.bits(), ..Features::NONE }),
+    (b"isel", Features { hwcap2: PowerHwcap2::ISEL.bits(), ..Features::NONE }),
+    (b"tar", Features { hwcap2: PowerHwcap2::TAR.bits(), ..Features::NONE }),
+    (b"vcrypto", Features { hwcap2: PowerHwcap2::VCRYPTO.bits(), ..Features::NONE }),
+    (b"arch_3_00", Features { hwcap2: PowerHwcap2::ARCH_3_00.bits(), ..Features::NONE }),
+    (b"ieee128", Features { hwcap2: PowerHwcap2::IEEE128.bits(), ..Features::NONE }),
+    (b"darn", Features { hwcap2: PowerHwcap2::DARN.bits(), ..Features::NONE }),
+    (b"scv", Features { hwcap2: PowerHwcap2::SCV.bits(), ..Features::NONE }),
+    (b"arch_3_1", Features { hwcap2: PowerHwcap2::ARCH_3_1.bits(), ..Features::NONE }),
+    (b"mma", Features { hwcap2: PowerHwcap2::MMA.bits(), ..Features::NONE }),
+];
+
+// Levels are processor generations, as in glibc-hwcaps (e.g. "power9" for a build with
+// -mcpu=power9). POWER8 (ISA 2.07) is the baseline of the little-endian ABI.
+const POWER8_HWCAPS: u64 = PowerHwcap::ALTIVEC.bits() | PowerHwcap::VSX.bits() | PowerHwcap::ARCH_2_06.bits();
+const POWER8_HWCAPS2: u64 = PowerHwcap2::ARCH_2_07.bits();
+// ISA 3.0, with quad-precision floating point, which glibc requires as well
+const POWER9_HWCAPS2: u64 = POWER8_HWCAPS2 | PowerHwcap2::ARCH_3_00.bits() | PowerHwcap2::IEEE128.bits();
+// ISA 3.1, with the matrix-multiply assist, which the kernel hides when it can't be used. MMA
+// variants require it instead, so ISA 3.1 is enough without it.
+#[cfg(not(feature = "mma_variants"))]
+const POWER10_HWCAPS2: u64 = POWER9_HWCAPS2 | PowerHwcap2::ARCH_3_1.bits() | PowerHwcap2::MMA.bits();
+#[cfg(feature = "mma_variants")]
+const POWER10_HWCAPS2: u64 = POWER9_HWCAPS2 | PowerHwcap2::ARCH_3_1.bits();
+
+// Auxiliary vector entries which hold the features we're interested in
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub hwcap: u64,
+    pub hwcap2: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        hwcap: 0,
+        hwcap2: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap | other.hwcap,
+            hwcap2: self.hwcap2 | other.hwcap2,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap & !other.hwcap,
+            hwcap2: self.hwcap2 & !other.hwcap2,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.hwcap & other.hwcap == other.hwcap
+        && self.hwcap2 & other.hwcap2 == other.hwcap2
+    }
+}
+
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"power8",
+        directory: b"power8",
+        features: Features { hwcap: POWER8_HWCAPS, hwcap2: POWER8_HWCAPS2 },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"power9",
+        directory: b"power9",
+        features: Features { hwcap: POWER8_HWCAPS, hwcap2: POWER9_HWCAPS2 },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"power10",
+        directory: b"power10",
+        features: Features { hwcap: POWER8_HWCAPS, hwcap2: POWER10_HWCAPS2 },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+pub const ARCH_NAME: &[u8] = b"ppc64le";
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Features come from the kernel, which knows which ones it has enabled (e.g. MMA, or
+// compatibility modes which hide a newer processor's features from guests). If the auxiliary
+// vector can't be found, only the baseline level is supported.
+#[inline]
+pub fn get_features() -> Features {
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+    let hwcap2 = sys::auxval(sys::AT_HWCAP2 as _).unwrap_or(0);
+
+    Features { hwcap, hwcap2 }
+}
+
+// Reads the Processor Version Register. It's privileged, but the kernel emulates reading it.
+fn get_pvr() -> u64 {
+    let pvr: u64;
+    unsafe { asm!("mfpvr {}", out(reg) pvr, options(nomem, nostack, preserves_flags)) };
+    pvr
+}
+
+// Passes the auxiliary vector entries detection reads (and the PVR) to report, as the kernel
+// returns them
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    let features = get_features();
+    report(&Raw { source: b"auxv", fields: &[
+        (b"hwcap", features.hwcap),
+        (b"hwcap2", features.hwcap2),
+    ]});
+
+    report(&Raw { source: b"pvr", fields: &[(b"value", get_pvr())] });
+}
+
+// Hypervisors can't be told apart from bare metal without help from the firmware, which
+// userspace can't ask, so we always assume bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    None
+}
+
+// CPUs whose implementation of some features is broken or too slow to be worth using
+struct Quirk {
+    // PVR's upper half (e.g. 0x004e, POWER9)
+    version: u32,
+    // Inclusive, as PVR's lower half
+    revisions: (u32, u32),
+    // Features which are ignored on matching CPUs
+    ignored: Features,
+}
+
+// Adding a CPU only takes an entry here
+const QUIRKS: &[Quirk] = &[];
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // "IBM", padded with NULs
+    pub vendor: [u8; 12],
+    // Processor version, PVR's upper half (e.g. 0x0080, POWER10)
+    pub family: u32,
+    // Always 0, since versions aren't split any further
+    pub model: u32,
+    // Revision, PVR's lower half
+    pub stepping: u32,
+}
+
+// Only the CPU we're running on is read. Guests see the host's PVR, even in compatibility modes.
+pub fn get_signature() -> Option<Signature> {
+    let pvr = get_pvr();
+
+    let mut vendor = [0u8; 12];
+    vendor[..3].copy_from_slice(b"IBM");
+
+    Some(Signature { vendor, family: (pvr >> 16) as u32 & 0xFFFF, model: 0, stepping: pvr as u32 & 0xFFFF })
+}
+
+// Returns the features which this CPU's quirks rule out
+pub fn get_quirks() -> Features {
+    let signature = match get_signature() {
+        Some(s) => s,
+        None => return Features::NONE
+    };
+
+    let mut ignored = Features::NONE;
+    for quirk in QUIRKS {
+        if quirk.version == signature.family
+            && (quirk.revisions.0..=quirk.revisions.1).contains(&signature.stepping) {
+            ignored = ignored.union(&quirk.ignored);
+        }
+    }
+    ignored
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
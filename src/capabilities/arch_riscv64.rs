@@ -0,0 +1,426 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+use core::arch::asm;
+
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+// Single-letter extensions are bits of their own, counted from 'a' (as in AT_HWCAP)
+const fn letter(c: u8) -> u64 {
+    1 << (c - b'a')
+}
+
+bitflags! {
+    // RISCV_HWPROBE_KEY_IMA_EXT_0, as in the kernel's arch/riscv/include/uapi/asm/hwprobe.h
+    pub struct RiscvExt0: u64 {
+        // Single-letter extensions, which are decoded into their letters' bits
+        const IMA_FD      = 1 << 0;
+        const IMA_C       = 1 << 1;
+        const IMA_V       = 1 << 2;
+        // Bit manipulation (together, the B extension)
+        const ZBA         = 1 << 3;
+        const ZBB         = 1 << 4;
+        const ZBS         = 1 << 5;
+        const ZICBOZ      = 1 << 6;
+        const ZBC         = 1 << 7;
+        // Scalar cryptography
+        const ZBKB        = 1 << 8;
+        const ZBKC        = 1 << 9;
+        const ZBKX        = 1 << 10;
+        const ZKND        = 1 << 11;
+        const ZKNE        = 1 << 12;
+        const ZKNH        = 1 << 13;
+        const ZKSED       = 1 << 14;
+        const ZKSH        = 1 << 15;
+        const ZKT         = 1 << 16;
+        // Vector cryptography
+        const ZVBB        = 1 << 17;
+        const ZVBC        = 1 << 18;
+        const ZVKB        = 1 << 19;
+        const ZVKG        = 1 << 20;
+        const ZVKNED      = 1 << 21;
+        const ZVKNHA      = 1 << 22;
+        const ZVKNHB      = 1 << 23;
+        const ZVKSED      = 1 << 24;
+        const ZVKSH       = 1 << 25;
+        const ZVKT        = 1 << 26;
+        const ZFH         = 1 << 27;
+        const ZFHMIN      = 1 << 28;
+        const ZIHINTNTL   = 1 << 29;
+        const ZVFH        = 1 << 30;
+        const ZVFHMIN     = 1 << 31;
+        const ZFA         = 1 << 32;
+        const ZTSO        = 1 << 33;
+        const ZACAS       = 1 << 34;
+        const ZICOND      = 1 << 35;
+        const ZIHINTPAUSE = 1 << 36;
+        // Embedded vector subsets
+        const ZVE32X      = 1 << 37;
+        const ZVE32F      = 1 << 38;
+        const ZVE64X      = 1 << 39;
+        const ZVE64F      = 1 << 40;
+        const ZVE64D      = 1 << 41;
+        const ZIMOP       = 1 << 42;
+        // Compressed instructions, split up (C is Zca, plus Zcd with D)
+        const ZCA         = 1 << 43;
+        const ZCB         = 1 << 44;
+        const ZCD         = 1 << 45;
+        const ZCF         = 1 << 46;
+        const ZCMOP       = 1 << 47;
+        const ZAWRS       = 1 << 48;
+        const SUPM        = 1 << 49;
+        const ZICNTR      = 1 << 50;
+        const ZIHPM       = 1 << 51;
+        const ZFBFMIN     = 1 << 52;
+        const ZVFBFMIN    = 1 << 53;
+        const ZVFBFWMA    = 1 << 54;
+        const ZICBOM      = 1 << 55;
+        const ZAAMO       = 1 << 56;
+        const ZALRSC      = 1 << 57;
+        const ZABHA       = 1 << 58;
+    }
+}
+
+// riscv_hwprobe() keys
+const HWPROBE_KEY_MVENDORID: i64 = 0;
+const HWPROBE_KEY_MARCHID: i64 = 1;
+const HWPROBE_KEY_MIMPID: i64 = 2;
+const HWPROBE_KEY_BASE_BEHAVIOR: i64 = 3;
+const HWPROBE_KEY_IMA_EXT_0: i64 = 4;
+// The base behaviour of Linux userspace: RV64I (or RV32I) with M and A
+const HWPROBE_BASE_BEHAVIOR_IMA: u64 = 1 << 0;
+
+// Names of the features levels are made of, as in /proc/cpuinfo's isa line
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"i", Features { isa: letter(b'i'), ..Features::NONE }),
+    (b"m", Features { isa: letter(b'm'), ..Features::NONE }),
+    (b"a", Features { isa: letter(b'a'), ..Features::NONE }),
+    (b"f", Features { isa: letter(b'f'), ..Features::NONE }),
+    (b"d", Features { isa: letter(b'd'), ..Features::NONE }),
+    (b"c", Features { isa: letter(b'c'), ..Features::NONE }),
+    (b"v", Features { isa: letter(b'v'), ..Features::NONE }),
+    (b"zba", Features { ext0: RiscvExt0::ZBA.bits(), ..Features::NONE }),
+    (b"zbb", Features { ext0: RiscvExt0::ZBB.bits(), ..Features::NONE }),
+    (b"zbs", Features { ext0: RiscvExt0::ZBS.bits(), ..Features::NONE }),
+    (b"zicboz", Features { ext0: RiscvExt0::ZICBOZ.bits(), ..Features::NONE }),
+    (b"zbc", Features { ext0: RiscvExt0::ZBC.bits(), ..Features::NONE }),
+    (b"zbkb", Features { ext0: RiscvExt0::ZBKB.bits(), ..Features::NONE }),
+    (b"zbkc", Features { ext0: RiscvExt0::ZBKC.bits(), ..Features::NONE }),
+    (b"zbkx", Features { ext0: RiscvExt0::ZBKX.bits(), ..Features::NONE }),
+    (b"zknd", Features { ext0: RiscvExt0::ZKND.bits(), ..Features::NONE }),
+    (b"zkne", Features { ext0: RiscvExt0::ZKNE.bits(), ..Features::NONE }),
+    (b"zknh", Features { ext0: RiscvExt0::ZKNH.bits(), ..Features::NONE }),
+    (b"zksed", Features { ext0: RiscvExt0::ZKSED.bits(), ..Features::NONE }),
+    (b"zksh", Features { ext0: RiscvExt0::ZKSH.bits(), ..Features::NONE }),
+    (b"zkt", Features { ext0: RiscvExt0::ZKT.bits(), ..Features::NONE }),
+    (b"zvbb", Features { ext0: RiscvExt0::ZVBB.bits(), ..Features::NONE }),
+    (b"zvbc", Features { ext0: RiscvExt0::ZVBC.bits(), ..Features::NONE }),
+    (b"zvkb", Features { ext0: RiscvExt0::ZVKB.bits(), ..Features::NONE }),
+    (b"zvkg", Features { ext0: RiscvExt0::ZVKG.bits(), ..Features::NONE }),
+    (b"zvkned", Features { ext0: RiscvExt0::ZVKNED.bits(), ..Features::NONE }),
+    (b"zvknha", Features { ext0: RiscvExt0::ZVKNHA.bits(), ..Features::NONE }),
+    (b"zvknhb", Features { ext0: RiscvExt0::ZVKNHB.bits(), ..Features::NONE }),
+    (b"zvksed", Features { ext0: RiscvExt0::ZVKSED.bits(), ..Features::NONE }),
+    (b"zvksh", Features { ext0: RiscvExt0::ZVKSH.bits(), ..Features::NONE }),
+    (b"zvkt", Features { ext0: RiscvExt0::ZVKT.bits(), ..Features::NONE }),
+    (b"zfh", Features { ext0: RiscvExt0::ZFH.bits(), ..Features::NONE }),
+    (b"zfhmin", Features { ext0: RiscvExt0::ZFHMIN.bits(), ..Features::NONE }),
+    (b"zihintntl", Features { ext0: RiscvExt0::ZIHINTNTL.bits(), ..Features::NONE }),
+    (b"zvfh", Features { ext0: RiscvExt0::ZVFH.bits(), ..Features::NONE }),
+    (b"zvfhmin", Features { ext0: RiscvExt0::ZVFHMIN.bits(), ..Features::NONE }),
+    (b"zfa", Features { ext0: RiscvExt0::ZFA.bits(), ..Features::NONE }),
+    (b"ztso", Features { ext0: RiscvExt0::ZTSO.bits(), ..Features::NONE }),
+    (b"zacas", Features { ext0: RiscvExt0::ZACAS.bits(), ..Features::NONE }),
+    (b"zicond", Features { ext0: RiscvExt0::ZICOND.bits(), ..Features::NONE }),
+    (b"zihintpause", Features { ext0: RiscvExt0::ZIHINTPAUSE.bits(), ..Features::NONE }),
+    (b"zve32x", Features { ext0: RiscvExt0::ZVE32X.bits(), ..Features::NONE }),
+    (b"zve32f", Features { ext0: RiscvExt0::ZVE32F.bits(), ..Features::NONE }),
+    (b"zve64x", Features { ext0: RiscvExt0::ZVE64X.bits(), ..Features::NONE }),
+    (b"zve64f", Features { ext0: RiscvExt0::ZVE64F.bits(), ..Features::NONE }),
+    (b"zve64d", Features { ext0: RiscvExt0::ZVE64D.bits(), ..Features::NONE }),
+    (b"zimop", Features { ext0: RiscvExt0::ZIMOP.bits(), ..Features::NONE }),
+    (b"zca", Features { ext0: RiscvExt0::ZCA.bits(), ..Features::NONE }),
+    (b"zcb", Features { ext0: RiscvExt0::ZCB.bits(), ..Features::NONE }),
+    (b"zcd", Features { ext0: RiscvExt0::ZCD.bits(), ..Features::NONE }),
+    (b"zcf", Features { ext0: RiscvExt0::ZCF.bits(), ..Features::NONE }),
+    (b"zcmop", Features { ext0: RiscvExt0::ZCMOP.bits(), ..Features::NONE }),
+    (b"zawrs", Features { ext0: RiscvExt0::ZAWRS.bits(), ..Features::NONE }),
+    (b"supm", Features { ext0: RiscvExt0::SUPM.bits(), ..Features::NONE }),
+    (b"zicntr", Features { ext0: RiscvExt0::ZICNTR.bits(), ..Features::NONE }),
+    (b"zihpm", Features { ext0: RiscvExt0::ZIHPM.bits(), ..Features::NONE }),
+    (b"zfbfmin", Features { ext0: RiscvExt0::ZFBFMIN.bits(), ..Features::NONE }),
+    (b"zvfbfmin", Features { ext0: RiscvExt0::ZVFBFMIN.bits(), ..Features::NONE }),
+    (b"zvfbfwma", Features { ext0: RiscvExt0::ZVFBFWMA.bits(), ..Features::NONE }),
+    (b"zicbom", Features { ext0: RiscvExt0::ZICBOM.bits(), ..Features::NONE }),
+    (b"zaamo", Features { ext0: RiscvExt0::ZAAMO.bits(), ..Features::NONE }),
+    (b"zalrsc", Features { ext0: RiscvExt0::ZALRSC.bits(), ..Features::NONE }),
+    (b"zabha", Features { ext0: RiscvExt0::ZABHA.bits(), ..Features::NONE }),
+];
+
+// Levels are the ratified RVA profiles for application processors (e.g. "rva22" for a build with
+// -march=rva22u64). Each one only requires the mandatory extensions compilers generate code for,
+// since the others are hints (e.g. Zihintpause, Zicbop), counters or memory model guarantees,
+// which the kernel can't report anyway, or don't change whether a binary runs.
+// RVA20 is RV64GC, which every riscv64 distribution requires.
+const RVA20_ISA: u64 = letter(b'i') | letter(b'm') | letter(b'a') | letter(b'f') | letter(b'd') | letter(b'c');
+// Bit manipulation and half-precision conversions
+const RVA22_EXT0: u64 = RiscvExt0::ZBA.bits() | RiscvExt0::ZBB.bits() | RiscvExt0::ZBS.bits() | RiscvExt0::ZFHMIN.bits();
+// Vectors (with vector bit manipulation), and scalar additions such as conditional zeroing
+const RVA23_ISA: u64 = RVA20_ISA | letter(b'v');
+const RVA23_EXT0: u64 = RVA22_EXT0 | RiscvExt0::ZVFHMIN.bits() | RiscvExt0::ZVBB.bits() | RiscvExt0::ZICOND.bits()
+                      | RiscvExt0::ZCB.bits() | RiscvExt0::ZFA.bits() | RiscvExt0::ZIMOP.bits() | RiscvExt0::ZCMOP.bits();
+
+// Single-letter extensions, and the multi-letter ones riscv_hwprobe() reports
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub isa: u64,
+    pub ext0: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        isa: 0,
+        ext0: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            isa: self.isa | other.isa,
+            ext0: self.ext0 | other.ext0,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            isa: self.isa & !other.isa,
+            ext0: self.ext0 & !other.ext0,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.isa & other.isa == other.isa
+        && self.ext0 & other.ext0 == other.ext0
+    }
+}
+
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"rva20",
+        directory: b"rva20",
+        features: Features { isa: RVA20_ISA, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"rva22",
+        directory: b"rva22",
+        features: Features { isa: RVA20_ISA, ext0: RVA22_EXT0 },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"rva23",
+        directory: b"rva23",
+        features: Features { isa: RVA23_ISA, ext0: RVA23_EXT0 },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+pub const ARCH_NAME: &[u8] = b"riscv64";
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Enough for the first hart's lines, which come first
+const CPUINFO_MAX: usize = 4096;
+
+// Asks the kernel (Linux 6.4 or newer) about the extensions every hart supports. Returns None
+// on older kernels.
+fn hwprobe() -> Option<[sys::riscv_hwprobe; 5]> {
+    let mut pairs = [
+        HWPROBE_KEY_MVENDORID, HWPROBE_KEY_MARCHID, HWPROBE_KEY_MIMPID,
+        HWPROBE_KEY_BASE_BEHAVIOR, HWPROBE_KEY_IMA_EXT_0,
+    ].map(|key| sys::riscv_hwprobe { key, value: 0 });
+
+    sys::riscv_hwprobe(&mut pairs).ok()?;
+    Some(pairs)
+}
+
+fn get_hwprobe_features(pairs: &[sys::riscv_hwprobe; 5]) -> Features {
+    let (base, ext0) = (&pairs[3], &pairs[4]);
+    // Extensions are only reported for the IMA base
+    if base.key != HWPROBE_KEY_BASE_BEHAVIOR || base.value & HWPROBE_BASE_BEHAVIOR_IMA == 0
+        || ext0.key != HWPROBE_KEY_IMA_EXT_0 {
+        return Features::NONE
+    }
+
+    let mut isa = letter(b'i') | letter(b'm') | letter(b'a');
+    if ext0.value & RiscvExt0::IMA_FD.bits() != 0 {
+        isa |= letter(b'f') | letter(b'd');
+    }
+    if ext0.value & RiscvExt0::IMA_C.bits() != 0 {
+        isa |= letter(b'c');
+    }
+    if ext0.value & RiscvExt0::IMA_V.bits() != 0 {
+        isa |= letter(b'v');
+    }
+
+    Features { isa, ext0: ext0.value }
+}
+
+// Parses /proc/cpuinfo's isa line (e.g. "rv64imafdcv_zba_zbb_zbs"), for kernels without
+// riscv_hwprobe(). Older ones only list single-letter extensions.
+fn get_cpuinfo_features() -> Option<Features> {
+    let mut buffer = [0u8; CPUINFO_MAX];
+    let len = sys::read_file(c"/proc/cpuinfo", &mut buffer).ok()?;
+
+    let line = buffer[..len].split(|c| *c == b'\n').find(|l| l.starts_with(b"isa"))?;
+    let separator = line.iter().position(|c| *c == b':')?;
+    let isa = line[separator + 1..].trim_ascii().strip_prefix(b"rv64")?;
+
+    let mut extensions = isa.split(|c| *c == b'_');
+    let mut features = Features::NONE;
+    for c in extensions.next()? {
+        if c.is_ascii_lowercase() {
+            features.isa |= letter(*c);
+        }
+    }
+    for name in extensions {
+        if let Some((_, f)) = FEATURE_NAMES.iter().find(|(n, _)| *n == name) {
+            features = features.union(f);
+        }
+    }
+    Some(features)
+}
+
+// Features come from the kernel, since the ISA registers can't be read from userspace. Before
+// riscv_hwprobe(), /proc/cpuinfo is the only source of multi-letter extensions, and without
+// procfs, AT_HWCAP only has single-letter ones.
+#[inline]
+pub fn get_features() -> Features {
+    if let Some(pairs) = hwprobe() {
+        return get_hwprobe_features(&pairs)
+    }
+
+    if let Some(features) = get_cpuinfo_features() {
+        return features
+    }
+
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+    Features { isa: hwcap, ..Features::NONE }
+}
+
+// Returns VLEN (the width of the vector registers, in bits) from the vlenb CSR, or None if features
+// don't have V. The CSR faults without it, including when the kernel doesn't let us use vectors
+// (which riscv_hwprobe() takes into account).
+pub fn get_vlen(features: &Features) -> Option<u32> {
+    if features.isa & letter(b'v') == 0 {
+        return None
+    }
+
+    let vlenb: u64;
+    // vlenb is 0xc22, which older assemblers only know by number
+    unsafe { asm!("csrr {}, 0xc22", out(reg) vlenb, options(nomem, nostack, preserves_flags)) };
+    Some(vlenb as u32 * 8)
+}
+
+// Passes what riscv_hwprobe() (if the kernel has it) and the auxiliary vector return to report
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    if let Some(pairs) = hwprobe() {
+        report(&Raw { source: b"hwprobe", fields: &[
+            (b"mvendorid", pairs[0].value),
+            (b"marchid", pairs[1].value),
+            (b"mimpid", pairs[2].value),
+            (b"base_behavior", pairs[3].value),
+            (b"ima_ext_0", pairs[4].value),
+        ]});
+    }
+
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+    report(&Raw { source: b"auxv", fields: &[(b"hwcap", hwcap)] });
+}
+
+// Hypervisors can't be told apart from bare metal without help from the firmware, which
+// userspace can't ask, so we always assume bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    None
+}
+
+// JEDEC codes (mvendorid) of well known vendors
+const VENDORS: [(u32, &[u8]); 3] = [
+    (0x31e, b"Andes"),
+    (0x489, b"SiFive"),
+    (0x5b7, b"T-Head"),
+];
+
+// CPUs whose implementation of some features is broken or too slow to be worth using
+struct Quirk {
+    mvendorid: u32,
+    marchid: u32,
+    // Inclusive
+    mimpids: (u32, u32),
+    // Features which are ignored on matching CPUs
+    ignored: Features,
+}
+
+// Adding a CPU only takes an entry here
+const QUIRKS: &[Quirk] = &[];
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // Vendor's name, padded with NULs (e.g. "SiFive"), or NULs if it isn't a well known one
+    pub vendor: [u8; 12],
+    // mvendorid
+    pub family: u32,
+    // marchid's low 32 bits (commercial implementations set the top bit)
+    pub model: u32,
+    // mimpid's low 32 bits
+    pub stepping: u32,
+}
+
+// Returns None on kernels without riscv_hwprobe(), or if the harts aren't all the same model
+pub fn get_signature() -> Option<Signature> {
+    let pairs = hwprobe()?;
+    if pairs[..3].iter().any(|p| p.key == -1 || p.value == u64::MAX) {
+        return None
+    }
+
+    let family = pairs[0].value as u32;
+    let model = pairs[1].value as u32;
+    let stepping = pairs[2].value as u32;
+
+    let mut vendor = [0u8; 12];
+    if let Some((_, name)) = VENDORS.iter().find(|(code, _)| *code == family) {
+        vendor[..name.len()].copy_from_slice(name);
+    }
+
+    Some(Signature { vendor, family, model, stepping })
+}
+
+// Returns the features which this CPU's quirks rule out
+pub fn get_quirks() -> Features {
+    let signature = match get_signature() {
+        Some(s) => s,
+        None => return Features::NONE
+    };
+
+    let mut ignored = Features::NONE;
+    for quirk in QUIRKS {
+        if quirk.mvendorid == signature.family && quirk.marchid == signature.model
+            && (quirk.mimpids.0..=quirk.mimpids.1).contains(&signature.stepping) {
+            ignored = ignored.union(&quirk.ignored);
+        }
+    }
+    ignored
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
@@ -0,0 +1,272 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+use core::arch::asm;
+
+use super::{FeatureLevel, Raw};
+use crate::sys;
+
+bitflags! {
+    // AT_HWCAP, as in the kernel's arch/s390/include/asm/elf.h
+    pub struct S390Hwcap: u64 {
+        const ESAN3     = 1 << 0;
+        const ZARCH     = 1 << 1;
+        const STFLE     = 1 << 2;
+        const MSA       = 1 << 3;
+        const LDISP     = 1 << 4;
+        const EIMM      = 1 << 5;
+        const DFP       = 1 << 6;
+        const EDAT      = 1 << 7;
+        const ETF3EH    = 1 << 8;
+        const HIGHGPRS  = 1 << 9;
+        const TE        = 1 << 10;
+        // Vector facilities, which the kernel only reports if it saves the vector registers
+        const VXRS      = 1 << 11;
+        const VXRS_BCD  = 1 << 12;
+        const VXRS_EXT  = 1 << 13;
+        const GS        = 1 << 14;
+        const VXRS_EXT2 = 1 << 15;
+        const VXRS_PDE  = 1 << 16;
+        const SORT      = 1 << 17;
+        const DFLT      = 1 << 18;
+        const VXRS_PDE2 = 1 << 19;
+        const NNPA      = 1 << 20;
+        const PCI_MIO   = 1 << 21;
+        const SIE       = 1 << 22;
+    }
+}
+
+// Facility bit of facility number n in STFLE's first doubleword, where facility 0 is the leftmost
+// (most significant) bit
+const fn facility(n: u32) -> u64 {
+    1 << (63 - n)
+}
+
+// Facilities which STFLE reports, but AT_HWCAP doesn't, since they only add general instructions
+// Distinct-operands, high-word, load/store-on-condition 1, population-count (z196)
+const FACILITY_Z196: u64 = facility(45);
+// Miscellaneous-instruction-extensions, execution-hint, load-and-trap (zEC12)
+const FACILITY_ZEC12: u64 = facility(49);
+// Load/store-on-condition 2, load-and-zero-rightmost-byte (z13)
+const FACILITY_LOC2: u64 = facility(53);
+// Miscellaneous-instruction-extensions 2 (z14)
+const FACILITY_MIE2: u64 = facility(58);
+// Miscellaneous-instruction-extensions 3 (z15)
+const FACILITY_MIE3: u64 = facility(61);
+
+// Names of the features levels are made of, as in /proc/cpuinfo
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"esan3", Features { hwcap: S390Hwcap::ESAN3.bits(), ..Features::NONE }),
+    (b"zarch", Features { hwcap: S390Hwcap::ZARCH.bits(), ..Features::NONE }),
+    (b"stfle", Features { hwcap: S390Hwcap::STFLE.bits(), ..Features::NONE }),
+    (b"msa", Features { hwcap: S390Hwcap::MSA.bits(), ..Features::NONE }),
+    (b"ldisp", Features { hwcap: S390Hwcap::LDISP.bits(), ..Features::NONE }),
+    (b"eimm", Features { hwcap: S390Hwcap::EIMM.bits(), ..Features::NONE }),
+    (b"dfp", Features { hwcap: S390Hwcap::DFP.bits(), ..Features::NONE }),
+    (b"edat", Features { hwcap: S390Hwcap::EDAT.bits(), ..Features::NONE }),
+    (b"etf3eh", Features { hwcap: S390Hwcap::ETF3EH.bits(), ..Features::NONE }),
+    (b"highgprs", Features { hwcap: S390Hwcap::HIGHGPRS.bits(), ..Features::NONE }),
+    (b"te", Features { hwcap: S390Hwcap::TE.bits(), ..Features::NONE }),
+    (b"vx", Features { hwcap: S390Hwcap::VXRS.bits(), ..Features::NONE }),
+    (b"vxd", Features { hwcap: S390Hwcap::VXRS_BCD.bits(), ..Features::NONE }),
+    (b"vxe", Features { hwcap: S390Hwcap::VXRS_EXT.bits(), ..Features::NONE }),
+    (b"gs", Features { hwcap: S390Hwcap::GS.bits(), ..Features::NONE }),
+    (b"vxe2", Features { hwcap: S390Hwcap::VXRS_EXT2.bits(), ..Features::NONE }),
+    (b"vxp", Features { hwcap: S390Hwcap::VXRS_PDE.bits(), ..Features::NONE }),
+    (b"sort", Features { hwcap: S390Hwcap::SORT.bits(), ..Features::NONE }),
+    (b"dflt", Features { hwcap: S390Hwcap::DFLT.bits(), ..Features::NONE }),
+    (b"vxp2", Features { hwcap: S390Hwcap::VXRS_PDE2.bits(), ..Features::NONE }),
+    (b"nnpa", Features { hwcap: S390Hwcap::NNPA.bits(), ..Features::NONE }),
+    (b"pcimio", Features { hwcap: S390Hwcap::PCI_MIO.bits(), ..Features::NONE }),
+    (b"sie", Features { hwcap: S390Hwcap::SIE.bits(), ..Features::NONE }),
+];
+
+// Levels are machine generations, as in glibc-hwcaps (e.g. "z15" for a build with -march=z15),
+// which require the same vector facilities glibc does. -march also lets compilers use the
+// generation's new general instructions, which only STFLE reports, so they're required as well.
+// The baseline is z900, the first z/Architecture machine.
+const Z13_HWCAPS: u64 = S390Hwcap::VXRS.bits();
+const Z13_FACILITIES: u64 = FACILITY_Z196 | FACILITY_ZEC12 | FACILITY_LOC2;
+const Z14_HWCAPS: u64 = Z13_HWCAPS | S390Hwcap::VXRS_EXT.bits();
+const Z14_FACILITIES: u64 = Z13_FACILITIES | FACILITY_MIE2;
+const Z15_HWCAPS: u64 = Z14_HWCAPS | S390Hwcap::VXRS_EXT2.bits() | S390Hwcap::VXRS_PDE.bits();
+const Z15_FACILITIES: u64 = Z14_FACILITIES | FACILITY_MIE3;
+// The neural network processing assist, which glibc requires too
+const Z16_HWCAPS: u64 = Z15_HWCAPS | S390Hwcap::VXRS_PDE2.bits() | S390Hwcap::NNPA.bits();
+
+// AT_HWCAP, and facilities 0 to 63 as STFLE stores them
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub hwcap: u64,
+    pub facilities: u64,
+}
+
+impl Features {
+    pub const NONE: Features = Features {
+        hwcap: 0,
+        facilities: 0,
+    };
+
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap | other.hwcap,
+            facilities: self.facilities | other.facilities,
+        }
+    }
+
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            hwcap: self.hwcap & !other.hwcap,
+            facilities: self.facilities & !other.facilities,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.hwcap & other.hwcap == other.hwcap
+        && self.facilities & other.facilities == other.facilities
+    }
+}
+
+pub const FEATURE_LEVELS: &[FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"z900",
+        directory: b"z900",
+        features: Features::NONE,
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"z13",
+        directory: b"z13",
+        features: Features { hwcap: Z13_HWCAPS, facilities: Z13_FACILITIES },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"z14",
+        directory: b"z14",
+        features: Features { hwcap: Z14_HWCAPS, facilities: Z14_FACILITIES },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"z15",
+        directory: b"z15",
+        features: Features { hwcap: Z15_HWCAPS, facilities: Z15_FACILITIES },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"z16",
+        directory: b"z16",
+        features: Features { hwcap: Z16_HWCAPS, facilities: Z15_FACILITIES },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+pub const ARCH_NAME: &[u8] = b"s390x";
+
+// The lowest feature level which every machine of this architecture supports
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+
+// Enough for the lines before the first processor's
+const CPUINFO_MAX: usize = 4096;
+
+// Stores the first doubleword of the facility list. STFLE isn't privileged, but very old
+// machines don't have it, which AT_HWCAP tells.
+fn stfle(hwcap: u64) -> u64 {
+    if hwcap & S390Hwcap::STFLE.bits() == 0 {
+        return 0
+    }
+
+    let mut list = [0u64; 1];
+    // r0 holds the number of doublewords to store, minus one
+    unsafe { asm!("stfle 0({list})", list = in(reg_addr) list.as_mut_ptr(), inout("r0") 0usize => _, options(nostack)) };
+    list[0]
+}
+
+// Vector facilities come from AT_HWCAP, since the kernel only reports them if it saves the vector
+// registers. Other facilities come from STFLE.
+#[inline]
+pub fn get_features() -> Features {
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+
+    Features { hwcap, facilities: stfle(hwcap) }
+}
+
+// Passes the auxiliary vector entry and the facility list detection reads to report, as they are
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    let hwcap = sys::auxval(sys::AT_HWCAP as _).unwrap_or(0);
+    report(&Raw { source: b"auxv", fields: &[(b"hwcap", hwcap)] });
+    report(&Raw { source: b"stfle", fields: &[(b"facilities0", stfle(hwcap))] });
+}
+
+// Hypervisors can't be told apart from bare metal (or LPARs) without help from the firmware, which
+// userspace can't ask, so we always assume bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    None
+}
+
+// CPUs whose implementation of some features is broken or too slow to be worth using
+struct Quirk {
+    // Machine type (e.g. 0x8561, z15)
+    machine: u32,
+    // Features which are ignored on matching CPUs
+    ignored: Features,
+}
+
+// Adding a machine only takes an entry here
+const QUIRKS: &[Quirk] = &[];
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // "IBM/S390", padded with NULs
+    pub vendor: [u8; 12],
+    // Machine type, whose decimal digits are read as hex (e.g. 0x3931, z16)
+    pub family: u32,
+    // Always 0, since machine types aren't split any further
+    pub model: u32,
+    // Always 0
+    pub stepping: u32,
+}
+
+// STIDP is privileged, so the machine type comes from /proc/cpuinfo's first processor line (e.g.
+// "processor 0: version = 00,  identification = 0F0E28,  machine = 3931"). Returns None if
+// procfs is unavailable.
+pub fn get_signature() -> Option<Signature> {
+    let mut buffer = [0u8; CPUINFO_MAX];
+    let len = sys::read_file(c"/proc/cpuinfo", &mut buffer).ok()?;
+
+    let line = buffer[..len].split(|c| *c == b'\n').find(|l| l.starts_with(b"processor "))?;
+    let start = line.windows(9).position(|w| w == b"machine =")? + 9;
+    let machine = line[start..].trim_ascii_start();
+    let end = machine.iter().position(|c| !c.is_ascii_hexdigit()).unwrap_or(machine.len());
+    let family = u32::from_str_radix(core::str::from_utf8(&machine[..end]).ok()?, 16).ok()?;
+
+    let mut vendor = [0u8; 12];
+    vendor[..8].copy_from_slice(b"IBM/S390");
+
+    Some(Signature { vendor, family, model: 0, stepping: 0 })
+}
+
+// Returns the features which this CPU's quirks rule out
+pub fn get_quirks() -> Features {
+    let signature = match get_signature() {
+        Some(s) => s,
+        None => return Features::NONE
+    };
+
+    let mut ignored = Features::NONE;
+    for quirk in QUIRKS.iter().filter(|q| q.machine == signature.family) {
+        ignored = ignored.union(&quirk.ignored);
+    }
+    ignored
+}
+
+// Features the CPU reports, without those its quirks rule out
+pub fn get_usable_features() -> Features {
+    get_features().without(&get_quirks())
+}
@@ -1,6 +1,12 @@
 #![allow(dead_code)]
 use bitflags::bitflags;
 use core::arch::asm;
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{CpuidResult, __cpuid, __cpuid_count};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{CpuidResult, __cpuid, __cpuid_count};
+
+use super::{FeatureLevel, Raw};
 
 bitflags! {
     pub struct X86Flags01hEdx: u32 {
@@ -27,12 +33,22 @@ bitflags! {
         const SSE4_1     = 1 << 19;
         const SSE4_2     = 1 << 20;
         const POPCNT     = 1 << 23;
+        // Crypto, which no level requires
+        const PCLMULQDQ  = 1 << 1;
+        const AES        = 1 << 25;
         //x86-64-v3
         const FMA        = 1 << 12;
         const MOVBE      = 1 << 22;
         const OSXSAVE    = 1 << 27;
         const AVX        = 1 << 28;
         const F16C       = 1 << 29;
+        // Set by hypervisors, never by real CPUs
+        const HYPERVISOR = 1 << 31;
+    }
+
+    pub struct X86Flags80000001hEdx: u32 {
+        //x86-64-v1
+        const LM = 1 << 29;
     }
 
     pub struct X86Flags80000001hEcx: u32 {
@@ -53,9 +69,92 @@ bitflags! {
         const AVX512CD = 1 << 28;
         const AVX512BW = 1 << 30;
         const AVX512VL = 1 << 31;
+        // Crypto, which no level requires
+        const SHA      = 1 << 29;
+    }
+
+    pub struct X86Flags07hEcx: u32 {
+        // 256-bit and 512-bit versions of AES-NI and PCLMULQDQ, which no level requires
+        const VAES       = 1 << 9;
+        const VPCLMULQDQ = 1 << 10;
+    }
+
+    pub struct X86Flags07hEdx: u32 {
+        // Tile registers and their matrix multiplications, which no level requires
+        const AMX_BF16 = 1 << 22;
+        const AMX_TILE = 1 << 24;
+        const AMX_INT8 = 1 << 25;
+    }
+
+    // Leaf 7h, subleaf 1
+    pub struct X86Flags07h1Edx: u32 {
+        //x86-64-v5 (provisional)
+        const AVX10    = 1 << 19;
+        const APX_F    = 1 << 21;
+    }
+
+    // XCR0, the register state the OS saves and restores (and so lets programs use)
+    pub struct X86Xcr0: u32 {
+        const X87       = 1 << 0;
+        const SSE       = 1 << 1;
+        // The upper halves of the YMM registers
+        const AVX       = 1 << 2;
+        const OPMASK    = 1 << 5;
+        // The upper halves of ZMM0-15, and ZMM16-31
+        const ZMM_HI256 = 1 << 6;
+        const HI16_ZMM  = 1 << 7;
+        // The tile configuration and the tiles themselves
+        const XTILECFG  = 1 << 17;
+        const XTILEDATA = 1 << 18;
+        // APX's 16 extra general purpose registers
+        const APX       = 1 << 19;
     }
 }
 
+// Names of the features levels are made of, as in /proc/cpuinfo
+pub const FEATURE_NAMES: &[(&[u8], Features)] = &[
+    (b"fpu", Features { leaf_01h_edx: X86Flags01hEdx::FPU.bits(), ..Features::NONE }),
+    (b"cx8", Features { leaf_01h_edx: X86Flags01hEdx::CX8.bits(), ..Features::NONE }),
+    (b"mmx", Features { leaf_01h_edx: X86Flags01hEdx::MMX.bits(), ..Features::NONE }),
+    (b"sep", Features { leaf_01h_edx: X86Flags01hEdx::SEP.bits(), ..Features::NONE }),
+    (b"cmov", Features { leaf_01h_edx: X86Flags01hEdx::CMOV.bits(), ..Features::NONE }),
+    (b"fxsr", Features { leaf_01h_edx: X86Flags01hEdx::FXSR.bits(), ..Features::NONE }),
+    (b"sse", Features { leaf_01h_edx: X86Flags01hEdx::SSE.bits(), ..Features::NONE }),
+    (b"sse2", Features { leaf_01h_edx: X86Flags01hEdx::SSE2.bits(), ..Features::NONE }),
+    (b"pni", Features { leaf_01h_ecx: X86Flags01hEcx::SSE3.bits(), ..Features::NONE }),
+    (b"ssse3", Features { leaf_01h_ecx: X86Flags01hEcx::SSSE3.bits(), ..Features::NONE }),
+    (b"cx16", Features { leaf_01h_ecx: X86Flags01hEcx::CMPXCHG16B.bits(), ..Features::NONE }),
+    (b"sse4_1", Features { leaf_01h_ecx: X86Flags01hEcx::SSE4_1.bits(), ..Features::NONE }),
+    (b"sse4_2", Features { leaf_01h_ecx: X86Flags01hEcx::SSE4_2.bits(), ..Features::NONE }),
+    (b"popcnt", Features { leaf_01h_ecx: X86Flags01hEcx::POPCNT.bits(), ..Features::NONE }),
+    (b"pclmulqdq", Features { leaf_01h_ecx: X86Flags01hEcx::PCLMULQDQ.bits(), ..Features::NONE }),
+    (b"aes", Features { leaf_01h_ecx: X86Flags01hEcx::AES.bits(), ..Features::NONE }),
+    (b"fma", Features { leaf_01h_ecx: X86Flags01hEcx::FMA.bits(), ..Features::NONE }),
+    (b"movbe", Features { leaf_01h_ecx: X86Flags01hEcx::MOVBE.bits(), ..Features::NONE }),
+    (b"osxsave", Features { leaf_01h_ecx: X86Flags01hEcx::OSXSAVE.bits(), ..Features::NONE }),
+    (b"avx", Features { leaf_01h_ecx: X86Flags01hEcx::AVX.bits(), ..Features::NONE }),
+    (b"f16c", Features { leaf_01h_ecx: X86Flags01hEcx::F16C.bits(), ..Features::NONE }),
+    (b"lm", Features { leaf_80000001h_edx: X86Flags80000001hEdx::LM.bits(), ..Features::NONE }),
+    (b"lahf_lm", Features { leaf_80000001h_ecx: X86Flags80000001hEcx::LAHF_SAHF.bits(), ..Features::NONE }),
+    (b"abm", Features { leaf_80000001h_ecx: X86Flags80000001hEcx::LZCNT.bits(), ..Features::NONE }),
+    (b"bmi1", Features { leaf_07h_ebx: X86Flags07hEbx::BMI1.bits(), ..Features::NONE }),
+    (b"avx2", Features { leaf_07h_ebx: X86Flags07hEbx::AVX2.bits(), ..Features::NONE }),
+    (b"bmi2", Features { leaf_07h_ebx: X86Flags07hEbx::BMI2.bits(), ..Features::NONE }),
+    (b"avx512f", Features { leaf_07h_ebx: X86Flags07hEbx::AVX512F.bits(), ..Features::NONE }),
+    (b"avx512dq", Features { leaf_07h_ebx: X86Flags07hEbx::AVX512DQ.bits(), ..Features::NONE }),
+    (b"avx512cd", Features { leaf_07h_ebx: X86Flags07hEbx::AVX512CD.bits(), ..Features::NONE }),
+    (b"avx512bw", Features { leaf_07h_ebx: X86Flags07hEbx::AVX512BW.bits(), ..Features::NONE }),
+    (b"avx512vl", Features { leaf_07h_ebx: X86Flags07hEbx::AVX512VL.bits(), ..Features::NONE }),
+    (b"sha_ni", Features { leaf_07h_ebx: X86Flags07hEbx::SHA.bits(), ..Features::NONE }),
+    (b"vaes", Features { leaf_07h_ecx: X86Flags07hEcx::VAES.bits(), ..Features::NONE }),
+    (b"vpclmulqdq", Features { leaf_07h_ecx: X86Flags07hEcx::VPCLMULQDQ.bits(), ..Features::NONE }),
+    (b"amx_bf16", Features { leaf_07h_edx: X86Flags07hEdx::AMX_BF16.bits(), ..Features::NONE }),
+    (b"amx_tile", Features { leaf_07h_edx: X86Flags07hEdx::AMX_TILE.bits(), ..Features::NONE }),
+    (b"amx_int8", Features { leaf_07h_edx: X86Flags07hEdx::AMX_INT8.bits(), ..Features::NONE }),
+    (b"avx10", Features { leaf_07h_1_edx: X86Flags07h1Edx::AVX10.bits(), ..Features::NONE }),
+    (b"apx", Features { leaf_07h_1_edx: X86Flags07h1Edx::APX_F.bits(), ..Features::NONE }),
+];
+
 // IA32 hwcaps
 const I486_HWCAPS: u32 = X86Flags01hEdx::FPU.bits();
 const I586_HWCAPS: u32 = I486_HWCAPS | X86Flags01hEdx::CX8.bits() | X86Flags01hEdx::MMX.bits();
@@ -66,9 +165,10 @@ const I886_HWCAPS: u32 = I786_HWCAPS | X86Flags01hEdx::SSE2.bits();
 
 // X86_64 hwcaps
 const X86_64_V1_HWCAPS: u32 = I886_HWCAPS;
+const X86_64_V1_HWCAPS_80000001H_EDX: u32 = X86Flags80000001hEdx::LM.bits();
 const X86_64_V2_HWCAPS_01H_ECX: u32 = X86Flags01hEcx::SSE3.bits() | X86Flags01hEcx::SSSE3.bits() | X86Flags01hEcx::CMPXCHG16B.bits()
-                                    | X86Flags01hEcx::SSE4_1.bits() | X86Flags01hEcx::SSE4_2.bits() | X86Flags01hEcx::SSE4_2.bits()
-                                    | X86Flags01hEcx::CMPXCHG16B.bits();
+                                    | X86Flags01hEcx::SSE4_1.bits() | X86Flags01hEcx::SSE4_2.bits()
+                                    | X86Flags01hEcx::POPCNT.bits();
 const X86_64_V2_HWCAPS_80000001H_ECX: u32 = X86Flags80000001hEcx::LAHF_SAHF.bits();
 const X86_64_V3_HWCAPS_01H_ECX: u32 = X86_64_V2_HWCAPS_01H_ECX | X86Flags01hEcx::FMA.bits() | X86Flags01hEcx::MOVBE.bits()
                                     | X86Flags01hEcx::OSXSAVE.bits() | X86Flags01hEcx::AVX.bits() | X86Flags01hEcx::F16C.bits();
@@ -76,165 +176,533 @@ const X86_64_V3_HWCAPS_80000001H_ECX: u32 = X86_64_V2_HWCAPS_80000001H_ECX | X86
 const X86_64_V3_HWCAPS_07H_EBX: u32 = X86Flags07hEbx::BMI1.bits() | X86Flags07hEbx::AVX2.bits() | X86Flags07hEbx::BMI2.bits();
 const X86_64_V4_HWCAPS_07H_EBX: u32 = X86_64_V3_HWCAPS_07H_EBX | X86Flags07hEbx::AVX512F.bits() | X86Flags07hEbx::AVX512DQ.bits()
                                     | X86Flags07hEbx::AVX512CD.bits() | X86Flags07hEbx::AVX512BW.bits() | X86Flags07hEbx::AVX512VL.bits();
-const X86_HWCAPS_STRING: &'static [u8] = b"i\086";
-const X86_HWCAPS_VERSION_INDEX: usize = 1;
-const X86_64_HWCAPS_STRING: &'static [u8] = b"x86-64-v\0";
-const X86_64_HWCAPS_VERSION_INDEX: usize = X86_64_HWCAPS_STRING.len() - 1;
-
-pub const HWCAPS_CHARS: [u8; 8] = [
-    b'3',
-    b'4',
-    b'5',
-    b'6',
-    b'1', // x86_64 feature levels (4)
-    b'2',
-    b'3',
-    b'4'
-];
-const X86_64_HWCAPS_INDEX: u32 = 4;
-
-#[inline]
-pub fn arch_name_changed(fl: u32) -> bool {
-    return fl + 1 == X86_64_HWCAPS_INDEX
+// Not finalized by the psABI yet. What compilers are expected to target after v4: APX, and AVX10
+// (whose version isn't checked yet) on top of AVX-512.
+const X86_64_V5_HWCAPS_07H_1_EDX: u32 = X86Flags07h1Edx::AVX10.bits() | X86Flags07h1Edx::APX_F.bits();
+
+// Features which need the OS to enable the YMM registers (VEX-encoded instructions), the ZMM and
+// opmask registers, and the tile registers. CPUID reports them either way, so like the kernel does
+// for /proc/cpuinfo, they're dropped if XCR0 says the registers aren't enabled.
+const YMM_STATE: u32 = X86Xcr0::SSE.bits() | X86Xcr0::AVX.bits();
+const ZMM_STATE: u32 = YMM_STATE | X86Xcr0::OPMASK.bits() | X86Xcr0::ZMM_HI256.bits() | X86Xcr0::HI16_ZMM.bits();
+const YMM_FEATURES_01H_ECX: u32 = X86Flags01hEcx::FMA.bits() | X86Flags01hEcx::AVX.bits() | X86Flags01hEcx::F16C.bits();
+const YMM_FEATURES_07H_EBX: u32 = X86Flags07hEbx::AVX2.bits() | AVX512;
+const YMM_FEATURES_07H_ECX: u32 = X86Flags07hEcx::VAES.bits() | X86Flags07hEcx::VPCLMULQDQ.bits();
+const ZMM_FEATURES_07H_EBX: u32 = AVX512;
+const TILE_STATE: u32 = X86Xcr0::XTILECFG.bits() | X86Xcr0::XTILEDATA.bits();
+const TILE_FEATURES_07H_EDX: u32 = X86Flags07hEdx::AMX_BF16.bits() | X86Flags07hEdx::AMX_TILE.bits() | X86Flags07hEdx::AMX_INT8.bits();
+const APX_STATE: u32 = X86Xcr0::APX.bits();
+
+// CPUID registers which hold the features we're interested in
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Features {
+    pub leaf_01h_edx: u32,
+    pub leaf_01h_ecx: u32,
+    pub leaf_07h_ebx: u32,
+    pub leaf_07h_ecx: u32,
+    pub leaf_07h_edx: u32,
+    // Subleaf 1
+    pub leaf_07h_1_edx: u32,
+    pub leaf_80000001h_ecx: u32,
+    pub leaf_80000001h_edx: u32,
 }
 
-#[inline]
-pub fn format_arch_name(buffer: &mut [u8], feature_level: u32) -> Result<(usize, usize), ()> {
-    let arch_string: &[u8];
-    let version_index: usize;
+impl Features {
+    pub const NONE: Features = Features {
+        leaf_01h_edx: 0,
+        leaf_01h_ecx: 0,
+        leaf_07h_ebx: 0,
+        leaf_07h_ecx: 0,
+        leaf_07h_edx: 0,
+        leaf_07h_1_edx: 0,
+        leaf_80000001h_ecx: 0,
+        leaf_80000001h_edx: 0,
+    };
 
-    if feature_level < X86_64_HWCAPS_INDEX {
-        arch_string = X86_HWCAPS_STRING;
-        version_index = X86_HWCAPS_VERSION_INDEX
-    } else {
-        arch_string = X86_64_HWCAPS_STRING;
-        version_index = X86_64_HWCAPS_VERSION_INDEX
+    // Features in either self or other
+    #[inline]
+    pub fn union(&self, other: &Features) -> Features {
+        Features {
+            leaf_01h_edx: self.leaf_01h_edx | other.leaf_01h_edx,
+            leaf_01h_ecx: self.leaf_01h_ecx | other.leaf_01h_ecx,
+            leaf_07h_ebx: self.leaf_07h_ebx | other.leaf_07h_ebx,
+            leaf_07h_ecx: self.leaf_07h_ecx | other.leaf_07h_ecx,
+            leaf_07h_edx: self.leaf_07h_edx | other.leaf_07h_edx,
+            leaf_07h_1_edx: self.leaf_07h_1_edx | other.leaf_07h_1_edx,
+            leaf_80000001h_ecx: self.leaf_80000001h_ecx | other.leaf_80000001h_ecx,
+            leaf_80000001h_edx: self.leaf_80000001h_edx | other.leaf_80000001h_edx,
+        }
     }
 
-    if buffer.len() < arch_string.len() {
-        return Err(())
+    // Features in self, but not in other
+    #[inline]
+    pub fn without(&self, other: &Features) -> Features {
+        Features {
+            leaf_01h_edx: self.leaf_01h_edx & !other.leaf_01h_edx,
+            leaf_01h_ecx: self.leaf_01h_ecx & !other.leaf_01h_ecx,
+            leaf_07h_ebx: self.leaf_07h_ebx & !other.leaf_07h_ebx,
+            leaf_07h_ecx: self.leaf_07h_ecx & !other.leaf_07h_ecx,
+            leaf_07h_edx: self.leaf_07h_edx & !other.leaf_07h_edx,
+            leaf_07h_1_edx: self.leaf_07h_1_edx & !other.leaf_07h_1_edx,
+            leaf_80000001h_ecx: self.leaf_80000001h_ecx & !other.leaf_80000001h_ecx,
+            leaf_80000001h_edx: self.leaf_80000001h_edx & !other.leaf_80000001h_edx,
+        }
     }
 
-    unsafe { buffer.get_unchecked_mut(0..arch_string.len()).copy_from_slice(arch_string) };
-    buffer[version_index] = HWCAPS_CHARS[feature_level as usize];
-
-    Ok((version_index, arch_string.len()))
+    #[inline]
+    pub fn contains(&self, other: &Features) -> bool {
+        self.leaf_01h_edx & other.leaf_01h_edx == other.leaf_01h_edx
+        && self.leaf_01h_ecx & other.leaf_01h_ecx == other.leaf_01h_ecx
+        && self.leaf_07h_ebx & other.leaf_07h_ebx == other.leaf_07h_ebx
+        && self.leaf_07h_ecx & other.leaf_07h_ecx == other.leaf_07h_ecx
+        && self.leaf_07h_edx & other.leaf_07h_edx == other.leaf_07h_edx
+        && self.leaf_07h_1_edx & other.leaf_07h_1_edx == other.leaf_07h_1_edx
+        && self.leaf_80000001h_ecx & other.leaf_80000001h_ecx == other.leaf_80000001h_ecx
+        && self.leaf_80000001h_edx & other.leaf_80000001h_edx == other.leaf_80000001h_edx
+    }
 }
 
+pub const FEATURE_LEVELS: &'static [FeatureLevel] = &[
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"i386",
+        directory: b"i386",
+        features: Features::NONE,
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"i486",
+        directory: b"i486",
+        features: Features { leaf_01h_edx: I486_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"i586",
+        directory: b"i586",
+        features: Features { leaf_01h_edx: I586_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"i686",
+        directory: b"i686",
+        features: Features { leaf_01h_edx: I686_HWCAPS, ..Features::NONE },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"x86-64-v1",
+        directory: b"x86-64-v1",
+        features: Features {
+            leaf_01h_edx: X86_64_V1_HWCAPS,
+            leaf_80000001h_edx: X86_64_V1_HWCAPS_80000001H_EDX,
+            ..Features::NONE
+        },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"x86-64-v2",
+        directory: b"x86-64-v2",
+        features: Features {
+            leaf_01h_edx: X86_64_V1_HWCAPS,
+            leaf_01h_ecx: X86_64_V2_HWCAPS_01H_ECX,
+            leaf_80000001h_ecx: X86_64_V2_HWCAPS_80000001H_ECX,
+            leaf_80000001h_edx: X86_64_V1_HWCAPS_80000001H_EDX,
+            ..Features::NONE
+        },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"x86-64-v3",
+        directory: b"x86-64-v3",
+        features: Features {
+            leaf_01h_edx: X86_64_V1_HWCAPS,
+            leaf_01h_ecx: X86_64_V3_HWCAPS_01H_ECX,
+            leaf_07h_ebx: X86_64_V3_HWCAPS_07H_EBX,
+            leaf_80000001h_ecx: X86_64_V3_HWCAPS_80000001H_ECX,
+            leaf_80000001h_edx: X86_64_V1_HWCAPS_80000001H_EDX,
+            ..Features::NONE
+        },
+    },
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"x86-64-v4",
+        directory: b"x86-64-v4",
+        features: Features {
+            leaf_01h_edx: X86_64_V1_HWCAPS,
+            leaf_01h_ecx: X86_64_V3_HWCAPS_01H_ECX,
+            leaf_07h_ebx: X86_64_V4_HWCAPS_07H_EBX,
+            leaf_80000001h_ecx: X86_64_V3_HWCAPS_80000001H_ECX,
+            leaf_80000001h_edx: X86_64_V1_HWCAPS_80000001H_EDX,
+            ..Features::NONE
+        },
+    },
+    // Named so it doesn't clash with the level the psABI ends up defining
+    #[cfg(feature = "provisional_levels")]
+    FeatureLevel {
+        #[cfg(feature = "level_names")]
+        name: b"x86-64-v5-draft",
+        directory: b"x86-64-v5-draft",
+        features: Features {
+            leaf_01h_edx: X86_64_V1_HWCAPS,
+            leaf_01h_ecx: X86_64_V3_HWCAPS_01H_ECX,
+            leaf_07h_ebx: X86_64_V4_HWCAPS_07H_EBX,
+            leaf_07h_1_edx: X86_64_V5_HWCAPS_07H_1_EDX,
+            leaf_80000001h_ecx: X86_64_V3_HWCAPS_80000001H_ECX,
+            leaf_80000001h_edx: X86_64_V1_HWCAPS_80000001H_EDX,
+            ..Features::NONE
+        },
+    },
+];
+
+// Name of the architecture, as used by configuration sections
+#[cfg(target_arch = "x86")]
+pub const ARCH_NAME: &'static [u8] = b"x86";
+#[cfg(target_arch = "x86_64")]
+pub const ARCH_NAME: &'static [u8] = b"x86_64";
+
+// The lowest feature level which every machine of this architecture supports
+#[cfg(target_arch = "x86")]
+pub const BASELINE_FEATURE_LEVEL: u32 = 0;
+#[cfg(target_arch = "x86_64")]
+pub const BASELINE_FEATURE_LEVEL: u32 = 4;
+
+// CPUID was only added to a revision of the i486. It's there if the ID bit of eflags can be
+// toggled, which intrinsics can't check.
 #[cfg(target_arch = "x86")]
 #[inline]
-pub fn get_max_feature_level() -> u32 {
-    let feature_bitset: u32;
+fn has_cpuid() -> bool {
+    let toggled: u32;
 
     unsafe {
         asm!(
-            //Dark magic adapted from https://www.prowaretech.com/articles/current/assembly/x86/cpuid-library
-            // CPUID was only added to a revision of the i486, so we need this workaround to confirm its presence
-	        "pushfd",           // push eflags on the stack
-	        "pop eax",          // pop them into eax
-	        "mov ebx, eax",     // save to ebx for restoring afterwards
-	        "xor eax, 200000h", // toggle bit 21
-	        "push eax",         // push the toggled eflags
-	        "popfd",            // pop them back into eflags
-	        "pushfd",           // push eflags
-	        "pop eax",          // pop them back into eax
-	        "cmp eax, ebx",     // see if bit 21 was reset
-	        "jz 2f",
-            "jmp 3f",
-
-            "2:", //CPUID supported
-            "xor edx, edx", // Set no bits so we fall back to i386
-	        "jmp 4f",
-
-            "3:", //CPUID supported
-            "mov eax, 1h",
-            "pop ebx",
-	        "cpuid",
-	        "push ebx",
-
-            "4:",
-            out("eax") _,
-            out("ecx") _,
-            out("edx") feature_bitset,
-        )
-    }
-
-    if feature_bitset == 0 {
-        return 0
+            "pushfd",
+            "pop {0}",
+            "mov {1}, {0}",
+            "xor {0}, 200000h",
+            "push {0}",
+            "popfd",
+            "pushfd",
+            "pop {0}",
+            // Put the original eflags back
+            "push {1}",
+            "popfd",
+            "xor {0}, {1}",
+            out(reg) toggled,
+            out(reg) _,
+        );
     }
+    toggled & 0x200000 != 0
+}
 
-    let mut feature_level = 0;
+// Leaves which features are decoded from, read in a single pass. Leaves the CPU may not have are
+// zeroes unless it does, since CPUs return another leaf's registers for them.
+struct Leaves {
+    leaf_00h: CpuidResult,
+    leaf_01h: CpuidResult,
+    #[cfg(target_arch = "x86_64")]
+    leaf_07h: CpuidResult,
+    #[cfg(all(target_arch = "x86_64", feature = "provisional_levels"))]
+    leaf_07h_1: CpuidResult,
+    #[cfg(target_arch = "x86_64")]
+    leaf_80000001h: CpuidResult,
+}
 
-    for i in 1..=(X86_64_HWCAPS_INDEX-1) {
-        let has_feature = match i {
-            1 => feature_bitset & I486_HWCAPS == I486_HWCAPS,
-            2 => feature_bitset & I586_HWCAPS == I586_HWCAPS,
-            3 => feature_bitset & I686_HWCAPS == I686_HWCAPS,
-            _ => false
-        };
+#[cfg(target_arch = "x86_64")]
+const NO_LEAF: CpuidResult = CpuidResult { eax: 0, ebx: 0, ecx: 0, edx: 0 };
 
-        if !has_feature {
-            break
-        }
+// Returns None if the CPU doesn't have CPUID
+#[inline]
+fn read_leaves() -> Option<Leaves> {
+    #[cfg(target_arch = "x86")]
+    if !has_cpuid() {
+        return None
+    }
+
+    // Leaf 0h's eax is the highest basic leaf, and leaf 1h exists on all x86 CPUs with CPUID
+    #[allow(unused_unsafe)]
+    let (leaf_00h, leaf_01h) = unsafe { (__cpuid(0), __cpuid(1)) };
+
+    // Leaf 7h was introduced with Core Duo, and hypervisors or firmware (e.g. "Limit CPUID
+    // Maxval") can hide it on newer CPUs too. It's only read if leaf 0h says it's there, since
+    // Intel CPUs return the highest basic leaf's registers instead, which may look like AVX2.
+    #[cfg(target_arch = "x86_64")]
+    #[allow(unused_unsafe)]
+    let leaf_07h = if leaf_00h.eax >= 7 { unsafe { __cpuid_count(7, 0) } } else { NO_LEAF };
+
+    // Leaf 7h's eax is its highest subleaf. Subleaf 1 is only read for the provisional level, so
+    // other builds detect exactly what they used to.
+    #[cfg(all(target_arch = "x86_64", feature = "provisional_levels"))]
+    #[allow(unused_unsafe)]
+    let leaf_07h_1 = if leaf_07h.eax >= 1 { unsafe { __cpuid_count(7, 1) } } else { NO_LEAF };
+
+    // Leaf 80000001h was introduced with Pentium 4, and exists on all x86-64 CPUs. 32-bit builds
+    // can't execute x86_64 binaries, so they don't need it.
+    #[cfg(target_arch = "x86_64")]
+    #[allow(unused_unsafe)]
+    let leaf_80000001h = unsafe { __cpuid(0x80000001) };
+
+    Some(Leaves {
+        leaf_00h,
+        leaf_01h,
+        #[cfg(target_arch = "x86_64")]
+        leaf_07h,
+        #[cfg(all(target_arch = "x86_64", feature = "provisional_levels"))]
+        leaf_07h_1,
+        #[cfg(target_arch = "x86_64")]
+        leaf_80000001h,
+    })
+}
+
+#[cfg(target_arch = "x86")]
+fn decode_features(leaves: &Leaves) -> Features {
+    Features { leaf_01h_edx: leaves.leaf_01h.edx, ..Features::NONE }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn decode_features(leaves: &Leaves) -> Features {
+    #[allow(unused_mut)]
+    let mut features = Features {
+        leaf_01h_edx: leaves.leaf_01h.edx,
+        leaf_01h_ecx: leaves.leaf_01h.ecx,
+        leaf_07h_ebx: leaves.leaf_07h.ebx,
+        leaf_07h_ecx: leaves.leaf_07h.ecx,
+        leaf_07h_edx: leaves.leaf_07h.edx,
+        leaf_80000001h_ecx: leaves.leaf_80000001h.ecx,
+        leaf_80000001h_edx: leaves.leaf_80000001h.edx,
+        ..Features::NONE
+    };
+    #[cfg(feature = "provisional_levels")]
+    {
+        features.leaf_07h_1_edx = leaves.leaf_07h_1.edx;
+    }
 
-        feature_level += 1
+    // The kernel or hypervisor may not have enabled the registers AVX, AVX-512 and AMX need (e.g.
+    // noxsave, or a VM whose CPU model hides them), and they'd fault with #UD
+    let xcr0 = get_xcr0(leaves.leaf_01h.ecx);
+    if xcr0 & YMM_STATE != YMM_STATE {
+        features.leaf_01h_ecx &= !YMM_FEATURES_01H_ECX;
+        features.leaf_07h_ebx &= !YMM_FEATURES_07H_EBX;
+        features.leaf_07h_ecx &= !YMM_FEATURES_07H_ECX;
+    }
+    if xcr0 & ZMM_STATE != ZMM_STATE {
+        features.leaf_07h_ebx &= !ZMM_FEATURES_07H_EBX;
+        features.leaf_07h_1_edx &= !X86Flags07h1Edx::AVX10.bits();
+    }
+    if xcr0 & TILE_STATE != TILE_STATE {
+        features.leaf_07h_edx &= !TILE_FEATURES_07H_EDX;
+    }
+    if xcr0 & APX_STATE != APX_STATE {
+        features.leaf_07h_1_edx &= !X86Flags07h1Edx::APX_F.bits();
     }
+    features
+}
 
-    feature_level
+// Without CPUID, no bits are set, so we fall back to i386
+#[inline]
+pub fn get_features() -> Features {
+    match read_leaves() {
+        Some(leaves) => decode_features(&leaves),
+        None => Features::NONE
+    }
 }
 
+// Like get_features() without get_quirks(), but the CPU model comes from the same pass's leaves,
+// so quirks don't take any more CPUID calls
+pub fn get_usable_features() -> Features {
+    let leaves = match read_leaves() {
+        Some(l) => l,
+        None => return Features::NONE
+    };
+
+    let signature = decode_signature(&leaves.leaf_00h, &leaves.leaf_01h);
+    decode_features(&leaves).without(&match_quirks(&signature))
+}
+
+// Returns XCR0's lower half, or 0 if the OS hasn't enabled XGETBV (OSXSAVE in leaf_01h_ecx), which
+// it also needs to enable any state beyond SSE
 #[cfg(target_arch = "x86_64")]
 #[inline]
-pub fn get_max_feature_level() -> u32 {
-    let feature_set_01h_ecx: u32;
-    let feature_set_80000001h_ecx: u32;
-    let feature_set_07h_ebx: u32;
+fn get_xcr0(leaf_01h_ecx: u32) -> u32 {
+    if leaf_01h_ecx & X86Flags01hEcx::OSXSAVE.bits() == 0 {
+        return 0
+    }
 
+    let xcr0: u32;
     unsafe {
         asm!(
-            "push rbx",
+            "xgetbv",
+            in("ecx") 0,
+            out("eax") xcr0,
+            out("edx") _,
+            // Not pure, like CPUID
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    xcr0
+}
+
+// Leaves and subleaves of CPUID which features, signatures and hypervisors are read from
+#[cfg(target_arch = "x86")]
+const RAW_LEAVES: &[(u32, u32)] = &[(0x0, 0), (0x1, 0), (0x40000000, 0)];
+#[cfg(all(target_arch = "x86_64", not(feature = "provisional_levels")))]
+const RAW_LEAVES: &[(u32, u32)] = &[(0x0, 0), (0x1, 0), (0x7, 0), (0x40000000, 0), (0x80000001, 0)];
+#[cfg(all(target_arch = "x86_64", feature = "provisional_levels"))]
+const RAW_LEAVES: &[(u32, u32)] = &[(0x0, 0), (0x1, 0), (0x7, 0), (0x7, 1), (0x40000000, 0), (0x80000001, 0)];
+
+// Passes every leaf detection reads to report, as the CPU returns it. Nothing is reported if
+// the CPU doesn't have CPUID.
+pub fn get_raw(report: &mut dyn FnMut(&Raw)) {
+    #[cfg(target_arch = "x86")]
+    if !has_cpuid() {
+        return
+    }
+
+    for (leaf, subleaf) in RAW_LEAVES {
+        #[allow(unused_unsafe)]
+        let registers = unsafe { __cpuid_count(*leaf, *subleaf) };
+        report(&Raw { source: b"cpuid", fields: &[
+            (b"leaf", *leaf as u64),
+            (b"subleaf", *subleaf as u64),
+            (b"eax", registers.eax as u64),
+            (b"ebx", registers.ebx as u64),
+            (b"ecx", registers.ecx as u64),
+            (b"edx", registers.edx as u64),
+        ]});
+    }
 
-            // Get leaf 1h (Exists on all x86 CPUs with cpuid)
-            "mov eax, 1h",
-            "cpuid",
-            "push rcx",
+    #[cfg(target_arch = "x86_64")]
+    {
+        #[allow(unused_unsafe)]
+        let xcr0 = get_xcr0(unsafe { __cpuid_count(1, 0) }.ecx);
+        report(&Raw { source: b"xgetbv", fields: &[(b"xcr", 0), (b"eax", xcr0 as u64)] });
+    }
+}
 
-            // Get leaf 7h (Introduced with Core Duo, exists on all x86-64-v2+ CPUs)
-            "mov eax, 7h",
-            "xor ecx, ecx",
-            "cpuid",
-            "push rbx",
+// Vendor signatures (leaf 40000000h's ebx, ecx and edx) of well known hypervisors
+const HYPERVISORS: [(&[u8; 12], &[u8]); 9] = [
+    (b"KVMKVMKVM\0\0\0", b"kvm"),
+    (b"Microsoft Hv", b"hyperv"),
+    (b"VMwareVMware", b"vmware"),
+    (b"XenVMMXenVMM", b"xen"),
+    (b"TCGTCGTCGTCG", b"qemu"),
+    (b"VBoxVBoxVBox", b"virtualbox"),
+    (b" lrpepyh  vr", b"parallels"),
+    (b"bhyve bhyve ", b"bhyve"),
+    (b"ACRNACRNACRN", b"acrn"),
+];
 
-            // Get leaf 80000001h (Introduced with Pentium 4, exists on all x86-64 CPUs)
-            "mov eax, 80000001h",
-            "cpuid",
+// Returns the name of the hypervisor we're running under ("unknown" if it isn't a well known
+// one), or None on bare metal
+pub fn get_hypervisor() -> Option<&'static [u8]> {
+    // CPUs without CPUID can't be virtualized anyway
+    #[cfg(target_arch = "x86")]
+    if !has_cpuid() {
+        return None
+    }
 
-            "pop rdx",
-            "pop rax",
+    #[allow(unused_unsafe)]
+    let (leaf_01h, leaf_40000000h) = unsafe { (__cpuid(1), __cpuid(0x40000000)) };
+    if leaf_01h.ecx & X86Flags01hEcx::HYPERVISOR.bits() == 0 {
+        return None
+    }
 
-            "pop rbx",
+    let mut signature = [0u8; 12];
+    signature[0..4].copy_from_slice(&leaf_40000000h.ebx.to_le_bytes());
+    signature[4..8].copy_from_slice(&leaf_40000000h.ecx.to_le_bytes());
+    signature[8..12].copy_from_slice(&leaf_40000000h.edx.to_le_bytes());
 
-            out("eax") feature_set_01h_ecx,
-            out("ecx") feature_set_80000001h_ecx,
-            out("edx") feature_set_07h_ebx,
-            options(pure, nomem)
-        );
-    };
+    match HYPERVISORS.iter().find(|(s, _)| **s == signature) {
+        Some((_, name)) => Some(name),
+        None => Some(b"unknown")
+    }
+}
 
-    if !(feature_set_01h_ecx & X86_64_V2_HWCAPS_01H_ECX == X86_64_V2_HWCAPS_01H_ECX)
-    || !(feature_set_80000001h_ecx  & X86_64_V2_HWCAPS_80000001H_ECX == X86_64_V2_HWCAPS_80000001H_ECX) {
-        return X86_64_HWCAPS_INDEX
+// CPUs whose implementation of some features is broken or too slow to be worth using
+struct Quirk {
+    // Leaf 0h's ebx, edx and ecx (e.g. "GenuineIntel")
+    vendor: &'static [u8; 12],
+    family: u32,
+    model: u32,
+    // Inclusive
+    steppings: (u32, u32),
+    // Features which are ignored on matching CPUs
+    ignored: Features,
+}
+
+const AVX512: u32 = X86Flags07hEbx::AVX512F.bits() | X86Flags07hEbx::AVX512DQ.bits() | X86Flags07hEbx::AVX512CD.bits()
+                  | X86Flags07hEbx::AVX512BW.bits() | X86Flags07hEbx::AVX512VL.bits();
+
+// Adding a CPU only takes an entry here
+const QUIRKS: &[Quirk] = &[
+    // Alder Lake's AVX-512 is unvalidated, and only appears with E-cores disabled on early firmware
+    Quirk {
+        vendor: b"GenuineIntel", family: 6, model: 0x97, steppings: (0, 15),
+        ignored: Features { leaf_07h_ebx: AVX512, ..Features::NONE },
+    },
+    Quirk {
+        vendor: b"GenuineIntel", family: 6, model: 0x9A, steppings: (0, 15),
+        ignored: Features { leaf_07h_ebx: AVX512, ..Features::NONE },
+    },
+];
+
+// Identifies the CPU model, for quirks and microcode rules
+#[derive(Clone, Copy)]
+pub struct Signature {
+    // Leaf 0h's ebx, edx and ecx (e.g. "GenuineIntel")
+    pub vendor: [u8; 12],
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+// Decodes the vendor from leaf 0h, and the family, model and stepping from leaf 1h
+fn decode_signature(leaf_00h: &CpuidResult, leaf_01h: &CpuidResult) -> Signature {
+    let mut vendor = [0u8; 12];
+    vendor[0..4].copy_from_slice(&leaf_00h.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&leaf_00h.edx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&leaf_00h.ecx.to_le_bytes());
+
+    // The extended family and model only count for some families
+    let stepping = leaf_01h.eax & 0xF;
+    let mut model = (leaf_01h.eax >> 4) & 0xF;
+    let mut family = (leaf_01h.eax >> 8) & 0xF;
+    if family == 0xF {
+        family += (leaf_01h.eax >> 20) & 0xFF;
     }
+    if family == 0x6 || family >= 0xF {
+        model |= ((leaf_01h.eax >> 16) & 0xF) << 4;
+    }
+
+    Signature { vendor, family, model, stepping }
+}
 
-    if !(feature_set_01h_ecx & X86_64_V3_HWCAPS_01H_ECX == X86_64_V3_HWCAPS_01H_ECX)
-    || !(feature_set_07h_ebx & X86_64_V3_HWCAPS_07H_EBX == X86_64_V3_HWCAPS_07H_EBX)
-    || !(feature_set_80000001h_ecx & X86_64_V3_HWCAPS_80000001H_ECX == X86_64_V3_HWCAPS_80000001H_ECX) {
-        return X86_64_HWCAPS_INDEX + 1
+// Returns None if the CPU doesn't have CPUID
+pub fn get_signature() -> Option<Signature> {
+    #[cfg(target_arch = "x86")]
+    if !has_cpuid() {
+        return None
     }
 
-    if !(feature_set_07h_ebx & X86_64_V4_HWCAPS_07H_EBX == X86_64_V4_HWCAPS_07H_EBX) {
-        return X86_64_HWCAPS_INDEX + 2
+    #[allow(unused_unsafe)]
+    let (leaf_00h, leaf_01h) = unsafe { (__cpuid(0), __cpuid(1)) };
+    Some(decode_signature(&leaf_00h, &leaf_01h))
+}
+
+fn match_quirks(signature: &Signature) -> Features {
+    let mut ignored = Features::NONE;
+    for quirk in QUIRKS {
+        if *quirk.vendor == signature.vendor && quirk.family == signature.family && quirk.model == signature.model
+            && (quirk.steppings.0..=quirk.steppings.1).contains(&signature.stepping) {
+            ignored = ignored.union(&quirk.ignored);
+        }
     }
+    ignored
+}
 
-    return X86_64_HWCAPS_INDEX + 3
+// Returns the features which this CPU's quirks rule out
+pub fn get_quirks() -> Features {
+    match get_signature() {
+        Some(signature) => match_quirks(&signature),
+        None => Features::NONE
+    }
 }
@@ -0,0 +1,73 @@
+/*
+   Level directories of every supported architecture.
+   Diagnostic modes can inspect hwcaps trees built for other architectures (e.g. an aarch64
+   image staged on an x86_64 build host), which only takes knowing their level directories.
+   Nothing here is detected, so unlike FEATURE_LEVELS, these are available on every target.
+   Architectures must list the same directories here as in their FEATURE_LEVELS.
+*/
+
+pub struct ArchLayout {
+    // As in ARCH_NAME
+    pub name: &'static [u8],
+    // Level directories, lowest first
+    pub directories: &'static [&'static [u8]],
+    // Index of the level every machine of the architecture supports
+    pub baseline: u32,
+}
+
+const X86_DIRECTORIES: &[&[u8]] = &[
+    b"i386", b"i486", b"i586", b"i686",
+    b"x86-64-v1", b"x86-64-v2", b"x86-64-v3", b"x86-64-v4",
+    #[cfg(feature = "provisional_levels")]
+    b"x86-64-v5-draft",
+];
+
+const AARCH64_DIRECTORIES: &[&[u8]] = &[
+    b"armv8-a", b"armv8.1-a", b"armv8.2-a", b"armv8.3-a", b"armv8.4-a", b"armv8.5-a", b"armv9-a",
+];
+
+const ARMHF_DIRECTORIES: &[&[u8]] = &[
+    b"armv7hl", b"armv7hl-neon", b"armv7hl-neon-vfpv4",
+];
+
+const MIPS_DIRECTORIES: &[&[u8]] = &[
+    b"mips32r2", b"mips32r2-msa", b"mips32r6",
+];
+
+const MIPS64_DIRECTORIES: &[&[u8]] = &[
+    b"mips64r2", b"mips64r2-msa", b"mips64r6",
+];
+
+const RISCV64_DIRECTORIES: &[&[u8]] = &[
+    b"rva20", b"rva22", b"rva23",
+];
+
+const PPC64LE_DIRECTORIES: &[&[u8]] = &[
+    b"power8", b"power9", b"power10",
+];
+
+const S390X_DIRECTORIES: &[&[u8]] = &[
+    b"z900", b"z13", b"z14", b"z15", b"z16",
+];
+
+const LOONGARCH64_DIRECTORIES: &[&[u8]] = &[
+    b"loongarch64", b"loongarch64-lsx", b"loongarch64-lasx",
+];
+
+pub const ARCH_LAYOUTS: &[ArchLayout] = &[
+    ArchLayout { name: b"x86", directories: X86_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"x86_64", directories: X86_DIRECTORIES, baseline: 4 },
+    ArchLayout { name: b"aarch64", directories: AARCH64_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"armhf", directories: ARMHF_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"mips", directories: MIPS_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"mips64", directories: MIPS64_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"riscv64", directories: RISCV64_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"ppc64le", directories: PPC64LE_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"s390x", directories: S390X_DIRECTORIES, baseline: 0 },
+    ArchLayout { name: b"loongarch64", directories: LOONGARCH64_DIRECTORIES, baseline: 0 },
+];
+
+// Returns the layout of the architecture called name (e.g. "x86_64")
+pub fn find_arch_layout(name: &[u8]) -> Option<&'static ArchLayout> {
+    ARCH_LAYOUTS.iter().find(|layout| layout.name == name)
+}
@@ -1,8 +1,133 @@
 #[cfg_attr(target_arch = "x86", path = "arch_x86.rs")]
 #[cfg_attr(target_arch = "x86_64", path = "arch_x86.rs")]
+#[cfg_attr(target_arch = "aarch64", path = "arch_aarch64.rs")]
+#[cfg_attr(target_arch = "arm", path = "arch_arm.rs")]
+#[cfg_attr(any(target_arch = "mips", target_arch = "mips64"), path = "arch_mips.rs")]
+#[cfg_attr(target_arch = "riscv64", path = "arch_riscv64.rs")]
+#[cfg_attr(all(target_arch = "powerpc64", target_endian = "little"), path = "arch_powerpc64le.rs")]
+#[cfg_attr(target_arch = "s390x", path = "arch_s390x.rs")]
+#[cfg_attr(target_arch = "loongarch64", path = "arch_loongarch64.rs")]
+// Architectures without a backend of their own read /proc/cpuinfo
+#[cfg_attr(not(any(
+    target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64", target_arch = "arm",
+    target_arch = "mips", target_arch = "mips64", target_arch = "riscv64",
+    all(target_arch = "powerpc64", target_endian = "little"), target_arch = "s390x", target_arch = "loongarch64"
+)), path = "arch_generic.rs")]
 mod arch;
+mod layouts;
 
-pub use arch::get_max_feature_level;
-pub use arch::format_arch_name;
-pub use arch::arch_name_changed;
-pub use arch::HWCAPS_CHARS;
+pub use arch::{Features, Signature, FEATURE_LEVELS, FEATURE_NAMES, ARCH_NAME, get_hypervisor, get_signature};
+#[allow(unused_imports)]
+pub use arch::BASELINE_FEATURE_LEVEL;
+// What the CPU reports and what quirks rule out, before detect() combines them
+#[allow(unused_imports)]
+pub use arch::{get_features, get_quirks};
+// Vector register width, for vector variants
+#[cfg(target_arch = "riscv64")]
+#[allow(unused_imports)]
+pub use arch::get_vlen;
+// Registers as read from the CPU, for bug reports
+#[allow(unused_imports)]
+pub use arch::get_raw;
+#[allow(unused_imports)]
+pub use layouts::{ArchLayout, ARCH_LAYOUTS, find_arch_layout};
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FeatureLevel {
+    // Human readable name, used for configuration (e.g. "x86-64-v3"). Only kept by the features
+    // which take or print names, so the minimal build doesn't carry them.
+    #[cfg(feature = "level_names")]
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_name"))]
+    pub name: &'static [u8],
+    // Name of the level's directory inside of the hwcaps directory
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_name"))]
+    pub directory: &'static [u8],
+    // Features which the machine must support to use this level
+    pub features: arch::Features,
+}
+
+// Registers (or other words) detection reads from one source, before they're decoded
+pub struct Raw<'a> {
+    // Where they come from (e.g. "cpuid")
+    pub source: &'static [u8],
+    // Names and values, including which leaf they're from
+    pub fields: &'a [(&'static [u8], u64)],
+}
+
+// What was found out about this machine
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Detection {
+    pub features: arch::Features,
+    // Index of the highest supported level in FEATURE_LEVELS
+    pub max_level: u32,
+}
+
+// Levels are sorted, so the first level which isn't supported marks the end of the search
+pub fn detect() -> Detection {
+    // Features which are known to be broken on this CPU don't count
+    #[cfg(feature = "quirks")]
+    let features = arch::get_usable_features();
+    #[cfg(not(feature = "quirks"))]
+    let features = arch::get_features();
+
+    Detection { features, max_level: get_feature_level(&features) }
+}
+
+// Returns the index of the highest level which features are enough for
+pub fn get_feature_level(features: &Features) -> u32 {
+    let mut feature_level = 0;
+
+    for (i, level) in FEATURE_LEVELS.iter().enumerate().skip(1) {
+        if !features.contains(&level.features) {
+            break
+        }
+        feature_level = i as u32;
+    }
+    feature_level
+}
+
+#[inline]
+pub fn get_max_feature_level() -> u32 {
+    detect().max_level
+}
+
+// Returns the highest level CPU number cpu supports, without running on it, or None if it can't be
+// told (e.g. the CPU is offline). Without quirks, every CPU supports the same level.
+#[cfg(target_arch = "aarch64")]
+#[cfg_attr(not(feature = "quirks"), allow(unused_variables))]
+#[allow(dead_code)]
+pub fn get_cpu_feature_level(cpu: u32) -> Option<u32> {
+    #[cfg(feature = "quirks")]
+    let features = arch::get_features().without(&arch::get_cpu_quirks(cpu)?);
+    #[cfg(not(feature = "quirks"))]
+    let features = arch::get_features();
+
+    Some(get_feature_level(&features))
+}
+
+// Returns the feature level of a directory in the hwcaps directory (e.g. "x86-64-v3+bigmem")
+#[allow(dead_code)]
+pub fn find_directory_level(directory: &[u8]) -> Option<u32> {
+    let end = directory.iter().position(|c| *c == b'+').unwrap_or(directory.len());
+    FEATURE_LEVELS.iter().position(|level| level.directory == &directory[..end]).map(|i| i as u32)
+}
+
+// Returns the features called name, as in /proc/cpuinfo (e.g. "avx2")
+#[allow(dead_code)]
+pub fn find_feature(name: &[u8]) -> Option<Features> {
+    FEATURE_NAMES.iter().find(|(n, _)| *n == name).map(|(_, features)| *features)
+}
+
+// Names are ASCII, so they're better off as strings than as byte arrays
+#[cfg(feature = "serde")]
+fn serialize_name<S: serde::Serializer>(name: &&'static [u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(core::str::from_utf8(name).unwrap_or_default())
+}
+
+// Returns the feature level called `name` (e.g. "x86-64-v3")
+#[cfg(feature = "level_names")]
+#[allow(dead_code)]
+pub fn find_feature_level(name: &[u8]) -> Option<u32> {
+    FEATURE_LEVELS.iter().position(|level| level.name == name).map(|i| i as u32)
+}
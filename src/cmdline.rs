@@ -0,0 +1,135 @@
+/*
+   Kernel command line parameters.
+   Binaries dispatched during early boot can't rely on /etc or the environment
+   being available yet, so these are read straight from /proc/cmdline:
+   - hwcaps_loader=off          -> Only execute the baseline feature level's build
+   - hwcaps_loader.level=<name> -> Don't consider feature levels above <name> (e.g. x86-64-v2)
+*/
+
+use crate::sys;
+use crate::capabilities;
+
+const DISABLE_PARAM: &'static [u8] = b"hwcaps_loader=off";
+const LEVEL_PARAM: &'static [u8] = b"hwcaps_loader.level=";
+
+// Larger than any architecture's default COMMAND_LINE_SIZE. Some (e.g. s390) can be configured
+// with larger ones, so a full buffer is treated as a truncated command line.
+const CMDLINE_MAX: usize = 4096;
+
+// What the kernel command line asks of us
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    // Dispatch is disabled, so only the baseline level's build is executed
+    Off,
+    // Feature levels above this one aren't considered
+    Level(u32)
+}
+
+// Reads the kernel command line into buffer. Returns the part made of complete parameters, and
+// whether it was cut short, in which case its last parameter is dropped along with the rest.
+fn read(buffer: &mut [u8; CMDLINE_MAX]) -> (&[u8], bool) {
+    let len = sys::read_file(c"/proc/cmdline", buffer).unwrap_or(0);
+    complete(&buffer[..len], CMDLINE_MAX)
+}
+
+fn complete(cmdline: &[u8], capacity: usize) -> (&[u8], bool) {
+    match cmdline.len() == capacity {
+        true => (&cmdline[..cmdline.iter().rposition(|c| c.is_ascii_whitespace()).unwrap_or(0)], true),
+        false => (cmdline, false)
+    }
+}
+
+// Returns how the kernel command line restricts feature levels, if it does at all.
+// Unknown level names are ignored, as aborting here would prevent the system from booting.
+pub fn get_feature_level_limit() -> Option<Limit> {
+    let mut buffer = [0u8; CMDLINE_MAX];
+    parse(read(&mut buffer).0)
+}
+
+// Returns whether parameters at the end of the kernel command line are ignored, for --selftest.
+// It's not reported while dispatching, as every program would print it.
+pub fn is_truncated() -> bool {
+    let mut buffer = [0u8; CMDLINE_MAX];
+    read(&mut buffer).1
+}
+
+fn parse(cmdline: &[u8]) -> Option<Limit> {
+    let mut limit = None;
+
+    for param in cmdline.split(|c| c.is_ascii_whitespace()) {
+        if param == DISABLE_PARAM {
+            // Dispatch being disabled takes precedence over everything else
+            return Some(Limit::Off)
+        }
+
+        if param.starts_with(LEVEL_PARAM) {
+            // Like the kernel, let the last occurence of a parameter win
+            limit = capabilities::find_feature_level(&param[LEVEL_PARAM.len()..]).map(Limit::Level).or(limit);
+        }
+    }
+
+    limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, complete, Limit};
+    use crate::capabilities::FEATURE_LEVELS;
+
+    fn level(i: usize) -> Option<Limit> {
+        Some(Limit::Level(i as u32))
+    }
+
+    #[test]
+    fn parse_limits() {
+        let name = FEATURE_LEVELS[1].name;
+        let other = FEATURE_LEVELS[FEATURE_LEVELS.len() - 1].name;
+
+        let mut param = [0u8; 64];
+        let mut other_param = [0u8; 64];
+        let len = crate::path::join(&mut param, &[b"hwcaps_loader.level=", name]).unwrap();
+        let other_len = crate::path::join(&mut other_param, &[b"hwcaps_loader.level=", other]).unwrap();
+        let (param, other_param) = (&param[..len], &other_param[..other_len]);
+
+        let cases: &[(&[&[u8]], Option<Limit>)] = &[
+            (&[], None),
+            (&[b"quiet", b"root=/dev/sda1"], None),
+            (&[b"hwcaps_loader=off"], Some(Limit::Off)),
+            // Disabling wins over any level, wherever it is
+            (&[param, b"hwcaps_loader=off"], Some(Limit::Off)),
+            (&[b"hwcaps_loader=off", param], Some(Limit::Off)),
+            (&[b"hwcaps_loader=on"], None),
+            (&[b"xhwcaps_loader=off"], None),
+            (&[b"quiet", param], level(1)),
+            // The last valid occurence wins
+            (&[other_param, param], level(1)),
+            (&[param, other_param], level(FEATURE_LEVELS.len() - 1)),
+            (&[param, b"hwcaps_loader.level=bogus"], level(1)),
+            (&[b"hwcaps_loader.level=bogus"], None),
+            (&[b"hwcaps_loader.level="], None),
+        ];
+
+        for (params, expected) in cases {
+            let mut buffer = [0u8; 256];
+            let mut len = 0;
+            for param in params.iter() {
+                buffer[len..len + param.len()].copy_from_slice(param);
+                buffer[len + param.len()] = b' ';
+                len += param.len() + 1;
+            }
+            // /proc/cmdline ends with a newline
+            if len > 0 {
+                buffer[len - 1] = b'\n';
+            }
+            assert!(parse(&buffer[..len]) == *expected, "{:?}", core::str::from_utf8(&buffer[..len]));
+        }
+    }
+
+    #[test]
+    fn truncated_cmdline() {
+        let cmdline = b"quiet hwcaps_loader=of";
+        assert!(complete(cmdline, cmdline.len()) == (&b"quiet"[..], true));
+        assert!(parse(complete(b"hwcaps_loader=of", 16).0).is_none());
+        assert!(complete(b"hwcaps_loader=off\n", 32) == (&b"hwcaps_loader=off\n"[..], false));
+    }
+}
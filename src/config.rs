@@ -0,0 +1,1011 @@
+/*
+   Configuration files
+   Like systemd, configuration is layered between vendor defaults (/usr/lib), runtime
+   configuration (/run) and admin overrides (/etc), in increasing order of priority:
+   - hwcaps-loader.conf is read from the first of /etc, /run and /usr/lib where it exists, so an
+     admin's file replaces the vendor's one as a whole.
+   - Drop-ins (hwcaps-loader.conf.d/<name>.conf) are read from all three directories, after the main
+     file, in lexical order of their names. A drop-in replaces drop-ins with the same name in
+     lower priority directories. Empty drop-ins (or links to /dev/null) can be used to mask them.
+   Files are made of "key = value" lines. Lines starting with '#' are comments.
+
+   Settings can be placed in sections, which only apply to matching machines or programs.
+   This allows a single image to carry different policies for different deployments:
+   - [<arch>]           -> The loader was built for <arch> (x86_64, x86...)
+   - [program:<name>]   -> The program being executed is called <name>
+   - [hostname:<name>]  -> The machine's hostname is <name>
+   - [machine-id:<id>]  -> /etc/machine-id contains <id>
+   - [container:<name>] -> We're running inside of a <name> container (docker, podman, lxc...)
+   - [hypervisor:<name>] -> We're running under a <name> hypervisor (kvm, hyperv, vmware, xen...),
+                            or any hypervisor if <name> is "any" (vm_policy)
+   Global settings must come before any section. Settings in matching sections override
+   global ones, and later sections override earlier ones.
+   For example, live-migrated guests can be kept to levels every host in the pool supports with
+   "[hypervisor:any]" and "max_level = x86-64-v2", while a later section (e.g. [hostname:<name>])
+   lifts the limit for guests which never migrate.
+
+   Supported keys:
+   - max_level = <name> -> Don't consider feature levels above <name> (e.g. x86-64-v2)
+   - latency_socket = <path> -> Send exec latency reports to the unix socket at <path> (latency_report)
+   - allowed_interpreter = <path> -> Allow candidates with the interpreter at <path> (interp_check).
+                                    Can be repeated, and replaces the default list. An empty value
+                                    clears the list.
+   - signing_key = <hex>  -> Check candidates' signatures with this Ed25519 public key, unless
+                             one was compiled in (signatures)
+   - device_mismatch = <abort|warn> -> What to do if /usr/hwcaps isn't on the same filesystem as
+                                      /usr/bin (device_check)
+   - affinity = <pin|intersect> -> Whether the target is pinned to the CPUs which support its
+                                  level, or only levels every CPU supports are considered (affinity)
+   - speculation = <misfeature>:<mode> -> Set a speculation control (e.g. store_bypass:disable)
+                                         before executing the target. Can be repeated, for
+                                         different misfeatures (speculation_ctrl)
+   - min_microcode = <cpu>:<revision>:<features> -> Consider features missing on a CPU model unless
+                                                   its microcode is at least <revision>
+                                                   (e.g. 06-55-04:0x2006e05:avx512f). Can be
+                                                   repeated (microcode_gate)
+   - microarch = <cpu>:<name>:<level> -> Try /usr/hwcaps/<name> before any level on a CPU model,
+                                        if the machine supports <level>
+                                        (e.g. 19-61:znver4:x86-64-v4). Can be repeated, and
+                                        takes precedence over built-in models (microarch_dirs)
+   - extra_dir = <path> -> Try candidates in <path>, laid out like /usr/hwcaps, before the
+                           system's (extra_dir)
+   - on_error = <abort|baseline> -> What to do when finding the best candidate fails for reasons
+                                   other than security, such as IO errors (resilience)
+   - pre_exec_hook = <path> -> Run the program at <path> (absolute) before executing the
+                              candidate. An empty value removes it (pre_exec_hook)
+   - pre_exec_hook_timeout = <ms>, pre_exec_hook_failure = <continue|abort> -> How long the hook
+                              may take, and what to do if it fails (pre_exec_hook)
+   - rlimit = [<level>:]<resource>:<limit> -> Set a resource's soft limit for candidates (of
+                                             <level> only, if given), e.g. x86-64-v4:stack:64m.
+                                             Can be repeated (rlimits)
+   - canary = <level>:<percent> -> Only use candidates for <level> in <percent> of launches, to
+                                  roll it out gradually. Can be repeated (canary)
+   - failure_cooldown = <seconds> -> How long to skip candidates after a crash is recorded for
+                                    them (600 by default, 0 to never skip) (failure_memory)
+   - integrity = <lenient|strict> -> Whether candidates which exist but can't be executed are
+                                    skipped, or make the loader fail (strict_integrity)
+   - build_id_mismatch = <abort|warn> -> What to do if a candidate's build-ID doesn't match its
+                                        package's manifest (build_ids)
+   - include = <path>   -> Read the file at <path> (absolute) in place of this line. Its settings
+                           only apply if the section the directive is in does.
+
+   A file with any error is ignored as a whole (with a message on stderr), rather than failing
+   every program on the system.
+   "hwcaps-loader --check-config" checks every file (including replaced ones) and reports every
+   problem, so configuration can be validated before it's deployed.
+*/
+
+use core::ffi::{c_char, CStr};
+
+use crate::sys::{self, ExitCode};
+use crate::output::{self, abort, Fatal};
+use crate::capabilities;
+use crate::path;
+#[cfg(feature = "latency_report")]
+use crate::latency;
+#[cfg(feature = "interp_check")]
+use crate::interp;
+#[cfg(feature = "signatures")]
+use crate::signature;
+#[cfg(feature = "speculation_ctrl")]
+use crate::speculation;
+#[cfg(feature = "microcode_gate")]
+use crate::microcode;
+#[cfg(feature = "microarch_dirs")]
+use crate::microarch;
+#[cfg(feature = "extra_dir")]
+use crate::extra_dir;
+#[cfg(feature = "pre_exec_hook")]
+use crate::hook;
+#[cfg(feature = "rlimits")]
+use crate::rlimits;
+#[cfg(feature = "canary")]
+use crate::canary;
+#[cfg(feature = "failure_memory")]
+use crate::failures;
+
+// In decreasing order of priority
+#[cfg(not(target_os = "freebsd"))]
+pub const CONFIG_DIRS: [&[u8]; 3] = [b"/etc", b"/run", b"/usr/lib"];
+#[cfg(target_os = "freebsd")]
+pub const CONFIG_DIRS: [&[u8]; 3] = [b"/usr/local/etc", b"/var/run", b"/usr/local/lib"];
+pub const CONFIG_NAME: &[u8] = b"/hwcaps-loader.conf";
+pub const DROPIN_DIR: &[u8] = b"/hwcaps-loader.conf.d";
+const DROPIN_SUFFIX: &[u8] = b".conf";
+
+const CONFIG_MAX: usize = 8192;
+const NAME_MAX: usize = 255;
+const MAX_DROPINS: usize = 32;
+const MAX_INCLUDE_DEPTH: u32 = 4;
+
+const MACHINE_ID_LEN: usize = 32;
+
+// Everything configuration reads from the system. Tests replace it with in-memory files.
+#[cfg(not(test))]
+mod system {
+    use core::ffi::CStr;
+    use crate::sys::{self, Errno};
+
+    pub use sys::{read_file, uname};
+
+    // Calls f with the name of every entry of the directory at path
+    pub fn read_dir(path: &CStr, f: impl FnMut(&[u8])) -> Result<(), Errno> {
+        let fd = sys::openat(sys::AT_FDCWD, path, sys::O_RDONLY | sys::O_DIRECTORY)?;
+        let result = sys::read_dir(fd, f);
+        let _ = sys::close(fd);
+        result
+    }
+
+    pub fn exists(path: &CStr) -> bool {
+        match sys::openat(sys::AT_FDCWD, path, sys::O_PATH) {
+            Ok(fd) => {
+                let _ = sys::close(fd);
+                true
+            },
+            Err(_) => false
+        }
+    }
+}
+#[cfg(test)]
+use tests::system;
+
+#[derive(Clone, Copy)]
+pub struct Config {
+    pub max_level: Option<u32>,
+    #[cfg(feature = "latency_report")]
+    pub latency_socket: Option<latency::SocketPath>,
+    #[cfg(feature = "interp_check")]
+    pub interpreters: Option<interp::Interpreters>,
+    #[cfg(feature = "signatures")]
+    pub signing_key: Option<signature::PublicKey>,
+    #[cfg(feature = "device_check")]
+    pub device_mismatch_warn: bool,
+    #[cfg(feature = "affinity")]
+    pub affinity_intersect: bool,
+    #[cfg(feature = "speculation_ctrl")]
+    pub speculation: speculation::Settings,
+    #[cfg(feature = "microcode_gate")]
+    pub microcode: microcode::Rules,
+    #[cfg(feature = "microarch_dirs")]
+    pub microarch: microarch::Rules,
+    #[cfg(feature = "extra_dir")]
+    pub extra_dir: Option<extra_dir::ExtraDir>,
+    #[cfg(feature = "resilience")]
+    pub on_error_baseline: bool,
+    #[cfg(feature = "pre_exec_hook")]
+    pub pre_exec_hook: hook::Settings,
+    #[cfg(feature = "rlimits")]
+    pub rlimits: rlimits::Rules,
+    #[cfg(feature = "canary")]
+    pub canary: canary::Rules,
+    #[cfg(feature = "failure_memory")]
+    pub failure_cooldown: u32,
+    #[cfg(feature = "strict_integrity")]
+    pub strict_integrity: bool,
+    #[cfg(feature = "build_ids")]
+    pub build_id_mismatch_warn: bool,
+}
+
+impl Config {
+    fn new() -> Self {
+        Config {
+            max_level: None,
+            #[cfg(feature = "latency_report")]
+            latency_socket: None,
+            #[cfg(feature = "interp_check")]
+            interpreters: None,
+            #[cfg(feature = "signatures")]
+            signing_key: None,
+            #[cfg(feature = "device_check")]
+            device_mismatch_warn: false,
+            #[cfg(feature = "affinity")]
+            affinity_intersect: false,
+            #[cfg(feature = "speculation_ctrl")]
+            speculation: speculation::Settings::default(),
+            #[cfg(feature = "microcode_gate")]
+            microcode: microcode::Rules::default(),
+            #[cfg(feature = "microarch_dirs")]
+            microarch: microarch::Rules::default(),
+            #[cfg(feature = "extra_dir")]
+            extra_dir: None,
+            #[cfg(feature = "resilience")]
+            on_error_baseline: false,
+            #[cfg(feature = "pre_exec_hook")]
+            pre_exec_hook: hook::Settings::default(),
+            #[cfg(feature = "rlimits")]
+            rlimits: rlimits::Rules::default(),
+            #[cfg(feature = "canary")]
+            canary: canary::Rules::default(),
+            #[cfg(feature = "failure_memory")]
+            failure_cooldown: failures::DEFAULT_COOLDOWN,
+            #[cfg(feature = "strict_integrity")]
+            strict_integrity: false,
+            #[cfg(feature = "build_ids")]
+            build_id_mismatch_warn: false
+        }
+    }
+}
+
+// Information about the machine, only gathered if a section needs it
+struct Host<'a> {
+    envp: *const *const c_char,
+    program: &'a [u8],
+    machine_id: Option<[u8; MACHINE_ID_LEN]>,
+    // None until a section asks about it, since CPUID is slow under hypervisors
+    #[cfg(feature = "vm_policy")]
+    hypervisor: Option<Option<&'static [u8]>>,
+}
+
+impl Host<'_> {
+    fn hostname_is(&self, name: &[u8]) -> bool {
+        let mut uts = unsafe { core::mem::zeroed::<sys::utsname>() };
+        if system::uname(&mut uts).is_err() {
+            return false
+        }
+
+        let nodename = unsafe { CStr::from_ptr(uts.nodename.as_ptr()) };
+        nodename.to_bytes() == name
+    }
+
+    fn machine_id_is(&mut self, id: &[u8]) -> bool {
+        if self.machine_id.is_none() {
+            let mut buffer = [0u8; MACHINE_ID_LEN];
+            if system::read_file(c"/etc/machine-id", &mut buffer) != Ok(MACHINE_ID_LEN) {
+                return false
+            }
+            self.machine_id = Some(buffer);
+        }
+
+        match &self.machine_id {
+            Some(machine_id) => machine_id == id,
+            None => false
+        }
+    }
+
+    fn container_is(&self, name: &[u8]) -> bool {
+        // Set by systemd-nspawn, lxc, podman and most other runtimes. The caller of a setuid alias
+        // controls the environment, so it could pick the section, and only files count then.
+        if !sys::is_secure() {
+            if let Some(container) = sys::getenv(self.envp, b"container") {
+                return container == name
+            }
+        }
+
+        // Written by systemd inside of containers, from the same variable
+        let mut buffer = [0u8; 64];
+        if let Ok(len) = system::read_file(c"/run/systemd/container", &mut buffer) {
+            if buffer[..len].trim_ascii() == name {
+                return true
+            }
+        }
+
+        let marker = match name {
+            b"podman" => c"/run/.containerenv",
+            b"docker" => c"/.dockerenv",
+            _ => return false
+        };
+
+        system::exists(marker)
+    }
+
+    #[cfg(feature = "vm_policy")]
+    fn hypervisor_is(&mut self, name: &[u8]) -> bool {
+        let hypervisor = *self.hypervisor.get_or_insert_with(capabilities::get_hypervisor);
+
+        match hypervisor {
+            Some(h) => name == b"any" || name == h,
+            None => false
+        }
+    }
+
+    fn matches(&mut self, section: &[u8]) -> Option<bool> {
+        let separator = match section.iter().position(|c| *c == b':') {
+            Some(s) => s,
+            // Sections without a kind are architecture sections
+            None => return Some(section == capabilities::ARCH_NAME)
+        };
+        let (kind, value) = (&section[..separator], &section[separator+1..]);
+
+        match kind {
+            b"program" => Some(value == self.program),
+            b"hostname" => Some(self.hostname_is(value)),
+            b"machine-id" => Some(self.machine_id_is(value)),
+            b"container" => Some(self.container_is(value)),
+            #[cfg(feature = "vm_policy")]
+            b"hypervisor" => Some(self.hypervisor_is(value)),
+            _ => None
+        }
+    }
+}
+
+#[cold]
+fn ignored(_msg: &'static str, _path: &CStr) {
+    #[cfg(feature = "error_output")]
+    let _ = output::print_to(sys::STDERR, None, _msg, 0, Some(_path.to_bytes()));
+}
+
+fn join<'a>(buffer: &'a mut [u8], parts: &[&[u8]]) -> &'a CStr {
+    match path::join(buffer, parts) {
+        Some(len) => unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) },
+        None => abort(&Fatal(ExitCode::ConfigInvalid, "Configuration path too large!"), 0, None)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DropIn {
+    // Index into CONFIG_DIRS
+    dir: usize,
+    name: [u8; NAME_MAX],
+    len: usize,
+}
+
+impl DropIn {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.len]
+    }
+}
+
+// Drop-ins from every directory, sorted by name
+struct DropIns {
+    entries: [DropIn; MAX_DROPINS],
+    count: usize,
+}
+
+impl DropIns {
+    // If all is set, drop-ins replaced by higher priority ones are kept too
+    fn collect(all: bool) -> Self {
+        let mut dropins = DropIns {
+            entries: [DropIn { dir: 0, name: [0; NAME_MAX], len: 0 }; MAX_DROPINS],
+            count: 0
+        };
+
+        for (dir, prefix) in CONFIG_DIRS.iter().enumerate() {
+            let mut buffer = [0u8; sys::PATH_MAX as usize];
+            let path = join(&mut buffer, &[prefix, DROPIN_DIR]);
+
+            match system::read_dir(path, |name| dropins.insert(dir, name, path, all)) {
+                Ok(()) => (),
+                Err(e) if e.into_raw() as u32 == sys::ENOENT => continue,
+                Err(e) => abort(&Fatal(ExitCode::ConfigIOError, "Failed to read configuration directory!"), e.into_raw() as u32, Some(path.to_bytes()))
+            }
+        }
+
+        dropins
+    }
+
+    fn insert(&mut self, dir: usize, name: &[u8], dir_path: &CStr, all: bool) {
+        if name.len() <= DROPIN_SUFFIX.len() || !name.ends_with(DROPIN_SUFFIX) || name[0] == b'.' {
+            return
+        }
+
+        // Directories are read in decreasing order of priority, so the first one wins
+        let position = self.entries[..self.count].partition_point(|entry| entry.name() <= name);
+        if !all && position > 0 && self.entries[position-1].name() == name {
+            return
+        }
+
+        if self.count == MAX_DROPINS {
+            if all {
+                abort(&Fatal(ExitCode::ConfigInvalid, "Too many configuration drop-ins!"), 0, Some(dir_path.to_bytes()))
+            }
+            ignored("Too many configuration drop-ins, ignoring some of them!", dir_path);
+            return
+        }
+
+        self.entries.copy_within(position..self.count, position + 1);
+        self.count += 1;
+
+        let entry = &mut self.entries[position];
+        entry.dir = dir;
+        entry.name[..name.len()].copy_from_slice(name);
+        entry.len = name.len();
+    }
+}
+
+struct Parser<'a> {
+    host: Host<'a>,
+    config: Config,
+    // Report every error instead of aborting at the first one
+    check: bool,
+    errors: u32,
+}
+
+impl Parser<'_> {
+    // line is 0 for errors which aren't about a specific line
+    fn error(&mut self, path: &CStr, line: u32, message: &[u8]) {
+        self.errors += 1;
+
+        if !self.check {
+            return ignored("Ignoring invalid configuration file!", path)
+        }
+
+        let mut line_buffer = [0u8; 16];
+        let line_len = path::itoa(line, &mut line_buffer);
+        let location: &[u8] = if line == 0 { b"" } else { &line_buffer[..line_len] };
+        let separator: &[u8] = if line == 0 { b"" } else { b":" };
+
+        let mut buffer = [0u8; sys::PATH_MAX as usize + 64];
+        let len = path::join(&mut buffer, &[path.to_bytes(), separator, location, b": ", message]).unwrap_or(0);
+        output::print_line(&buffer[..len]);
+    }
+
+    // Returns false if the file doesn't exist.
+    // Settings outside of sections only apply if scope is true.
+    fn parse_file(&mut self, path: &CStr, scope: bool, depth: u32) -> bool {
+        let mut buffer = [0u8; CONFIG_MAX];
+        let len = match system::read_file(path, &mut buffer) {
+            Ok(l) => l,
+            Err(e) if e.into_raw() as u32 == sys::ENOENT => return false,
+            Err(e) => abort(&Fatal(ExitCode::ConfigIOError, "Failed to read configuration file!"), e.into_raw() as u32, Some(path.to_bytes()))
+        };
+
+        if len == buffer.len() {
+            self.error(path, 0, b"file too large");
+            return true
+        }
+
+        let mut active = scope;
+        // Restored if the file turns out to be invalid, so none of it applies
+        let saved = self.config;
+
+        for (i, line) in buffer[..len].split(|c| *c == b'\n').enumerate() {
+            if let Err(message) = self.parse_line(line.trim_ascii(), &mut active, scope, depth) {
+                self.error(path, i as u32 + 1, message);
+
+                if !self.check {
+                    self.config = saved;
+                    break
+                }
+            }
+        }
+
+        true
+    }
+
+    fn parse_line(&mut self, line: &[u8], active: &mut bool, scope: bool, depth: u32) -> Result<(), &'static [u8]> {
+        if line.is_empty() || line[0] == b'#' {
+            return Ok(())
+        }
+
+        if line[0] == b'[' {
+            if line.len() < 2 || line[line.len()-1] != b']' {
+                return Err(b"unterminated section header")
+            }
+
+            *active = match self.host.matches(&line[1..line.len()-1]) {
+                Some(m) => scope && m,
+                None => return Err(b"unknown section kind")
+            };
+            return Ok(())
+        }
+
+        let separator = line.iter().position(|c| *c == b'=').ok_or(b"expected \"key = value\"".as_slice())?;
+        let key = line[..separator].trim_ascii();
+        let value = line[separator+1..].trim_ascii();
+
+        match key {
+            b"max_level" => {
+                let level = capabilities::find_feature_level(value).ok_or(b"unknown feature level".as_slice())?;
+
+                if *active {
+                    self.config.max_level = Some(level);
+                }
+            },
+            #[cfg(feature = "latency_report")]
+            b"latency_socket" => {
+                let socket = latency::SocketPath::new(value).ok_or(b"invalid socket path".as_slice())?;
+
+                if *active {
+                    self.config.latency_socket = Some(socket);
+                }
+            },
+            #[cfg(feature = "interp_check")]
+            b"allowed_interpreter" => {
+                let mut interpreters = match value.is_empty() {
+                    true => interp::Interpreters::new(),
+                    false => self.config.interpreters.unwrap_or(interp::Interpreters::new())
+                };
+                if !value.is_empty() && interpreters.push(value).is_err() {
+                    return Err(b"invalid interpreter path, or too many interpreters")
+                }
+
+                if *active {
+                    self.config.interpreters = Some(interpreters);
+                }
+            },
+            #[cfg(feature = "signatures")]
+            b"signing_key" => {
+                let key = signature::PublicKey::from_hex(value).ok_or(b"invalid key, expected 64 hex digits".as_slice())?;
+
+                if *active {
+                    self.config.signing_key = Some(key);
+                }
+            },
+            #[cfg(feature = "device_check")]
+            b"device_mismatch" => {
+                let warn = match value {
+                    b"abort" => false,
+                    b"warn" => true,
+                    _ => return Err(b"expected \"abort\" or \"warn\"")
+                };
+
+                if *active {
+                    self.config.device_mismatch_warn = warn;
+                }
+            },
+            #[cfg(feature = "affinity")]
+            b"affinity" => {
+                let intersect = match value {
+                    b"pin" => false,
+                    b"intersect" => true,
+                    _ => return Err(b"expected \"pin\" or \"intersect\"")
+                };
+
+                if *active {
+                    self.config.affinity_intersect = intersect;
+                }
+            },
+            #[cfg(feature = "speculation_ctrl")]
+            b"speculation" => {
+                let mut settings = self.config.speculation;
+                settings.set(value).ok_or(b"expected \"<misfeature>:<mode>\"".as_slice())?;
+
+                if *active {
+                    self.config.speculation = settings;
+                }
+            },
+            #[cfg(feature = "microcode_gate")]
+            b"min_microcode" => {
+                let mut rules = self.config.microcode;
+                rules.push(value).ok_or(b"expected \"<cpu>:<revision>:<features>\", or too many rules".as_slice())?;
+
+                if *active {
+                    self.config.microcode = rules;
+                }
+            },
+            #[cfg(feature = "microarch_dirs")]
+            b"microarch" => {
+                let mut rules = self.config.microarch;
+                rules.push(value).ok_or(b"expected \"<cpu>:<name>:<level>\", or too many rules".as_slice())?;
+
+                if *active {
+                    self.config.microarch = rules;
+                }
+            },
+            #[cfg(feature = "extra_dir")]
+            b"extra_dir" => {
+                let directory = extra_dir::ExtraDir::new(value).ok_or(b"invalid directory path".as_slice())?;
+
+                if *active {
+                    self.config.extra_dir = Some(directory);
+                }
+            },
+            #[cfg(feature = "resilience")]
+            b"on_error" => {
+                let baseline = match value {
+                    b"abort" => false,
+                    b"baseline" => true,
+                    _ => return Err(b"expected \"abort\" or \"baseline\"")
+                };
+
+                if *active {
+                    self.config.on_error_baseline = baseline;
+                }
+            },
+            #[cfg(feature = "pre_exec_hook")]
+            b"pre_exec_hook" | b"pre_exec_hook_timeout" | b"pre_exec_hook_failure" => {
+                let mut settings = self.config.pre_exec_hook;
+                match key {
+                    b"pre_exec_hook" => settings.set_path(value).ok_or(b"hook path must be absolute".as_slice())?,
+                    b"pre_exec_hook_timeout" => settings.set_timeout(value).ok_or(b"expected a timeout in milliseconds".as_slice())?,
+                    _ => settings.set_failure(value).ok_or(b"expected \"continue\" or \"abort\"".as_slice())?
+                }
+
+                if *active {
+                    self.config.pre_exec_hook = settings;
+                }
+            },
+            #[cfg(feature = "rlimits")]
+            b"rlimit" => {
+                let mut rules = self.config.rlimits;
+                rules.push(value).ok_or(b"expected \"[<level>:]<resource>:<limit>\", or too many rules".as_slice())?;
+
+                if *active {
+                    self.config.rlimits = rules;
+                }
+            },
+            #[cfg(feature = "canary")]
+            b"canary" => {
+                let mut rules = self.config.canary;
+                rules.push(value).ok_or(b"expected \"<level>:<percent>\", or too many rules".as_slice())?;
+
+                if *active {
+                    self.config.canary = rules;
+                }
+            },
+            #[cfg(feature = "failure_memory")]
+            b"failure_cooldown" => {
+                let cooldown = path::atoi(value).ok_or(b"expected a number of seconds".as_slice())?;
+
+                if *active {
+                    self.config.failure_cooldown = cooldown;
+                }
+            },
+            #[cfg(feature = "strict_integrity")]
+            b"integrity" => {
+                let strict = match value {
+                    b"lenient" => false,
+                    b"strict" => true,
+                    _ => return Err(b"expected \"lenient\" or \"strict\"")
+                };
+
+                if *active {
+                    self.config.strict_integrity = strict;
+                }
+            },
+            #[cfg(feature = "build_ids")]
+            b"build_id_mismatch" => {
+                let warn = match value {
+                    b"abort" => false,
+                    b"warn" => true,
+                    _ => return Err(b"expected \"abort\" or \"warn\"")
+                };
+
+                if *active {
+                    self.config.build_id_mismatch_warn = warn;
+                }
+            },
+            b"include" => {
+                if value.first() != Some(&b'/') {
+                    return Err(b"included path must be absolute")
+                }
+                if value.len() >= sys::PATH_MAX as usize {
+                    return Err(b"included path too long")
+                }
+                if depth == MAX_INCLUDE_DEPTH {
+                    return Err(b"includes nested too deeply")
+                }
+
+                let mut include_buffer = [0u8; sys::PATH_MAX as usize];
+                let include = join(&mut include_buffer, &[value]);
+
+                if !self.parse_file(include, *active, depth + 1) {
+                    return Err(b"included file doesn't exist")
+                }
+            },
+            _ => return Err(b"unknown key")
+        }
+
+        Ok(())
+    }
+
+    fn parse_all(&mut self) {
+        for dir in CONFIG_DIRS {
+            let mut buffer = [0u8; sys::PATH_MAX as usize];
+            // Only the first main file is used, but every one of them is checked
+            if self.parse_file(join(&mut buffer, &[dir, CONFIG_NAME]), true, 0) && !self.check {
+                break
+            }
+        }
+
+        let dropins = DropIns::collect(self.check);
+        for dropin in &dropins.entries[..dropins.count] {
+            let mut buffer = [0u8; sys::PATH_MAX as usize];
+            let path = join(&mut buffer, &[CONFIG_DIRS[dropin.dir], DROPIN_DIR, b"/", dropin.name()]);
+            self.parse_file(path, true, 0);
+        }
+    }
+}
+
+// program is the name of the program being executed (e.g. "foo")
+pub fn load(envp: *const *const c_char, program: &[u8]) -> Config {
+    let mut parser = Parser {
+        host: Host {
+            envp,
+            program,
+            machine_id: None,
+            #[cfg(feature = "vm_policy")]
+            hypervisor: None
+        },
+        config: Config::new(),
+        check: false,
+        errors: 0
+    };
+
+    parser.parse_all();
+    parser.config
+}
+
+// Checks the configuration, for "hwcaps-loader --check-config".
+// Every problem found is printed as "<path>:<line>: <problem>".
+pub fn run(envp: *const *const c_char) -> ! {
+    let mut parser = Parser {
+        host: Host {
+            envp,
+            program: b"",
+            machine_id: None,
+            #[cfg(feature = "vm_policy")]
+            hypervisor: None
+        },
+        config: Config::new(),
+        check: true,
+        errors: 0
+    };
+
+    parser.parse_all();
+
+    if parser.errors != 0 {
+        abort(&Fatal(ExitCode::ConfigInvalid, "Configuration has errors!"), 0, None)
+    }
+    sys::exit(0)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{ffi::{c_char, CStr}, ptr};
+    use std::{cell::RefCell, format, string::String, vec::Vec};
+    use super::{load, DropIns, Host, CONFIG_DIRS, CONFIG_NAME, DROPIN_DIR, MAX_DROPINS};
+    use crate::capabilities::{ARCH_NAME, FEATURE_LEVELS};
+
+    std::thread_local! {
+        // Path and contents of every file the configuration can read
+        static FILES: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+        static HOSTNAME: RefCell<String> = const { RefCell::new(String::new()) };
+    }
+
+    pub mod system {
+        use core::ffi::CStr;
+        use super::{FILES, HOSTNAME, String};
+        use crate::sys::{utsname, Errno};
+
+        fn path_str(path: &CStr) -> &str {
+            path.to_str().unwrap()
+        }
+
+        pub fn read_file(path: &CStr, buffer: &mut [u8]) -> Result<usize, Errno> {
+            FILES.with_borrow(|files| {
+                let (_, contents) = files.iter().find(|(p, _)| p == path_str(path)).ok_or(Errno::ENOENT)?;
+                let len = contents.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&contents.as_bytes()[..len]);
+                Ok(len)
+            })
+        }
+
+        pub fn read_dir(path: &CStr, mut f: impl FnMut(&[u8])) -> Result<(), Errno> {
+            let prefix = String::from(path_str(path)) + "/";
+            let mut found = false;
+
+            FILES.with_borrow(|files| {
+                for (p, _) in files.iter() {
+                    if let Some(name) = p.strip_prefix(&prefix) {
+                        found = true;
+                        f(name.as_bytes());
+                    }
+                }
+            });
+            if found { Ok(()) } else { Err(Errno::ENOENT) }
+        }
+
+        pub fn exists(path: &CStr) -> bool {
+            FILES.with_borrow(|files| files.iter().any(|(p, _)| p == path_str(path)))
+        }
+
+        pub fn uname(buffer: &mut utsname) -> Result<usize, Errno> {
+            HOSTNAME.with_borrow(|hostname| {
+                for (i, c) in hostname.bytes().chain([0]).enumerate() {
+                    buffer.nodename[i] = c as _;
+                }
+            });
+            Ok(0)
+        }
+    }
+
+    // dir is an index into CONFIG_DIRS, and name is the name of a drop-in, or empty for the
+    // main file
+    fn path(dir: usize, name: &str) -> String {
+        let dir = core::str::from_utf8(CONFIG_DIRS[dir]).unwrap();
+        match name.is_empty() {
+            true => format!("{}{}", dir, core::str::from_utf8(CONFIG_NAME).unwrap()),
+            false => format!("{}{}/{}", dir, core::str::from_utf8(DROPIN_DIR).unwrap(), name)
+        }
+    }
+
+    // Replaces the files of the current test
+    fn set_files(files: &[(usize, &str, String)]) {
+        FILES.set(files.iter().map(|(dir, name, contents)| (path(*dir, name), contents.clone())).collect());
+    }
+
+    // Not every architecture has many levels, so they're reused there
+    fn level(i: usize) -> u32 {
+        i.min(FEATURE_LEVELS.len() - 1) as u32
+    }
+
+    fn max_level(i: usize) -> String {
+        format!("max_level = {}\n", core::str::from_utf8(FEATURE_LEVELS[level(i) as usize].name).unwrap())
+    }
+
+    fn loaded_level(program: &[u8]) -> Option<u32> {
+        let envp: [*const c_char; 1] = [ptr::null()];
+        load(envp.as_ptr(), program).max_level
+    }
+
+    #[test]
+    fn layering() {
+        let (etc, run, usr) = (0, 1, 2);
+
+        let cases: &[(&[(usize, &str, String)], Option<usize>)] = &[
+            (&[], None),
+            // The first main file replaces the others as a whole
+            (&[(etc, "", max_level(1)), (run, "", max_level(2)), (usr, "", max_level(3))], Some(1)),
+            (&[(run, "", max_level(2)), (usr, "", max_level(3))], Some(2)),
+            (&[(usr, "", max_level(3))], Some(3)),
+            (&[(etc, "", String::new()), (usr, "", max_level(3))], None),
+            // Invalid files are ignored, but still replace lower priority ones
+            (&[(etc, "", max_level(1) + "bogus = 1\n"), (usr, "", max_level(3))], None),
+            // Drop-ins are read after the main file, from every directory
+            (&[(etc, "", max_level(1)), (usr, "10-a.conf", max_level(2))], Some(2)),
+            (&[(usr, "", max_level(1)), (run, "10-a.conf", max_level(2))], Some(2)),
+            // Settings before an invalid line don't apply either
+            (&[(usr, "", max_level(1)), (etc, "10-a.conf", max_level(2) + "bogus = 1\n")], Some(1)),
+            (&[(usr, "", max_level(1)), (etc, "10-a.conf", max_level(2) + "bogus = 1\n"), (usr, "20-b.conf", max_level(3))], Some(3)),
+        ];
+
+        for (i, (files, expected)) in cases.iter().enumerate() {
+            set_files(files);
+            assert!(loaded_level(b"foo") == expected.map(level), "case {}", i);
+        }
+    }
+
+    #[test]
+    fn dropin_order() {
+        let (etc, run, usr) = (0, 1, 2);
+
+        let cases: &[(&[(usize, &str, String)], Option<usize>)] = &[
+            // In lexical order of their names, whatever directory they're in
+            (&[(etc, "10-a.conf", max_level(1)), (usr, "20-b.conf", max_level(2))], Some(2)),
+            (&[(usr, "10-a.conf", max_level(1)), (etc, "20-b.conf", max_level(2))], Some(2)),
+            (&[(etc, "20-b.conf", max_level(1)), (usr, "10-a.conf", max_level(2))], Some(1)),
+            // Higher priority directories replace drop-ins with the same name
+            (&[(usr, "10-a.conf", max_level(1)), (etc, "10-a.conf", max_level(2))], Some(2)),
+            (&[(etc, "10-a.conf", max_level(1)), (run, "10-a.conf", max_level(2))], Some(1)),
+            (&[(usr, "10-a.conf", max_level(1)), (usr, "20-b.conf", max_level(2)), (etc, "20-b.conf", String::new())], Some(1)),
+            // Only visible .conf files are drop-ins
+            (&[(usr, "10-a.conf", max_level(1)), (etc, "20-b.txt", max_level(2)), (etc, ".30-c.conf", max_level(3))], Some(1)),
+            (&[(usr, "10-a.conf", max_level(1)), (etc, ".conf", max_level(2))], Some(1)),
+        ];
+
+        for (i, (files, expected)) in cases.iter().enumerate() {
+            set_files(files);
+            assert!(loaded_level(b"foo") == expected.map(level), "case {}", i);
+        }
+    }
+
+    #[test]
+    fn dropin_limit() {
+        FILES.set((0..MAX_DROPINS + 8).rev().map(|i| (path(i % 3, &format!("{:02}.conf", i)), String::new())).collect());
+
+        // Further drop-ins are ignored, and the ones kept are still sorted
+        let dropins = DropIns::collect(false);
+        assert_eq!(dropins.count, MAX_DROPINS);
+        let names = &dropins.entries[..dropins.count];
+        assert!(names.windows(2).all(|pair| pair[0].name() < pair[1].name()));
+    }
+
+    #[test]
+    fn include_depth() {
+        // The main file includes /inc/1, which includes /inc/2... until /inc/<depth> sets the level
+        for (depth, expected) in [(1, Some(2)), (4, Some(2)), (5, None)] {
+            set_files(&[(0, "", String::from("include = /inc/1\n"))]);
+            FILES.with_borrow_mut(|table| {
+                for i in 1..depth {
+                    table.push((format!("/inc/{}", i), format!("include = /inc/{}\n", i + 1)));
+                }
+                table.push((format!("/inc/{}", depth), max_level(2)));
+            });
+            assert!(loaded_level(b"foo") == expected.map(level), "depth {}", depth);
+        }
+
+        // Missing and relative includes are errors
+        set_files(&[(0, "", max_level(1) + "include = /inc/missing\n")]);
+        assert!(loaded_level(b"foo").is_none());
+        set_files(&[(0, "", max_level(1) + "include = inc/1\n")]);
+        assert!(loaded_level(b"foo").is_none());
+    }
+
+    #[test]
+    fn section_matching() {
+        let machine_id = b"machine-id:0123456789abcdef0123456789abcdef";
+        HOSTNAME.set(String::from("builder01"));
+
+        let cases: &[(&[&CStr], &[(&str, &str)], &[u8], Option<bool>)] = &[
+            (&[], &[], ARCH_NAME, Some(true)),
+            (&[], &[], b"sparc", Some(false)),
+            (&[], &[], b"program:foo", Some(true)),
+            (&[], &[], b"program:fo", Some(false)),
+            (&[], &[], b"hostname:builder01", Some(true)),
+            (&[], &[], b"hostname:builder0", Some(false)),
+            (&[], &[("/etc/machine-id", "0123456789abcdef0123456789abcdef\n")], machine_id, Some(true)),
+            (&[], &[("/etc/machine-id", "fedcba9876543210fedcba9876543210\n")], machine_id, Some(false)),
+            (&[], &[("/etc/machine-id", "0123456789abcdef")], machine_id, Some(false)),
+            (&[], &[], machine_id, Some(false)),
+            // The variable is trusted over files
+            (&[c"container=podman"], &[], b"container:podman", Some(true)),
+            (&[c"container=lxc"], &[("/run/.containerenv", "")], b"container:podman", Some(false)),
+            (&[], &[("/run/systemd/container", "lxc\n")], b"container:lxc", Some(true)),
+            (&[], &[("/run/.containerenv", "")], b"container:podman", Some(true)),
+            (&[], &[("/.dockerenv", "")], b"container:docker", Some(true)),
+            (&[], &[("/.dockerenv", "")], b"container:podman", Some(false)),
+            (&[], &[], b"container:docker", Some(false)),
+            (&[], &[], b"bogus:foo", None),
+            (&[], &[], b":foo", None),
+        ];
+
+        for (i, (env, files, section, expected)) in cases.iter().enumerate() {
+            FILES.set(files.iter().map(|(path, contents)| (String::from(*path), String::from(*contents))).collect());
+            let envp: Vec<*const c_char> = env.iter().map(|var| var.as_ptr()).chain([ptr::null()]).collect();
+
+            let mut host = Host {
+                envp: envp.as_ptr(),
+                program: b"foo",
+                machine_id: None,
+                #[cfg(feature = "vm_policy")]
+                hypervisor: None
+            };
+            assert!(host.matches(section) == *expected, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn hypervisor_sections() {
+        let envp: [*const c_char; 1] = [ptr::null()];
+        let mut host = Host {
+            envp: envp.as_ptr(),
+            program: b"foo",
+            machine_id: None,
+            #[cfg(feature = "vm_policy")]
+            hypervisor: Some(Some(b"kvm"))
+        };
+
+        #[cfg(feature = "vm_policy")]
+        {
+            assert_eq!(host.matches(b"hypervisor:kvm"), Some(true));
+            assert_eq!(host.matches(b"hypervisor:any"), Some(true));
+            assert_eq!(host.matches(b"hypervisor:xen"), Some(false));
+
+            host.hypervisor = Some(None);
+            assert_eq!(host.matches(b"hypervisor:kvm"), Some(false));
+            assert_eq!(host.matches(b"hypervisor:any"), Some(false));
+        }
+        #[cfg(not(feature = "vm_policy"))]
+        assert_eq!(host.matches(b"hypervisor:kvm"), None);
+    }
+
+    #[test]
+    fn section_scope() {
+        HOSTNAME.set(String::from("builder01"));
+
+        let cases: &[(String, &[u8], Option<usize>)] = &[
+            (max_level(1) + "[program:foo]\n" + &max_level(2), b"foo", Some(2)),
+            (max_level(1) + "[program:foo]\n" + &max_level(2), b"bar", Some(1)),
+            // Later sections override earlier ones, and global settings can't come back
+            (max_level(1) + "[program:foo]\n" + &max_level(2) + "[hostname:builder01]\n" + &max_level(3), b"foo", Some(3)),
+            (max_level(1) + "[hostname:builder01]\n" + &max_level(3) + "[program:foo]\n" + &max_level(2), b"foo", Some(2)),
+            (max_level(1) + "[program:bar]\n" + &max_level(2) + &max_level(3), b"foo", Some(1)),
+            // Includes only apply if their section does
+            (max_level(1) + "[program:bar]\ninclude = /inc/1\n", b"foo", Some(1)),
+            (max_level(1) + "[program:foo]\ninclude = /inc/1\n", b"foo", Some(3)),
+            // Invalid headers make the file invalid
+            (max_level(1) + "[program:foo\n", b"foo", None),
+            (max_level(1) + "[bogus:foo]\n", b"foo", None),
+            (max_level(1) + "[\n", b"foo", None),
+        ];
+
+        for (i, (contents, program, expected)) in cases.iter().enumerate() {
+            set_files(&[(0, "", contents.clone())]);
+            FILES.with_borrow_mut(|files| files.push((String::from("/inc/1"), max_level(3))));
+            assert!(loaded_level(program) == expected.map(level), "case {}", i);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+/*
+   Resolution daemon.
+   In exec-heavy environments (CI runners, build farms), every execution repeats feature level
+   detection and walks the candidates from the highest level down. When executed as
+   "hwcaps-loader --daemon", the loader instead stays in the foreground and answers queries on
+   /run/hwcaps-loader/resolve.sock. Detection is done once, and which level directories exist is
+   cached until the hwcaps directory changes.
+   Queries are a single packet, "<max level> <path>", where the path is relative to /usr
+   (e.g. "x86-64-v3 /bin/foo"). The answer is the directory of the best candidate
+   (e.g. "x86-64-v2+bigmem"), or nothing if there are none.
+   The loader only uses the answer as a starting point: it's capped to the loader's own level and
+   candidates are still tried in the usual order from there. Without a daemon (or an answer in
+   time), the loader resolves the program by itself. Answers only count if the daemon runs as the
+   owner of /run/hwcaps-loader (e.g. through RuntimeDirectory=) or as root, so other users can't
+   stand in for it.
+   Clients are served from a single poll() loop, so one which doesn't send anything only takes up
+   a slot until its timeout, instead of holding up the others.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::output::{abort, Fatal};
+use crate::capabilities::{self, FEATURE_LEVELS};
+use crate::variants::Variants;
+use crate::HWCAPS_PATH;
+
+const RUN_PATH: &'static CStr = c"/run/hwcaps-loader";
+pub const SOCKET_PATH: &'static CStr = c"/run/hwcaps-loader/resolve.sock";
+const QUERY_MAX: usize = sys::PATH_MAX as usize + 64;
+const ANSWER_MAX: usize = 64;
+const BACKLOG: u32 = 128;
+
+// The daemon answers right away or not at all, so the loader doesn't wait for long
+const QUERY_TIMEOUT_MS: i32 = 50;
+// Clients which connect but don't send anything are dropped after this long
+const CLIENT_TIMEOUT_MS: u64 = 100;
+// Clients served at once. Past that, the one which connected first is dropped to make room.
+const CLIENTS_MAX: usize = 64;
+
+fn get_address() -> sys::sockaddr_un {
+    let mut address = unsafe { core::mem::zeroed::<sys::sockaddr_un>() };
+    address.sun_family = sys::AF_UNIX as sys::sa_family_t;
+
+    for (dst, src) in address.sun_path.iter_mut().zip(SOCKET_PATH.to_bytes()) {
+        *dst = *src as _;
+    }
+    address
+}
+
+// Whether fd becomes readable within timeout_ms
+fn wait(fd: i32, timeout_ms: i32) -> bool {
+    let mut fds = [sys::pollfd { fd, events: sys::POLLIN as i16, revents: 0 }];
+    matches!(sys::poll(&mut fds, timeout_ms), Ok(1))
+}
+
+// Whether the daemon on the other end of fd is who it's expected to be
+fn is_trusted(fd: i32) -> bool {
+    let mut run = unsafe { core::mem::zeroed::<sys::statx>() };
+    if sys::statx(sys::AT_FDCWD, RUN_PATH, 0, sys::STATX_UID, &mut run).is_err() {
+        return false
+    }
+    matches!(sys::peer_uid(fd), Ok(uid) if uid == run.stx_uid || uid == 0)
+}
+
+fn exchange(fd: i32, query: &[u8], answer: &mut [u8]) -> Option<usize> {
+    sys::connect(fd, &get_address()).ok()?;
+    if !is_trusted(fd) {
+        return None
+    }
+    sys::send(fd, query, sys::MSG_NOSIGNAL).ok()?;
+
+    if !wait(fd, QUERY_TIMEOUT_MS) {
+        return None
+    }
+    sys::read(fd, answer).ok()
+}
+
+// Asks the daemon for the feature level of the best candidate of program, up to max_level.
+// program is relative to /usr (e.g. "/bin/foo"), without a terminator.
+// Returns None if there's no daemon, or it didn't find any candidates.
+pub fn query(program: &[u8], max_level: u32) -> Option<u32> {
+    let mut query = [0u8; QUERY_MAX];
+    let len = path::join(&mut query, &[FEATURE_LEVELS[max_level as usize].directory, b" ", program])?;
+
+    let fd = sys::socket(sys::AF_UNIX, sys::SOCK_SEQPACKET | sys::SOCK_CLOEXEC | sys::SOCK_NONBLOCK, 0).ok()?;
+    let mut answer = [0u8; ANSWER_MAX];
+    let result = exchange(fd, &query[..len], &mut answer);
+    let _ = sys::close(fd);
+
+    let level = capabilities::find_directory_level(&answer[..result?])?;
+    Some(core::cmp::min(level, max_level))
+}
+
+// Paths must stay inside the level directories
+fn is_valid_program(program: &[u8]) -> bool {
+    program.first() == Some(&b'/') && !program.contains(&b'\0') &&
+        program[1..].split(|c| *c == b'/').all(|part| !part.is_empty() && part != b"." && part != b"..")
+}
+
+struct Cache {
+    max_level: u32,
+    variants: Variants,
+    // Modification time of the hwcaps directory when it was last scanned
+    mtime: Option<(i64, u32)>,
+    // Level directories which don't exist, one bit per level and variant
+    missing: u64,
+}
+
+impl Cache {
+    fn bit(&self, level: u32, suffix: &[u8]) -> u64 {
+        let variant = self.variants.suffixes().iter().position(|s| *s == suffix).unwrap_or(0);
+        let stride = self.variants.suffixes().len() as u32;
+        // Directories which don't fit are never considered missing, so they're always checked
+        1u64.checked_shl(level * stride + variant as u32).unwrap_or(0)
+    }
+
+    fn refresh(&mut self) {
+        let mut hwcaps = unsafe { core::mem::zeroed::<sys::statx>() };
+        let mtime = sys::statx(sys::AT_FDCWD, c"/usr/hwcaps", 0, sys::STATX_MTIME, &mut hwcaps).ok()
+            .map(|_| (hwcaps.stx_mtime.tv_sec, hwcaps.stx_mtime.tv_nsec));
+
+        if mtime.is_some() && mtime == self.mtime {
+            return
+        }
+
+        self.mtime = mtime;
+        self.missing = 0;
+        for (level, suffix) in self.variants.candidates(self.max_level) {
+            let mut buffer = [0u8; sys::PATH_MAX as usize];
+            let exists = match path::join(&mut buffer, &[HWCAPS_PATH, FEATURE_LEVELS[level as usize].directory, suffix]) {
+                Some(len) => {
+                    let directory = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+                    sys::faccessat(sys::AT_FDCWD, directory, sys::F_OK).is_ok()
+                },
+                None => false
+            };
+
+            if !exists {
+                self.missing |= self.bit(level, suffix);
+            }
+        }
+    }
+
+    // Writes the directory of the best candidate to answer, returning its length
+    fn resolve(&mut self, query: &[u8], answer: &mut [u8; ANSWER_MAX]) -> usize {
+        let separator = match query.iter().position(|c| *c == b' ') {
+            Some(s) => s,
+            None => return 0
+        };
+        let (level, program) = (&query[..separator], &query[separator+1..]);
+
+        let max_level = match capabilities::find_directory_level(level) {
+            Some(l) => core::cmp::min(l, self.max_level),
+            None => return 0
+        };
+        if !is_valid_program(program) {
+            return 0
+        }
+
+        self.refresh();
+
+        for (level, suffix) in self.variants.candidates(max_level) {
+            if self.missing & self.bit(level, suffix) != 0 {
+                continue
+            }
+
+            let directory = FEATURE_LEVELS[level as usize].directory;
+            let mut buffer = [0u8; sys::PATH_MAX as usize];
+            let len = match path::join(&mut buffer, &[HWCAPS_PATH, directory, suffix, program]) {
+                Some(l) => l,
+                None => return 0
+            };
+            let candidate = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+
+            if sys::faccessat(sys::AT_FDCWD, candidate, sys::X_OK).is_ok() {
+                return path::join(answer, &[directory, suffix]).unwrap_or(0)
+            }
+        }
+        0
+    }
+}
+
+fn listen() -> i32 {
+    // Usually created by the service manager, with the right permissions
+    let _ = sys::mkdirat(sys::AT_FDCWD, RUN_PATH, 0o755);
+    // Left behind by a previous instance
+    let _ = sys::unlinkat(sys::AT_FDCWD, SOCKET_PATH, 0);
+
+    let fd = match sys::socket(sys::AF_UNIX, sys::SOCK_SEQPACKET | sys::SOCK_CLOEXEC | sys::SOCK_NONBLOCK, 0) {
+        Ok(fd) => fd,
+        Err(e) => abort(&Fatal(ExitCode::DaemonIOError, "Failed to create daemon socket!"), e.into_raw() as u32, None)
+    };
+
+    // Every user runs programs, so every user can ask
+    let result = sys::bind(fd, &get_address())
+        .and_then(|_| sys::fchmodat(sys::AT_FDCWD, SOCKET_PATH, 0o666))
+        .and_then(|_| sys::listen(fd, BACKLOG));
+
+    if let Err(e) = result {
+        abort(&Fatal(ExitCode::DaemonIOError, "Failed to listen on daemon socket!"), e.into_raw() as u32, Some(SOCKET_PATH.to_bytes()))
+    }
+    fd
+}
+
+// Monotonic time in milliseconds
+fn now() -> u64 {
+    let mut time = sys::timespec { tv_sec: 0, tv_nsec: 0 };
+    match sys::clock_gettime(sys::CLOCK_MONOTONIC, &mut time) {
+        Ok(_) => time.tv_sec as u64 * 1000 + time.tv_nsec as u64 / 1_000_000,
+        Err(_) => 0
+    }
+}
+
+// Clients waiting for an answer. The listener is polled along with them, in the first slot.
+struct Clients {
+    fds: [sys::pollfd; CLIENTS_MAX + 1],
+    deadlines: [u64; CLIENTS_MAX + 1],
+}
+
+impl Clients {
+    fn add(&mut self, fd: i32, now: u64) {
+        let slot = match self.fds[1..].iter().position(|client| client.fd < 0) {
+            Some(i) => i + 1,
+            None => {
+                // Every slot is taken, so the client which connected first gives up its own
+                let oldest = (1..=CLIENTS_MAX).min_by_key(|i| self.deadlines[*i]).unwrap_or(1);
+                self.remove(oldest);
+                oldest
+            }
+        };
+        self.fds[slot] = sys::pollfd { fd, events: sys::POLLIN as i16, revents: 0 };
+        self.deadlines[slot] = now + CLIENT_TIMEOUT_MS;
+    }
+
+    fn remove(&mut self, slot: usize) {
+        let _ = sys::close(self.fds[slot].fd);
+        // poll() ignores negative descriptors
+        self.fds[slot].fd = -1;
+    }
+
+    // How long poll() can wait before a client times out, or -1 if there are none
+    fn timeout(&self, now: u64) -> i32 {
+        (1..=CLIENTS_MAX).filter(|i| self.fds[*i].fd >= 0)
+            .map(|i| self.deadlines[i].saturating_sub(now) as i32)
+            .min()
+            .unwrap_or(-1)
+    }
+}
+
+// Answers queries forever, for "hwcaps-loader --daemon"
+pub fn run(max_level: u32) -> ! {
+    let listener = listen();
+    let mut cache = Cache { max_level, variants: Variants::detect(), mtime: None, missing: 0 };
+
+    let unused = sys::pollfd { fd: -1, events: sys::POLLIN as i16, revents: 0 };
+    let mut clients = Clients { fds: [unused; CLIENTS_MAX + 1], deadlines: [0; CLIENTS_MAX + 1] };
+    clients.fds[0].fd = listener;
+
+    loop {
+        let timeout = clients.timeout(now());
+        if sys::poll(&mut clients.fds, timeout).is_err() {
+            continue
+        }
+        let time = now();
+
+        for slot in 1..=CLIENTS_MAX {
+            let client = clients.fds[slot];
+            if client.fd < 0 {
+                continue
+            }
+
+            // Hung up and errors are answered with nothing, like queries which can't be read
+            if client.revents != 0 {
+                let mut query = [0u8; QUERY_MAX];
+                if let Ok(len) = sys::read(client.fd, &mut query) {
+                    let mut answer = [0u8; ANSWER_MAX];
+                    let answer_len = cache.resolve(&query[..len], &mut answer);
+                    let _ = sys::send(client.fd, &answer[..answer_len], sys::MSG_NOSIGNAL);
+                }
+                clients.remove(slot);
+            } else if clients.deadlines[slot] <= time {
+                clients.remove(slot);
+            }
+        }
+
+        // Everyone who connected since the last round
+        if clients.fds[0].revents != 0 {
+            while let Ok(fd) = sys::accept4(listener, sys::SOCK_CLOEXEC | sys::SOCK_NONBLOCK) {
+                clients.add(fd, time);
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+/*
+   Filesystem tamper heuristic.
+   /usr/hwcaps is expected to live on the same filesystem (st_dev) as /usr/bin. If it doesn't,
+   an attacker or a misconfiguration may have mounted an unexpected filesystem over the hwcaps
+   tree, so refuse to execute anything from it (or just warn about it, if configured to).
+   Note that btrfs subvolumes have their own device numbers, so both directories must be in the
+   same subvolume.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys::{self, ExitCode};
+use crate::output::{abort, Fatal};
+
+fn get_device(path: &CStr) -> Option<(u32, u32)> {
+    let mut buffer = unsafe { core::mem::zeroed::<sys::statx>() };
+    sys::statx(sys::AT_FDCWD, path, sys::AT_STATX_SYNC_AS_STAT, sys::STATX_TYPE, &mut buffer).ok()?;
+
+    Some((buffer.stx_dev_major, buffer.stx_dev_minor))
+}
+
+// Aborts (or warns, if warn_only is set) if /usr/hwcaps and /usr/bin are on different devices
+pub fn check(warn_only: bool) {
+    let hwcaps_path = c"/usr/hwcaps";
+
+    // Missing directories are reported later on, when executing candidates
+    let (hwcaps, bin) = match (get_device(hwcaps_path), get_device(c"/usr/bin")) {
+        (Some(h), Some(b)) => (h, b),
+        _ => return
+    };
+
+    if hwcaps == bin {
+        return
+    }
+
+    if !warn_only {
+        abort(&Fatal(ExitCode::HwcapsDeviceMismatch, "hwcaps directory is on an unexpected filesystem!"), 0, Some(hwcaps_path.to_bytes()))
+    }
+
+    #[cfg(feature = "error_output")]
+    crate::output::hint("Warning: hwcaps directory is on an unexpected filesystem.", Some(hwcaps_path.to_bytes()));
+}
@@ -0,0 +1,58 @@
+/*
+   Raw detection dump.
+   "hwcaps-loader --dump-cpuid" prints the registers feature detection reads, exactly as the CPU
+   returned them and before anything is decoded, so a misdetection can be reproduced from a bug
+   report. The first line names the architecture, and every other one is a source and its fields:
+   arch=x86_64
+   cpuid leaf=0x00000007 subleaf=0x00000000 eax=0x00000002 ebx=0xd19f4fbb ecx=0x184007ec edx=0xfc1cc410
+   Architectures without CPUID print their own sources (e.g. "auxv hwcap=... hwcap2=..." on
+   aarch64). Values are hexadecimal, zero-padded to at least 8 digits. The format is stable:
+   fields may be added at the end of a line, and sources on lines of their own, but never changed
+   or removed.
+*/
+
+use crate::sys;
+use crate::path;
+use crate::output;
+use crate::capabilities::{self, Raw, ARCH_NAME};
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+// "0x" and 16 digits
+const VALUE_MAX: usize = 18;
+const LINE_MAX: usize = 512;
+
+fn format_hex(value: u64, buffer: &mut [u8; VALUE_MAX]) -> &[u8] {
+    let digits = core::cmp::max(8, (64 - value.leading_zeros() as usize).div_ceil(4));
+    buffer[..2].copy_from_slice(b"0x");
+    for i in 0..digits {
+        buffer[2 + i] = HEX[((value >> ((digits - 1 - i) * 4)) & 0xf) as usize];
+    }
+    &buffer[..2 + digits]
+}
+
+fn print_raw(raw: &Raw) {
+    let mut line = [0u8; LINE_MAX];
+    let mut len = match path::join(&mut line, &[raw.source]) {
+        Some(l) => l,
+        None => return
+    };
+
+    for (name, value) in raw.fields {
+        let mut buffer = [0u8; VALUE_MAX];
+        len += match path::join(&mut line[len..], &[b" ", name, b"=", format_hex(*value, &mut buffer)]) {
+            Some(l) => l,
+            None => break
+        };
+    }
+    output::print_line(&line[..len]);
+}
+
+// Prints the raw feature registers, for "hwcaps-loader --dump-cpuid"
+pub fn run() -> ! {
+    let mut line = [0u8; LINE_MAX];
+    let len = path::join(&mut line, &[b"arch=", ARCH_NAME]).unwrap_or(0);
+    output::print_line(&line[..len]);
+
+    capabilities::get_raw(&mut print_raw);
+    sys::exit(0)
+}
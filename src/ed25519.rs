@@ -0,0 +1,651 @@
+/*
+   Ed25519 signature verification (RFC 8032), along with the SHA-512 it relies on.
+   Only verification is implemented, which handles public data exclusively, so none of this
+   has to run in constant time. The message is hashed as it's read, so files of any size can be
+   verified without holding them in memory.
+*/
+
+/*
+   SHA-512
+*/
+
+const K: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc, 0x3956c25bf348b538,
+    0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118, 0xd807aa98a3030242, 0x12835b0145706fbe,
+    0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2, 0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235,
+    0xc19bf174cf692694, 0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5, 0x983e5152ee66dfab,
+    0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4, 0xc6e00bf33da88fc2, 0xd5a79147930aa725,
+    0x06ca6351e003826f, 0x142929670a0e6e70, 0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df, 0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30, 0xd192e819d6ef5218,
+    0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8, 0x19a4c116b8d2d0c8, 0x1e376c085141ab53,
+    0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8, 0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3, 0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b, 0xca273eceea26619c,
+    0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178, 0x06f067aa72176fba, 0x0a637dc5a2c898a6,
+    0x113f9804bef90dae, 0x1b710b35131c471b, 0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c, 0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+const SHA512_INIT: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+const BLOCK_SIZE: usize = 128;
+
+pub struct Sha512 {
+    state: [u64; 8],
+    block: [u8; BLOCK_SIZE],
+    block_len: usize,
+    total_len: u128,
+}
+
+impl Sha512 {
+    pub fn new() -> Self {
+        Sha512 { state: SHA512_INIT, block: [0; BLOCK_SIZE], block_len: 0, total_len: 0 }
+    }
+
+    fn compress(state: &mut [u64; 8], block: &[u8]) {
+        let mut w = [0u64; 80];
+        for (i, word) in block.chunks_exact(8).enumerate() {
+            w[i] = u64::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            let s0 = w[i-15].rotate_right(1) ^ w[i-15].rotate_right(8) ^ (w[i-15] >> 7);
+            let s1 = w[i-2].rotate_right(19) ^ w[i-2].rotate_right(61) ^ (w[i-2] >> 6);
+            w[i] = w[i-16].wrapping_add(s0).wrapping_add(w[i-7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..80 {
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u128;
+
+        while !data.is_empty() {
+            let n = core::cmp::min(BLOCK_SIZE - self.block_len, data.len());
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+
+            if self.block_len == BLOCK_SIZE {
+                Self::compress(&mut self.state, &self.block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    pub fn finalize(mut self) -> [u8; 64] {
+        let bits = self.total_len * 8;
+
+        self.block[self.block_len] = 0x80;
+        self.block[self.block_len + 1..].fill(0);
+        if self.block_len + 1 > BLOCK_SIZE - 16 {
+            Self::compress(&mut self.state, &self.block);
+            self.block.fill(0);
+        }
+        self.block[BLOCK_SIZE - 16..].copy_from_slice(&bits.to_be_bytes());
+        Self::compress(&mut self.state, &self.block);
+
+        let mut digest = [0u8; 64];
+        for (chunk, s) in digest.chunks_exact_mut(8).zip(self.state) {
+            chunk.copy_from_slice(&s.to_be_bytes());
+        }
+        digest
+    }
+}
+
+/*
+   Field arithmetic modulo 2^255 - 19, with 51-bit limbs
+*/
+
+const MASK: u64 = (1 << 51) - 1;
+
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const ZERO: Fe = Fe([0, 0, 0, 0, 0]);
+const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+// -121665/121666
+const D: Fe = Fe([0x34dca135978a3, 0x1a8283b156ebd, 0x5e7a26001c029, 0x739c663a03cbb, 0x52036cee2b6ff]);
+const D2: Fe = Fe([0x69b9426b2f159, 0x35050762add7a, 0x3cf44c0038052, 0x6738cc7407977, 0x2406d9dc56dff]);
+const SQRT_M1: Fe = Fe([0x61b274a0ea0b0, 0xd5a5fc8f189d, 0x7ef5e9cbd0c60, 0x78595a6804c9e, 0x2b8324804fc1d]);
+
+// Exponents for inversion (p - 2) and square roots ((p - 5) / 8), little endian
+const P_MINUS_2: [u8; 32] = {
+    let mut e = [0xff; 32];
+    e[0] = 0xeb;
+    e[31] = 0x7f;
+    e
+};
+const P_MINUS_5_DIV_8: [u8; 32] = {
+    let mut e = [0xff; 32];
+    e[0] = 0xfd;
+    e[31] = 0x0f;
+    e
+};
+
+impl Fe {
+    fn carry(mut l: [u64; 5]) -> Fe {
+        for i in 0..4 {
+            l[i+1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[0] += (l[4] >> 51) * 19;
+        l[4] &= MASK;
+        l[1] += l[0] >> 51;
+        l[0] &= MASK;
+        Fe(l)
+    }
+
+    // The top bit is ignored
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let w = |i: usize| u64::from_le_bytes(bytes[i*8..i*8+8].try_into().unwrap());
+        Fe([
+            w(0) & MASK,
+            (w(0) >> 51 | w(1) << 13) & MASK,
+            (w(1) >> 38 | w(2) << 26) & MASK,
+            (w(2) >> 25 | w(3) << 39) & MASK,
+            (w(3) >> 12) & MASK,
+        ])
+    }
+
+    fn to_bytes(&self) -> [u8; 32] {
+        let mut l = Fe::carry(self.0).0;
+
+        // Subtract p if the value is p or above, which is the case if adding 19 overflows 2^255
+        let mut q = (l[0] + 19) >> 51;
+        for limb in &l[1..] {
+            q = (limb + q) >> 51;
+        }
+        l[0] += 19 * q;
+        for i in 0..4 {
+            l[i+1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[4] &= MASK;
+
+        let mut bytes = [0u8; 32];
+        let (mut acc, mut bits, mut i) = (0u128, 0, 0);
+        for limb in l {
+            acc |= (limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 && i < 32 {
+                bytes[i] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                i += 1;
+            }
+        }
+        bytes[31] |= acc as u8;
+        bytes
+    }
+
+    fn add(&self, b: &Fe) -> Fe {
+        let (a, b) = (self.0, b.0);
+        Fe::carry([a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3], a[4] + b[4]])
+    }
+
+    fn sub(&self, b: &Fe) -> Fe {
+        // Add 16p first, so limbs can't underflow
+        let (a, b) = (self.0, b.0);
+        Fe::carry([
+            (a[0] + 0x7ffffffffffed0) - b[0],
+            (a[1] + 0x7ffffffffffff0) - b[1],
+            (a[2] + 0x7ffffffffffff0) - b[2],
+            (a[3] + 0x7ffffffffffff0) - b[3],
+            (a[4] + 0x7ffffffffffff0) - b[4],
+        ])
+    }
+
+    fn neg(&self) -> Fe {
+        ZERO.sub(self)
+    }
+
+    fn mul(&self, b: &Fe) -> Fe {
+        let (a, b) = (self.0, b.0);
+        let m = |x: u64, y: u64| x as u128 * y as u128;
+        let (b1, b2, b3, b4) = (b[1] * 19, b[2] * 19, b[3] * 19, b[4] * 19);
+
+        let mut c = [
+            m(a[0], b[0]) + m(a[1], b4) + m(a[2], b3) + m(a[3], b2) + m(a[4], b1),
+            m(a[0], b[1]) + m(a[1], b[0]) + m(a[2], b4) + m(a[3], b3) + m(a[4], b2),
+            m(a[0], b[2]) + m(a[1], b[1]) + m(a[2], b[0]) + m(a[3], b4) + m(a[4], b3),
+            m(a[0], b[3]) + m(a[1], b[2]) + m(a[2], b[1]) + m(a[3], b[0]) + m(a[4], b4),
+            m(a[0], b[4]) + m(a[1], b[3]) + m(a[2], b[2]) + m(a[3], b[1]) + m(a[4], b[0]),
+        ];
+
+        for i in 0..4 {
+            c[i+1] += c[i] >> 51;
+            c[i] &= MASK as u128;
+        }
+        c[0] += (c[4] >> 51) * 19;
+        c[4] &= MASK as u128;
+        c[1] += c[0] >> 51;
+        c[0] &= MASK as u128;
+
+        Fe([c[0] as u64, c[1] as u64, c[2] as u64, c[3] as u64, c[4] as u64])
+    }
+
+    fn square(&self) -> Fe {
+        self.mul(self)
+    }
+
+    fn pow(&self, exponent: &[u8; 32]) -> Fe {
+        let mut result = ONE;
+        for bit in (0..256).rev() {
+            result = result.square();
+            if exponent[bit / 8] >> (bit % 8) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn equals(&self, b: &Fe) -> bool {
+        self.to_bytes() == b.to_bytes()
+    }
+
+    fn is_negative(&self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+}
+
+/*
+   Points on edwards25519, in extended coordinates (X:Y:Z:T, where x = X/Z, y = Y/Z and xy = T/Z)
+*/
+
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+const IDENTITY: Point = Point { x: ZERO, y: ONE, z: ONE, t: ZERO };
+
+// The encoding of the base point (y = 4/5, x is even)
+const BASE: [u8; 32] = {
+    let mut b = [0x66; 32];
+    b[0] = 0x58;
+    b
+};
+
+impl Point {
+    // RFC 8032, section 5.1.3
+    fn decompress(bytes: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let sign = bytes[31] >> 7 == 1;
+
+        // Non-canonical encodings (y >= p) are rejected
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None
+        }
+
+        let y2 = y.square();
+        let u = y2.sub(&ONE);
+        let v = y2.mul(&D).add(&ONE);
+
+        // x = u * v^3 * (u * v^7)^((p - 5) / 8)
+        let v3 = v.square().mul(&v);
+        let v7 = v3.square().mul(&v);
+        let mut x = u.mul(&v3).mul(&u.mul(&v7).pow(&P_MINUS_5_DIV_8));
+
+        let vx2 = v.mul(&x.square());
+        if vx2.equals(&u) {
+            // x is a square root
+        } else if vx2.equals(&u.neg()) {
+            x = x.mul(&SQRT_M1);
+        } else {
+            return None
+        }
+
+        if x.equals(&ZERO) && sign {
+            return None
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+
+        Some(Point { x, y, z: ONE, t: x.mul(&y) })
+    }
+
+    fn compress(&self) -> [u8; 32] {
+        let z_inverse = self.z.pow(&P_MINUS_2);
+        let x = self.x.mul(&z_inverse);
+        let mut bytes = self.y.mul(&z_inverse).to_bytes();
+        bytes[31] |= (x.is_negative() as u8) << 7;
+        bytes
+    }
+
+    // The addition formula is complete, so it also works for doubling
+    fn add(&self, b: &Point) -> Point {
+        let a = self.y.sub(&self.x).mul(&b.y.sub(&b.x));
+        let bb = self.y.add(&self.x).mul(&b.y.add(&b.x));
+        let c = self.t.mul(&D2).mul(&b.t);
+        let d = self.z.add(&self.z).mul(&b.z);
+        let (e, f, g, h) = (bb.sub(&a), d.sub(&c), d.add(&c), bb.add(&a));
+
+        Point { x: e.mul(&f), y: g.mul(&h), z: f.mul(&g), t: e.mul(&h) }
+    }
+
+    fn neg(&self) -> Point {
+        Point { x: self.x.neg(), y: self.y, z: self.z, t: self.t.neg() }
+    }
+}
+
+/*
+   Scalars modulo the group order
+*/
+
+// 2^252 + 27742317777372353535851937790883648493, little endian 64-bit words
+const L: [u64; 4] = [0x5812631a5cf5d3ed, 0x14def9dea2f79cd6, 0, 0x1000000000000000];
+
+fn less_than_l(s: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if s[i] != L[i] {
+            return s[i] < L[i]
+        }
+    }
+    false
+}
+
+fn words(bytes: &[u8]) -> impl DoubleEndedIterator<Item = u64> + '_ {
+    bytes.chunks_exact(8).map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+}
+
+// Reduces a 512-bit number modulo L, one bit at a time
+fn reduce(bytes: &[u8; 64]) -> [u64; 4] {
+    let mut r = [0u64; 4];
+
+    for word in words(bytes).rev() {
+        for bit in (0..64).rev() {
+            // r = 2r + bit. r < L < 2^253, so this can't overflow
+            let mut carry = word >> bit & 1;
+            for limb in r.iter_mut() {
+                let next = *limb >> 63;
+                *limb = *limb << 1 | carry;
+                carry = next;
+            }
+
+            if !less_than_l(&r) {
+                let mut borrow = 0;
+                for (limb, l) in r.iter_mut().zip(L) {
+                    let (v, b1) = limb.overflowing_sub(l);
+                    let (v, b2) = v.overflowing_sub(borrow);
+                    *limb = v;
+                    borrow = (b1 | b2) as u64;
+                }
+            }
+        }
+    }
+    r
+}
+
+/*
+   Verification
+*/
+
+pub struct Verifier {
+    hash: Sha512,
+    public_key: Point,
+    signature: [u8; 64],
+}
+
+impl Verifier {
+    // Returns None if the key or signature are malformed
+    pub fn new(public_key: &[u8; 32], signature: &[u8; 64]) -> Option<Self> {
+        let mut s = [0u64; 4];
+        for (word, value) in s.iter_mut().zip(words(&signature[32..])) {
+            *word = value;
+        }
+        if !less_than_l(&s) {
+            return None
+        }
+
+        let mut hash = Sha512::new();
+        hash.update(&signature[..32]);
+        hash.update(public_key);
+
+        Some(Verifier { hash, public_key: Point::decompress(public_key)?, signature: *signature })
+    }
+
+    // Feeds the next part of the message
+    pub fn update(&mut self, data: &[u8]) {
+        self.hash.update(data);
+    }
+
+    // Checks [S]B = R + [k]A, where k = SHA-512(R || A || message)
+    pub fn verify(self) -> bool {
+        let k = reduce(&self.hash.finalize());
+
+        let mut s = [0u64; 4];
+        for (word, value) in s.iter_mut().zip(words(&self.signature[32..])) {
+            *word = value;
+        }
+
+        let base = match Point::decompress(&BASE) {
+            Some(b) => b,
+            None => return false
+        };
+        let minus_a = self.public_key.neg();
+
+        // [S]B - [k]A, which must be R
+        let mut result = IDENTITY;
+        for bit in (0..256).rev() {
+            result = result.add(&result);
+            if s[bit / 64] >> (bit % 64) & 1 == 1 {
+                result = result.add(&base);
+            }
+            if k[bit / 64] >> (bit % 64) & 1 == 1 {
+                result = result.add(&minus_a);
+            }
+        }
+
+        result.compress() == self.signature[..32]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+    use super::{Sha512, Verifier};
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    // None if the key or signature were rejected outright
+    fn verify(public_key: &[u8], signature: &[u8], message: &[u8]) -> Option<bool> {
+        let mut verifier = Verifier::new(public_key.try_into().unwrap(), signature.try_into().unwrap())?;
+        verifier.update(message);
+        Some(verifier.verify())
+    }
+
+    const TEST_1024_MESSAGE: &str = concat!(
+        "08b8b2b733424243760fe426a4b54908632110a66c2f6591eabd3345e3e4eb98fa6e264bf09efe12ee50f8f54e9f77b1",
+        "e355f6c50544e23fb1433ddf73be84d879de7c0046dc4996d9e773f4bc9efe5738829adb26c81b37c93a1b270b20329d",
+        "658675fc6ea534e0810a4432826bf58c941efb65d57a338bbd2e26640f89ffbc1a858efcb8550ee3a5e1998bd177e93a",
+        "7363c344fe6b199ee5d02e82d522c4feba15452f80288a821a579116ec6dad2b3b310da903401aa62100ab5d1a36553e",
+        "06203b33890cc9b832f79ef80560ccb9a39ce767967ed628c6ad573cb116dbefefd75499da96bd68a8a97b928a8bbc10",
+        "3b6621fcde2beca1231d206be6cd9ec7aff6f6c94fcd7204ed3455c68c83f4a41da4af2b74ef5c53f1d8ac70bdcb7ed1",
+        "85ce81bd84359d44254d95629e9855a94a7c1958d1f8ada5d0532ed8a5aa3fb2d17ba70eb6248e594e1a2297acbbb39d",
+        "502f1a8c6eb6f1ce22b3de1a1f40cc24554119a831a9aad6079cad88425de6bde1a9187ebb6092cf67bf2b13fd65f270",
+        "88d78b7e883c8759d2c4f5c65adb7553878ad575f9fad878e80a0c9ba63bcbcc2732e69485bbc9c90bfbd62481d9089b",
+        "eccf80cfe2df16a2cf65bd92dd597b0707e0917af48bbb75fed413d238f5555a7a569d80c3414a8d0859dc65a46128ba",
+        "b27af87a71314f318c782b23ebfe808b82b0ce26401d2e22f04d83d1255dc51addd3b75a2b1ae0784504df543af8969b",
+        "e3ea7082ff7fc9888c144da2af58429ec96031dbcad3dad9af0dcbaaaf268cb8fcffead94f3c7ca495e056a9b47acdb7",
+        "51fb73e666c6c655ade8297297d07ad1ba5e43f1bca32301651339e22904cc8c42f58c30c04aafdb038dda0847dd988d",
+        "cda6f3bfd15c4b4c4525004aa06eeff8ca61783aacec57fb3d1f92b0fe2fd1a85f6724517b65e614ad6808d6f6ee34df",
+        "f7310fdc82aebfd904b01e1dc54b2927094b2db68d6f903b68401adebf5a7e08d78ff4ef5d63653a65040cf9bfd4aca7",
+        "984a74d37145986780fc0b16ac451649de6188a7dbdf191f64b5fc5e2ab47b57f7f7276cd419c17a3ca8e1b939ae49e4",
+        "88acba6b965610b5480109c8b17b80e1b7b750dfc7598d5d5011fd2dcc5600a32ef5b52a1ecc820e308aa342721aac09",
+        "43bf6686b64b2579376504ccc493d97e6aed3fb0f9cd71a43dd497f01f17c0e2cb3797aa2a2f256656168e6c496afc5f",
+        "b93246f6b1116398a346f1a641f3b041e989f7914f90cc2c7fff357876e506b50d334ba77c225bc307ba537152f3f161",
+        "0e4eafe595f6d9d90d11faa933a15ef1369546868a7f3a45a96768d40fd9d03412c091c6315cf4fde7cb68606937380d",
+        "b2eaaa707b4c4185c32eddcdd306705e4dc1ffc872eeee475a64dfac86aba41c0618983f8741c5ef68d3a101e8a3b8ca",
+        "c60c905c15fc910840b94c00a0b9d0",
+    );
+
+    // RFC 8032, section 7.1: TEST 1, 2, 3 and 1024, as (public key, signature, message)
+    const RFC8032: &[(&str, &str, &str)] = &[
+        ("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+         concat!("e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155",
+                 "5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"),
+         ""),
+        ("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+         concat!("92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da",
+                 "085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"),
+         "72"),
+        ("fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+         concat!("6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac",
+                 "18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a"),
+         "af82"),
+        ("278117fc144c72340f67d0f2316e8386ceffbf2b2428c9c51fef7c597f1d426e",
+         concat!("0aab4c900501b3e24d7cdf4663326a3a87df5e4843b2cbdb67cbf6e460fec350",
+                 "aa5371b1508f9f4528ecea23c436d94b5e8fcd4f681e30a6ac00a9704a188a03"),
+         TEST_1024_MESSAGE),
+    ];
+
+    #[test]
+    fn sha512_vectors() {
+        // FIPS 180-2, appendix C, and the empty message
+        let cases: &[(&[u8], usize, &str)] = &[
+            (b"abc", 1, concat!("ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a",
+                                "2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f")),
+            (b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu", 1,
+             concat!("8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018",
+                     "501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909")),
+            (b"a", 1_000_000, concat!("e718483d0ce769644e2e42c7bc15b4638e1f98b13b2044285632a803afa973eb",
+                                      "de0ff244877ea60a4cb0432ce577c31beb009c5c2c49aa2e4eadb217ad8cc09b")),
+            (b"", 1, concat!("cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce",
+                             "47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e")),
+        ];
+
+        for (data, repeat, digest) in cases {
+            let mut hash = Sha512::new();
+            for _ in 0..*repeat {
+                hash.update(data);
+            }
+            assert_eq!(hash.finalize().as_slice(), hex(digest), "{:?}", std::str::from_utf8(data));
+        }
+
+        // Updates of any size must add up to the same digest
+        let message = hex(TEST_1024_MESSAGE);
+        let mut whole = Sha512::new();
+        whole.update(&message);
+        let mut parts = Sha512::new();
+        for chunk in message.chunks(7) {
+            parts.update(chunk);
+        }
+        assert_eq!(whole.finalize(), parts.finalize());
+    }
+
+    #[test]
+    fn rfc8032_vectors() {
+        for (public_key, signature, message) in RFC8032 {
+            assert_eq!(verify(&hex(public_key), &hex(signature), &hex(message)), Some(true), "{}", public_key);
+
+            // Fed in parts, like files are
+            let mut verifier = Verifier::new(&hex(public_key).try_into().unwrap(), &hex(signature).try_into().unwrap()).unwrap();
+            for chunk in hex(message).chunks(100) {
+                verifier.update(chunk);
+            }
+            assert!(verifier.verify());
+        }
+    }
+
+    #[test]
+    fn flipped_bits() {
+        for (public_key, signature, message) in RFC8032 {
+            let (public_key, signature, message) = (hex(public_key), hex(signature), hex(message));
+
+            // The empty message gets a byte instead
+            let mut flipped = message.clone();
+            match flipped.last_mut() {
+                Some(last) => *last ^= 1,
+                None => flipped.push(0)
+            }
+            assert_eq!(verify(&public_key, &signature, &flipped), Some(false));
+
+            // In R and in S
+            for i in [0, 32] {
+                let mut flipped = signature.clone();
+                flipped[i] ^= 1;
+                assert_ne!(verify(&public_key, &flipped, &message), Some(true));
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_signatures() {
+        let (public_key, _, _) = RFC8032[0];
+
+        // S >= L, with TEST 1's S + L (which would verify if it were reduced) and L itself
+        let cases = [
+            concat!("e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155",
+                    "4c8c7872aa064e049dbb3013fbf29380d25bf5f0595bbe24655141438e7a101b"),
+            concat!("e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155",
+                    "edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010"),
+        ];
+        for signature in cases {
+            assert_eq!(verify(&hex(public_key), &hex(signature), b""), None, "{}", signature);
+        }
+
+        // TEST 1's key signing the empty message with R = identity, encoded canonically (as y = 1)
+        // and not (as y = p + 1). Only the canonical encoding is accepted.
+        let canonical = concat!("0100000000000000000000000000000000000000000000000000000000000000",
+                                "756cf9b1d6f0d7a979b9d2af3dc2bc1294ec7cb6daa20eaff534c024fc57920f");
+        let non_canonical = concat!("eeffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f",
+                                    "3fdd9411ef77c7b937c975b1193128983db0482a002663080c0dd63cf3466c06");
+        assert_eq!(verify(&hex(public_key), &hex(canonical), b""), Some(true));
+        assert_ne!(verify(&hex(public_key), &hex(non_canonical), b""), Some(true));
+    }
+
+    #[test]
+    fn malformed_public_keys() {
+        let (_, signature, _) = RFC8032[0];
+
+        let cases = [
+            // The identity point (y = 1) encoded as y = p + 1
+            "eeffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f",
+            // y = p
+            "edffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f",
+            // x = 0 with the sign bit set
+            "0100000000000000000000000000000000000000000000000000000000000080",
+            // y = 2 isn't on the curve
+            "0200000000000000000000000000000000000000000000000000000000000000",
+        ];
+        for public_key in cases {
+            assert_eq!(verify(&hex(public_key), &hex(signature), b""), None, "{}", public_key);
+        }
+    }
+}
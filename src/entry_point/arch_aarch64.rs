@@ -0,0 +1,23 @@
+#[no_mangle]
+#[unsafe(naked)]
+pub unsafe extern "C" fn _start() -> ! {
+    core::arch::naked_asm!(
+        // Mark the outermost frame, for backtraces
+        "mov x29, xzr",
+        "mov x30, xzr",
+
+        //Get argc
+        "ldr x0, [sp]",
+
+        //Get argv
+        "add x1, sp, 8",
+
+        //Get envp (after argv's terminator)
+        "add x2, x1, x0, lsl 3",
+        "add x2, x2, 8",
+
+        //Start main
+        "bl {entry}",
+        entry = sym super::super::main
+    )
+}
@@ -0,0 +1,30 @@
+#[no_mangle]
+#[unsafe(naked)]
+pub unsafe extern "C" fn _start() -> ! {
+    core::arch::naked_asm!(
+        // Set up the global pointer, which the linker may relax accesses to (without relaxing this)
+        ".option push",
+        ".option norelax",
+        "lla gp, __global_pointer$",
+        ".option pop",
+
+        // Mark the outermost frame, for backtraces
+        "mv fp, zero",
+        "mv ra, zero",
+
+        //Get argc
+        "ld a0, 0(sp)",
+
+        //Get argv
+        "addi a1, sp, 8",
+
+        //Get envp (after argv's terminator)
+        "slli a2, a0, 3",
+        "add a2, a1, a2",
+        "addi a2, a2, 8",
+
+        //Start main
+        "call {entry}",
+        entry = sym super::super::main
+    )
+}
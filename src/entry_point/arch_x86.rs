@@ -1,12 +1,12 @@
 #[no_mangle]
-#[naked]
+#[unsafe(naked)]
 pub unsafe extern "C" fn _start() -> ! {
     core::arch::naked_asm!(
         //Get argc
         "mov rdi, rsp",
 
-        //Get envp
-        "movsx rax, byte ptr [rdi]",
+        //Get envp, which follows argv's terminator (the auxiliary vector follows envp's)
+        "mov rax, [rdi]",
         "lea rdx, [rdi + rax*8]",
         "add rdx, 16",
 
@@ -0,0 +1,64 @@
+/*
+   Developer candidate directory.
+   Developers can test freshly built optimized binaries through the real alias, without
+   installing them into /usr, by pointing HWCAPS_EXTRA_DIR at a directory laid out like
+   /usr/hwcaps:
+   HWCAPS_EXTRA_DIR=/home/me/build/hwcaps foo [args...]
+   tries /home/me/build/hwcaps/<level>[<variant>]/bin/foo for every level this machine
+   supports, in the usual order, before any of the system's candidates. "extra_dir = <path>" in
+   the configuration does the same without the variable (e.g. on a test machine), which wins if
+   both are set.
+   Either is ignored in secure-execution mode (setuid/setgid programs, for example), since it
+   would let unprivileged users pick what privileged programs run.
+*/
+
+use core::ffi::c_char;
+
+use crate::sys::{self, ExitCode};
+use crate::output::{abort, Fatal};
+
+const EXTRA_DIR_VAR: &[u8] = b"HWCAPS_EXTRA_DIR";
+
+// Path of a directory, without a trailing slash
+#[derive(Clone, Copy)]
+pub struct ExtraDir {
+    path: [u8; ExtraDir::MAX],
+    len: usize,
+}
+
+impl ExtraDir {
+    // Leaves room for the candidates' paths under it
+    const MAX: usize = 1024;
+
+    pub fn new(path: &[u8]) -> Option<Self> {
+        let path = path.strip_suffix(b"/").unwrap_or(path);
+        if path.first() != Some(&b'/') || path.len() > Self::MAX || path.contains(&b'\0') {
+            return None
+        }
+
+        let mut directory = ExtraDir { path: [0; Self::MAX], len: path.len() };
+        directory.path[..path.len()].copy_from_slice(path);
+        Some(directory)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.path[..self.len]
+    }
+}
+
+// Returns the directory to try before the system's candidates, if there's one
+pub fn get(envp: *const *const c_char, configured: Option<&ExtraDir>) -> Option<ExtraDir> {
+    // Checked first, so unprivileged users can't make privileged programs abort with a bad path
+    if sys::is_secure() {
+        return None
+    }
+
+    match sys::getenv(envp, EXTRA_DIR_VAR) {
+        // Developers should know right away why their builds aren't being used
+        Some(path) => match ExtraDir::new(path) {
+            Some(d) => Some(d),
+            None => abort(&Fatal(ExitCode::TargetPathInvalid, "Extra candidate directory isn't a valid absolute path!"), 0, Some(path))
+        },
+        None => configured.copied()
+    }
+}
@@ -0,0 +1,175 @@
+/*
+   Recent-failure memory.
+   A build which crashes right away (e.g. SIGILL from a miscompiled or misdetected level) can keep
+   a system service in a crash loop. Once the loader executes a candidate it's gone, so crashes
+   are reported to it by whatever watches the program, usually the service manager:
+   hwcaps-loader --record-failure <program>
+   records that the candidate <program> (an alias' name or path, as in resolve) currently
+   resolves to has failed, in /run/hwcaps-loader/failures/<name>@<directory> (e.g.
+   foo@x86-64-v3+bigmem). For the next "failure_cooldown = <seconds>" (600 by default), launches
+   of the program skip that candidate, so the next best one is executed instead.
+   With systemd, "ExecStopPost=/usr/bin/hwcaps-loader --record-failure foo" only records crashes
+   from SIGILL, SIGSEGV and SIGBUS, going by $EXIT_CODE and $EXIT_STATUS. Without them, every
+   call is recorded.
+   Which candidate that is is decided with the program's configuration, like launches do, so
+   [program:<name>] sections apply.
+   Baseline candidates are never skipped, since there would be nothing left to fall back to. The
+   directory isn't created by us (e.g. it can be created with tmpfiles.d). Records are timed with
+   the monotonic clock, so ones left over from before a reboot are ignored.
+*/
+
+use core::ffi::{c_char, CStr};
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::config;
+use crate::resolve;
+use crate::capabilities::{FEATURE_LEVELS, BASELINE_FEATURE_LEVEL};
+use crate::output::{abort, Fatal};
+#[cfg(feature = "ignore_markers")]
+use crate::{ignore, HWCAPS_PATH};
+#[cfg(feature = "canary")]
+use crate::canary;
+
+#[cfg(not(target_os = "freebsd"))]
+pub const FAILURES_PATH: &CStr = c"/run/hwcaps-loader/failures";
+#[cfg(target_os = "freebsd")]
+pub const FAILURES_PATH: &CStr = c"/var/run/hwcaps-loader/failures";
+
+pub const DEFAULT_COOLDOWN: u32 = 600;
+// Set by systemd for ExecStopPost= commands
+const EXIT_CODE_VAR: &[u8] = b"EXIT_CODE";
+const EXIT_STATUS_VAR: &[u8] = b"EXIT_STATUS";
+const CRASH_SIGNALS: &[&[u8]] = &[b"ILL", b"SEGV", b"BUS"];
+// Seconds, in decimal
+const RECORD_MAX: usize = 20;
+
+// Monotonic time in seconds, which starts over on reboot
+fn now() -> u64 {
+    let mut time = sys::timespec { tv_sec: 0, tv_nsec: 0 };
+    match sys::clock_gettime(sys::CLOCK_MONOTONIC, &mut time) {
+        Ok(_) => time.tv_sec as u64,
+        Err(_) => 0
+    }
+}
+
+// Writes the path of the record for name's candidate in directory (e.g. "x86-64-v3+bigmem")
+fn record_path<'a>(buffer: &'a mut [u8], name: &[u8], directory: &[u8], suffix: &[u8]) -> Option<&'a CStr> {
+    let len = path::join(buffer, &[FAILURES_PATH.to_bytes(), b"/", name, b"@", directory, suffix])?;
+    Some(unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) })
+}
+
+// Candidates of one program which failed recently
+pub struct Failures<'a> {
+    name: &'a [u8],
+    cooldown: u32,
+}
+
+impl<'a> Failures<'a> {
+    // name is the name of the program being executed (e.g. "foo"). A cooldown of 0 disables
+    // skipping.
+    pub fn new(name: &'a [u8], cooldown: u32) -> Self {
+        Failures { name, cooldown }
+    }
+
+    // Whether the candidate for level and suffix failed less than the cooldown ago
+    pub fn skips(&self, level: u32, suffix: &[u8]) -> bool {
+        if self.cooldown == 0 || level <= BASELINE_FEATURE_LEVEL {
+            return false
+        }
+
+        let mut buffer = [0u8; sys::PATH_MAX as usize];
+        let record = match record_path(&mut buffer, self.name, FEATURE_LEVELS[level as usize].directory, suffix) {
+            Some(r) => r,
+            None => return false
+        };
+
+        // Missing (or garbled) records are the usual case
+        let mut time = [0u8; RECORD_MAX];
+        let recorded = match sys::read_file(record, &mut time) {
+            Ok(len) => path::atoi(time[..len].trim_ascii()),
+            Err(_) => None
+        };
+
+        // Records from the future were made before a reboot
+        match recorded.and_then(|recorded| now().checked_sub(recorded as u64)) {
+            Some(age) => age < self.cooldown as u64,
+            None => false
+        }
+    }
+}
+
+// Whether the environment says the program crashed, or doesn't say anything
+fn is_crash(envp: *const *const c_char) -> bool {
+    match (sys::getenv(envp, EXIT_CODE_VAR), sys::getenv(envp, EXIT_STATUS_VAR)) {
+        (Some(b"killed" | b"dumped"), Some(status)) => CRASH_SIGNALS.contains(&status),
+        (Some(_), _) => false,
+        (None, _) => true
+    }
+}
+
+// Records a failure of program's candidate, for "hwcaps-loader --record-failure <program>"
+pub fn run(program: &[u8], envp: *const *const c_char, max_level: u32) -> ! {
+    // Clean exits and other signals aren't the build's fault
+    if !is_crash(envp) {
+        sys::exit(0)
+    }
+
+    let name = path::file_name(program);
+    let config = config::load(envp, name);
+    let max_level = match config.max_level {
+        Some(limit) => core::cmp::min(max_level, limit),
+        None => max_level
+    };
+    #[cfg(feature = "canary")]
+    let canary = canary::Canary::new(&config.canary, name);
+    let failures = Failures::new(name, config.failure_cooldown);
+
+    let candidates = match resolve::candidates(program, max_level) {
+        Ok(c) => c,
+        Err(_) => abort(&Fatal(ExitCode::TargetPathInvalid, "Invalid program!"), 0, Some(program))
+    };
+
+    // The one launches currently execute, skipping the same candidates they do
+    let candidate = candidates.filter(|c| !c.generic && !failures.skips(c.level, c.suffix))
+        .filter(|_c| {
+            #[cfg(feature = "ignore_markers")]
+            if ignore::is_ignored(HWCAPS_PATH, &_c.as_bytes()[HWCAPS_PATH.len()..]) {
+                return false
+            }
+            #[cfg(feature = "canary")]
+            if canary.skips(_c.level) {
+                return false
+            }
+            true
+        })
+        .find(|c| sys::faccessat(sys::AT_FDCWD, c.as_c_str(), sys::X_OK).is_ok());
+    let candidate = match candidate {
+        Some(c) if c.level > BASELINE_FEATURE_LEVEL => c,
+        // There's nothing to fall back to
+        _ => sys::exit(0)
+    };
+
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let record = match record_path(&mut buffer, name, FEATURE_LEVELS[candidate.level as usize].directory, candidate.suffix) {
+        Some(r) => r,
+        None => abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, Some(name))
+    };
+
+    let mut time = [0u8; RECORD_MAX];
+    let len = path::itoa(now() as u32, &mut time);
+    let flags = sys::O_WRONLY | sys::O_CREAT | sys::O_TRUNC | sys::O_NOFOLLOW;
+    let result = sys::openat_mode(sys::AT_FDCWD, record, flags, 0o644).and_then(|fd| {
+        let result = sys::write(fd, &time[..len]);
+        let _ = sys::close(fd);
+        result
+    });
+
+    if let Err(e) = result {
+        abort(&Fatal(ExitCode::FailureRecordIOError, "Failed to record failure!"), e.into_raw() as u32, Some(record.to_bytes()))
+    }
+
+    #[cfg(feature = "error_output")]
+    crate::output::hint("Candidate will be skipped for a while.", Some(candidate.as_bytes()));
+    sys::exit(0)
+}
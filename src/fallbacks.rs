@@ -0,0 +1,93 @@
+/*
+   Fallback counters.
+   Dispatching below the highest feature level this machine can use usually means the hwcaps
+   tree is missing builds, which still "works", but silently loses the optimization benefit.
+   Every such dispatch increments the counter in /run/hwcaps-loader/fallbacks/<program>, a
+   zero-padded decimal number (e.g. "0000001532") followed by a newline. Its width never changes,
+   so it's overwritten in place, and files stay the same size however often programs fall back.
+   Updates are serialized with flock() on the file. Fallbacks which happen while the lock is held
+   elsewhere aren't counted, as they never wait for it. Truncating or removing a file resets its
+   counter. The directory isn't created by us, and failing to count never prevents the target
+   from being executed.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::path;
+
+#[cfg(not(target_os = "freebsd"))]
+pub const FALLBACKS_PATH: &CStr = c"/run/hwcaps-loader/fallbacks";
+#[cfg(target_os = "freebsd")]
+pub const FALLBACKS_PATH: &CStr = c"/var/run/hwcaps-loader/fallbacks";
+
+// As many digits as u32::MAX has, and a newline
+const COUNTER_LEN: usize = 11;
+
+// Anything but a counter (e.g. an empty file) counts as 0
+fn parse(counter: &[u8]) -> u32 {
+    counter.strip_suffix(b"\n").and_then(path::atoi).unwrap_or(0)
+}
+
+fn format(mut count: u32) -> [u8; COUNTER_LEN] {
+    let mut counter = [b'\n'; COUNTER_LEN];
+    for digit in counter[..COUNTER_LEN - 1].iter_mut().rev() {
+        *digit = b'0' + (count % 10) as u8;
+        count /= 10;
+    }
+    counter
+}
+
+// Counts a fallback of the program called name
+pub fn record(name: &[u8]) {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let len = match path::join(&mut buffer, &[FALLBACKS_PATH.to_bytes(), b"/", name]) {
+        Some(l) => l,
+        None => return
+    };
+    let path = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+
+    let flags = sys::O_RDWR | sys::O_CREAT | sys::O_NOFOLLOW;
+    let fd = match sys::openat_mode(sys::AT_FDCWD, path, flags, 0o644) {
+        Ok(fd) => fd,
+        Err(_) => return
+    };
+
+    // Launches never wait for each other (or for whoever else holds the lock), so the fallback
+    // is dropped instead
+    if sys::flock(fd, sys::LOCK_EX | sys::LOCK_NB).is_ok() {
+        let mut counter = [0u8; COUNTER_LEN];
+        if let Ok(len) = sys::pread64(fd, &mut counter, 0) {
+            let _ = sys::pwrite64(fd, &format(parse(&counter[..len]).saturating_add(1)), 0);
+        }
+    }
+
+    // Closing the file releases the lock
+    let _ = sys::close(fd);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, parse};
+
+    #[test]
+    fn counters() {
+        assert_eq!(&format(0), b"0000000000\n");
+        assert_eq!(&format(1532), b"0000001532\n");
+        assert_eq!(&format(u32::MAX), b"4294967295\n");
+
+        let cases: &[(&[u8], u32)] = &[
+            (b"0000001532\n", 1532),
+            (b"4294967295\n", u32::MAX),
+            (b"12\n", 12),
+            // Truncated, or written by something else
+            (b"", 0),
+            (b"0000001532", 0),
+            (b"..........\n", 0),
+            (b"99999999999\n", 0),
+        ];
+        for (counter, count) in cases {
+            assert_eq!(parse(counter), *count, "{:?}", core::str::from_utf8(counter));
+        }
+    }
+}
@@ -0,0 +1,42 @@
+/*
+   HWCAPS_FORCE_TARGET debugging override.
+   Developers bisecting crashes across feature levels can bypass level selection and
+   execute a specific candidate instead:
+   HWCAPS_FORCE_TARGET=/usr/hwcaps/x86-64-v2/bin/foo foo [args...]
+   The variable is inherited by child processes, so it's ignored by programs other than the
+   one it names. It's also ignored in secure-execution mode (setuid/setgid programs, for
+   example), since it would let unprivileged users pick what privileged programs run.
+*/
+
+use core::ffi::{c_char, CStr};
+
+use crate::sys::{self, ExitCode};
+use crate::output::{abort, Fatal};
+use crate::HWCAPS_PATH;
+
+const FORCE_TARGET_VAR: &'static [u8] = b"HWCAPS_FORCE_TARGET";
+
+// Returns the forced candidate for bin_path (e.g. "/bin/foo", null-terminated), if there's one
+pub fn get_forced_target(envp: *const *const c_char, bin_path: &[u8]) -> Option<&'static CStr> {
+    let target = sys::getenv(envp, FORCE_TARGET_VAR)?;
+
+    if sys::is_secure() {
+        return None
+    }
+
+    // Meant for another program
+    let bin_path = &bin_path[..bin_path.len()-1];
+    let directory = target.strip_suffix(bin_path)?;
+
+    // The candidate must be inside one of the hwcaps directories: /usr/hwcaps/<directory>/bin/foo
+    let valid = match directory.strip_prefix(HWCAPS_PATH) {
+        Some(d) => !d.is_empty() && d != b"." && d != b".." && !d.contains(&b'/'),
+        None => false
+    };
+    if !valid {
+        abort(&Fatal(ExitCode::TargetPathInvalid, "Forced target isn't inside of the hwcaps directories!"), 0, Some(target))
+    }
+
+    // Environment variables are null-terminated
+    Some(unsafe { CStr::from_ptr(target.as_ptr() as *const c_char) })
+}
@@ -0,0 +1,189 @@
+/*
+   Pre-exec hook.
+   Sites can run a program of their own right before a candidate is executed (for logging,
+   license metering or warming caches up) with "pre_exec_hook = <path>" in the configuration.
+   The loader runs it in a child process, and waits for it:
+   <hook> <alias> <candidate> <level>
+   e.g. "/usr/libexec/site-hook foo /usr/hwcaps/x86-64-v3/bin/foo x86-64-v3", with the environment
+   the candidate gets. <level> is the candidate's directory, with its variant suffix (e.g.
+   x86-64-v3+bigmem), or "generic". If a candidate fails to execute, the hook runs again for the
+   next one.
+   - pre_exec_hook_timeout = <ms>            -> How long the hook may take before it's killed
+                                               (1000 by default)
+   - pre_exec_hook_failure = <continue|abort> -> What to do when the hook can't be run, exits
+                                               with anything but 0, is killed or times out:
+                                               execute the candidate anyway (after a warning,
+                                               the default), or fail with PreExecHookFailed,
+                                               for hooks which mustn't be skipped
+   The hook must be owned by root and not writable by anyone else, since it runs as whoever
+   executes the program. It's never run in secure-execution mode (setuid/setgid programs, for
+   example), where it would run with the program's privileges in the caller's environment.
+   If SIGCHLD is ignored, the kernel reaps the hook by itself and its exit status is lost, so
+   hooks which exited in time count as successful.
+*/
+
+use core::ffi::{c_char, c_int, CStr};
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::output::{abort, Fatal};
+
+const DEFAULT_TIMEOUT_MS: u32 = 1000;
+// How often the hook is checked on, on kernels without pidfds (before 5.3)
+const POLL_INTERVAL_MS: u32 = 10;
+const NAME_MAX: usize = 255;
+// What the child exits with if the hook couldn't be executed, like shells do
+const EXEC_FAILED: u8 = 127;
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    // Null-terminated. Empty if there's no hook.
+    path: [u8; sys::PATH_MAX as usize],
+    len: usize,
+    timeout_ms: u32,
+    abort_on_failure: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { path: [0; sys::PATH_MAX as usize], len: 0, timeout_ms: DEFAULT_TIMEOUT_MS, abort_on_failure: false }
+    }
+}
+
+impl Settings {
+    // An empty path removes the hook. Returns None if it isn't absolute.
+    pub fn set_path(&mut self, path: &[u8]) -> Option<()> {
+        if !path.is_empty() && (path[0] != b'/' || path.len() >= self.path.len() || path.contains(&b'\0')) {
+            return None
+        }
+
+        self.path[..path.len()].copy_from_slice(path);
+        self.path[path.len()] = b'\0';
+        self.len = path.len();
+        Some(())
+    }
+
+    // Parses a timeout in milliseconds. Returns None if it isn't a positive number.
+    pub fn set_timeout(&mut self, value: &[u8]) -> Option<()> {
+        // poll() takes a signed timeout
+        self.timeout_ms = path::atoi(value).filter(|ms| *ms != 0 && *ms <= c_int::MAX as u32)?;
+        Some(())
+    }
+
+    // Parses "continue" or "abort". Returns None if it's neither.
+    pub fn set_failure(&mut self, value: &[u8]) -> Option<()> {
+        self.abort_on_failure = match value {
+            b"continue" => false,
+            b"abort" => true,
+            _ => return None
+        };
+        Some(())
+    }
+
+    fn path(&self) -> &CStr {
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.path[..self.len+1]) }
+    }
+
+    // Runs the hook for alias' candidate, built for level. Aborts if it fails, and failures
+    // mustn't be ignored.
+    pub fn run(&self, alias: &CStr, candidate: &CStr, level: &[u8], envp: *const *const c_char) {
+        let errno = match self.try_run(alias, candidate, level, envp) {
+            Ok(()) => return,
+            Err(errno) => errno
+        };
+
+        if self.abort_on_failure {
+            abort(&Fatal(ExitCode::PreExecHookFailed, "Pre-exec hook failed!"), errno, Some(self.path().to_bytes()))
+        }
+
+        #[cfg(feature = "error_output")]
+        crate::output::hint("Pre-exec hook failed, executing the program anyway.", Some(self.path().to_bytes()));
+    }
+
+    // Returns the errno of whatever failed, or 0 if the hook itself did
+    fn try_run(&self, alias: &CStr, candidate: &CStr, level: &[u8], envp: *const *const c_char) -> Result<(), u32> {
+        let path = self.path();
+        check_owner(path)?;
+
+        let mut level_buffer = [0u8; NAME_MAX + 1];
+        let len = path::join(&mut level_buffer, &[level]).ok_or(sys::Errno::ENAMETOOLONG.into_raw() as u32)?;
+        let level = unsafe { CStr::from_bytes_with_nul_unchecked(&level_buffer[..len+1]) };
+
+        let argv = [path.as_ptr(), alias.as_ptr(), candidate.as_ptr(), level.as_ptr(), core::ptr::null()];
+        let pid = sys::fork().map_err(|e| e.into_raw() as u32)?;
+        if pid == 0 {
+            let _ = sys::execve(path, argv.as_ptr(), envp);
+            sys::exit(EXEC_FAILED)
+        }
+
+        match wait(pid, self.timeout_ms)? {
+            Some(0) | None => Ok(()),
+            Some(_) => Err(0)
+        }
+    }
+}
+
+// Returns the hook to run before executing candidates, if there's one
+pub fn get(settings: &Settings) -> Option<&Settings> {
+    if settings.len == 0 || sys::is_secure() {
+        return None
+    }
+    Some(settings)
+}
+
+fn check_owner(path: &CStr) -> Result<(), u32> {
+    let mut buffer = unsafe { core::mem::zeroed::<sys::statx>() };
+    let mask = sys::STATX_MODE | sys::STATX_UID;
+    sys::statx(sys::AT_FDCWD, path, 0, mask, &mut buffer).map_err(|e| e.into_raw() as u32)?;
+
+    match buffer.stx_uid == 0 && buffer.stx_mode as u32 & 0o022 == 0 {
+        true => Ok(()),
+        false => Err(sys::EPERM)
+    }
+}
+
+// Waits up to timeout_ms for pid to exit, killing it if it doesn't. Returns its wait status (0 if
+// it exited with 0), or None if it's unknown (SIGCHLD is ignored).
+fn wait(pid: u32, timeout_ms: u32) -> Result<Option<c_int>, u32> {
+    let result = match sys::pidfd_open(pid) {
+        Ok(fd) => {
+            let mut fds = [sys::pollfd { fd, events: sys::POLLIN as i16, revents: 0 }];
+            let exited = sys::poll(&mut fds, timeout_ms as c_int) == Ok(1);
+            let _ = sys::close(fd);
+
+            let mut status = 0;
+            exited.then(|| sys::wait4(pid, &mut status, 0).map(|_| status))
+        },
+        Err(_) => poll_exit(pid, timeout_ms)
+    };
+
+    match result {
+        Some(Ok(status)) => Ok(Some(status)),
+        Some(Err(e)) if e.into_raw() as u32 == sys::ECHILD => Ok(None),
+        Some(Err(e)) => Err(e.into_raw() as u32),
+        None => {
+            // Always reaped, or it would linger until the candidate exits
+            let _ = sys::kill(pid, sys::SIGKILL);
+            let _ = sys::wait4(pid, &mut 0, 0);
+            Err(sys::ETIMEDOUT)
+        }
+    }
+}
+
+// Like waiting on a pidfd, for kernels without them. Returns None if pid didn't exit in time.
+fn poll_exit(pid: u32, timeout_ms: u32) -> Option<Result<c_int, sys::Errno>> {
+    let interval = sys::timespec { tv_sec: 0, tv_nsec: (POLL_INTERVAL_MS * 1_000_000) as _ };
+    let mut waited = 0;
+
+    loop {
+        let mut status = 0;
+        match sys::wait4(pid, &mut status, sys::WNOHANG) {
+            Ok(0) if waited < timeout_ms => (),
+            Ok(0) => return None,
+            result => return Some(result.map(|_| status))
+        }
+
+        let _ = sys::clock_nanosleep(sys::CLOCK_MONOTONIC, &interval);
+        waited += POLL_INTERVAL_MS;
+    }
+}
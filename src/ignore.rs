@@ -0,0 +1,62 @@
+/*
+   Opt-out markers.
+   When a batch of optimized builds turns out to be broken, operators need a kill switch which
+   works right away, without removing files packages own or waiting for fixed ones. A
+   ".hwcaps-ignore" file in a level's directory, or any directory beneath it, makes the loader
+   skip candidates under that directory:
+   - An empty marker skips all of them
+     (e.g. /usr/hwcaps/x86-64-v3/.hwcaps-ignore skips the whole level)
+   - Otherwise, it lists the ones to skip, relative to its directory, one per line
+     (e.g. "bin/foo" in /usr/hwcaps/x86-64-v3/.hwcaps-ignore, or "foo" in
+     /usr/hwcaps/x86-64-v3/bin/.hwcaps-ignore)
+   Lines starting with '#' are comments, and don't make a marker non-empty.
+   Skipped candidates are handled like missing ones, so lower levels are tried instead.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::path;
+
+pub const MARKER_NAME: &[u8] = b".hwcaps-ignore";
+const MARKER_MAX: usize = 4096;
+
+// Whether a marker's contents skip name
+fn skips(marker: &[u8], name: &[u8]) -> bool {
+    let mut lines = marker.split(|c| *c == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .peekable();
+
+    lines.peek().is_none() || lines.any(|line| line == name)
+}
+
+// Whether a marker skips candidate, relative to hwcaps_dir (e.g. "x86-64-v3/bin/foo" in "/usr/hwcaps/")
+pub fn is_ignored(hwcaps_dir: &[u8], candidate: &[u8]) -> bool {
+    let mut marker_path = [0u8; sys::PATH_MAX as usize];
+    let mut marker = [0u8; MARKER_MAX];
+
+    // Every directory from the level's down to the candidate's own
+    for (i, _) in candidate.iter().enumerate().filter(|(_, c)| **c == b'/') {
+        let len = match path::join(&mut marker_path, &[hwcaps_dir, &candidate[..i+1], MARKER_NAME]) {
+            Some(l) => l,
+            None => return false
+        };
+        let c_str = unsafe { CStr::from_bytes_with_nul_unchecked(&marker_path[..len+1]) };
+
+        let len = match sys::read_file(c_str, &mut marker) {
+            Ok(l) => l,
+            Err(_) => continue
+        };
+        // Whatever's past the limit is left out, including a partial last line
+        let len = match len == MARKER_MAX {
+            true => marker.iter().rposition(|c| *c == b'\n').unwrap_or(0),
+            false => len
+        };
+
+        if skips(&marker[..len], &candidate[i+1..]) {
+            return true
+        }
+    }
+    false
+}
@@ -0,0 +1,66 @@
+/*
+   Strict integrity.
+   By default, the loader moves on to the next candidate whenever execve() fails with ENOENT,
+   which also happens when a candidate exists but can't be executed as-is (a dangling symlink, or
+   a missing ELF interpreter), and other failures can be recovered from with on_error = baseline.
+   Either way, a broken or tampered build for a supported level quietly turns into a slower one.
+   With "integrity = strict" in the configuration, a candidate which exists but can't be executed
+   (not executable, wrong format, missing interpreter...) makes the loader fail with
+   CandidateUnusable instead, which on_error = baseline doesn't recover from. Only candidates
+   which don't exist at all are skipped. The resolution daemon's answers are ignored, since it
+   can't tell missing candidates from unusable ones.
+   Candidates which fail interp_check, signatures or build_ids already abort with their own codes,
+   whatever the policy. Candidates skipped by the administrator (.hwcaps-ignore markers, canary rollouts,
+   failure memory) are still skipped.
+*/
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::ffi::CStr;
+
+use crate::sys::{self, Errno, ExitCode};
+use crate::path;
+use crate::output::{abort, Fatal};
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+// Makes check() abort for every candidate which exists but can't be executed, from now on
+pub fn enable() {
+    STRICT.store(true, Ordering::Relaxed);
+}
+
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+// Whether there's anything at path, including a dangling symlink
+fn exists(path: &[u8]) -> bool {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let len = match path::join(&mut buffer, &[path]) {
+        Some(l) => l,
+        // Too large to check, so it can't be skipped either
+        None => return true
+    };
+    let path = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+
+    match sys::faccessat(sys::AT_FDCWD, path, sys::F_OK) {
+        Ok(_) => true,
+        // Symlinks exist even if their target doesn't
+        Err(e) if matches!(e.into_raw() as u32, sys::ENOENT | sys::ENOTDIR) => sys::readlinkat(sys::AT_FDCWD, path, &mut [0u8; 1]).is_ok(),
+        Err(_) => true
+    }
+}
+
+// Aborts if strict integrity is enabled, and executing the candidate at path failed with errno
+// even though it exists
+pub fn check(errno: Errno, path: &[u8]) {
+    if !is_strict() {
+        return
+    }
+
+    match errno.into_raw() as u32 {
+        sys::ENOENT if !exists(path) => (),
+        // Another candidate wouldn't take fewer arguments, so it's handled as usual
+        sys::E2BIG => (),
+        other => abort(&Fatal(ExitCode::CandidateUnusable, "Candidate exists, but can't be executed!"), other, Some(path))
+    }
+}
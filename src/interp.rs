@@ -0,0 +1,196 @@
+/*
+   Interpreter allow-list.
+   Before executing a candidate, its program headers are read and its interpreter (PT_INTERP) is
+   compared with a list of allowed ones. A writable hwcaps subtree could otherwise be used to
+   smuggle in a binary which runs with a rogue dynamic loader.
+   Static binaries don't have an interpreter, and are always allowed. Files which aren't ELF
+   binaries (scripts) aren't checked.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys::{self, ExitCode};
+use crate::output::{abort, Fatal};
+
+#[cfg(target_arch = "x86_64")]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib64/ld-linux-x86-64.so.2", b"/lib/ld-musl-x86_64.so.1"];
+
+#[cfg(target_arch = "x86")]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib/ld-linux.so.2", b"/lib/ld-musl-i386.so.1"];
+
+#[cfg(target_arch = "aarch64")]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib/ld-linux-aarch64.so.1", b"/lib/ld-musl-aarch64.so.1"];
+
+#[cfg(target_arch = "arm")]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib/ld-linux-armhf.so.3", b"/lib/ld-musl-armhf.so.1"];
+
+#[cfg(all(target_arch = "mips", target_endian = "little"))]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib/ld.so.1", b"/lib/ld-musl-mipsel.so.1"];
+
+#[cfg(all(target_arch = "mips", target_endian = "big"))]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib/ld.so.1", b"/lib/ld-musl-mips.so.1"];
+
+#[cfg(all(target_arch = "mips64", target_endian = "little"))]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib64/ld.so.1", b"/lib/ld-musl-mips64el.so.1"];
+
+#[cfg(all(target_arch = "mips64", target_endian = "big"))]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib64/ld.so.1", b"/lib/ld-musl-mips64.so.1"];
+
+#[cfg(target_arch = "riscv64")]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib/ld-linux-riscv64-lp64d.so.1", b"/lib/ld-musl-riscv64.so.1"];
+
+#[cfg(all(target_arch = "powerpc64", target_endian = "little"))]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib64/ld64.so.2", b"/lib/ld-musl-powerpc64le.so.1"];
+
+#[cfg(all(target_arch = "powerpc64", target_endian = "big"))]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib64/ld64.so.1", b"/lib/ld-musl-powerpc64.so.1"];
+
+#[cfg(target_arch = "s390x")]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib/ld64.so.1", b"/lib/ld-musl-s390x.so.1"];
+
+#[cfg(target_arch = "loongarch64")]
+const DEFAULT_INTERPRETERS: &[&[u8]] = &[b"/lib64/ld-linux-loongarch-lp64d.so.1", b"/lib/ld-musl-loongarch64.so.1"];
+
+const MAX_INTERPRETERS: usize = 8;
+const INTERPRETER_MAX: usize = 128;
+
+// Enough for the program headers of any reasonable binary
+const HEADERS_MAX: usize = 4096;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+#[cfg(target_endian = "little")]
+const ELFDATA_NATIVE: u8 = 1;
+#[cfg(target_endian = "big")]
+const ELFDATA_NATIVE: u8 = 2;
+const PT_INTERP: u32 = 3;
+
+// Interpreters configured by the administrator
+#[derive(Clone, Copy)]
+pub struct Interpreters {
+    paths: [[u8; INTERPRETER_MAX]; MAX_INTERPRETERS],
+    lens: [usize; MAX_INTERPRETERS],
+    count: usize,
+}
+
+impl Interpreters {
+    pub fn new() -> Self {
+        Interpreters { paths: [[0; INTERPRETER_MAX]; MAX_INTERPRETERS], lens: [0; MAX_INTERPRETERS], count: 0 }
+    }
+
+    pub fn push(&mut self, path: &[u8]) -> Result<(), ()> {
+        if self.count == MAX_INTERPRETERS || path.len() > INTERPRETER_MAX || path.first() != Some(&b'/') {
+            return Err(())
+        }
+
+        self.paths[self.count][..path.len()].copy_from_slice(path);
+        self.lens[self.count] = path.len();
+        self.count += 1;
+        Ok(())
+    }
+
+    fn contains(&self, path: &[u8]) -> bool {
+        (0..self.count).any(|i| self.paths[i][..self.lens[i]] == *path)
+    }
+}
+
+fn read_exact(fd: i32, buffer: &mut [u8], offset: u64) -> Result<(), ()> {
+    match sys::pread64(fd, buffer, offset) {
+        Ok(n) if n == buffer.len() => Ok(()),
+        _ => Err(())
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> usize {
+    u16::from_ne_bytes([bytes[offset], bytes[offset+1]]) as usize
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u64 {
+    u32::from_ne_bytes(bytes[offset..offset+4].try_into().unwrap()) as u64
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_ne_bytes(bytes[offset..offset+8].try_into().unwrap())
+}
+
+// Returns the length of the interpreter's path (0 if there's none), or None if the file isn't an ELF binary.
+// Malformed binaries are Err.
+fn read_interpreter(fd: i32, buffer: &mut [u8]) -> Result<Option<usize>, ()> {
+    let mut header = [0u8; 64];
+    let header_len = match sys::pread64(fd, &mut header, 0) {
+        Ok(n) if n >= 52 && header[..4] == *ELF_MAGIC => n,
+        Ok(_) => return Ok(None),
+        Err(_) => return Err(())
+    };
+
+    if header[5] != ELFDATA_NATIVE {
+        return Err(())
+    }
+
+    // Offsets of e_phoff, e_phentsize and e_phnum, and of p_offset and p_filesz
+    let (phoff, phentsize, phnum, p_offset, p_filesz) = match header[4] {
+        ELFCLASS64 => (read_u64(&header, 32), read_u16(&header, 54), read_u16(&header, 56), 8, 32),
+        ELFCLASS32 => (read_u32(&header, 28), read_u16(&header, 42), read_u16(&header, 44), 4, 16),
+        _ => return Err(())
+    };
+    let is_64 = header[4] == ELFCLASS64;
+
+    if is_64 && header_len < header.len() {
+        return Err(())
+    }
+
+    let size = phentsize * phnum;
+    if phentsize < p_filesz + 8 || size > HEADERS_MAX {
+        return Err(())
+    }
+
+    let mut headers = [0u8; HEADERS_MAX];
+    read_exact(fd, &mut headers[..size], phoff)?;
+
+    for entry in headers[..size].chunks_exact(phentsize) {
+        if read_u32(entry, 0) as u32 != PT_INTERP {
+            continue
+        }
+
+        let (offset, len) = match is_64 {
+            true => (read_u64(entry, p_offset), read_u64(entry, p_filesz)),
+            false => (read_u32(entry, p_offset), read_u32(entry, p_filesz))
+        };
+
+        // The path includes its terminator
+        if len < 2 || len as usize > buffer.len() {
+            return Err(())
+        }
+        let len = len as usize;
+
+        read_exact(fd, &mut buffer[..len], offset)?;
+        if buffer[len-1] != b'\0' {
+            return Err(())
+        }
+        return Ok(Some(len - 1))
+    }
+
+    Ok(Some(0))
+}
+
+// Aborts if the candidate (open as fd) has an interpreter which isn't allowed.
+// allowed replaces the default list if set.
+pub fn check(fd: i32, path: &CStr, allowed: Option<&Interpreters>) {
+    let mut interpreter = [0u8; sys::PATH_MAX as usize];
+    let len = match read_interpreter(fd, &mut interpreter) {
+        Ok(Some(l)) => l,
+        Ok(None) => return,
+        Err(()) => abort(&Fatal(ExitCode::TargetInterpreterRejected, "Target binary is malformed!"), 0, Some(path.to_bytes()))
+    };
+    let interpreter = &interpreter[..len];
+
+    let is_allowed = match allowed {
+        Some(list) => list.contains(interpreter),
+        None => DEFAULT_INTERPRETERS.contains(&interpreter)
+    };
+
+    if len != 0 && !is_allowed {
+        abort(&Fatal(ExitCode::TargetInterpreterRejected, "Target binary's interpreter isn't allowed!"), 0, Some(path.to_bytes()))
+    }
+}
@@ -0,0 +1,31 @@
+/*
+   Last error file.
+   Services often run with stdout closed, so an abort's message can't always be printed. When
+   it can't, it's written to /run/hwcaps-loader/last-error instead (replacing the previous one),
+   in the usual format plus the exit code, so administrators can still find out why a service's
+   binary failed to dispatch. The file's modification time is when it happened.
+   The directory isn't created here, and the file is only written by processes allowed to, so
+   distributions decide who gets to record errors (e.g. with a tmpfiles.d entry).
+*/
+
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::output::{self, Fatal};
+
+#[cfg(not(target_os = "freebsd"))]
+pub const LAST_ERROR_PATH: &CStr = c"/run/hwcaps-loader/last-error";
+#[cfg(target_os = "freebsd")]
+pub const LAST_ERROR_PATH: &CStr = c"/var/run/hwcaps-loader/last-error";
+
+// Best effort: we're about to exit anyway
+pub fn record(fatal: &Fatal, errno: u32, path: Option<&[u8]>) {
+    let flags = sys::O_WRONLY | sys::O_CREAT | sys::O_TRUNC | sys::O_NOFOLLOW;
+    let fd = match sys::openat_mode(sys::AT_FDCWD, LAST_ERROR_PATH, flags, 0o644) {
+        Ok(fd) => fd,
+        Err(_) => return
+    };
+
+    let _ = output::print_to(fd, Some(fatal.0), fatal.1, errno, path);
+    let _ = sys::close(fd);
+}
@@ -0,0 +1,95 @@
+/*
+   Exec latency reports.
+   Right before executing the target, a datagram describing the dispatch is sent to a unix socket,
+   so performance teams can measure the loader's overhead across a fleet without tracing it.
+   Datagrams are a single line, e.g. "program=foo level=x86-64-v3+bigmem usec=42".
+   The socket is /run/hwcaps-loader/latency.sock, unless "latency_socket" is set in the
+   configuration. Nothing is sent if nobody is listening, and failing to send never prevents the
+   target from being executed.
+*/
+
+use crate::sys;
+use crate::capabilities::FEATURE_LEVELS;
+use crate::path::itoa;
+
+#[cfg(not(target_os = "freebsd"))]
+const DEFAULT_SOCKET: &'static [u8] = b"/run/hwcaps-loader/latency.sock";
+#[cfg(target_os = "freebsd")]
+const DEFAULT_SOCKET: &[u8] = b"/var/run/hwcaps-loader/latency.sock";
+const REPORT_MAX: usize = 512;
+
+// Path of a unix socket, which has to fit in sockaddr_un
+#[derive(Clone, Copy)]
+pub struct SocketPath {
+    path: [u8; SocketPath::MAX],
+    len: usize,
+}
+
+impl SocketPath {
+    // Leaves room for the terminator
+    const MAX: usize = 107;
+
+    pub fn new(path: &[u8]) -> Option<Self> {
+        if path.first() != Some(&b'/') || path.len() > Self::MAX || path.contains(&b'\0') {
+            return None
+        }
+
+        let mut socket = SocketPath { path: [0; Self::MAX], len: path.len() };
+        socket.path[..path.len()].copy_from_slice(path);
+        Some(socket)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.path[..self.len]
+    }
+}
+
+// Monotonic time in nanoseconds
+pub fn now() -> u64 {
+    let mut time = sys::timespec { tv_sec: 0, tv_nsec: 0 };
+    match sys::clock_gettime(sys::CLOCK_MONOTONIC, &mut time) {
+        Ok(_) => time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64,
+        Err(_) => 0
+    }
+}
+
+// start is the value of now() when the loader started
+pub fn report(socket: Option<&SocketPath>, program: &[u8], level: u32, suffix: &[u8], start: u64) {
+    let usec = now().saturating_sub(start) / 1000;
+
+    let mut report = [0u8; REPORT_MAX];
+    let mut len = 0;
+    let mut push = |bytes: &[u8]| {
+        // Reports are best effort, so truncate anything unreasonably long
+        let n = core::cmp::min(bytes.len(), REPORT_MAX - len);
+        report[len..len + n].copy_from_slice(&bytes[..n]);
+        len += n;
+    };
+
+    let mut digits = [0u8; 10];
+    let digits_len = itoa(core::cmp::min(usec, u32::MAX as u64) as u32, &mut digits);
+
+    push(b"program=");
+    push(program);
+    push(b" level=");
+    push(FEATURE_LEVELS[level as usize].directory);
+    push(suffix);
+    push(b" usec=");
+    push(&digits[..digits_len]);
+
+    let mut address = unsafe { core::mem::zeroed::<sys::sockaddr_un>() };
+    address.sun_family = sys::AF_UNIX as sys::sa_family_t;
+
+    let path = socket.map_or(DEFAULT_SOCKET, SocketPath::as_bytes);
+    for (dst, src) in address.sun_path.iter_mut().zip(path) {
+        *dst = *src as _;
+    }
+
+    let fd = match sys::socket(sys::AF_UNIX, sys::SOCK_DGRAM | sys::SOCK_CLOEXEC, 0) {
+        Ok(fd) => fd,
+        Err(_) => return
+    };
+
+    let _ = sys::sendto(fd, &report[..len], sys::MSG_DONTWAIT, &address);
+    let _ = sys::close(fd);
+}
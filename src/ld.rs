@@ -0,0 +1,118 @@
+/*
+   Explicit dynamic loader invocation.
+   Instead of executing candidates directly, execute them through the dynamic loader and
+   point it at the feature level's library directory. This way, optimized binaries pick up
+   matching optimized libraries without modifying the global environment:
+   <loader> --library-path /usr/hwcaps/<level>/<libdir> --argv0 <argv0> <candidate> [args...]
+   (--argv0 requires glibc 2.33 or newer)
+*/
+
+use core::ffi::{c_char, CStr};
+use core::ptr;
+use crate::sys::Errno;
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::output::{abort, Fatal};
+use crate::resolve::LIBRARY_DIR;
+
+#[cfg(target_arch = "x86_64")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib64/ld-linux-x86-64.so.2";
+
+#[cfg(target_arch = "x86")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib/ld-linux.so.2";
+
+#[cfg(target_arch = "aarch64")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib/ld-linux-aarch64.so.1";
+
+#[cfg(target_arch = "arm")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib/ld-linux-armhf.so.3";
+
+#[cfg(target_arch = "mips")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib/ld.so.1";
+
+#[cfg(target_arch = "mips64")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib64/ld.so.1";
+
+#[cfg(target_arch = "riscv64")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib/ld-linux-riscv64-lp64d.so.1";
+
+#[cfg(all(target_arch = "powerpc64", target_endian = "little"))]
+const DYNAMIC_LOADER: &'static CStr = c"/lib64/ld64.so.2";
+
+#[cfg(all(target_arch = "powerpc64", target_endian = "big"))]
+const DYNAMIC_LOADER: &'static CStr = c"/lib64/ld64.so.1";
+
+#[cfg(target_arch = "s390x")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib/ld64.so.1";
+
+#[cfg(target_arch = "loongarch64")]
+const DYNAMIC_LOADER: &'static CStr = c"/lib64/ld-linux-loongarch-lp64d.so.1";
+
+// Arguments we add before the original ones: loader, --library-path, path, --argv0, argv0, candidate
+const EXTRA_ARGS: usize = 5;
+
+// Like sys::execve, but through the dynamic loader. Returns ENOENT if the candidate doesn't exist.
+// directory is the candidate's hwcaps directory (e.g. "/usr/hwcaps/x86-64-v3").
+// Set-id candidates are executed directly, as their bits only apply when they're executed themselves.
+//
+// Safety: argv must hold argc arguments followed by a terminator, like main()'s.
+pub unsafe fn execve(path: &CStr, directory: &[u8], argc: i32, argv: *const *const c_char, envp: *const *const c_char) -> Errno {
+    // The dynamic loader would happily start and then fail, so check if the candidate exists beforehand
+    if let Err(e) = sys::faccessat(sys::AT_FDCWD, path, sys::X_OK) {
+        return e
+    }
+
+    let mut stat = unsafe { core::mem::zeroed::<sys::statx>() };
+    if let Err(e) = sys::statx(sys::AT_FDCWD, path, 0, sys::STATX_MODE, &mut stat) {
+        return e
+    }
+    if stat.stx_mode as u32 & (sys::S_ISUID | sys::S_ISGID) != 0 {
+        return sys::execve(path, argv, envp)
+    }
+
+    let mut library_path = [0u8; sys::PATH_MAX as usize];
+    let library_path_len = match path::join(&mut library_path, &[directory, LIBRARY_DIR]) {
+        Some(len) => len,
+        None => abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), (directory.len() + LIBRARY_DIR.len()) as u32, None)
+    };
+
+    // --library-path overrides LD_LIBRARY_PATH, so its directories are searched after ours
+    let inherited = sys::getenv(envp, b"LD_LIBRARY_PATH").filter(|value| !value.is_empty()).unwrap_or(b"");
+    let library_path_size = library_path_len + 1 + inherited.len() + 1;
+
+    // Allocate the new argument vector (including its terminator) and the library path outside of
+    // the stack, since their sizes depend on argc and the environment.
+    let new_argc = argc as usize + EXTRA_ARGS;
+    let argv_size = (new_argc + 1) * size_of::<*const c_char>();
+    let prot = sys::PROT_READ | sys::PROT_WRITE;
+    let flags = sys::MAP_PRIVATE | sys::MAP_ANONYMOUS;
+
+    let new_argv = match sys::mmap(ptr::null_mut(), argv_size + library_path_size, prot, flags, -1, 0) {
+        Ok(p) => p as *mut *const c_char,
+        Err(e) => return e
+    };
+
+    // Anonymous mappings are zeroed, so the library path is already terminated
+    let full_library_path = unsafe { core::slice::from_raw_parts_mut((new_argv as *mut u8).add(argv_size), library_path_size) };
+    full_library_path[..library_path_len].copy_from_slice(&library_path[..library_path_len]);
+    if !inherited.is_empty() {
+        full_library_path[library_path_len] = b':';
+        full_library_path[library_path_len + 1..library_path_size - 1].copy_from_slice(inherited);
+    }
+
+    unsafe {
+        new_argv.add(0).write(DYNAMIC_LOADER.as_ptr());
+        new_argv.add(1).write(c"--library-path".as_ptr());
+        new_argv.add(2).write(full_library_path.as_ptr() as *const c_char);
+        new_argv.add(3).write(c"--argv0".as_ptr());
+        new_argv.add(4).write(*argv);
+        new_argv.add(5).write(path.as_ptr());
+
+        // Copy the original arguments (except argv0), along with the terminator
+        ptr::copy_nonoverlapping(argv.add(1), new_argv.add(EXTRA_ARGS + 1), argc as usize);
+    }
+
+    let errno = sys::execve(DYNAMIC_LOADER, new_argv, envp);
+    abort(&Fatal(ExitCode::TargetExecutionError, "Failed to execute the dynamic loader!"), errno.into_raw() as u32, Some(DYNAMIC_LOADER.to_bytes()))
+}
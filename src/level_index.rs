@@ -0,0 +1,66 @@
+/*
+   Level index.
+   Candidates are tried from the highest feature level down, so every level without binaries on
+   this system costs a failed execve() on every execution. Distributions can keep an index of the
+   level directories which actually exist (/usr/hwcaps/.levels, one name per line, written by
+   "hwcaps-loader reindex"), so candidates in those directories are tried first and the common
+   case hits on the first attempt.
+   The order between indexed candidates is unchanged, since the best build must always win.
+   Candidates in directories which aren't indexed are still tried afterwards, in case the index
+   is out of date.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::capabilities::FEATURE_LEVELS;
+use crate::variants::Variants;
+
+#[cfg(not(target_os = "freebsd"))]
+pub const INDEX_PATH: &'static CStr = c"/usr/hwcaps/.levels";
+#[cfg(target_os = "freebsd")]
+pub const INDEX_PATH: &CStr = c"/usr/local/hwcaps/.levels";
+// Relative to the hwcaps directory
+pub const INDEX_FILE: &'static CStr = c".levels";
+pub const INDEX_TMP_FILE: &'static CStr = c".levels.tmp";
+pub const INDEX_MAX: usize = 1024;
+
+pub struct LevelIndex {
+    buffer: [u8; INDEX_MAX],
+    // None if there's no usable index
+    len: Option<usize>,
+}
+
+impl LevelIndex {
+    pub fn load() -> Self {
+        let mut index = LevelIndex { buffer: [0; INDEX_MAX], len: None };
+
+        // Truncated indexes can't be trusted
+        index.len = match sys::read_file(INDEX_PATH, &mut index.buffer) {
+            Ok(len) if len < INDEX_MAX => Some(len),
+            _ => None
+        };
+        index
+    }
+
+    // Whether the directory for level and suffix (e.g. "x86-64-v3+bigmem") is indexed.
+    // Everything is, if there's no index.
+    fn contains(&self, level: u32, suffix: &[u8]) -> bool {
+        let len = match self.len {
+            Some(l) => l,
+            None => return true
+        };
+
+        let directory = FEATURE_LEVELS[level as usize].directory;
+        self.buffer[..len].split(|c| *c == b'\n').any(|line| {
+            line.len() == directory.len() + suffix.len() && line.starts_with(directory) && line.ends_with(suffix)
+        })
+    }
+
+    // Same as variants.candidates(max_level), but indexed candidates come first
+    pub fn candidates<'a>(&'a self, variants: &'a Variants, max_level: u32) -> impl Iterator<Item = (u32, &'static [u8])> + 'a {
+        let indexed = variants.candidates(max_level).filter(|(level, suffix)| self.contains(*level, suffix));
+        let others = variants.candidates(max_level).filter(|(level, suffix)| !self.contains(*level, suffix));
+        indexed.chain(others)
+    }
+}
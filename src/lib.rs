@@ -0,0 +1,196 @@
+/*
+ * Copyright (C) 2024 José Relvas.
+ *
+ * This program is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU General Public License as
+ * published by the Free Software Foundation; either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program; if not, see <http://www.gnu.org/licenses/>.
+ *
+ * Written by:
+ *     José Relvas <josemonsantorelvas@gmail.com>
+ */
+
+/*
+   hwcaps-loader as a library.
+   The loader's selection policy is available to other programs through the capabilities,
+   variants and resolve modules, so they can make the same decisions without reimplementing them.
+   Package managers can check the trees they install through the transaction module.
+   The remaining modules are only public for the loader binary's sake.
+*/
+
+#![no_std]
+// Inline assembly is still unstable there
+#![cfg_attr(any(target_arch = "mips", target_arch = "mips64"), feature(asm_experimental_arch))]
+
+// These need interfaces only Linux has (procfs, io_uring, statx, prctl...)
+#[cfg(all(target_os = "freebsd", any(
+    feature = "kernel_cmdline", feature = "io_uring", feature = "procfs_fallback", feature = "ld_invocation",
+    feature = "maintenance", feature = "mount_aware", feature = "selftest", feature = "interp_check",
+    feature = "signatures", feature = "device_check", feature = "affinity", feature = "speculation_ctrl",
+    feature = "resolve_daemon", feature = "microcode_gate", feature = "pre_exec_hook",
+    feature = "canary", feature = "build_ids"
+)))]
+compile_error!("This feature is only supported on Linux.");
+
+pub mod capabilities;
+pub mod variants;
+pub mod resolve;
+pub mod transaction;
+
+#[doc(hidden)]
+pub mod sys;
+#[doc(hidden)]
+pub mod syscall;
+#[doc(hidden)]
+pub mod path;
+#[doc(hidden)]
+pub mod output;
+#[doc(hidden)]
+pub mod arg_size;
+#[cfg(feature = "kernel_cmdline")]
+#[doc(hidden)]
+pub mod cmdline;
+#[cfg(feature = "config")]
+#[doc(hidden)]
+pub mod config;
+#[cfg(feature = "io_uring")]
+#[doc(hidden)]
+pub mod probe;
+#[cfg(feature = "procfs_fallback")]
+#[doc(hidden)]
+pub mod procfs_fallback;
+#[cfg(feature = "ld_invocation")]
+#[doc(hidden)]
+pub mod ld;
+#[cfg(feature = "metrics")]
+#[doc(hidden)]
+pub mod metrics;
+#[cfg(feature = "suggestions")]
+#[doc(hidden)]
+pub mod suggest;
+#[cfg(feature = "maintenance")]
+#[doc(hidden)]
+pub mod maintenance;
+#[cfg(feature = "force_target")]
+#[doc(hidden)]
+pub mod force;
+#[cfg(feature = "mount_aware")]
+#[doc(hidden)]
+pub mod mounts;
+#[cfg(feature = "latency_report")]
+#[doc(hidden)]
+pub mod latency;
+#[cfg(feature = "selftest")]
+#[doc(hidden)]
+pub mod selftest;
+#[cfg(feature = "interp_check")]
+#[doc(hidden)]
+pub mod interp;
+#[cfg(feature = "signatures")]
+#[doc(hidden)]
+pub mod signature;
+#[cfg(feature = "signatures")]
+#[doc(hidden)]
+pub mod ed25519;
+#[cfg(any(feature = "interp_check", feature = "signatures", feature = "build_ids"))]
+#[doc(hidden)]
+pub mod verify;
+#[cfg(feature = "device_check")]
+#[doc(hidden)]
+pub mod device_check;
+#[cfg(feature = "secure_env")]
+#[doc(hidden)]
+pub mod secure_env;
+#[cfg(feature = "affinity")]
+#[doc(hidden)]
+pub mod affinity;
+#[cfg(feature = "speculation_ctrl")]
+#[doc(hidden)]
+pub mod speculation;
+#[cfg(feature = "level_index")]
+#[doc(hidden)]
+pub mod level_index;
+#[cfg(feature = "resolve_daemon")]
+#[doc(hidden)]
+pub mod daemon;
+#[cfg(feature = "microcode_gate")]
+#[doc(hidden)]
+pub mod microcode;
+#[cfg(feature = "microarch_dirs")]
+#[doc(hidden)]
+pub mod microarch;
+#[cfg(feature = "exec_retry")]
+#[doc(hidden)]
+pub mod retry;
+#[cfg(feature = "search_paths")]
+#[doc(hidden)]
+pub mod search_paths;
+#[cfg(feature = "error_file")]
+#[doc(hidden)]
+pub mod last_error;
+#[cfg(feature = "xattr_overrides")]
+#[doc(hidden)]
+pub mod xattr;
+// Always built, since exec_preload honors markers whatever the loader is built with
+#[doc(hidden)]
+pub mod ignore;
+#[cfg(feature = "extra_dir")]
+#[doc(hidden)]
+pub mod extra_dir;
+#[cfg(feature = "fallback_counters")]
+#[doc(hidden)]
+pub mod fallbacks;
+#[cfg(feature = "resilience")]
+#[doc(hidden)]
+pub mod resilience;
+#[cfg(feature = "trace_file")]
+#[doc(hidden)]
+pub mod trace;
+#[cfg(feature = "pre_exec_hook")]
+#[doc(hidden)]
+pub mod hook;
+#[cfg(feature = "rlimits")]
+#[doc(hidden)]
+pub mod rlimits;
+#[cfg(feature = "canary")]
+#[doc(hidden)]
+pub mod canary;
+#[cfg(feature = "failure_memory")]
+#[doc(hidden)]
+pub mod failures;
+#[cfg(feature = "dump_cpuid")]
+#[doc(hidden)]
+pub mod dump;
+#[cfg(feature = "shebang_rewrite")]
+#[doc(hidden)]
+pub mod shebang;
+#[cfg(feature = "strict_integrity")]
+#[doc(hidden)]
+pub mod integrity;
+#[cfg(feature = "build_ids")]
+#[doc(hidden)]
+pub mod build_id;
+
+#[cfg(not(target_os = "freebsd"))]
+pub const HWCAPS_PATH: &'static [u8] = b"/usr/hwcaps/";
+#[cfg(not(target_os = "freebsd"))]
+pub const USR_PATH: &'static [u8] = b"/usr";
+#[cfg(not(target_os = "freebsd"))]
+pub const BIN_PATH: &'static [u8] = b"/usr/bin/";
+// Packages aren't part of FreeBSD's base system, so they live under /usr/local
+#[cfg(target_os = "freebsd")]
+pub const HWCAPS_PATH: &[u8] = b"/usr/local/hwcaps/";
+#[cfg(target_os = "freebsd")]
+pub const USR_PATH: &[u8] = b"/usr/local";
+#[cfg(target_os = "freebsd")]
+pub const BIN_PATH: &[u8] = b"/usr/local/bin/";
+// Builds for any level, tried after every level's (generic_fallback)
+pub const GENERIC_DIRECTORY: &[u8] = b"generic";
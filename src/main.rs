@@ -24,22 +24,98 @@
 //#![feature(c_size_t)]
 //#![feature(str_from_raw_parts)]
 
-#![cfg_attr(target_os="none", feature(naked_functions))]
-
-mod sys;
-mod capabilities;
-mod path;
-mod output;
+mod runtime;
 
 use core::ffi::{c_char, CStr};
 use core::slice;
 
+use hwcaps_loader::{sys, capabilities, path, output, variants, arg_size, make_uninit_array};
+use hwcaps_loader::{HWCAPS_PATH, USR_PATH, BIN_PATH};
+#[cfg(feature = "generic_fallback")]
+use hwcaps_loader::GENERIC_DIRECTORY;
+#[cfg(feature = "kernel_cmdline")]
+use hwcaps_loader::cmdline;
+#[cfg(feature = "config")]
+use hwcaps_loader::config;
+#[cfg(feature = "io_uring")]
+use hwcaps_loader::probe;
+#[cfg(feature = "procfs_fallback")]
+use hwcaps_loader::procfs_fallback;
+#[cfg(feature = "ld_invocation")]
+use hwcaps_loader::ld;
+#[cfg(feature = "metrics")]
+use hwcaps_loader::metrics;
+#[cfg(feature = "suggestions")]
+use hwcaps_loader::suggest;
+#[cfg(feature = "maintenance")]
+use hwcaps_loader::maintenance;
+#[cfg(feature = "resolve_lib")]
+use hwcaps_loader::resolve;
+#[cfg(feature = "force_target")]
+use hwcaps_loader::force;
+#[cfg(feature = "mount_aware")]
+use hwcaps_loader::mounts;
+#[cfg(feature = "latency_report")]
+use hwcaps_loader::latency;
+#[cfg(feature = "selftest")]
+use hwcaps_loader::selftest;
+#[cfg(feature = "signatures")]
+use hwcaps_loader::signature;
+#[cfg(any(feature = "interp_check", feature = "signatures", feature = "build_ids"))]
+use hwcaps_loader::verify;
+#[cfg(feature = "device_check")]
+use hwcaps_loader::device_check;
+#[cfg(feature = "secure_env")]
+use hwcaps_loader::secure_env;
+#[cfg(feature = "affinity")]
+use hwcaps_loader::affinity;
+#[cfg(feature = "speculation_ctrl")]
+use hwcaps_loader::speculation;
+#[cfg(feature = "level_index")]
+use hwcaps_loader::level_index;
+#[cfg(feature = "resolve_daemon")]
+use hwcaps_loader::daemon;
+#[cfg(feature = "microcode_gate")]
+use hwcaps_loader::microcode;
+#[cfg(feature = "exec_retry")]
+use hwcaps_loader::retry;
+#[cfg(feature = "search_paths")]
+use hwcaps_loader::search_paths;
+#[cfg(feature = "xattr_overrides")]
+use hwcaps_loader::xattr;
+#[cfg(feature = "ignore_markers")]
+use hwcaps_loader::ignore;
+#[cfg(feature = "extra_dir")]
+use hwcaps_loader::extra_dir;
+#[cfg(feature = "microarch_dirs")]
+use hwcaps_loader::microarch;
+#[cfg(feature = "fallback_counters")]
+use hwcaps_loader::fallbacks;
+#[cfg(feature = "resilience")]
+use hwcaps_loader::resilience;
+#[cfg(feature = "trace_file")]
+use hwcaps_loader::trace;
+#[cfg(feature = "pre_exec_hook")]
+use hwcaps_loader::hook;
+#[cfg(feature = "rlimits")]
+use hwcaps_loader::rlimits;
+#[cfg(feature = "canary")]
+use hwcaps_loader::canary;
+#[cfg(feature = "failure_memory")]
+use hwcaps_loader::failures;
+#[cfg(feature = "dump_cpuid")]
+use hwcaps_loader::dump;
+#[cfg(feature = "shebang_rewrite")]
+use hwcaps_loader::shebang;
+#[cfg(feature = "strict_integrity")]
+use hwcaps_loader::integrity;
+
 use sys::ExitCode;
-use output::abort;
+use output::{abort, Fatal};
 
-const HWCAPS_PATH: &'static [u8] = b"/usr/hwcaps/";
-const USR_PATH: &'static [u8] = b"/usr";
-const BIN_PATH: &'static [u8] = b"/usr/bin/";
+// Upper bound of candidates listed when none of them exist
+#[cfg(feature = "error_output")]
+const MAX_LISTED_CANDIDATES: usize = 16;
 
 fn extract_argv0(ptr: *const *const c_char) -> &'static [u8]  {
     let argv0 = unsafe {
@@ -50,35 +126,73 @@ fn extract_argv0(ptr: *const *const c_char) -> &'static [u8]  {
     };
 
     if argv0.len() > sys::PATH_MAX as usize || argv0.len() < 1 {
-        abort(ExitCode::CommandPathInvalid, "Command path doesn't fit bounds!", 0, None)
+        abort(&Fatal(ExitCode::CommandPathInvalid, "Command path doesn't fit bounds!"), 0, None)
     }
 
     argv0
 }
 
+// Appended by the kernel to /proc/self/exe once the binary is unlinked
+#[cfg(not(target_os = "freebsd"))]
+const DELETED_SUFFIX: &[u8] = b" (deleted)";
+
+// While a package upgrade replaces the loader, copies which are still running see themselves as
+// "/usr/bin/hwcaps-loader (deleted)", which matches neither argv0 nor the aliases' targets.
+// Unless a file is really named that way, the path is the one without the suffix.
+#[cfg(not(target_os = "freebsd"))]
+fn strip_deleted(buffer: &mut [u8], len: usize) -> usize {
+    let stripped_len = match buffer[..len].ends_with(DELETED_SUFFIX) && len < buffer.len() {
+        true => len - DELETED_SUFFIX.len(),
+        false => return len
+    };
+
+    buffer[len] = b'\0';
+    let c_str = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+    match sys::faccessat(sys::AT_FDCWD, c_str, sys::F_OK) {
+        Err(sys::Errno::ENOENT) => stripped_len,
+        _ => len
+    }
+}
+
 fn get_loader_path(buffer: &mut [u8]) -> usize {
-    let loader_size = match sys::readlink(c"/proc/self/exe", buffer) {
+    #[cfg(not(target_os = "freebsd"))]
+    let result = sys::readlink(c"/proc/self/exe", buffer).map(|len| strip_deleted(buffer, len));
+    #[cfg(target_os = "freebsd")]
+    let result = sys::executable_path(buffer);
+
+    #[cfg(feature = "procfs_fallback")]
+    let result = result.or_else(|_| procfs_fallback::get_loader_path(buffer));
+
+    let loader_size = match result {
         Ok(p) => p,
-        Err(e) => abort(ExitCode::ProcPathIOError, "Failed to read loader path!", e.into_raw() as u32, None)
+        Err(e) => abort(&Fatal(ExitCode::ProcPathIOError, "Failed to read loader path!"), e.into_raw() as u32, None)
+    };
+
+    // We might've been reached through another mount of our directory
+    #[cfg(feature = "mount_aware")]
+    let loader_size = match buffer[1..BIN_PATH.len()] != BIN_PATH[1..] {
+        true => mounts::rebase(buffer, loader_size, BIN_PATH).unwrap_or(loader_size),
+        false => loader_size
     };
 
     if buffer[1..BIN_PATH.len()] != BIN_PATH[1..] {
-        abort(ExitCode::ProcPathInvalid, "Invalid loader binary location!", 0, None)
+        abort(&Fatal(ExitCode::ProcPathInvalid, "Invalid loader binary location!"), 0, None)
     }
 
     loader_size
 }
 
+#[cfg(not(target_os = "freebsd"))]
 fn resolve_path(cwd_fd: i32, path: &[u8], buffer: &mut [u8]) -> usize {
     let c_str = unsafe {
-        let str_ptr = path.as_ptr() as *const i8;
+        let str_ptr = path.as_ptr() as *const c_char;
         CStr::from_ptr(str_ptr)
     };
 
     let fd = match sys::openat(cwd_fd, c_str, sys::O_PATH | sys::O_NOFOLLOW) {
         Ok(d) => d,
         Err(e) => {
-            abort(ExitCode::PathResolutionIOError, "Failed to resolve path!", e.into_raw() as u32, Some(path))
+            abort(&Fatal(ExitCode::PathResolutionIOError, "Failed to resolve path!"), e.into_raw() as u32, Some(path))
         }
     };
 
@@ -88,18 +202,207 @@ fn resolve_path(cwd_fd: i32, path: &[u8], buffer: &mut [u8]) -> usize {
 
     let fd_cstr = unsafe { CStr::from_bytes_with_nul_unchecked(&fd_path) };
 
-    match sys::readlink(fd_cstr, buffer) {
+    let result = sys::readlink(fd_cstr, buffer);
+
+    #[cfg(feature = "procfs_fallback")]
+    let result = result.or_else(|_| procfs_fallback::resolve_path(cwd_fd, path, buffer));
+
+    match result {
         Ok(p) => p,
-        Err(e) => abort(ExitCode::PathResolutionIOError, "Failed to resolve path!", e.into_raw() as u32, Some(&fd_path))
+        Err(e) => abort(&Fatal(ExitCode::PathResolutionIOError, "Failed to resolve path!"), e.into_raw() as u32, Some(&fd_path))
+    }
+}
+
+// FreeBSD can't tell the path of a symlink itself, so the alias' directory is resolved instead,
+// and its name is appended to it
+#[cfg(target_os = "freebsd")]
+fn resolve_path(cwd_fd: i32, path: &[u8], buffer: &mut [u8]) -> usize {
+    let path = path.strip_suffix(b"\0").unwrap_or(path);
+    let (directory, name): (&[u8], &[u8]) = match path.iter().rposition(|c| *c == b'/') {
+        Some(0) => (b"/", &path[1..]),
+        Some(i) => (&path[..i], &path[i+1..]),
+        None => (b".", path)
+    };
+
+    let mut directory_buffer = [0u8; sys::PATH_MAX as usize];
+    directory_buffer[..directory.len()].copy_from_slice(directory);
+    let c_str = unsafe { CStr::from_bytes_with_nul_unchecked(&directory_buffer[..directory.len()+1]) };
+
+    let fd = match sys::openat(cwd_fd, c_str, sys::O_PATH | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(e) => {
+            abort(&Fatal(ExitCode::PathResolutionIOError, "Failed to resolve path!"), e.into_raw() as u32, Some(path))
+        }
+    };
+    let result = sys::fd_path(fd, buffer);
+    let _ = sys::close(fd);
+
+    let len = match result {
+        Ok(l) => l,
+        Err(e) => abort(&Fatal(ExitCode::PathResolutionIOError, "Failed to resolve path!"), e.into_raw() as u32, Some(path))
+    };
+
+    // Only the root directory's path ends with a slash
+    let separator: &[u8] = if buffer[..len].ends_with(b"/") { b"" } else { b"/" };
+    let mut resolved = [0u8; sys::PATH_MAX as usize];
+    match path::join(&mut resolved, &[&buffer[..len], separator, name]) {
+        Some(l) => {
+            buffer[..l].copy_from_slice(&resolved[..l]);
+            l
+        },
+        None => abort(&Fatal(ExitCode::PathResolutionIOError, "Failed to resolve path!"), sys::Errno::ENAMETOOLONG.into_raw() as u32, Some(path))
+    }
+}
+
+// Formats the part of the target path which depends on the feature level and variant.
+// Returns the length of the path, including its terminator.
+fn format_target_path(target_path: &mut [u8], feature_level: u32, suffix: &[u8], bin_path: &[u8]) -> usize {
+    let directory = capabilities::FEATURE_LEVELS[feature_level as usize].directory;
+    format_directory_path(target_path, directory, suffix, bin_path)
+}
+
+// Like format_target_path, but for any directory in the hwcaps directory
+fn format_directory_path(target_path: &mut [u8], directory: &[u8], suffix: &[u8], bin_path: &[u8]) -> usize {
+    let directory_index = HWCAPS_PATH.len();
+    let suffix_index = directory_index + directory.len();
+    let bin_index = suffix_index + suffix.len();
+    let path_len = bin_index + bin_path.len();
+
+    if path_len > sys::PATH_MAX as usize {
+        abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), path_len as u32, None)
+    }
+
+    unsafe {
+        target_path.get_unchecked_mut(directory_index..suffix_index).copy_from_slice(directory);
+        target_path.get_unchecked_mut(suffix_index..bin_index).copy_from_slice(suffix);
+        path::copy(target_path.get_unchecked_mut(bin_index..path_len), bin_path);
+    }
+
+    path_len
+}
+
+// Like format_target_path, but for the extra directory (extra_dir). Returns None if it doesn't fit.
+#[cfg(feature = "extra_dir")]
+fn format_extra_path(target_path: &mut [u8], extra_dir: &[u8], feature_level: u32, suffix: &[u8], bin_path: &[u8]) -> Option<usize> {
+    let directory = capabilities::FEATURE_LEVELS[feature_level as usize].directory;
+    path::join(target_path, &[extra_dir, b"/", directory, suffix, bin_path])
+}
+
+// Whether an opt-out marker skips the candidate at path, whose first hwcaps_len bytes are the hwcaps
+// directory it's in (ignore_markers)
+fn is_ignored(_path: &[u8], _hwcaps_len: usize) -> bool {
+    #[cfg(feature = "ignore_markers")]
+    if ignore::is_ignored(&_path[.._hwcaps_len], &_path[_hwcaps_len..]) {
+        return true
+    }
+    false
+}
+
+// Whether candidates which exist but can't be executed make us fail (strict_integrity)
+#[cfg(feature = "resolve_daemon")]
+fn is_strict() -> bool {
+    #[cfg(feature = "strict_integrity")]
+    if integrity::is_strict() {
+        return true
     }
+    false
 }
 
+// Why the candidate at path wasn't executed, when none of them were
+#[cfg(feature = "error_output")]
+fn missing_hint(path: &[u8], hwcaps_len: usize) -> &'static str {
+    match is_ignored(path, hwcaps_len) {
+        true => "Candidate is skipped by a .hwcaps-ignore marker.",
+        false => "Candidate doesn't exist."
+    }
+}
+
+// What we were asked to do when executed directly (e.g. "hwcaps-loader --selftest")
+enum Mode {
+    #[cfg(feature = "config")]
+    CheckConfig,
+    #[cfg(feature = "selftest")]
+    Selftest,
+    #[cfg(feature = "dump_cpuid")]
+    DumpCpuid,
+    #[cfg(feature = "resolve_lib")]
+    ResolveLib(&'static [u8]),
+    #[cfg(feature = "resolve_daemon")]
+    Daemon,
+    #[cfg(feature = "failure_memory")]
+    RecordFailure(&'static [u8]),
+    #[cfg(feature = "search_paths")]
+    PrintSearchPaths,
+    // A subcommand and its arguments
+    #[cfg(feature = "maintenance")]
+    Maintenance(sys::Args<'static>),
+    // Nothing we know of
+    Unknown
+}
+
+// Our arguments are valid until we execute something or exit, so they're 'static
+fn parse_mode(args: sys::Args<'static>) -> Mode {
+    match (args.get(1), args.get(2), args.len()) {
+        #[cfg(feature = "config")]
+        (Some(b"--check-config"), _, 2) => Mode::CheckConfig,
+        #[cfg(feature = "selftest")]
+        (Some(b"--selftest"), _, 2) => Mode::Selftest,
+        #[cfg(feature = "dump_cpuid")]
+        (Some(b"--dump-cpuid"), _, 2) => Mode::DumpCpuid,
+        #[cfg(feature = "resolve_lib")]
+        (Some(b"--resolve-lib"), Some(name), 3) => Mode::ResolveLib(name),
+        #[cfg(feature = "resolve_daemon")]
+        (Some(b"--daemon"), _, 2) => Mode::Daemon,
+        #[cfg(feature = "failure_memory")]
+        (Some(b"--record-failure"), Some(program), 3) => Mode::RecordFailure(program),
+        #[cfg(feature = "search_paths")]
+        (Some(b"--print-search-paths"), _, 2) => Mode::PrintSearchPaths,
+        #[cfg(feature = "maintenance")]
+        (Some(_), _, _) => Mode::Maintenance(args.skip(1)),
+        _ => Mode::Unknown
+    }
+}
+
+// Returns if the candidate at path doesn't exist, so the next one can be tried. Aborts otherwise.
+fn check_exec_error(errno: sys::Errno, argv: *const *const c_char, envp: *const *const c_char, path: &[u8]) {
+    // Candidates which exist mustn't be skipped in favor of a lower level's
+    #[cfg(feature = "strict_integrity")]
+    integrity::check(errno, path);
+
+    match errno.into_raw() as u32 {
+        sys::ENOENT => (),
+        // Another candidate wouldn't take fewer arguments
+        sys::E2BIG => arg_size::abort_too_large(argv, envp, path),
+        other => abort(&Fatal(ExitCode::TargetExecutionError, "Failed to execute target binary!"), other, Some(path))
+    }
+}
 
 #[no_mangle]
-pub extern fn main(_argc: i32, argv: *const *const c_char, envp: *const *const c_char) -> ! {
+extern fn main(_argc: i32, argv: *const *const c_char, envp: *const *const c_char) -> ! {
+    // Everything up to the execution of the target counts towards our overhead
+    #[cfg(feature = "latency_report")]
+    let start = latency::now();
+
+    // Some architectures' features come from the auxiliary vector, which follows envp. So does
+    // AT_SECURE, which some features check.
+    #[cfg(any(feature = "auxv", not(any(target_arch = "x86", target_arch = "x86_64"))))]
+    unsafe { sys::init_auxv(envp) };
+
+    // Determine the maximum feature level supported by this machine
+    let feature_level = capabilities::get_max_feature_level();
+
+    // Path routines can take advantage of it right away
+    #[cfg(feature = "simd_paths")]
+    path::init_simd(feature_level);
+
     // argv0 includes a terminator character. This comes in handy when interfacing with syscalls.
     let argv0 = extract_argv0(argv);
 
+    // "/usr/bin//foo" and "foo/" must be handled like "/usr/bin/foo" and "foo"
+    let mut argv0_buffer = make_uninit_array!(sys::PATH_MAX as usize);
+    let argv0_len = path::normalize(&mut argv0_buffer, &argv0[..argv0.len()-1]);
+    let argv0 = &argv0_buffer[..argv0_len];
+
     let mut loader_path = make_uninit_array!(sys::PATH_MAX as usize);
     // Note: The linux kernel doesn't write a null terminator. Since loader_path is an uninitialized array,
     //       we cannot assume there's a null terminator.
@@ -109,10 +412,71 @@ pub extern fn main(_argc: i32, argv: *const *const c_char, envp: *const *const c
     let bin_index = BIN_PATH.len();
     let usr_index = USR_PATH.len();
 
-    //Make sure we're not trying to execute ourselves!
-    #[cfg(feature = "self_execution_check")]
-    if path::is_loader_binary(&loader_path[..loader_end_index], argv0) {
-        abort(ExitCode::SelfExecution, "Do not run hwcaps-loader directly!", 0, None)
+    // When executed directly, our own arguments say what to do instead of dispatching
+    let mode = match path::is_loader_binary(&loader_path[..loader_end_index], argv0) {
+        true => Some(parse_mode(unsafe { sys::Args::new(_argc, argv) })),
+        false => None
+    };
+
+    // Checking the configuration must happen before it's loaded
+    #[cfg(feature = "config")]
+    if let Some(Mode::CheckConfig) = mode {
+        config::run(envp)
+    }
+
+    // Honor any restrictions set by the administrator
+    #[cfg(feature = "config")]
+    let config = config::load(envp, path::file_name(&argv0[..argv0.len()-1]));
+    #[cfg(feature = "config")]
+    let feature_level = match config.max_level {
+        Some(limit) => core::cmp::min(feature_level, limit),
+        None => feature_level
+    };
+    #[cfg(feature = "microcode_gate")]
+    let feature_level = microcode::apply(&config.microcode, feature_level);
+
+    // Honor any restrictions passed on the kernel command line
+    #[cfg(feature = "kernel_cmdline")]
+    let cmdline_limit = cmdline::get_feature_level_limit();
+    #[cfg(feature = "kernel_cmdline")]
+    let feature_level = match cmdline_limit {
+        Some(cmdline::Limit::Level(limit)) => core::cmp::min(feature_level, limit),
+        Some(cmdline::Limit::Off) => capabilities::BASELINE_FEATURE_LEVEL,
+        None => feature_level
+    };
+
+    // Run the requested mode, within the same limits as dispatching
+    match mode {
+        #[cfg(feature = "selftest")]
+        Some(Mode::Selftest) => selftest::run(),
+        #[cfg(feature = "dump_cpuid")]
+        Some(Mode::DumpCpuid) => dump::run(),
+        #[cfg(feature = "resolve_lib")]
+        Some(Mode::ResolveLib(name)) => resolve::run(name, feature_level),
+        #[cfg(feature = "resolve_daemon")]
+        Some(Mode::Daemon) => daemon::run(feature_level),
+        #[cfg(feature = "failure_memory")]
+        Some(Mode::RecordFailure(program)) => failures::run(program, envp, feature_level),
+        #[cfg(feature = "search_paths")]
+        Some(Mode::PrintSearchPaths) => search_paths::run(&loader_path[..loader_end_index], feature_level),
+        #[cfg(feature = "maintenance")]
+        Some(Mode::Maintenance(args)) => maintenance::run(args, &loader_path[..loader_end_index], feature_level),
+        //Make sure we're not trying to execute ourselves!
+        #[cfg(feature = "self_execution_check")]
+        Some(_) => abort(&Fatal(ExitCode::SelfExecution, "Do not run hwcaps-loader directly!"), 0, None),
+        _ => ()
+    }
+
+    // Broken builds mustn't be replaced by slower ones, if the administrator says so
+    #[cfg(feature = "strict_integrity")]
+    if config.strict_integrity {
+        integrity::enable();
+    }
+
+    // Slower but running beats failing to start, if the administrator says so
+    #[cfg(feature = "resilience")]
+    if config.on_error_baseline {
+        unsafe { resilience::arm(argv, envp, argv0) };
     }
 
     let mut cwd = sys::AT_FDCWD;
@@ -128,7 +492,7 @@ pub extern fn main(_argc: i32, argv: *const *const c_char, envp: *const *const c
 
         cwd = match sys::openat(sys::AT_FDCWD, c_str, sys::O_PATH) {
             Ok(d) => d,
-            Err(e) => abort(ExitCode::PathResolutionIOError, "Failed to get parent directory of loader!", e.into_raw() as u32, None)
+            Err(e) => abort(&Fatal(ExitCode::PathResolutionIOError, "Failed to get parent directory of loader!"), e.into_raw() as u32, None)
         };
         //Restore the previous character
         loader_path[bin_index] = byte;
@@ -137,10 +501,19 @@ pub extern fn main(_argc: i32, argv: *const *const c_char, envp: *const *const c
     let mut cmd_path = make_uninit_array!(sys::PATH_MAX as usize);
     let cmd_path_len = resolve_path(cwd, argv0, &mut cmd_path);
 
+    // Same for the alias, if it's in our directory
+    #[cfg(feature = "mount_aware")]
+    let cmd_path_len = match cmd_path[..usr_index] != *USR_PATH {
+        true => mounts::rebase(&mut cmd_path, cmd_path_len, BIN_PATH).unwrap_or(cmd_path_len),
+        false => cmd_path_len
+    };
+
     // cmd_path_len+1 must fit in cmd_path, because of the terminator.
     if cmd_path_len+1 >= cmd_path.len() {
-        abort(ExitCode::TargetPathTooLarge, "Target path too large!", 0, None)
+        abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, None)
     }
+    // readlink() doesn't write a terminator, so we must do it ourselves
+    cmd_path[cmd_path_len] = b'\0';
 
     // These aren't problematic because argv0 is guaranteed to be  bytes long
     let cmd_path_usr_slice = unsafe { cmd_path.get_unchecked(..usr_index) };
@@ -148,90 +521,455 @@ pub extern fn main(_argc: i32, argv: *const *const c_char, envp: *const *const c
 
     // Check if our target's on /usr/
     if cmd_path_usr_slice != USR_PATH {
-        abort(ExitCode::TargetPathInvalid, "Invalid target location!", 0, None)
+        abort(&Fatal(ExitCode::TargetPathInvalid, "Invalid target location!"), 0, None)
     }
 
-    // Prepare execution target path
-    let base_length = HWCAPS_PATH.len() + cmd_path_bin_slice.len();
-
     // Very hacky and unsafe code :)iov_base
     // We can reuse the string we already have instead of allocating a new one, saving on time.
     let mut target_path = loader_path;
 
     // We've already determined the path starts with /usr/, so we only need to copy from hwcaps/
     // Copy the part of the path which we won't be changing anymore
-    let copy_index = unsafe {
+    unsafe {
         let src = HWCAPS_PATH.get_unchecked(usr_index..);
-        let copy_index = usr_index+src.len();
-        let dst = target_path.get_unchecked_mut(usr_index..usr_index+src.len());
+        let dst = target_path.get_unchecked_mut(usr_index..HWCAPS_PATH.len());
         dst.copy_from_slice(src);
-        copy_index
+    }
+
+    // Make sure nothing unexpected was mounted over our directory
+    #[cfg(all(feature = "device_check", feature = "config"))]
+    device_check::check(config.device_mismatch_warn);
+    #[cfg(all(feature = "device_check", not(feature = "config")))]
+    device_check::check(false);
+
+    // Checks candidates must pass before being executed
+    #[cfg(all(feature = "signatures", feature = "config"))]
+    let signing_key = signature::get_key(config.signing_key.as_ref());
+    #[cfg(all(feature = "signatures", not(feature = "config")))]
+    let signing_key = signature::get_key(None);
+    #[cfg(any(feature = "interp_check", feature = "signatures", feature = "build_ids"))]
+    let policy = verify::Policy {
+        #[cfg(all(feature = "interp_check", feature = "config"))]
+        interpreters: config.interpreters.as_ref(),
+        #[cfg(all(feature = "interp_check", not(feature = "config")))]
+        interpreters: None,
+        #[cfg(feature = "signatures")]
+        signing_key: &signing_key,
+        #[cfg(all(feature = "build_ids", feature = "config"))]
+        build_id_mismatch_warn: config.build_id_mismatch_warn,
+        #[cfg(all(feature = "build_ids", not(feature = "config")))]
+        build_id_mismatch_warn: false,
+        #[cfg(not(any(feature = "interp_check", feature = "signatures")))]
+        lifetime: core::marker::PhantomData
     };
 
-    let mut must_format_arch = true;
-    let mut version_char_index: usize = 0;
+    // Don't let unprivileged users alter how privileged targets run
+    #[cfg(feature = "secure_env")]
+    let mut env_buffer = secure_env::new_buffer();
+    #[cfg(feature = "secure_env")]
+    let target_envp = unsafe { secure_env::filter(envp, &mut env_buffer) };
+    #[cfg(not(feature = "secure_env"))]
+    let target_envp = envp;
+
+    #[cfg(feature = "resilience")]
+    if config.on_error_baseline {
+        unsafe { resilience::rearm(argv, target_envp, cmd_path_bin_slice) };
+    }
 
-    // Determine the maximum feature level supported by this machine
-    let feature_level = capabilities::get_max_feature_level();
+    // Speculation controls are inherited by whatever we execute
+    #[cfg(feature = "speculation_ctrl")]
+    speculation::apply(&config.speculation);
+
+    // Bypass level selection when a developer asks for a specific candidate
+    #[cfg(feature = "force_target")]
+    if let Some(target) = force::get_forced_target(envp, cmd_path_bin_slice) {
+        #[cfg(not(any(feature = "interp_check", feature = "signatures", feature = "build_ids")))]
+        let errno = sys::execve(target, argv, target_envp);
+        #[cfg(any(feature = "interp_check", feature = "signatures", feature = "build_ids"))]
+        let errno = verify::execve(target, &policy, argv, target_envp, || ());
+        if errno.into_raw() as u32 == sys::E2BIG {
+            arg_size::abort_too_large(argv, target_envp, target.to_bytes())
+        }
+        abort(&Fatal(ExitCode::TargetExecutionError, "Failed to execute forced target!"), errno.into_raw() as u32, Some(target.to_bytes()))
+    }
+
+    // Interpreters of scripts are subject to the same limits, but not to the script's own policies
+    #[cfg(feature = "shebang_rewrite")]
+    let interpreter_level = feature_level;
+
+    // Admins can limit a single program through its baseline build's attributes
+    #[cfg(feature = "xattr_overrides")]
+    let feature_level = {
+        let path_len = format_target_path(&mut target_path, capabilities::BASELINE_FEATURE_LEVEL, b"", cmd_path_bin_slice);
+        let c_str = unsafe { CStr::from_bytes_with_nul_unchecked(&target_path[..path_len]) };
+        xattr::apply(c_str, feature_level)
+    };
+
+    // Levels being rolled out are only used by some launches. Those above the rest are a limit,
+    // so launches kept off them aren't counted as fallbacks.
+    #[cfg(feature = "canary")]
+    let canary = canary::Canary::new(&config.canary, path::file_name(&cmd_path_bin_slice[..cmd_path_bin_slice.len()-1]));
+    #[cfg(feature = "canary")]
+    let feature_level = canary.limit(feature_level);
+
+    // Feature levels supported by every CPU, in case they aren't the same everywhere
+    #[cfg(feature = "affinity")]
+    let cpu_levels = affinity::CpuLevels::detect();
+
+    // Or only levels every CPU supports, so the target isn't pinned to some of them. That's a
+    // limit too, so launches kept off the other CPUs' levels aren't counted as fallbacks.
+    #[cfg(all(feature = "affinity", feature = "config"))]
+    let feature_level = match &cpu_levels {
+        Some(cpu_levels) if config.affinity_intersect => core::cmp::min(feature_level, cpu_levels.min_level()),
+        _ => feature_level
+    };
+
+    // Candidates which crashed recently are given a rest, so services don't crash in a loop
+    #[cfg(feature = "failure_memory")]
+    let failures = failures::Failures::new(path::file_name(&cmd_path_bin_slice[..cmd_path_bin_slice.len()-1]), config.failure_cooldown);
+
+    #[cfg(any(feature = "metrics", feature = "fallback_counters"))]
+    let max_feature_level = feature_level;
+
+    #[cfg(all(feature = "latency_report", feature = "config"))]
+    let latency_socket = config.latency_socket.as_ref();
+    #[cfg(all(feature = "latency_report", not(feature = "config")))]
+    let latency_socket = None;
+
+    // A launch is recorded once, when the first candidate which exists passes its checks and is
+    // executed. Candidates tried after it fails (e.g. without its interpreter) don't count again.
+    #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+    let pending_record = core::cell::Cell::new(None::<(u32, &[u8])>);
+    #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+    let recorded = core::cell::Cell::new(false);
+    #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+    let record = || {
+        if let Some((_level, _suffix)) = pending_record.take() {
+            recorded.set(true);
+
+            #[cfg(feature = "metrics")]
+            metrics::record_dispatch(max_feature_level, _level);
+            #[cfg(feature = "fallback_counters")]
+            if _level < max_feature_level {
+                fallbacks::record(path::file_name(&cmd_path_bin_slice[..cmd_path_bin_slice.len()-1]));
+            }
+            #[cfg(feature = "latency_report")]
+            latency::report(latency_socket, path::file_name(&argv0[..argv0.len()-1]), _level, _suffix, start);
+        }
+    };
+
+    // Variants preferred by this machine, tried before the plain directory of each level
+    let variants = variants::Variants::detect();
+
+    // Developers' builds aren't known to the probes below, which only look at the system's
+    #[cfg(all(feature = "extra_dir", feature = "config"))]
+    let extra_dir = extra_dir::get(envp, config.extra_dir.as_ref());
+    #[cfg(all(feature = "extra_dir", not(feature = "config")))]
+    let extra_dir = extra_dir::get(envp, None);
+    #[cfg(feature = "extra_dir")]
+    let extra_level = feature_level;
+
+    // Builds tuned for this CPU model, which the probes below don't know about either
+    #[cfg(all(feature = "microarch_dirs", feature = "config"))]
+    let microarch = microarch::find(&config.microarch, feature_level);
+    #[cfg(all(feature = "microarch_dirs", not(feature = "config")))]
+    let microarch = microarch::find(&microarch::Rules::default(), feature_level);
+
+    // Find the best existing candidate in a single batch, instead of trying them one by one.
+    // If there are none (or io_uring is unavailable), the loop below takes care of it.
+    #[cfg(feature = "io_uring")]
+    let feature_level = match probe::find_best_level(feature_level, variants.suffixes(), cmd_path_bin_slice) {
+        Ok(Some(level)) => level,
+        _ => feature_level
+    };
+
+    // Let the daemon skip levels without candidates, if it's running
+    #[cfg(feature = "resolve_daemon")]
+    let feature_level = match daemon::query(&cmd_path_bin_slice[..cmd_path_bin_slice.len()-1], feature_level) {
+        // It can't tell candidates which don't exist from unusable ones
+        Some(level) if !is_strict() => level,
+        _ => feature_level
+    };
+
+    // Levels which actually exist on this system are tried first, if there's an index of them
+    #[cfg(feature = "level_index")]
+    let level_index = level_index::LevelIndex::load();
+    // Listing what was tried, if nothing works, goes through them again
+    #[cfg(feature = "level_index")]
+    let candidates = || level_index.candidates(&variants, feature_level);
+    #[cfg(not(feature = "level_index"))]
+    let candidates = || variants.candidates(feature_level);
+
+    // Debugging sessions and CI harnesses can follow every attempt
+    #[cfg(feature = "trace_file")]
+    let trace = trace::open(envp);
+
+    // Site-specific tasks which must happen before the program runs
+    #[cfg(feature = "pre_exec_hook")]
+    let hook = hook::get(&config.pre_exec_hook);
+    #[cfg(feature = "pre_exec_hook")]
+    let alias = unsafe { CStr::from_bytes_with_nul_unchecked(argv0) };
+
+    // Limits are inherited by the target, and may depend on its level
+    #[cfg(feature = "rlimits")]
+    let limits = rlimits::Limits::new(&config.rlimits);
+
+    // Executes a binary with the given arguments, through the checks and the dynamic loader if
+    // they're enabled. Returns why it failed. The launch is recorded once the checks pass.
+    let exec_with = |c_str: &CStr, _directory: &[u8], _argc: i32, argv: *const *const c_char| {
+        #[cfg(not(any(feature = "ld_invocation", feature = "interp_check", feature = "signatures", feature = "build_ids")))]
+        let errno = {
+            #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+            record();
+            sys::execve(c_str, argv, target_envp)
+        };
+        #[cfg(all(not(feature = "ld_invocation"), any(feature = "interp_check", feature = "signatures", feature = "build_ids")))]
+        let errno = verify::execve(c_str, &policy, argv, target_envp, || {
+            #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+            record();
+        });
+        #[cfg(all(feature = "ld_invocation", not(any(feature = "interp_check", feature = "signatures", feature = "build_ids"))))]
+        let errno = {
+            #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+            record();
+            unsafe { ld::execve(c_str, _directory, _argc, argv, target_envp) }
+        };
+        #[cfg(all(feature = "ld_invocation", any(feature = "interp_check", feature = "signatures", feature = "build_ids")))]
+        let errno = match verify::check(c_str, &policy) {
+            Ok(()) => {
+                #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+                record();
+                unsafe { ld::execve(c_str, _directory, _argc, argv, target_envp) }
+            }
+            Err(e) => e
+        };
+        errno
+    };
+
+    // Executes a candidate of the given level and suffix, returning why it failed. Its directory is
+    // the path up to the program's.
+    let execute = |c_str: &CStr, _level: u32, _suffix: &'static [u8]| {
+        #[cfg(any(feature = "ld_invocation", feature = "rlimits", feature = "pre_exec_hook"))]
+        let _directory = &c_str.to_bytes()[..c_str.to_bytes().len() + 1 - cmd_path_bin_slice.len()];
+        #[cfg(not(any(feature = "ld_invocation", feature = "rlimits", feature = "pre_exec_hook")))]
+        let _directory = b"".as_slice();
+
+        // Only candidates which exist can be recorded
+        #[cfg(any(feature = "metrics", feature = "latency_report", feature = "fallback_counters"))]
+        if !recorded.get() && sys::faccessat(sys::AT_FDCWD, c_str, sys::X_OK).is_ok() {
+            pending_record.set(Some((_level, _suffix)));
+        }
+
+        // Candidates which don't exist only get a line for their failure
+        #[cfg(feature = "trace_file")]
+        if let Some(trace) = &trace {
+            if sys::faccessat(sys::AT_FDCWD, c_str, sys::X_OK).is_ok() {
+                trace.exec(c_str);
+            }
+        }
+
+        #[cfg(feature = "rlimits")]
+        limits.apply(path::file_name(_directory));
+
+        // The hook is only run for candidates which exist. Its level is the candidate's directory.
+        #[cfg(feature = "pre_exec_hook")]
+        if let Some(hook) = hook {
+            if sys::faccessat(sys::AT_FDCWD, c_str, sys::X_OK).is_ok() {
+                hook.run(alias, c_str, path::file_name(_directory), target_envp);
+            }
+        }
+
+        let exec = || {
+            // Scripts run by an alias skip the second pass through the loader
+            #[cfg(feature = "shebang_rewrite")]
+            if let Some(script) = shebang::find(c_str, interpreter_level) {
+                #[cfg(any(feature = "interp_check", feature = "signatures", feature = "build_ids"))]
+                if let Err(e) = verify::check(c_str, &policy) {
+                    return e
+                }
+
+                let errno = match unsafe { script.argv(c_str, _argc, argv) } {
+                    Ok((argc, argv)) => exec_with(script.candidate(), script.directory(), argc, argv),
+                    Err(e) => e
+                };
+                script.failed(errno, argv, target_envp)
+            }
+
+            exec_with(c_str, _directory, _argc, argv)
+        };
 
-    // Generate a path for every available feature level, then attempt to execute it.
-    // Repeat until execve() is sucessful or we run out of levels.
-    for i in (0..=feature_level).rev() {
-        let mut path_len = 0;
+        // Package upgrades can leave a candidate briefly unexecutable
+        #[cfg(feature = "exec_retry")]
+        let errno = retry::with_retry(exec);
+        #[cfg(not(feature = "exec_retry"))]
+        let errno = exec();
 
-        if capabilities::arch_name_changed(i) {
-            must_format_arch = true;
+        #[cfg(feature = "trace_file")]
+        if let Some(trace) = &trace {
+            trace.failed(c_str, errno);
         }
+        errno
+    };
+
+    // Why this launch skips a level's candidates, other than markers, if it does
+    let skip_reason = |_level: u32, _suffix: &[u8]| -> Option<&'static str> {
+        #[cfg(feature = "canary")]
+        if canary.skips(_level) {
+            return Some("Candidate is skipped by a canary rollout.")
+        }
+        #[cfg(feature = "failure_memory")]
+        if failures.skips(_level, _suffix) {
+            return Some("Candidate is skipped after a recent crash.")
+        }
+        None
+    };
 
-        // Format the second part of the path, which is dependent on the arch name.
-        if must_format_arch {
-            let mut target_relative_slice = unsafe {
-                target_path.get_unchecked_mut(copy_index..)
-            };
+    // Executes a level's candidate, unless it's skipped. Its first hwcaps_len bytes are the hwcaps
+    // directory it's in. Returns if it was skipped or doesn't exist, so the next one can be tried.
+    // Aborts otherwise.
+    let launch = |c_str: &CStr, hwcaps_len: usize, level: u32, suffix: &'static [u8]| {
+        let path = c_str.to_bytes();
 
-            let (relative_char_index, arch_name_len) = match capabilities::format_arch_name(&mut target_relative_slice, i) {
-                Ok(v) => v,
-                Err(_) => abort(ExitCode::TargetPathTooLarge, "Target path too large!", 0, None)
-            };
-            version_char_index = relative_char_index + copy_index;
+        // Operators can switch off broken builds without removing them
+        if is_ignored(path, hwcaps_len) || skip_reason(level, suffix).is_some() {
+            return
+        }
+
+        // The target mustn't end up on CPUs which don't support its feature level
+        #[cfg(feature = "affinity")]
+        if let Some(cpu_levels) = &cpu_levels {
+            cpu_levels.pin(level);
+        }
+
+        let errno = execute(c_str, level, suffix);
+        check_exec_error(errno, argv, target_envp, path);
+    };
 
-            // Copy the relative bin path
-            path_len = base_length + arch_name_len;
+    // With dispatch disabled on the kernel command line, only the baseline level's build is run
+    #[cfg(feature = "kernel_cmdline")]
+    if cmdline_limit == Some(cmdline::Limit::Off) {
+        let path_len = format_target_path(&mut target_path, capabilities::BASELINE_FEATURE_LEVEL, b"", cmd_path_bin_slice);
+        let c_str = unsafe { CStr::from_ptr(target_path.as_ptr() as *const c_char) };
+
+        if !is_ignored(&target_path[..path_len - 1], HWCAPS_PATH.len()) {
+            let errno = execute(c_str, capabilities::BASELINE_FEATURE_LEVEL, b"");
+            check_exec_error(errno, argv, target_envp, &target_path[..path_len - 1]);
+        }
+        #[cfg(feature = "error_output")]
+        output::hint(missing_hint(&target_path[..path_len - 1], HWCAPS_PATH.len()), Some(&target_path[..path_len - 1]));
+
+        // Generic builds are built for the baseline level too
+        #[cfg(feature = "generic_fallback")]
+        {
+            let path_len = format_directory_path(&mut target_path, GENERIC_DIRECTORY, b"", cmd_path_bin_slice);
+            let c_str = unsafe { CStr::from_ptr(target_path.as_ptr() as *const c_char) };
+
+            if !is_ignored(&target_path[..path_len - 1], HWCAPS_PATH.len()) {
+                let errno = execute(c_str, capabilities::BASELINE_FEATURE_LEVEL, b"");
+                check_exec_error(errno, argv, target_envp, &target_path[..path_len - 1]);
+            }
+            #[cfg(feature = "error_output")]
+            output::hint(missing_hint(&target_path[..path_len - 1], HWCAPS_PATH.len()), Some(&target_path[..path_len - 1]));
+        }
+
+        abort(&Fatal(ExitCode::TargetNoViableBinaries, "Program has no supported binaries available. Is it installed properly?"), 0, None)
+    }
 
-            if path_len > sys::PATH_MAX as usize {
-                abort(ExitCode::TargetPathTooLarge, "Target path too large!", path_len as u32, None)
+    // Developers' builds are tried before any of the system's
+    #[cfg(feature = "extra_dir")]
+    if let Some(extra_dir) = &extra_dir {
+        for (i, suffix) in variants.candidates(extra_level) {
+            if format_extra_path(&mut target_path, extra_dir.as_bytes(), i, suffix, cmd_path_bin_slice).is_none() {
+                abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, Some(extra_dir.as_bytes()))
             }
+            let c_str = unsafe { CStr::from_ptr(target_path.as_ptr() as *const c_char) };
 
-            unsafe {
-                let copy_index = copy_index + arch_name_len;
-                let src = cmd_path_bin_slice;
-                let dst = target_path.get_unchecked_mut(copy_index..copy_index + cmd_path_bin_slice.len());
-                dst.copy_from_slice(src);
+            launch(c_str, extra_dir.as_bytes().len() + 1, i, suffix);
+        }
+
+        // The system's candidates are in the hwcaps directory again
+        target_path[..HWCAPS_PATH.len()].copy_from_slice(HWCAPS_PATH);
+    }
+
+    // Then this CPU model's, which are better than any level's
+    #[cfg(feature = "microarch_dirs")]
+    if let Some(microarch) = &microarch {
+        let path_len = format_directory_path(&mut target_path, microarch.name(), b"", cmd_path_bin_slice);
+        let c_str = unsafe { CStr::from_ptr(target_path.as_ptr() as *const c_char) };
+
+        if !is_ignored(&target_path[..path_len - 1], HWCAPS_PATH.len()) {
+            #[cfg(feature = "affinity")]
+            if let Some(cpu_levels) = &cpu_levels {
+                cpu_levels.pin(microarch.level());
             }
 
-            must_format_arch = false;
+            let errno = execute(c_str, microarch.level(), b"");
+            check_exec_error(errno, argv, target_envp, &target_path[..path_len - 1]);
         }
+    }
 
-        // Unless the arch name changes, all we need to do is update the character representing the arch version.
-        target_path[version_char_index] = capabilities::HWCAPS_CHARS[i as usize];
+    // Generate a path for every available feature level and variant, then attempt to execute it.
+    // Repeat until execve() is sucessful or we run out of candidates.
+    for (i, suffix) in candidates() {
+        let path_len = format_target_path(&mut target_path, i, suffix, cmd_path_bin_slice);
 
         #[cfg(debug_assertions)]
         {
-            let path_buffer = unsafe { slice::from_raw_parts(target_path.as_ptr(), path_len) };
+            let path_buffer = unsafe { slice::from_raw_parts(target_path.as_ptr(), path_len - 1) };
             output::debug_print("(DEBUG) Executing target.", 0, Some(path_buffer));
         }
 
-        let str_ptr = target_path.as_ptr() as *const i8;
+        let str_ptr = target_path.as_ptr() as *const c_char;
         let c_str = unsafe { CStr::from_ptr(str_ptr) };
 
-        match sys::execve(c_str, argv, envp).into_raw() as u32 {
-            sys::ENOENT => continue,
-            other => {
-                let path_buffer = unsafe { slice::from_raw_parts(target_path.as_ptr(), path_len) };
-                abort(ExitCode::TargetExecutionError, "Failed to execute target binary!", other as u32, Some(path_buffer))
+        launch(c_str, HWCAPS_PATH.len(), i, suffix);
+    }
+
+    // Builds which run on any level of the architecture, for programs which don't need one per level
+    #[cfg(feature = "generic_fallback")]
+    {
+        let path_len = format_directory_path(&mut target_path, GENERIC_DIRECTORY, b"", cmd_path_bin_slice);
+        let c_str = unsafe { CStr::from_ptr(target_path.as_ptr() as *const c_char) };
+
+        // They're recorded as the baseline level's, which they're built for
+        if !is_ignored(&target_path[..path_len - 1], HWCAPS_PATH.len()) {
+            let errno = execute(c_str, capabilities::BASELINE_FEATURE_LEVEL, b"");
+            check_exec_error(errno, argv, target_envp, &target_path[..path_len - 1]);
+        }
+    }
+
+    // List what we've tried, in the same order, so the user can tell where the binaries should be
+    #[cfg(all(feature = "error_output", feature = "extra_dir"))]
+    if let Some(extra_dir) = &extra_dir {
+        for (i, suffix) in variants.candidates(extra_level).take(MAX_LISTED_CANDIDATES) {
+            if let Some(path_len) = format_extra_path(&mut target_path, extra_dir.as_bytes(), i, suffix, cmd_path_bin_slice) {
+                let path = &target_path[..path_len - 1];
+                output::hint(skip_reason(i, suffix).unwrap_or_else(|| missing_hint(path, extra_dir.as_bytes().len() + 1)), Some(path));
             }
-        };
+        }
+
+        target_path[..HWCAPS_PATH.len()].copy_from_slice(HWCAPS_PATH);
     }
+    #[cfg(all(feature = "error_output", feature = "microarch_dirs"))]
+    if let Some(microarch) = &microarch {
+        let path_len = format_directory_path(&mut target_path, microarch.name(), b"", cmd_path_bin_slice);
+        output::hint(missing_hint(&target_path[..path_len - 1], HWCAPS_PATH.len()), Some(&target_path[..path_len - 1]));
+    }
+    #[cfg(feature = "error_output")]
+    for (i, suffix) in candidates().take(MAX_LISTED_CANDIDATES) {
+        let path_len = format_target_path(&mut target_path, i, suffix, cmd_path_bin_slice);
+        let path = &target_path[..path_len - 1];
+        output::hint(skip_reason(i, suffix).unwrap_or_else(|| missing_hint(path, HWCAPS_PATH.len())), Some(path));
+    }
+    #[cfg(all(feature = "error_output", feature = "generic_fallback"))]
+    {
+        let path_len = format_directory_path(&mut target_path, GENERIC_DIRECTORY, b"", cmd_path_bin_slice);
+        output::hint(missing_hint(&target_path[..path_len - 1], HWCAPS_PATH.len()), Some(&target_path[..path_len - 1]));
+    }
+
+    // Typos in alias names are a common packaging mistake
+    #[cfg(feature = "suggestions")]
+    suggest::print_suggestion(feature_level, cmd_path_bin_slice);
 
-    abort(ExitCode::TargetNoViableBinaries, "Program has no supported binaries available. Is it installed properly?", 0, None)
+    abort(&Fatal(ExitCode::TargetNoViableBinaries, "Program has no supported binaries available. Is it installed properly?"), 0, None)
 }
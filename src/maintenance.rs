@@ -0,0 +1,851 @@
+/*
+   Maintenance subcommands.
+   When executed directly, hwcaps-loader can manage its own command aliases, so distributions
+   can ship a single binary instead of a separate management tool:
+   - install-alias <name>   -> Create /usr/bin/<name>, pointing to hwcaps-loader
+   - remove-alias <name>    -> Remove /usr/bin/<name>, as long as it's an alias
+   - verify [name...]       -> Check that aliases (every one of them, by default) have candidates
+                               for this machine
+   - migrate <name>         -> Move /usr/bin/<name> (and its hardlinks in /usr/bin) to the
+                               baseline level's directory, replacing it with an alias
+   - list                   -> Print the name of every alias, one per line
+   - doctor                 -> Look for common deployment mistakes, and suggest how to fix them
+   - prune [--dry-run] [name...] -> Remove candidates (of every program, by default) for feature
+                                    levels this machine doesn't support, to reclaim disk space
+   - reindex                -> Rewrite the index of level directories (level_index feature)
+   - which <name>           -> Print the level and path of the candidate name would execute
+   - validate [path...]     -> Check the programs a package transaction touched (paths are read
+                               from stdin, one per line, if there are none). See transaction.rs.
+   Package builds never touch the live /usr, so "--root=<dir>" before the subcommand manages the
+   tree staged in <dir> instead (e.g. "hwcaps-loader --root=/tmp/pkgroot install-alias foo").
+   Aliases point at <dir>/usr/bin/hwcaps-loader with relative links, and checks consider every
+   feature level, since the tree is meant for other machines. prune can't be used with it.
+   "--arch=<name>" (e.g. aarch64) simulates another architecture instead: its level directories
+   are used, and every level and variant counts as supported, since nothing can be detected.
+   prune and validate can't be used with it either.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::output::{self, abort, hint, Fatal};
+use crate::capabilities::{self, ArchLayout, FEATURE_LEVELS, BASELINE_FEATURE_LEVEL};
+use crate::variants::Variants;
+use crate::transaction::{Validator, Problem, Issue};
+use crate::{BIN_PATH, HWCAPS_PATH};
+#[cfg(feature = "generic_fallback")]
+use crate::GENERIC_DIRECTORY;
+#[cfg(feature = "ignore_markers")]
+use crate::ignore;
+
+const NAME_MAX: usize = 255;
+// Where the loader is in staged trees
+const LOADER_PATH: &[u8] = b"/usr/bin/hwcaps-loader";
+const BIN_DIR: &[u8] = b"/usr/bin";
+// Most names a migrated program can have in /usr/bin
+const MAX_LINKS: usize = 16;
+
+fn get_arg<'a>(args: &sys::Args<'a>, i: usize) -> &'a [u8] {
+    args.get(i).unwrap_or(b"")
+}
+
+fn join<'a>(buffer: &'a mut [u8], parts: &[&[u8]]) -> &'a CStr {
+    match path::join(buffer, parts) {
+        Some(len) => unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) },
+        None => abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, None)
+    }
+}
+
+// The tree being managed: the live one, or one staged by a package build
+struct Tree<'a> {
+    // Prepended to every path, without a trailing slash. Empty for the live tree.
+    root: &'a [u8],
+    loader_path: &'a [u8],
+    staged: bool,
+    // Level directories of a simulated architecture, instead of this one's
+    layout: Option<&'static ArchLayout>,
+}
+
+impl<'a> Tree<'a> {
+    fn new(root: &'a [u8], loader_path: &'a [u8]) -> Self {
+        let root = root.strip_suffix(b"/").unwrap_or(root);
+        match root.is_empty() {
+            true => Tree { root, loader_path, staged: false, layout: None },
+            false => Tree { root, loader_path: LOADER_PATH, staged: true, layout: None }
+        }
+    }
+
+    fn level_count(&self) -> u32 {
+        match self.layout {
+            Some(layout) => layout.directories.len() as u32,
+            None => FEATURE_LEVELS.len() as u32
+        }
+    }
+
+    fn directory(&self, level: u32) -> &'static [u8] {
+        match self.layout {
+            Some(layout) => layout.directories[level as usize],
+            None => FEATURE_LEVELS[level as usize].directory
+        }
+    }
+
+    fn baseline(&self) -> u32 {
+        match self.layout {
+            Some(layout) => layout.baseline,
+            None => BASELINE_FEATURE_LEVEL
+        }
+    }
+
+    // Like capabilities::find_directory_level(), but for the tree's architecture
+    fn find_directory_level(&self, directory: &[u8]) -> Option<u32> {
+        let layout = match self.layout {
+            Some(layout) => layout,
+            None => return capabilities::find_directory_level(directory)
+        };
+
+        let end = directory.iter().position(|c| *c == b'+').unwrap_or(directory.len());
+        layout.directories.iter().position(|d| *d == &directory[..end]).map(|i| i as u32)
+    }
+
+    // Like join(), but inside the tree
+    fn join<'b>(&self, buffer: &'b mut [u8], parts: &[&[u8]]) -> &'b CStr {
+        if self.root.len() >= buffer.len() {
+            abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, Some(self.root))
+        }
+        buffer[..self.root.len()].copy_from_slice(self.root);
+
+        match path::join(&mut buffer[self.root.len()..], parts) {
+            Some(len) => unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..self.root.len()+len+1]) },
+            None => abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, None)
+        }
+    }
+}
+
+fn check_name(name: &[u8]) {
+    if name.is_empty() || name.len() > NAME_MAX || name == b"." || name == b".." || name.contains(&b'/') {
+        abort(&Fatal(ExitCode::MaintenanceInvalid, "Invalid program name!"), 0, Some(name))
+    }
+}
+
+// Returns the directory and suffix of the first candidate for name, in the order the loader
+// tries them, at any feature level up to max_level
+fn find_candidate(tree: &Tree, name: &[u8], max_level: u32, variants: &Variants) -> Option<(&'static [u8], &'static [u8])> {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let mut exists = |directory: &[u8], suffix: &[u8]| {
+        let path = tree.join(&mut buffer, &[HWCAPS_PATH, directory, suffix, b"/bin/", name]);
+        // Skipped candidates are handled like missing ones
+        #[cfg(feature = "ignore_markers")]
+        {
+            let (hwcaps_dir, candidate) = path.to_bytes().split_at(tree.root.len() + HWCAPS_PATH.len());
+            if ignore::is_ignored(hwcaps_dir, candidate) {
+                return false
+            }
+        }
+        sys::faccessat(sys::AT_FDCWD, path, sys::X_OK).is_ok()
+    };
+
+    let found = variants.candidates(max_level).map(|(level, suffix)| (tree.directory(level), suffix)).find(|(directory, suffix)| exists(directory, suffix));
+    if found.is_some() {
+        return found
+    }
+
+    // Tried after every level
+    #[cfg(feature = "generic_fallback")]
+    if exists(GENERIC_DIRECTORY, b"") {
+        return Some((GENERIC_DIRECTORY, b""))
+    }
+    None
+}
+
+// Whether a candidate for name exists at any feature level up to max_level
+fn has_candidate(tree: &Tree, name: &[u8], max_level: u32, variants: &Variants) -> bool {
+    find_candidate(tree, name, max_level, variants).is_some()
+}
+
+// Whether path (relative to dirfd) is a symlink to the loader
+fn is_alias(dirfd: i32, path: &CStr, loader_path: &[u8]) -> bool {
+    let mut target = [0u8; sys::PATH_MAX as usize];
+
+    match sys::readlinkat(dirfd, path, &mut target) {
+        Ok(len) => target[..len] == loader_path[BIN_PATH.len()..] || target[..len] == *loader_path,
+        Err(_) => false
+    }
+}
+
+fn install_alias(tree: &Tree, name: &[u8], variants: &Variants) -> ! {
+    check_name(name);
+
+    // Any candidate will do, even if it isn't supported by this machine
+    if !has_candidate(tree, name, tree.level_count() - 1, variants) {
+        abort(&Fatal(ExitCode::MaintenanceInvalid, "Program has no binaries in the hwcaps directories!"), 0, Some(name))
+    }
+
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let mut target_buffer = [0u8; sys::PATH_MAX as usize];
+    let alias = tree.join(&mut buffer, &[BIN_PATH, name]);
+    // Relative, so the alias stays valid wherever the tree ends up
+    let target = join(&mut target_buffer, &[&tree.loader_path[BIN_PATH.len()..]]);
+
+    if let Err(e) = sys::symlinkat(target, sys::AT_FDCWD, alias) {
+        abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to create alias!"), e.into_raw() as u32, Some(alias.to_bytes()))
+    }
+
+    hint("Alias installed.", Some(alias.to_bytes()));
+    sys::exit(0)
+}
+
+fn remove_alias(tree: &Tree, name: &[u8]) -> ! {
+    check_name(name);
+
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let alias = tree.join(&mut buffer, &[BIN_PATH, name]);
+
+    // Never remove something which doesn't belong to us
+    if !is_alias(sys::AT_FDCWD, alias, tree.loader_path) {
+        abort(&Fatal(ExitCode::MaintenanceInvalid, "Not an alias of hwcaps-loader!"), 0, Some(alias.to_bytes()))
+    }
+
+    if let Err(e) = sys::unlinkat(sys::AT_FDCWD, alias, 0) {
+        abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to remove alias!"), e.into_raw() as u32, Some(alias.to_bytes()))
+    }
+
+    hint("Alias removed.", Some(alias.to_bytes()));
+    sys::exit(0)
+}
+
+fn verify(tree: &Tree, names: sys::Args, max_level: u32, variants: &Variants) -> ! {
+    let mut bin_buffer = [0u8; sys::PATH_MAX as usize];
+    let bin_dir = tree.join(&mut bin_buffer, &[BIN_DIR]);
+    let dirfd = match sys::openat(sys::AT_FDCWD, bin_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to open binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+    };
+
+    let mut failures = 0;
+    let mut check = |name: &[u8], explicit: bool| {
+        let mut name_buffer = [0u8; NAME_MAX + 1];
+        let mut buffer = [0u8; sys::PATH_MAX as usize];
+        let alias = tree.join(&mut buffer, &[BIN_PATH, name]).to_bytes();
+
+        if !is_alias(dirfd, join(&mut name_buffer, &[name]), tree.loader_path) {
+            // Other programs in /usr/bin are none of our business, unless we were asked about them
+            if explicit {
+                hint("Not an alias of hwcaps-loader!", Some(alias));
+                failures += 1;
+            }
+            return
+        }
+
+        if !has_candidate(tree, name, max_level, variants) {
+            hint("Alias has no candidates for this machine.", Some(alias));
+            failures += 1;
+        }
+    };
+
+    if names.is_empty() {
+        if let Err(e) = sys::read_dir(dirfd, |name| check(name, false)) {
+            abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+        }
+    } else {
+        for name in names.iter() {
+            check_name(name);
+            check(name, true);
+        }
+    }
+
+    if failures != 0 {
+        abort(&Fatal(ExitCode::VerifyFailed, "Some aliases failed verification!"), 0, None)
+    }
+
+    hint("Every alias has candidates for this machine.", None);
+    sys::exit(0)
+}
+
+fn stat_at(dirfd: i32, path: &CStr) -> Result<sys::statx, u32> {
+    let mut buffer = unsafe { core::mem::zeroed::<sys::statx>() };
+    let mask = sys::STATX_TYPE | sys::STATX_INO | sys::STATX_NLINK;
+
+    match sys::statx(dirfd, path, sys::AT_SYMLINK_NOFOLLOW, mask, &mut buffer) {
+        Ok(_) => Ok(buffer),
+        Err(e) => Err(e.into_raw() as u32)
+    }
+}
+
+fn is_same_file(a: &sys::statx, b: &sys::statx) -> bool {
+    a.stx_ino == b.stx_ino && a.stx_dev_major == b.stx_dev_major && a.stx_dev_minor == b.stx_dev_minor
+}
+
+// Creates every missing directory in path, which must be absolute
+fn create_dirs(path: &[u8]) {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+
+    for (i, c) in path.iter().enumerate().skip(1).chain([(path.len(), &b'/')]) {
+        if *c != b'/' {
+            continue
+        }
+
+        let dir = join(&mut buffer, &[&path[..i]]);
+        match sys::mkdirat(sys::AT_FDCWD, dir, 0o755) {
+            Ok(_) => (),
+            Err(e) if e.into_raw() as u32 == sys::EEXIST => (),
+            Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to create directory!"), e.into_raw() as u32, Some(dir.to_bytes()))
+        }
+    }
+}
+
+// Names of a program in /usr/bin, including its hardlinks
+struct Links {
+    names: [[u8; NAME_MAX]; MAX_LINKS],
+    lens: [usize; MAX_LINKS],
+    count: usize,
+}
+
+impl Links {
+    fn push(&mut self, name: &[u8]) {
+        if self.count == MAX_LINKS {
+            abort(&Fatal(ExitCode::MaintenanceInvalid, "Program has too many hardlinks!"), 0, Some(name))
+        }
+
+        self.names[self.count][..name.len()].copy_from_slice(name);
+        self.lens[self.count] = name.len();
+        self.count += 1;
+    }
+
+    fn get(&self, i: usize) -> &[u8] {
+        &self.names[i][..self.lens[i]]
+    }
+}
+
+fn migrate(tree: &Tree, name: &[u8]) -> ! {
+    check_name(name);
+
+    let mut bin_buffer = [0u8; sys::PATH_MAX as usize];
+    let bin_dir = tree.join(&mut bin_buffer, &[BIN_DIR]);
+    let dirfd = match sys::openat(sys::AT_FDCWD, bin_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to open binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+    };
+
+    let mut name_buffer = [0u8; NAME_MAX + 1];
+    let mut program_buffer = [0u8; sys::PATH_MAX as usize];
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let program = tree.join(&mut program_buffer, &[BIN_PATH, name]).to_bytes();
+
+    // Aliases are symlinks, so this also rejects programs which were already migrated
+    let stat = match stat_at(dirfd, join(&mut name_buffer, &[name])) {
+        Ok(s) => s,
+        Err(errno) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to inspect program!"), errno, Some(program))
+    };
+    if stat.stx_mode as u32 & sys::S_IFMT != sys::S_IFREG {
+        abort(&Fatal(ExitCode::MaintenanceInvalid, "Not a regular file!"), 0, Some(program))
+    }
+
+    // Hardlinks in /usr/bin are other names for the same program, so they're migrated too
+    let mut links = Links { names: [[0; NAME_MAX]; MAX_LINKS], lens: [0; MAX_LINKS], count: 0 };
+    if stat.stx_nlink > 1 {
+        let result = sys::read_dir(dirfd, |entry| {
+            let mut entry_buffer = [0u8; NAME_MAX + 1];
+            if stat_at(dirfd, join(&mut entry_buffer, &[entry])).is_ok_and(|s| is_same_file(&s, &stat)) {
+                links.push(entry);
+            }
+        });
+
+        if let Err(e) = result {
+            abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+        }
+    } else {
+        links.push(name);
+    }
+
+    let mut dest_dir_buffer = [0u8; sys::PATH_MAX as usize];
+    let directory = tree.directory(tree.baseline());
+    let dest_dir = tree.join(&mut dest_dir_buffer, &[HWCAPS_PATH, directory, b"/bin/"]).to_bytes();
+
+    // Check everything before changing anything
+    for i in 0..links.count {
+        let link = links.get(i);
+        if link == &tree.loader_path[BIN_PATH.len()..] {
+            abort(&Fatal(ExitCode::MaintenanceInvalid, "Refusing to migrate hwcaps-loader itself!"), 0, Some(program))
+        }
+
+        let dest = join(&mut buffer, &[dest_dir, link]);
+        if sys::faccessat(sys::AT_FDCWD, dest, sys::F_OK).is_ok() {
+            abort(&Fatal(ExitCode::MaintenanceInvalid, "Program already has a baseline binary!"), 0, Some(dest.to_bytes()))
+        }
+    }
+
+    create_dirs(&dest_dir[..dest_dir.len()-1]);
+
+    let mut target_buffer = [0u8; sys::PATH_MAX as usize];
+    let target = join(&mut target_buffer, &[&tree.loader_path[BIN_PATH.len()..]]);
+
+    for i in 0..links.count {
+        let link = links.get(i);
+        let mut dest_buffer = [0u8; sys::PATH_MAX as usize];
+        let mut temp_buffer = [0u8; NAME_MAX + 32];
+        let source = join(&mut name_buffer, &[link]);
+        let dest = join(&mut dest_buffer, &[dest_dir, link]);
+        let temp = join(&mut temp_buffer, &[b".", link, b".hwcaps-migrate"]);
+
+        // The program is linked into place before the alias replaces it, so its name never
+        // disappears from /usr/bin, not even for a moment
+        if let Err(e) = sys::linkat(dirfd, source, sys::AT_FDCWD, dest, 0) {
+            abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to move program to the hwcaps directory!"), e.into_raw() as u32, Some(dest.to_bytes()))
+        }
+
+        let result = sys::symlinkat(target, dirfd, temp).and_then(|_| sys::renameat(dirfd, temp, dirfd, source));
+        if let Err(e) = result {
+            let _ = sys::unlinkat(dirfd, temp, 0);
+            abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to create alias!"), e.into_raw() as u32, Some(source.to_bytes()))
+        }
+
+        hint("Program migrated.", Some(tree.join(&mut buffer, &[BIN_PATH, link]).to_bytes()));
+    }
+
+    sys::exit(0)
+}
+
+fn list(tree: &Tree) -> ! {
+    let mut bin_buffer = [0u8; sys::PATH_MAX as usize];
+    let bin_dir = tree.join(&mut bin_buffer, &[BIN_DIR]);
+    let dirfd = match sys::openat(sys::AT_FDCWD, bin_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to open binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+    };
+
+    let result = sys::read_dir(dirfd, |name| {
+        let mut name_buffer = [0u8; NAME_MAX + 1];
+        if is_alias(dirfd, join(&mut name_buffer, &[name]), tree.loader_path) {
+            output::print_line(name);
+        }
+    });
+
+    if let Err(e) = result {
+        abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+    }
+    sys::exit(0)
+}
+
+// Problems found by doctor(), as "<problem> Fix: <solution>"
+const LOADER_MISSING: &str = "hwcaps-loader isn't in the staged tree. Fix: install it to <root>/usr/bin/hwcaps-loader.";
+const LOADER_NESTED: &str = "hwcaps-loader isn't directly in /usr/bin. Fix: move it to /usr/bin/hwcaps-loader.";
+const LOADER_NOT_ROOT: &str = "hwcaps-loader isn't owned by root. Fix: chown root:root <path>";
+const LOADER_WRITABLE: &str = "hwcaps-loader is writable by other users. Fix: chmod go-w <path>";
+const LOADER_NOT_EXECUTABLE: &str = "hwcaps-loader isn't executable by everyone. Fix: chmod a+x <path>";
+const HWCAPS_DANGLING: &str = "hwcaps directory is a dangling symlink. Fix: point it to an existing directory, or remove it.";
+const HWCAPS_MISSING: &str = "hwcaps directory doesn't exist. Fix: install programs with hwcaps-loader migrate, or their packages.";
+const HWCAPS_NOT_DIRECTORY: &str = "hwcaps directory isn't a directory. Fix: remove it.";
+const HWCAPS_WRITABLE: &str = "hwcaps directory is writable by other users. Fix: chmod go-w <path>";
+const UNKNOWN_LEVEL: &str = "Unknown feature level directory (built for another architecture?). Fix: remove it, or install the right packages.";
+const ALIAS_WRONG_TARGET: &str = "Alias doesn't point at hwcaps-loader. Fix: ln -sf hwcaps-loader <path>";
+const ALIAS_NO_CANDIDATES: &str = "Alias has no candidates for this machine. Fix: install a build for a supported level, or remove-alias it.";
+
+// Whether directory is the generic one, which isn't a level but is still used
+fn is_generic(_directory: &[u8]) -> bool {
+    #[cfg(feature = "generic_fallback")]
+    if _directory == GENERIC_DIRECTORY {
+        return true
+    }
+    false
+}
+
+fn get_mode(path: &CStr, flags: u32) -> Result<sys::statx, u32> {
+    let mut buffer = unsafe { core::mem::zeroed::<sys::statx>() };
+    let mask = sys::STATX_TYPE | sys::STATX_MODE | sys::STATX_UID;
+
+    match sys::statx(sys::AT_FDCWD, path, flags, mask, &mut buffer) {
+        Ok(_) => Ok(buffer),
+        Err(e) => Err(e.into_raw() as u32)
+    }
+}
+
+fn doctor(tree: &Tree, max_level: u32, variants: &Variants) -> ! {
+    let mut problems = 0;
+    let mut report = |problem: &'static str, path: &[u8]| {
+        hint(problem, Some(path));
+        problems += 1;
+    };
+
+    // The loader must be in /usr/bin itself, since aliases point at it by name
+    let loader_name = &tree.loader_path[BIN_PATH.len()..];
+    if loader_name.contains(&b'/') {
+        report(LOADER_NESTED, tree.loader_path);
+    }
+
+    let mut loader_buffer = [0u8; sys::PATH_MAX as usize];
+    let loader_path = tree.join(&mut loader_buffer, &[tree.loader_path]);
+    match get_mode(loader_path, 0) {
+        Ok(loader) => {
+            let mode = loader.stx_mode as u32;
+            // Package builds run unprivileged, ownership is up to the package
+            if loader.stx_uid != 0 && !tree.staged {
+                report(LOADER_NOT_ROOT, loader_path.to_bytes());
+            }
+            if mode & 0o022 != 0 {
+                report(LOADER_WRITABLE, loader_path.to_bytes());
+            }
+            if mode & 0o111 != 0o111 {
+                report(LOADER_NOT_EXECUTABLE, loader_path.to_bytes());
+            }
+        },
+        // The running loader always exists, but a staged one may not
+        Err(_) if tree.staged => report(LOADER_MISSING, loader_path.to_bytes()),
+        Err(_) => ()
+    }
+
+    // Without the trailing slash, or symlinks would always be followed
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let hwcaps_dir = tree.join(&mut buffer, &[&HWCAPS_PATH[..HWCAPS_PATH.len()-1]]);
+    let hwcaps_path = hwcaps_dir.to_bytes();
+    match (get_mode(hwcaps_dir, 0), get_mode(hwcaps_dir, sys::AT_SYMLINK_NOFOLLOW)) {
+        (Ok(hwcaps), _) if hwcaps.stx_mode as u32 & sys::S_IFMT != sys::S_IFDIR => report(HWCAPS_NOT_DIRECTORY, hwcaps_path),
+        (Ok(hwcaps), _) if hwcaps.stx_mode as u32 & 0o022 != 0 => report(HWCAPS_WRITABLE, hwcaps_path),
+        (Ok(_), _) => (),
+        (Err(_), Ok(_)) => report(HWCAPS_DANGLING, hwcaps_path),
+        (Err(_), Err(_)) => report(HWCAPS_MISSING, hwcaps_path)
+    }
+
+    // Directories for other architectures' levels are never used
+    if let Ok(hwcaps_fd) = sys::openat(sys::AT_FDCWD, hwcaps_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+        let _ = sys::read_dir(hwcaps_fd, |directory| {
+            // Dotfiles (e.g. the level index) aren't level directories
+            if !directory.starts_with(b".") && !is_generic(directory) && tree.find_directory_level(directory).is_none() {
+                let mut path_buffer = [0u8; sys::PATH_MAX as usize];
+                report(UNKNOWN_LEVEL, tree.join(&mut path_buffer, &[HWCAPS_PATH, directory]).to_bytes());
+            }
+        });
+        let _ = sys::close(hwcaps_fd);
+    }
+
+    let mut bin_buffer = [0u8; sys::PATH_MAX as usize];
+    let bin_dir = tree.join(&mut bin_buffer, &[BIN_DIR]);
+    let dirfd = match sys::openat(sys::AT_FDCWD, bin_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to open binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+    };
+
+    let result = sys::read_dir(dirfd, |name| {
+        let mut name_buffer = [0u8; NAME_MAX + 1];
+        let mut target = [0u8; sys::PATH_MAX as usize];
+        let mut path_buffer = [0u8; sys::PATH_MAX as usize];
+        let link = join(&mut name_buffer, &[name]);
+        let alias = tree.join(&mut path_buffer, &[BIN_PATH, name]).to_bytes();
+
+        // Links which are meant to be aliases, but lead somewhere else (e.g. ../local/bin/hwcaps-loader)
+        let len = match sys::readlinkat(dirfd, link, &mut target) {
+            Ok(l) => l,
+            Err(_) => return
+        };
+        if path::file_name(&target[..len]) != loader_name {
+            return
+        }
+
+        if !is_alias(dirfd, link, tree.loader_path) {
+            report(ALIAS_WRONG_TARGET, alias);
+        } else if !has_candidate(tree, name, max_level, variants) {
+            report(ALIAS_NO_CANDIDATES, alias);
+        }
+    });
+
+    if let Err(e) = result {
+        abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read binary directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+    }
+
+    if problems != 0 {
+        abort(&Fatal(ExitCode::VerifyFailed, "Some problems were found!"), 0, None)
+    }
+
+    hint("No problems found.", None);
+    sys::exit(0)
+}
+
+fn prune(args: sys::Args) -> ! {
+    let (dry_run, names) = match args.get(0) {
+        Some(b"--dry-run") => (true, args.skip(1)),
+        _ => (false, args)
+    };
+    for name in names.iter() {
+        check_name(name);
+    }
+
+    // Unlike everything else, this ignores configured limits. Only what the hardware can't
+    // execute is removed.
+    let max_level = capabilities::get_max_feature_level();
+
+    let mut hwcaps_buffer = [0u8; sys::PATH_MAX as usize];
+    let hwcaps_dir = join(&mut hwcaps_buffer, &[HWCAPS_PATH]);
+    let hwcaps_fd = match sys::openat(sys::AT_FDCWD, hwcaps_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to open hwcaps directory!"), e.into_raw() as u32, Some(HWCAPS_PATH))
+    };
+
+    let result = sys::read_dir(hwcaps_fd, |directory| {
+        if !capabilities::find_directory_level(directory).is_some_and(|level| level > max_level) {
+            return
+        }
+
+        let mut bin_buffer = [0u8; sys::PATH_MAX as usize];
+        let bin_dir = join(&mut bin_buffer, &[HWCAPS_PATH, directory, b"/bin"]);
+        let fd = match sys::openat(sys::AT_FDCWD, bin_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+            Ok(d) => d,
+            Err(e) if e.into_raw() as u32 == sys::ENOENT => return,
+            Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to open hwcaps directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+        };
+
+        let mut remove = |name: &[u8]| {
+            if name == b"." || name == b".." {
+                return
+            }
+
+            let mut name_buffer = [0u8; NAME_MAX + 1];
+            let mut buffer = [0u8; sys::PATH_MAX as usize];
+            let name = join(&mut name_buffer, &[name]);
+            let path = join(&mut buffer, &[HWCAPS_PATH, directory, b"/bin/", name.to_bytes()]).to_bytes();
+
+            let result = match dry_run {
+                true => sys::faccessat(fd, name, sys::F_OK),
+                false => sys::unlinkat(fd, name, 0)
+            };
+
+            match result {
+                Ok(_) => output::print_line(path),
+                // Programs which were asked about don't need candidates at every level
+                Err(e) if e.into_raw() as u32 == sys::ENOENT => (),
+                Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to remove candidate!"), e.into_raw() as u32, Some(path))
+            }
+        };
+
+        if names.is_empty() {
+            if let Err(e) = sys::read_dir(fd, &mut remove) {
+                abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read hwcaps directory!"), e.into_raw() as u32, Some(bin_dir.to_bytes()))
+            }
+        } else {
+            for name in names.iter() {
+                remove(name);
+            }
+        }
+
+        let _ = sys::close(fd);
+    });
+
+    if let Err(e) = result {
+        abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read hwcaps directory!"), e.into_raw() as u32, Some(HWCAPS_PATH))
+    }
+
+    sys::exit(0)
+}
+
+#[cfg(feature = "level_index")]
+fn reindex(tree: &Tree) -> ! {
+    use crate::level_index::{INDEX_MAX, INDEX_TMP_FILE, INDEX_FILE};
+
+    let mut hwcaps_buffer = [0u8; sys::PATH_MAX as usize];
+    let hwcaps_dir = tree.join(&mut hwcaps_buffer, &[HWCAPS_PATH]);
+    let hwcaps_fd = match sys::openat(sys::AT_FDCWD, hwcaps_dir, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(e) => abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to open hwcaps directory!"), e.into_raw() as u32, Some(hwcaps_dir.to_bytes()))
+    };
+
+    let mut index = [0u8; INDEX_MAX];
+    let mut len = 0;
+    let result = sys::read_dir(hwcaps_fd, |directory| {
+        if tree.find_directory_level(directory).is_none() {
+            return
+        }
+
+        // Directories without binaries have no reason to be tried first
+        let mut bin_buffer = [0u8; sys::PATH_MAX as usize];
+        let bin_dir = tree.join(&mut bin_buffer, &[HWCAPS_PATH, directory, b"/bin"]);
+        if sys::faccessat(sys::AT_FDCWD, bin_dir, sys::F_OK).is_err() {
+            return
+        }
+
+        // Truncated indexes are ignored by the loader, so this must always fit
+        if len + directory.len() + 1 >= INDEX_MAX {
+            abort(&Fatal(ExitCode::MaintenanceInvalid, "Too many level directories!"), 0, Some(hwcaps_dir.to_bytes()))
+        }
+        index[len..len + directory.len()].copy_from_slice(directory);
+        index[len + directory.len()] = b'\n';
+        len += directory.len() + 1;
+
+        output::print_line(directory);
+    });
+
+    if let Err(e) = result {
+        abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read hwcaps directory!"), e.into_raw() as u32, Some(hwcaps_dir.to_bytes()))
+    }
+
+    // Replaced atomically, so the loader never sees a partial index
+    let _ = sys::unlinkat(hwcaps_fd, INDEX_TMP_FILE, 0);
+
+    let flags = sys::O_WRONLY | sys::O_CREAT | sys::O_EXCL | sys::O_NOFOLLOW;
+    let result = sys::openat_mode(hwcaps_fd, INDEX_TMP_FILE, flags, 0o644).and_then(|fd| {
+        let result = sys::write(fd, &index[..len]);
+        let _ = sys::close(fd);
+
+        match result? == len {
+            true => sys::renameat(hwcaps_fd, INDEX_TMP_FILE, hwcaps_fd, INDEX_FILE),
+            false => Err(sys::Errno::EIO)
+        }
+    });
+
+    if let Err(e) = result {
+        let _ = sys::unlinkat(hwcaps_fd, INDEX_TMP_FILE, 0);
+        abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to write level index!"), e.into_raw() as u32, Some(INDEX_FILE.to_bytes()))
+    }
+
+    sys::exit(0)
+}
+
+fn which(tree: &Tree, name: &[u8], max_level: u32, variants: &Variants) -> ! {
+    check_name(name);
+
+    let (directory, suffix) = match find_candidate(tree, name, max_level, variants) {
+        Some(c) => c,
+        None => abort(&Fatal(ExitCode::VerifyFailed, "Program has no candidates for this machine."), 0, Some(name))
+    };
+
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let candidate = tree.join(&mut buffer, &[HWCAPS_PATH, directory, suffix, b"/bin/", name]).to_bytes();
+
+    let mut line = [0u8; sys::PATH_MAX as usize];
+    if let Some(len) = path::join(&mut line, &[directory, b" ", candidate]) {
+        output::print_line(&line[..len]);
+    }
+    sys::exit(0)
+}
+
+const NOT_MANAGED: &str = "Program has builds, but isn't an alias of hwcaps-loader. Fix: ln -sf hwcaps-loader <path>";
+const NO_CANDIDATES: &str = "Alias has no builds left. Fix: reinstall the program, or remove-alias it.";
+const NO_BASELINE: &str = "Program has no baseline build, so older machines can't run it.";
+const NOT_EXECUTABLE: &str = "Build isn't executable. Fix: chmod +x <path>";
+const UNKNOWN_DIRECTORY: &str = "Unknown feature level directory (built for another architecture?).";
+
+// Calls f with every line read from fd. Lines which don't fit in the buffer are split.
+fn read_lines(fd: i32, mut f: impl FnMut(&[u8])) -> Result<(), sys::Errno> {
+    let mut buffer = [0u8; 2 * sys::PATH_MAX as usize];
+    let mut len = 0;
+
+    loop {
+        let read = sys::read(fd, &mut buffer[len..])?;
+        let end = len + read;
+
+        let mut start = 0;
+        while let Some(i) = buffer[start..end].iter().position(|c| *c == b'\n') {
+            f(&buffer[start..start+i]);
+            start += i + 1;
+        }
+
+        if read == 0 {
+            if start < end {
+                f(&buffer[start..end]);
+            }
+            return Ok(())
+        }
+
+        len = match end - start {
+            l if l == buffer.len() => {
+                f(&buffer[..l]);
+                0
+            },
+            l => l
+        };
+        buffer.copy_within(start..end, 0);
+    }
+}
+
+fn validate(tree: &Tree, paths: sys::Args) -> ! {
+    let mut validator = Validator::new(tree.root);
+    let mut errors = 0;
+    let mut check = |path: &[u8]| {
+        if path.is_empty() {
+            return
+        }
+
+        errors += validator.check(path, |issue: &Issue| {
+            let message = match issue.problem {
+                Problem::NotManaged => NOT_MANAGED,
+                Problem::NoCandidates => NO_CANDIDATES,
+                Problem::NoBaseline => NO_BASELINE,
+                Problem::NotExecutable => NOT_EXECUTABLE,
+                Problem::UnknownDirectory => UNKNOWN_DIRECTORY
+            };
+            hint(message, Some(issue.path));
+        });
+    };
+
+    // Package manager hooks (e.g. pacman's NeedsTargets) pass them through stdin
+    if paths.is_empty() {
+        if let Err(e) = read_lines(sys::STDIN, &mut check) {
+            abort(&Fatal(ExitCode::MaintenanceIOError, "Failed to read paths!"), e.into_raw() as u32, None)
+        }
+    } else {
+        for path in paths.iter() {
+            check(path);
+        }
+    }
+
+    // Warnings are only worth a look, the transaction is fine
+    if errors != 0 {
+        abort(&Fatal(ExitCode::VerifyFailed, "Some programs touched by the transaction can't be executed!"), 0, None)
+    }
+    sys::exit(0)
+}
+
+// Runs the subcommand in args[0], with the rest of args (and any options before it)
+pub fn run(mut args: sys::Args, loader_path: &[u8], max_level: u32) -> ! {
+    // Package builds manage a staged tree instead of the live one, possibly for another architecture
+    let mut root: &[u8] = b"";
+    let mut arch = None;
+    loop {
+        let arg = get_arg(&args, 0);
+        if let Some(dir) = arg.strip_prefix(b"--root=") {
+            root = dir;
+        } else if let Some(name) = arg.strip_prefix(b"--arch=") {
+            arch = Some(name);
+        } else {
+            break
+        }
+        args = args.skip(1);
+    }
+
+    let mut tree = Tree::new(root, loader_path);
+    if let Some(name) = arch {
+        match capabilities::find_arch_layout(name) {
+            Some(layout) => tree.layout = Some(layout),
+            None => abort(&Fatal(ExitCode::MaintenanceInvalid, "Unknown architecture!"), 0, Some(name))
+        }
+    }
+
+    // Other architectures can't be detected, so every variant counts as supported
+    let variants = match tree.layout {
+        Some(_) => Variants::all(),
+        None => Variants::detect()
+    };
+    // Staged trees are meant for other machines, so any level counts as supported
+    let max_level = match tree.staged || tree.layout.is_some() {
+        true => tree.level_count() - 1,
+        false => max_level
+    };
+
+    match (get_arg(&args, 0), args.len()) {
+        (b"install-alias", 2) => install_alias(&tree, get_arg(&args, 1), &variants),
+        (b"remove-alias", 2) => remove_alias(&tree, get_arg(&args, 1)),
+        (b"verify", _) => verify(&tree, args.skip(1), max_level, &variants),
+        (b"migrate", 2) => migrate(&tree, get_arg(&args, 1)),
+        (b"list", 1) => list(&tree),
+        (b"doctor", 1) => doctor(&tree, max_level, &variants),
+        // Only this machine's hardware decides what's pruned
+        (b"prune", _) if tree.staged => abort(&Fatal(ExitCode::MaintenanceInvalid, "prune can't be used with --root!"), 0, None),
+        (b"prune", _) if tree.layout.is_some() => abort(&Fatal(ExitCode::MaintenanceInvalid, "prune can't be used with --arch!"), 0, None),
+        (b"prune", _) => prune(args.skip(1)),
+        #[cfg(feature = "level_index")]
+        (b"reindex", 1) => reindex(&tree),
+        (b"which", 2) => which(&tree, get_arg(&args, 1), max_level, &variants),
+        // Transactions only ever touch this architecture's tree
+        (b"validate", _) if tree.layout.is_some() => abort(&Fatal(ExitCode::MaintenanceInvalid, "validate can't be used with --arch!"), 0, None),
+        (b"validate", _) => validate(&tree, args.skip(1)),
+        _ => abort(&Fatal(ExitCode::MaintenanceInvalid, "Usage: hwcaps-loader [--root=<dir>] [--arch=<name>] install-alias <name> | remove-alias <name> | verify [name...] | migrate <name> | list | doctor | prune [--dry-run] [name...] | reindex | which <name> | validate [path...]"), 0, None)
+    }
+}
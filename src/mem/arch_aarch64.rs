@@ -0,0 +1,99 @@
+use core::ffi::{c_int, c_void};
+use core::arch::asm;
+
+type Word = usize;
+const WORD_SIZE: usize = size_of::<Word>();
+
+// Copies go a byte at a time, which is tiny and fast enough for the few paths we copy. Written in
+// assembly so the compiler can't turn them back into memcpy() calls.
+#[no_mangle]
+pub unsafe extern "C" fn memcpy(dest: *mut c_void, src: *const c_void, n: usize) -> *mut c_void {
+    asm!(
+        "cbz {n}, 3f",
+        "2:",
+        "ldrb {byte:w}, [{src}], 1",
+        "strb {byte:w}, [{dest}], 1",
+        "subs {n}, {n}, 1",
+        "b.ne 2b",
+        "3:",
+        n = inout(reg) n => _,
+        dest = inout(reg) dest => _,
+        src = inout(reg) src => _,
+        byte = out(reg) _,
+        options(nostack),
+    );
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memmove(dest: *mut c_void, src: *const c_void, n: usize) -> *mut c_void {
+    if (dest as usize).wrapping_sub(src as usize) >= n {
+        // No overlap, or dest is before src
+        return memcpy(dest, src, n)
+    }
+
+    // dest overlaps the end of src, so copy backwards
+    asm!(
+        "cbz {n}, 3f",
+        "2:",
+        "ldrb {byte:w}, [{src}, -1]!",
+        "strb {byte:w}, [{dest}, -1]!",
+        "subs {n}, {n}, 1",
+        "b.ne 2b",
+        "3:",
+        n = inout(reg) n => _,
+        dest = inout(reg) (dest as *mut u8).add(n) => _,
+        src = inout(reg) (src as *const u8).add(n) => _,
+        byte = out(reg) _,
+        options(nostack),
+    );
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn memset(s: *mut c_void, c: c_int, n: usize) -> *mut c_void {
+    asm!(
+        "cbz {n}, 3f",
+        "2:",
+        "strb {c:w}, [{s}], 1",
+        "subs {n}, {n}, 1",
+        "b.ne 2b",
+        "3:",
+        n = inout(reg) n => _,
+        s = inout(reg) s => _,
+        c = in(reg) c,
+        options(nostack),
+    );
+    s
+}
+
+// Compares a word at a time, then finds the differing byte
+#[no_mangle]
+pub unsafe extern "C" fn memcmp(s1: *const c_void, s2: *const c_void, n: usize) -> c_int {
+    let (s1, s2) = (s1 as *const u8, s2 as *const u8);
+    let mut i = 0;
+
+    while i + WORD_SIZE <= n {
+        let a = (s1.add(i) as *const Word).read_unaligned();
+        let b = (s2.add(i) as *const Word).read_unaligned();
+        if a != b {
+            break
+        }
+        i += WORD_SIZE;
+    }
+
+    while i < n {
+        let (a, b) = (*s1.add(i), *s2.add(i));
+        if a != b {
+            return a as c_int - b as c_int
+        }
+        i += 1;
+    }
+    0
+}
+
+// Only equality matters here, which LLVM uses for slice comparisons
+#[no_mangle]
+pub unsafe extern "C" fn bcmp(s1: *const c_void, s2: *const c_void, n: usize) -> c_int {
+    memcmp(s1, s2, n)
+}
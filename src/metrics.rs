@@ -0,0 +1,243 @@
+/*
+   Prometheus textfile metrics.
+   Every dispatch updates /run/hwcaps-loader/metrics.prom, which can be collected by
+   node-exporter's textfile collector:
+   - hwcaps_loader_max_level_info{level}     -> Highest feature level considered on this machine
+   - hwcaps_loader_dispatches_total{level}   -> Number of dispatches to each feature level
+   - hwcaps_loader_fallbacks_total           -> Number of dispatches below the highest feature level
+   Updates are serialized with flock() on the directory and written atomically through rename().
+   Dispatches made while the lock is held elsewhere aren't counted, as they never wait for it.
+   The directory isn't created by us. Failing to update the metrics never prevents the target
+   from being executed.
+*/
+
+use core::ffi::CStr;
+use crate::sys::Errno;
+
+use crate::sys;
+use crate::capabilities::FEATURE_LEVELS;
+use crate::path::{atoi, itoa};
+
+#[cfg(not(target_os = "freebsd"))]
+pub const RUN_PATH: &'static CStr = c"/run/hwcaps-loader";
+#[cfg(target_os = "freebsd")]
+pub const RUN_PATH: &CStr = c"/var/run/hwcaps-loader";
+const METRICS_FILE: &'static CStr = c"metrics.prom";
+const METRICS_TMP_FILE: &'static CStr = c"metrics.prom.tmp";
+
+const MAX_LEVEL_METRIC: &'static [u8] = b"hwcaps_loader_max_level_info";
+const DISPATCHES_METRIC: &'static [u8] = b"hwcaps_loader_dispatches_total";
+const FALLBACKS_METRIC: &'static [u8] = b"hwcaps_loader_fallbacks_total";
+
+const METRICS_MAX: usize = 4096;
+
+struct Writer {
+    buffer: [u8; METRICS_MAX],
+    len: usize,
+}
+
+impl Writer {
+    fn push(&mut self, bytes: &[u8]) {
+        // Our output is bounded by the amount of feature levels, so it always fits
+        self.buffer[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn push_number(&mut self, n: u32) {
+        let mut digits = [0u8; 10];
+        let len = itoa(n, &mut digits);
+        self.push(&digits[..len]);
+    }
+
+    fn push_metric(&mut self, name: &[u8], kind: &[u8], help: &[u8]) {
+        self.push(b"# HELP ");
+        self.push(name);
+        self.push(b" ");
+        self.push(help);
+        self.push(b"\n# TYPE ");
+        self.push(name);
+        self.push(b" ");
+        self.push(kind);
+        self.push(b"\n");
+    }
+
+    fn push_sample(&mut self, name: &[u8], level: Option<&[u8]>, value: u32) {
+        self.push(name);
+        if let Some(l) = level {
+            self.push(b"{level=\"");
+            self.push(l);
+            self.push(b"\"}");
+        }
+        self.push(b" ");
+        self.push_number(value);
+        self.push(b"\n");
+    }
+}
+
+// Returns the value of a sample line, if it belongs to the metric (and level)
+fn parse_sample(line: &[u8], name: &[u8], level: Option<&[u8]>) -> Option<u32> {
+    let rest = line.strip_prefix(name)?;
+
+    let rest = match level {
+        Some(l) => rest.strip_prefix(b"{level=\"")?.strip_prefix(l)?.strip_prefix(b"\"}")?,
+        None => rest
+    };
+
+    atoi(rest.strip_prefix(b" ")?)
+}
+
+// Writes the metrics with the dispatch counted, keeping the counts of the previous ones
+fn rewrite(previous: &[u8], max_level: u32, level: u32, writer: &mut Writer) {
+    let mut dispatches = [0u32; FEATURE_LEVELS.len()];
+    let mut fallbacks = 0u32;
+
+    for line in previous.split(|c| *c == b'\n') {
+        if let Some(n) = parse_sample(line, FALLBACKS_METRIC, None) {
+            fallbacks = n;
+            continue
+        }
+
+        for (i, l) in FEATURE_LEVELS.iter().enumerate() {
+            if let Some(n) = parse_sample(line, DISPATCHES_METRIC, Some(l.name)) {
+                dispatches[i] = n;
+            }
+        }
+    }
+
+    dispatches[level as usize] = dispatches[level as usize].wrapping_add(1);
+    if level < max_level {
+        fallbacks = fallbacks.wrapping_add(1);
+    }
+
+    writer.push_metric(MAX_LEVEL_METRIC, b"gauge", b"Highest feature level considered on this machine.");
+    writer.push_sample(MAX_LEVEL_METRIC, Some(FEATURE_LEVELS[max_level as usize].name), 1);
+
+    writer.push_metric(DISPATCHES_METRIC, b"counter", b"Number of programs dispatched to each feature level.");
+    for (i, l) in FEATURE_LEVELS.iter().enumerate() {
+        if dispatches[i] != 0 {
+            writer.push_sample(DISPATCHES_METRIC, Some(l.name), dispatches[i]);
+        }
+    }
+
+    writer.push_metric(FALLBACKS_METRIC, b"counter", b"Number of programs dispatched below the highest feature level.");
+    writer.push_sample(FALLBACKS_METRIC, None, fallbacks);
+}
+
+fn update(dirfd: i32, max_level: u32, level: u32) -> Result<(), Errno> {
+    let mut buffer = [0u8; METRICS_MAX];
+    let len = match sys::read_file_at(dirfd, METRICS_FILE, sys::O_NOFOLLOW, &mut buffer) {
+        Ok(l) => l,
+        Err(e) if e.into_raw() as u32 == sys::ENOENT => 0,
+        Err(e) => return Err(e)
+    };
+
+    let mut writer = Writer { buffer: [0; METRICS_MAX], len: 0 };
+    rewrite(&buffer[..len], max_level, level, &mut writer);
+
+    // Remove leftovers (or anything planted there) from interrupted updates
+    let _ = sys::unlinkat(dirfd, METRICS_TMP_FILE, 0);
+
+    let flags = sys::O_WRONLY | sys::O_CREAT | sys::O_EXCL | sys::O_NOFOLLOW;
+    let fd = sys::openat_mode(dirfd, METRICS_TMP_FILE, flags, 0o644)?;
+    let result = sys::write(fd, &writer.buffer[..writer.len]);
+    let _ = sys::close(fd);
+
+    if result? != writer.len {
+        return Err(Errno::EIO)
+    }
+
+    sys::renameat(dirfd, METRICS_TMP_FILE, dirfd, METRICS_FILE)?;
+    Ok(())
+}
+
+pub fn record_dispatch(max_level: u32, level: u32) {
+    let dirfd = match sys::openat(sys::AT_FDCWD, RUN_PATH, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(d) => d,
+        Err(_) => return
+    };
+
+    // Launches never wait for each other (or for whoever else holds the lock), so the sample is
+    // dropped instead
+    if sys::flock(dirfd, sys::LOCK_EX | sys::LOCK_NB).is_ok() {
+        let _ = update(dirfd, max_level, level);
+    }
+
+    // Closing the directory releases the lock
+    let _ = sys::close(dirfd);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_sample, rewrite, Writer, METRICS_MAX, DISPATCHES_METRIC, FALLBACKS_METRIC};
+    use crate::capabilities::FEATURE_LEVELS;
+
+    fn rewritten(previous: &[u8], max_level: u32, level: u32) -> Writer {
+        let mut writer = Writer { buffer: [0; METRICS_MAX], len: 0 };
+        rewrite(previous, max_level, level, &mut writer);
+        writer
+    }
+
+    fn sample(writer: &Writer, name: &[u8], level: Option<&[u8]>) -> Option<u32> {
+        writer.buffer[..writer.len].split(|c| *c == b'\n').find_map(|line| parse_sample(line, name, level))
+    }
+
+    #[test]
+    fn parse_samples() {
+        assert_eq!(parse_sample(b"hwcaps_loader_fallbacks_total 12", FALLBACKS_METRIC, None), Some(12));
+        assert_eq!(parse_sample(b"hwcaps_loader_dispatches_total{level=\"x\"} 3", DISPATCHES_METRIC, Some(b"x")), Some(3));
+
+        let cases: &[(&[u8], Option<&[u8]>)] = &[
+            // Another level, or none at all
+            (b"hwcaps_loader_dispatches_total{level=\"y\"} 3", Some(b"x")),
+            (b"hwcaps_loader_dispatches_total 3", Some(b"x")),
+            (b"hwcaps_loader_dispatches_total{level=\"x\"}", Some(b"x")),
+            // Another metric, with our name as its prefix
+            (b"hwcaps_loader_fallbacks_total_other 1", None),
+            (b"# TYPE hwcaps_loader_fallbacks_total counter", None),
+            (b"hwcaps_loader_fallbacks_total -1", None),
+            (b"hwcaps_loader_fallbacks_total", None),
+            (b"", None),
+        ];
+
+        for (line, level) in cases {
+            let name = match level {
+                Some(_) => DISPATCHES_METRIC,
+                None => FALLBACKS_METRIC
+            };
+            assert_eq!(parse_sample(line, name, *level), None, "{:?}", core::str::from_utf8(line));
+        }
+    }
+
+    #[test]
+    fn rewrite_counts_dispatches() {
+        let max_level = FEATURE_LEVELS.len() as u32 - 1;
+        let top = Some(FEATURE_LEVELS[max_level as usize].name);
+        let baseline = Some(FEATURE_LEVELS[0].name);
+
+        let first = rewritten(b"", max_level, max_level);
+        assert_eq!(sample(&first, DISPATCHES_METRIC, top), Some(1));
+        assert_eq!(sample(&first, FALLBACKS_METRIC, None), Some(0));
+
+        // The file we wrote is read back, along with dispatches to other levels
+        let second = rewritten(&first.buffer[..first.len], max_level, max_level);
+        assert_eq!(sample(&second, DISPATCHES_METRIC, top), Some(2));
+
+        let third = rewritten(&second.buffer[..second.len], max_level, 0);
+        assert_eq!(sample(&third, DISPATCHES_METRIC, top), Some(2));
+        if max_level != 0 {
+            assert_eq!(sample(&third, DISPATCHES_METRIC, baseline), Some(1));
+            assert_eq!(sample(&third, FALLBACKS_METRIC, None), Some(1));
+        }
+    }
+
+    #[test]
+    fn rewrite_ignores_garbage() {
+        let previous = b"garbage\nhwcaps_loader_dispatches_total{level=\"unknown\"} 7\nhwcaps_loader_fallbacks_total 5\n";
+        let writer = rewritten(previous, 0, 0);
+
+        assert_eq!(sample(&writer, DISPATCHES_METRIC, Some(FEATURE_LEVELS[0].name)), Some(1));
+        assert_eq!(sample(&writer, DISPATCHES_METRIC, Some(b"unknown")), None);
+        assert_eq!(sample(&writer, FALLBACKS_METRIC, None), Some(5));
+        assert!(!writer.buffer[..writer.len].windows(7).any(|w| w == b"garbage"));
+    }
+}
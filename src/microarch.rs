@@ -0,0 +1,190 @@
+/*
+   Microarchitecture directories.
+   Packages can opt into builds tuned for a specific microarchitecture (e.g. -march=znver4), placed
+   in /usr/hwcaps/<name>/, which are tried before any level's directory. The name comes from the
+   CPU model (CPUID's vendor, family, model and stepping), through a built-in table of well known
+   x86 microarchitectures. Configuration can add models, or override the table's, with
+   "microarch = <cpu>:<name>:<level>":
+   - <cpu> is the family, model and optionally stepping in hex, like min_microcode's
+     (e.g. "19-61" or "06-55-04")
+   - <name> is the directory's name (e.g. "znver4")
+   - <level> is the feature level the build requires (e.g. "x86-64-v4")
+   The directory is only tried if the machine supports its level, after configured limits, so a
+   hypervisor hiding features or max_level keeps it from being used like any level's.
+*/
+
+use crate::capabilities::{self, Signature};
+
+const MAX_RULES: usize = 16;
+const MAX_NAME: usize = 32;
+
+// A microarchitecture directory, and the level its builds require
+#[derive(Clone, Copy)]
+pub struct Directory {
+    name: [u8; MAX_NAME],
+    len: usize,
+    level: u32,
+}
+
+impl Directory {
+    // Returns None if name can't be a directory in the hwcaps directory
+    fn new(name: &[u8], level: u32) -> Option<Self> {
+        if name.is_empty() || name.len() > MAX_NAME || name.contains(&b'/') || name.contains(&b'\0')
+            || name == b"." || name == b".." {
+            return None
+        }
+
+        let mut directory = Directory { name: [0; MAX_NAME], len: name.len(), level };
+        directory.name[..name.len()].copy_from_slice(name);
+        Some(directory)
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.len]
+    }
+
+    // Index of the level in FEATURE_LEVELS
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+}
+
+struct Microarch {
+    // Leaf 0h's ebx, edx and ecx (e.g. "GenuineIntel")
+    vendor: &'static [u8; 12],
+    family: u32,
+    // Inclusive
+    models: (u32, u32),
+    steppings: (u32, u32),
+    name: &'static [u8],
+    // As in FEATURE_LEVELS
+    level: &'static [u8],
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const INTEL: &[u8; 12] = b"GenuineIntel";
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const AMD: &[u8; 12] = b"AuthenticAMD";
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const ANY: (u32, u32) = (0, 15);
+
+// Named like GCC's -march values. Adding a CPU only takes an entry here.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const MICROARCHES: &[Microarch] = &[
+    Microarch { vendor: INTEL, family: 6, models: (0x4E, 0x4E), steppings: ANY, name: b"skylake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0x5E, 0x5E), steppings: ANY, name: b"skylake", level: b"x86-64-v3" },
+    // Kaby Lake, Coffee Lake and Comet Lake are Skylake too
+    Microarch { vendor: INTEL, family: 6, models: (0x8E, 0x8E), steppings: ANY, name: b"skylake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0x9E, 0x9E), steppings: ANY, name: b"skylake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0xA5, 0xA6), steppings: ANY, name: b"skylake", level: b"x86-64-v3" },
+    // Skylake-SP, Cascade Lake and Cooper Lake only differ by stepping
+    Microarch { vendor: INTEL, family: 6, models: (0x55, 0x55), steppings: (0, 4), name: b"skylake-avx512", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x55, 0x55), steppings: (5, 7), name: b"cascadelake", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x55, 0x55), steppings: (10, 11), name: b"cooperlake", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x7D, 0x7E), steppings: ANY, name: b"icelake-client", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x6A, 0x6A), steppings: ANY, name: b"icelake-server", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x6C, 0x6C), steppings: ANY, name: b"icelake-server", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x8C, 0x8D), steppings: ANY, name: b"tigerlake", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x8F, 0x8F), steppings: ANY, name: b"sapphirerapids", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0xCF, 0xCF), steppings: ANY, name: b"emeraldrapids", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0xAD, 0xAE), steppings: ANY, name: b"graniterapids", level: b"x86-64-v4" },
+    Microarch { vendor: INTEL, family: 6, models: (0x97, 0x97), steppings: ANY, name: b"alderlake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0x9A, 0x9A), steppings: ANY, name: b"alderlake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0xB7, 0xB7), steppings: ANY, name: b"raptorlake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0xBA, 0xBA), steppings: ANY, name: b"raptorlake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0xBF, 0xBF), steppings: ANY, name: b"raptorlake", level: b"x86-64-v3" },
+    Microarch { vendor: INTEL, family: 6, models: (0xAA, 0xAC), steppings: ANY, name: b"meteorlake", level: b"x86-64-v3" },
+    Microarch { vendor: AMD, family: 0x17, models: (0x00, 0x2F), steppings: ANY, name: b"znver1", level: b"x86-64-v3" },
+    Microarch { vendor: AMD, family: 0x17, models: (0x30, 0xFF), steppings: ANY, name: b"znver2", level: b"x86-64-v3" },
+    Microarch { vendor: AMD, family: 0x19, models: (0x00, 0x0F), steppings: ANY, name: b"znver3", level: b"x86-64-v3" },
+    Microarch { vendor: AMD, family: 0x19, models: (0x20, 0x5F), steppings: ANY, name: b"znver3", level: b"x86-64-v3" },
+    Microarch { vendor: AMD, family: 0x19, models: (0x10, 0x1F), steppings: ANY, name: b"znver4", level: b"x86-64-v4" },
+    Microarch { vendor: AMD, family: 0x19, models: (0x60, 0x7F), steppings: ANY, name: b"znver4", level: b"x86-64-v4" },
+    Microarch { vendor: AMD, family: 0x19, models: (0xA0, 0xAF), steppings: ANY, name: b"znver4", level: b"x86-64-v4" },
+    Microarch { vendor: AMD, family: 0x1A, models: (0x00, 0x4F), steppings: ANY, name: b"znver5", level: b"x86-64-v4" },
+    Microarch { vendor: AMD, family: 0x1A, models: (0x60, 0x7F), steppings: ANY, name: b"znver5", level: b"x86-64-v4" },
+];
+// Other architectures' signatures aren't CPUID's, so only configuration can name their models
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+const MICROARCHES: &[Microarch] = &[];
+
+#[derive(Clone, Copy)]
+struct Rule {
+    family: u32,
+    model: u32,
+    stepping: Option<u32>,
+    directory: Directory,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rules {
+    rules: [Option<Rule>; MAX_RULES],
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules { rules: [None; MAX_RULES] }
+    }
+}
+
+fn parse_hex(value: &[u8]) -> Option<u32> {
+    let digits = value.strip_prefix(b"0x").unwrap_or(value);
+    if digits.is_empty() || digits.len() > 8 {
+        return None
+    }
+
+    digits.iter().try_fold(0u32, |n, c| Some(n << 4 | (*c as char).to_digit(16)?))
+}
+
+impl Rules {
+    // Parses "<cpu>:<name>:<level>" (e.g. "19-61:znver4:x86-64-v4"). Returns None if it's
+    // invalid, or there are too many rules.
+    pub fn push(&mut self, value: &[u8]) -> Option<()> {
+        let mut fields = value.split(|c| *c == b':');
+        let (cpu, name, level) = (fields.next()?, fields.next()?, fields.next()?);
+        if fields.next().is_some() {
+            return None
+        }
+
+        let mut cpu = cpu.split(|c| *c == b'-');
+        let family = parse_hex(cpu.next()?)?;
+        let model = parse_hex(cpu.next()?)?;
+        let stepping = match cpu.next() {
+            Some(s) => Some(parse_hex(s)?),
+            None => None
+        };
+        if cpu.next().is_some() {
+            return None
+        }
+
+        let directory = Directory::new(name, capabilities::find_feature_level(level)?)?;
+
+        let slot = self.rules.iter_mut().find(|r| r.is_none())?;
+        *slot = Some(Rule { family, model, stepping, directory });
+        Some(())
+    }
+
+    fn find(&self, signature: &Signature) -> Option<Directory> {
+        self.rules.iter().flatten()
+            .find(|r| r.family == signature.family && r.model == signature.model
+                && r.stepping.is_none_or(|s| s == signature.stepping))
+            .map(|r| r.directory)
+    }
+}
+
+fn find_builtin(signature: &Signature) -> Option<Directory> {
+    let microarch = MICROARCHES.iter().find(|m| *m.vendor == signature.vendor && m.family == signature.family
+        && (m.models.0..=m.models.1).contains(&signature.model)
+        && (m.steppings.0..=m.steppings.1).contains(&signature.stepping))?;
+
+    Directory::new(microarch.name, capabilities::find_feature_level(microarch.level)?)
+}
+
+// Returns this CPU's microarchitecture directory, configured ones first, if its level is at most
+// max_level. Returns None if the CPU model is unknown.
+pub fn find(rules: &Rules, max_level: u32) -> Option<Directory> {
+    let signature = capabilities::get_signature()?;
+    let directory = rules.find(&signature).or_else(|| find_builtin(&signature))?;
+
+    (directory.level <= max_level).then_some(directory)
+}
@@ -0,0 +1,128 @@
+/*
+   Microcode revision gating.
+   Some features are only safe (or fast) once a microcode update has fixed them, and CPUID keeps
+   advertising them either way. Configuration can name the minimum revision a CPU model needs for
+   specific features, with "min_microcode = <cpu>:<revision>:<feature>[,<feature>...]":
+   - <cpu> is the family, model and optionally stepping in hex, like microcode file names
+     (e.g. "06-55" or "06-55-04")
+   - <revision> is the minimum revision in hex (e.g. "0x2006e05")
+   - <feature> is named as in /proc/cpuinfo's flags (e.g. "avx512f")
+   Below the minimum, the features are considered missing, so the loader falls back to the
+   highest level which doesn't need them. A revision which can't be read (e.g. the kernel was
+   built without CONFIG_MICROCODE) counts as being below every minimum, since running a lower
+   level is better than running a broken one. Under hypervisors, the revision is the one
+   /proc/cpuinfo reports.
+*/
+
+use crate::sys;
+use crate::capabilities::{self, Features};
+
+const REVISION_PATH: &'static core::ffi::CStr = c"/sys/devices/system/cpu/cpu0/microcode/version";
+// The first processor's "microcode" line comes before its (long) flags line
+const CPUINFO_MAX: usize = 1024;
+const MAX_RULES: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Rule {
+    family: u32,
+    model: u32,
+    stepping: Option<u32>,
+    revision: u32,
+    features: Features,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rules {
+    rules: [Option<Rule>; MAX_RULES],
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules { rules: [None; MAX_RULES] }
+    }
+}
+
+fn parse_hex(value: &[u8]) -> Option<u32> {
+    let digits = value.strip_prefix(b"0x").unwrap_or(value);
+    if digits.is_empty() || digits.len() > 8 {
+        return None
+    }
+
+    digits.iter().try_fold(0u32, |n, c| Some(n << 4 | (*c as char).to_digit(16)?))
+}
+
+impl Rules {
+    // Parses "<cpu>:<revision>:<features>" (e.g. "06-55-04:0x2006e05:avx512f,avx512bw").
+    // Returns None if it's invalid, or there are too many rules.
+    pub fn push(&mut self, value: &[u8]) -> Option<()> {
+        let mut fields = value.split(|c| *c == b':');
+        let (cpu, revision, names) = (fields.next()?, fields.next()?, fields.next()?);
+        if fields.next().is_some() {
+            return None
+        }
+
+        let mut cpu = cpu.split(|c| *c == b'-');
+        let family = parse_hex(cpu.next()?)?;
+        let model = parse_hex(cpu.next()?)?;
+        let stepping = match cpu.next() {
+            Some(s) => Some(parse_hex(s)?),
+            None => None
+        };
+        if cpu.next().is_some() {
+            return None
+        }
+
+        let mut features = Features::NONE;
+        for name in names.split(|c| *c == b',') {
+            features = features.union(&capabilities::find_feature(name)?);
+        }
+
+        let slot = self.rules.iter_mut().find(|r| r.is_none())?;
+        *slot = Some(Rule { family, model, stepping, revision: parse_hex(revision)?, features });
+        Some(())
+    }
+}
+
+fn get_revision() -> Option<u32> {
+    // e.g. "0x2006e05\n"
+    let mut buffer = [0u8; 32];
+    if let Ok(len) = sys::read_file(REVISION_PATH, &mut buffer) {
+        return parse_hex(buffer[..len].trim_ascii())
+    }
+
+    // The microcode driver isn't loaded under hypervisors, but /proc/cpuinfo still knows
+    let mut cpuinfo = [0u8; CPUINFO_MAX];
+    let len = sys::read_file(c"/proc/cpuinfo", &mut cpuinfo).ok()?;
+    let line = cpuinfo[..len].split(|c| *c == b'\n').find(|line| line.starts_with(b"microcode"))?;
+    let separator = line.iter().position(|c| *c == b':')?;
+    parse_hex(line[separator+1..].trim_ascii())
+}
+
+// Returns max_level, lowered to the highest level this CPU supports without the features its
+// microcode isn't recent enough for
+pub fn apply(rules: &Rules, max_level: u32) -> u32 {
+    if rules.rules[0].is_none() {
+        return max_level
+    }
+
+    let signature = match capabilities::get_signature() {
+        Some(s) => s,
+        None => return max_level
+    };
+    let revision = get_revision();
+
+    let mut gated = Features::NONE;
+    for rule in rules.rules.iter().flatten() {
+        if rule.family != signature.family || rule.model != signature.model
+            || rule.stepping.is_some_and(|s| s != signature.stepping) {
+            continue
+        }
+
+        if revision.is_none_or(|r| r < rule.revision) {
+            gated = gated.union(&rule.features);
+        }
+    }
+
+    let features = capabilities::detect().features.without(&gated);
+    core::cmp::min(max_level, capabilities::get_feature_level(&features))
+}
@@ -0,0 +1,147 @@
+/*
+   Mount-aware path validation.
+   Paths reported by the kernel depend on the mount they were reached through. If /usr/bin is
+   also mounted somewhere else (bind mounts, container image layers), executing an alias through
+   that other mount reports paths outside of /usr, which fail our prefix checks even though
+   they're the very same files.
+   When that happens, compare the identity (device and inode, from statx) of the path's parent
+   directory with the expected directory, and rewrite the path to be under it if they match.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::make_uninit_array;
+
+// Device and inode of a directory. Mount IDs differ between bind mounts of the same directory,
+// so they can't be used to tell whether two paths are the same.
+#[derive(PartialEq, Eq)]
+struct Identity {
+    dev_major: u32,
+    dev_minor: u32,
+    ino: u64,
+}
+
+fn get_identity(path: &CStr) -> Option<Identity> {
+    let mut buffer = unsafe { core::mem::zeroed::<sys::statx>() };
+    sys::statx(sys::AT_FDCWD, path, sys::AT_STATX_SYNC_AS_STAT, sys::STATX_INO, &mut buffer).ok()?;
+
+    if buffer.stx_mask & sys::STATX_INO == 0 {
+        return None
+    }
+
+    Some(Identity { dev_major: buffer.stx_dev_major, dev_minor: buffer.stx_dev_minor, ino: buffer.stx_ino })
+}
+
+// If the parent directory of path[..len] is the same directory as dir (e.g. "/usr/bin/"),
+// rewrites path to be inside of dir. Returns the new length.
+pub fn rebase(path: &mut [u8], len: usize, dir: &[u8]) -> Option<usize> {
+    let separator = path[..len].iter().rposition(|c| *c == b'/')?;
+    let name_len = len - separator - 1;
+
+    let mut parent = make_uninit_array!(sys::PATH_MAX as usize);
+    let mut expected = make_uninit_array!(sys::PATH_MAX as usize);
+
+    if separator + 1 >= parent.len() || dir.len() + 1 > expected.len() || dir.len() + name_len > path.len() {
+        return None
+    }
+
+    // The root directory's path is "/", rather than an empty one
+    let parent_len = core::cmp::max(separator, 1);
+    parent[..parent_len].copy_from_slice(&path[..parent_len]);
+    parent[parent_len] = b'\0';
+    expected[..dir.len()].copy_from_slice(dir);
+    expected[dir.len()] = b'\0';
+
+    let parent = unsafe { CStr::from_bytes_with_nul_unchecked(&parent[..parent_len+1]) };
+    let expected = unsafe { CStr::from_bytes_with_nul_unchecked(&expected[..dir.len()+1]) };
+
+    if get_identity(parent)? != get_identity(expected)? {
+        return None
+    }
+
+    path.copy_within(separator+1..len, dir.len());
+    path[..dir.len()].copy_from_slice(dir);
+
+    Some(dir.len() + name_len)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{fs, os::unix::fs::symlink, path::PathBuf, process};
+    use super::{rebase, Identity};
+
+    // A scratch directory with "bin" (the expected directory), "link" and "l" (the same directory
+    // through other paths, like other mounts of it) and "copy" (a different directory)
+    fn scratch(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(std::format!("hwcaps-mounts-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("bin")).unwrap();
+        fs::create_dir_all(root.join("copy")).unwrap();
+        symlink(root.join("bin"), root.join("link")).unwrap();
+        symlink(root.join("bin"), root.join("l")).unwrap();
+        root
+    }
+
+    fn bytes(path: &PathBuf, suffix: &str) -> std::vec::Vec<u8> {
+        std::format!("{}{}", path.display(), suffix).into_bytes()
+    }
+
+    #[test]
+    fn identity_needs_device_and_inode() {
+        let identity = Identity { dev_major: 8, dev_minor: 1, ino: 42 };
+
+        assert!(identity == Identity { dev_major: 8, dev_minor: 1, ino: 42 });
+        // The same inode number on another filesystem is another directory
+        assert!(identity != Identity { dev_major: 8, dev_minor: 2, ino: 42 });
+        assert!(identity != Identity { dev_major: 0, dev_minor: 1, ino: 42 });
+        assert!(identity != Identity { dev_major: 8, dev_minor: 1, ino: 43 });
+    }
+
+    #[test]
+    fn rebase_same_directory() {
+        let root = scratch("same");
+        let dir = bytes(&root, "/bin/");
+
+        let mut path = [0u8; 512];
+        let linked = bytes(&root, "/link/foo");
+        path[..linked.len()].copy_from_slice(&linked);
+
+        let len = rebase(&mut path, linked.len(), &dir).unwrap();
+        assert_eq!(&path[..len], bytes(&root, "/bin/foo").as_slice());
+
+        // Paths which wouldn't fit once rewritten are left alone
+        let short = bytes(&root, "/l/foo");
+        path[..short.len()].copy_from_slice(&short);
+        assert_eq!(rebase(&mut path[..short.len()], short.len(), &dir), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rebase_other_directory() {
+        let root = scratch("other");
+        let dir = bytes(&root, "/bin/");
+
+        let mut path = [0u8; 512];
+        let copied = bytes(&root, "/copy/foo");
+        path[..copied.len()].copy_from_slice(&copied);
+        assert_eq!(rebase(&mut path, copied.len(), &dir), None);
+
+        // Directories which don't exist have no identity
+        let missing = bytes(&root, "/missing/foo");
+        path[..missing.len()].copy_from_slice(&missing);
+        assert_eq!(rebase(&mut path, missing.len(), &dir), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rebase_root() {
+        let mut path = *b"/foo";
+        assert_eq!(rebase(&mut path, 4, b"/"), Some(4));
+        assert_eq!(&path, b"/foo");
+    }
+}
@@ -1,5 +1,9 @@
-use crate::sys::{exit, ExitCode, writev, iovec, STDOUT};
+use crate::sys::{exit, ExitCode, writev, iovec, STDOUT, PATH_MAX};
 use crate::path::itoa;
+#[cfg(feature = "error_file")]
+use crate::last_error;
+#[cfg(feature = "resilience")]
+use crate::resilience;
 
 use core::mem::MaybeUninit;
 
@@ -40,8 +44,72 @@ pub mod debug {
     }
 }
 
+// Control characters in paths could fake log lines or send escape sequences to the terminal,
+// so they're written as "\xNN". Backslashes are escaped too, or escapes would be ambiguous.
+// Other bytes are left alone, so UTF-8 names stay readable.
+fn needs_escape(c: u8) -> bool {
+    c < 0x20 || c == 0x7f || c == b'\\'
+}
+
+#[inline(always)]
+fn print(msg: &'static str, errno: u32, path: Option<&[u8]>) -> bool {
+    print_to(STDOUT, None, msg, errno, path)
+}
+
+// Writes a message to fd, along with the exit code if there's one.
+// Returns whether it was written.
+#[inline(always)]
+pub fn print_to(fd: i32, code: Option<ExitCode>, msg: &'static str, errno: u32, path: Option<&[u8]>) -> bool {
+    // Some callers pass paths along with their terminator
+    let path = path.map(|p| &p[..p.iter().position(|c| *c == b'\0').unwrap_or(p.len())]);
+
+    match path {
+        Some(p) if p.iter().any(|c| needs_escape(*c)) => print_escaped(fd, code, msg, errno, p),
+        _ => write_message(fd, code, msg, errno, path)
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn print_escaped(fd: i32, code: Option<ExitCode>, msg: &'static str, errno: u32, path: &[u8]) -> bool {
+    let mut buffer = [0u8; PATH_MAX as usize];
+    let len = escape(path, &mut buffer);
+
+    write_message(fd, code, msg, errno, Some(&buffer[..len]))
+}
+
+// Writes path to buffer, escaped like messages' paths. Returns its length.
+// Paths which don't fit are cut short, and end with "...".
+pub fn escape(path: &[u8], buffer: &mut [u8]) -> usize {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    const ELLIPSIS: &[u8] = b"...";
+
+    let mut len = 0;
+
+    for c in path {
+        let escaped = needs_escape(*c);
+        let width = if escaped { 4 } else { 1 };
+
+        // Paths are usually bounded by PATH_MAX, but escaping can make them longer
+        if len + width > buffer.len() - ELLIPSIS.len() {
+            buffer[len..len + ELLIPSIS.len()].copy_from_slice(ELLIPSIS);
+            len += ELLIPSIS.len();
+            break
+        }
+
+        if escaped {
+            buffer[len..len + 4].copy_from_slice(&[b'\\', b'x', HEX[(*c >> 4) as usize], HEX[(*c & 0xf) as usize]]);
+        } else {
+            buffer[len] = *c;
+        }
+        len += width;
+    }
+
+    len
+}
+
 #[inline(always)]
-fn print(msg: &'static str, errno: u32, path: Option<&[u8]>) {
+fn write_message(fd: i32, code: Option<ExitCode>, msg: &'static str, errno: u32, path: Option<&[u8]>) -> bool {
     let mut array: [MaybeUninit<iovec>; 9] = [const { MaybeUninit::uninit() }; 9];
     let mut offset = 0;
 
@@ -70,22 +138,87 @@ fn print(msg: &'static str, errno: u32, path: Option<&[u8]>) {
         _ => ()
     }
 
+    let mut code_buffer: [u8; 16];
+    if let Some(code) = code {
+        write_part(b" | Exit code: ");
+
+        code_buffer = [0; 16];
+        let len = itoa(code as u32, &mut code_buffer);
+
+        write_part(&code_buffer[..len]);
+    }
+
     write_part(b"\n");
 
-    let _ = writev(STDOUT, (array).as_ptr(), offset);
+    writev(fd, (array).as_ptr(), offset).is_ok()
 }
 
+// Everything an abort site knows at compile time. Written as &Fatal(code, message), so it's
+// promoted to a static and aborting only takes a pointer, instead of setting up every argument.
+pub struct Fatal(pub ExitCode, pub &'static str);
+
+// Every abort goes through here, out of line, to keep hot code small
 #[cold]
-pub fn abort(err: ExitCode, msg: &'static str, errno: u32, path: Option<&[u8]>) -> ! {
+#[inline(never)]
+pub fn abort(fatal: &'static Fatal, errno: u32, path: Option<&[u8]>) -> ! {
     #[cfg(feature = "error_output")]
-    print(msg, errno, path);
+    let _printed = print(fatal.1, errno, path);
+    #[cfg(not(feature = "error_output"))]
+    let _printed = false;
+
+    #[cfg(feature = "error_file")]
+    if !_printed {
+        last_error::record(fatal, errno, path);
+    }
 
-    exit(err as u8)
+    #[cfg(feature = "resilience")]
+    resilience::recover(fatal.0);
+
+    exit(fatal.0 as u8)
+}
+
+#[cfg(feature = "error_output")]
+#[cold]
+pub fn hint(msg: &'static str, path: Option<&[u8]>) {
+    let _ = print(msg, 0, path);
+}
+
+// Prints line as-is, for modes whose output is read by other programs
+#[allow(dead_code)]
+pub fn print_line(line: &[u8]) {
+    let parts = [MaybeUninit::new(iovec::new(line)), MaybeUninit::new(iovec::new(b"\n"))];
+    let _ = writev(STDOUT, parts.as_ptr(), parts.len());
 }
 
 #[cfg(debug_assertions)]
 #[cold]
 pub fn debug_print(msg: &'static str, errno: u32, path: Option<&[u8]>) {
-    print(msg, errno, path);
+    let _ = print(msg, errno, path);
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::escape;
+
+    #[test]
+    fn escaping() {
+        let cases: &[(&[u8], usize, &[u8])] = &[
+            (b"/usr/bin/foo", 64, b"/usr/bin/foo"),
+            (b"/usr/bin/f\xc3\xb6\xc3\xb6", 64, b"/usr/bin/f\xc3\xb6\xc3\xb6"),
+            (b"/tmp/foo\n123 /bin/sh exec", 64, b"/tmp/foo\\x0a123 /bin/sh exec"),
+            (b"/tmp/\x1b[2J\x7f", 64, b"/tmp/\\x1b[2J\\x7f"),
+            (b"/tmp/a\\x0a", 64, b"/tmp/a\\x5cx0a"),
+            // Escapes aren't cut in half
+            (b"/tmp/abc", 8, b"/tmp/..."),
+            (b"/tmp/\n", 8, b"/tmp/..."),
+            (b"/tmp/\n", 12, b"/tmp/\\x0a"),
+        ];
+
+        for (path, size, expected) in cases {
+            let mut buffer = [0u8; 64];
+            let len = escape(path, &mut buffer[..*size]);
+            assert!(&buffer[..len] == *expected, "{:?}", core::str::from_utf8(&buffer[..len]));
+        }
+    }
+}
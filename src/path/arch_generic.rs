@@ -35,26 +35,149 @@ pub fn itoa(mut n: u32, arr: &mut [u8]) -> usize {
     i
 }
 
+#[allow(dead_code)]
+pub fn atoi(arr: &[u8]) -> Option<u32> {
+    if arr.is_empty() {
+        return None
+    }
+
+    let mut n: u32 = 0;
+    for c in arr {
+        if !c.is_ascii_digit() {
+            return None
+        }
+        n = n.checked_mul(10)?.checked_add((c - b'0') as u32)?;
+    }
+    Some(n)
+}
+
+// Concatenates parts into buffer, adding a terminator.
+// Returns the length without the terminator, or None if it doesn't fit.
+#[allow(dead_code)]
+pub fn join(buffer: &mut [u8], parts: &[&[u8]]) -> Option<usize> {
+    let mut len = 0;
+    for part in parts {
+        if len + part.len() >= buffer.len() {
+            return None
+        }
+        buffer[len..len + part.len()].copy_from_slice(part);
+        len += part.len();
+    }
+    buffer[len] = b'\0';
+
+    Some(len)
+}
+
+// Collapses repeated slashes and removes trailing ones (e.g. "/usr/bin//foo/" -> "/usr/bin/foo"),
+// except for a lone "/". path doesn't include a terminator, but one is added to buffer.
+// Returns the length including the terminator. buffer must fit path and its terminator.
+pub fn normalize(buffer: &mut [u8], path: &[u8]) -> usize {
+    let mut len = 0;
+    for c in path {
+        if *c == b'/' && len != 0 && buffer[len-1] == b'/' {
+            continue
+        }
+        buffer[len] = *c;
+        len += 1;
+    }
+
+    if len > 1 && buffer[len-1] == b'/' {
+        len -= 1;
+    }
+    buffer[len] = b'\0';
+
+    len + 1
+}
+
+// Returns the last component of path (e.g. "foo" for "/usr/bin/foo")
+pub fn file_name(path: &[u8]) -> &[u8] {
+    match path.iter().rposition(|c| *c == b'/') {
+        Some(i) => &path[i+1..],
+        None => path
+    }
+}
+
+// Scalar path routines, replaced by SIMD ones where available
+#[allow(dead_code)]
+#[inline]
+pub fn init_simd(_feature_level: u32) {}
+
+#[allow(dead_code)]
+#[inline]
+pub fn copy(dst: &mut [u8], src: &[u8]) {
+    dst.copy_from_slice(src)
+}
+
+#[allow(dead_code)]
+#[inline]
+pub fn equal(a: &[u8], b: &[u8]) -> bool {
+    a == b
+}
+
 pub fn is_loader_binary(loader_path: &[u8], argv0_path: &[u8]) -> bool {
     if loader_path.len() <= BIN_PATH.len() {return false};
     let loader_name = &loader_path[BIN_PATH.len()..];
 
-    if argv0_path.len() - 1 <= loader_name.len() {return false};
-    let argv0_name = &argv0_path[argv0_path.len()-1-loader_name.len()..argv0_path.len()-1];
-
-    // We use this simple (but unoptimized) loop here due to Rust using very large (600+ bytes)
-    // intrisic functions for array/slice comparisons which don't fit in a architectural word (1/2/4/8) bytes.
-    // If libc is linked, its implementation of memcmp is used instead, bypassing this issue.
-    #[cfg(target_os="none")]
-    {
-        for i in 0..argv0_name.len()-1 {
-            if loader_name[i] == argv0_name[i] {
-                return true
-            }
+    // argv0 may be a bare name (hwcaps-loader) or a path (/usr/bin/hwcaps-loader, ./hwcaps-loader)
+    let argv0_name = file_name(&argv0_path[..argv0_path.len()-1]);
+
+    super::equal(loader_name, argv0_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+    use crate::capabilities;
+
+    #[test]
+    fn normalize_paths() {
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"/usr/bin//foo", b"/usr/bin/foo"),
+            (b"/usr/bin//foo/", b"/usr/bin/foo"),
+            (b"///usr///bin///", b"/usr/bin"),
+            (b"foo/", b"foo"),
+            (b"foo", b"foo"),
+            (b"//", b"/"),
+            (b"/", b"/"),
+            (b"", b""),
+        ];
+
+        for (path, expected) in cases {
+            let mut buffer = [0xFF; 32];
+            let len = normalize(&mut buffer, path);
+
+            assert_eq!(&buffer[..len-1], *expected, "{:?}", core::str::from_utf8(path));
+            assert_eq!(buffer[len-1], b'\0');
         }
-        false
     }
 
-    #[cfg(not(target_os="none"))]
-    return loader_name == argv0_name;
+    // Paths are composed with copy() and compared with equal(), which are SIMD routines with
+    // simd_paths, so they're checked against the scalar ones at every level this machine has
+    #[test]
+    fn compose_paths() {
+        let mut long = [0u8; 128];
+        for (i, c) in long.iter_mut().enumerate() {
+            *c = if i % 8 == 0 { b'/' } else { b'a' + (i % 26) as u8 };
+        }
+
+        for level in 0..=capabilities::get_max_feature_level() {
+            crate::path::init_simd(level);
+
+            for len in 0..long.len() {
+                let mut dst = [0u8; 128];
+                crate::path::copy(&mut dst[..len], &long[..len]);
+                assert_eq!(&dst[..len], &long[..len], "level {level}, length {len}");
+
+                let mut buffer = [0u8; 129];
+                let normalized = normalize(&mut buffer, &dst[..len]);
+                assert!(crate::path::equal(&buffer[..normalized-1], &long[..normalized-1]));
+
+                for i in 0..len {
+                    dst[i] ^= 1;
+                    assert!(!crate::path::equal(&dst[..len], &long[..len]), "level {level}, length {len}, byte {i}");
+                    dst[i] ^= 1;
+                }
+            }
+        }
+    }
 }
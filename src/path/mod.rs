@@ -1,3 +1,5 @@
+// Only needed by architectures with their own routines, which fall back to it for the rest
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[path = "arch_generic.rs"]
 mod arch_fallback;
 
@@ -6,3 +8,9 @@ mod arch_fallback;
 mod arch_generic;
 
 pub use arch_generic::*;
+
+#[cfg(all(feature = "simd_paths", target_arch = "x86_64"))]
+mod simd_x86_64;
+
+#[cfg(all(feature = "simd_paths", target_arch = "x86_64"))]
+pub use simd_x86_64::{init_simd, copy, equal};
@@ -0,0 +1,123 @@
+/*
+   SIMD path routines.
+   Once the machine's feature level is known, longer copies and comparisons done while composing
+   paths use AVX2 (x86-64-v3 and newer) or SSE2 (every x86_64 machine). Short paths aren't worth
+   the vector setup, so they keep using the scalar routines.
+*/
+
+use core::arch::x86_64::*;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::capabilities;
+
+// Shorter slices are handled by the scalar routines
+const MIN_SIMD_LEN: usize = 32;
+
+static AVX2: AtomicBool = AtomicBool::new(false);
+
+pub fn init_simd(feature_level: u32) {
+    let avx2 = capabilities::find_directory_level(b"x86-64-v3").is_some_and(|level| feature_level >= level);
+    AVX2.store(avx2, Ordering::Relaxed);
+}
+
+// Same as dst.copy_from_slice(src)
+pub fn copy(dst: &mut [u8], src: &[u8]) {
+    assert!(dst.len() == src.len());
+
+    if src.len() < MIN_SIMD_LEN {
+        return dst.copy_from_slice(src)
+    }
+
+    unsafe {
+        if AVX2.load(Ordering::Relaxed) {
+            copy_avx2(dst.as_mut_ptr(), src.as_ptr(), src.len())
+        } else {
+            copy_sse2(dst.as_mut_ptr(), src.as_ptr(), src.len())
+        }
+    }
+}
+
+// Same as a == b
+pub fn equal(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false
+    }
+
+    if a.len() < MIN_SIMD_LEN {
+        return a == b
+    }
+
+    unsafe {
+        if AVX2.load(Ordering::Relaxed) {
+            equal_avx2(a.as_ptr(), b.as_ptr(), a.len())
+        } else {
+            equal_sse2(a.as_ptr(), b.as_ptr(), a.len())
+        }
+    }
+}
+
+// The tails are handled with one last (overlapping) vector, so len must fit at least one.
+
+#[target_feature(enable = "avx2")]
+unsafe fn copy_avx2(dst: *mut u8, src: *const u8, len: usize) {
+    let mut i = 0;
+    while i + 32 <= len {
+        _mm256_storeu_si256(dst.add(i) as *mut __m256i, _mm256_loadu_si256(src.add(i) as *const __m256i));
+        i += 32;
+    }
+
+    if i < len {
+        let tail = len - 32;
+        _mm256_storeu_si256(dst.add(tail) as *mut __m256i, _mm256_loadu_si256(src.add(tail) as *const __m256i));
+    }
+}
+
+unsafe fn copy_sse2(dst: *mut u8, src: *const u8, len: usize) {
+    let mut i = 0;
+    while i + 16 <= len {
+        _mm_storeu_si128(dst.add(i) as *mut __m128i, _mm_loadu_si128(src.add(i) as *const __m128i));
+        i += 16;
+    }
+
+    if i < len {
+        let tail = len - 16;
+        _mm_storeu_si128(dst.add(tail) as *mut __m128i, _mm_loadu_si128(src.add(tail) as *const __m128i));
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn equal_avx2(a: *const u8, b: *const u8, len: usize) -> bool {
+    let compare = |i: usize| {
+        let x = _mm256_loadu_si256(a.add(i) as *const __m256i);
+        let y = _mm256_loadu_si256(b.add(i) as *const __m256i);
+        _mm256_movemask_epi8(_mm256_cmpeq_epi8(x, y)) == -1
+    };
+
+    let mut i = 0;
+    while i + 32 <= len {
+        if !compare(i) {
+            return false
+        }
+        i += 32;
+    }
+
+    i == len || compare(len - 32)
+}
+
+unsafe fn equal_sse2(a: *const u8, b: *const u8, len: usize) -> bool {
+    let compare = |i: usize| {
+        let x = _mm_loadu_si128(a.add(i) as *const __m128i);
+        let y = _mm_loadu_si128(b.add(i) as *const __m128i);
+        _mm_movemask_epi8(_mm_cmpeq_epi8(x, y)) == 0xFFFF
+    };
+
+    let mut i = 0;
+    while i + 16 <= len {
+        if !compare(i) {
+            return false
+        }
+        i += 16;
+    }
+
+    i == len || compare(len - 16)
+}
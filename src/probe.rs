@@ -0,0 +1,192 @@
+/*
+   Candidate probing through io_uring.
+   Instead of attempting to execute every candidate until one exists, submit a statx()
+   for every candidate in a single ring submission and pick the best existing one from
+   the completions. This saves syscall round-trips on high-latency filesystems
+   (NFS-mounted /usr, for example), since the lookups happen concurrently.
+*/
+
+use core::mem::size_of;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::sys;
+use crate::capabilities;
+use crate::variants::MAX_VARIANTS;
+use crate::HWCAPS_PATH;
+
+const MAX_CANDIDATES: usize = capabilities::FEATURE_LEVELS.len() * MAX_VARIANTS;
+// Candidate paths are packed together. Unusually long ones are left to the fallback loop.
+const CANDIDATES_MAX: usize = 2 * sys::PATH_MAX as usize;
+
+// Only the fields used by IORING_OP_STATX. The bindgen layout is a mess of unions.
+#[repr(C)]
+struct StatxSqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    dirfd: i32,
+    statx_buffer: u64,
+    path: u64,
+    mask: u32,
+    statx_flags: u32,
+    user_data: u64,
+    _pad: [u64; 3],
+}
+
+struct Ring {
+    fd: i32,
+    rings: *mut u8,
+    rings_len: usize,
+    sqes: *mut StatxSqe,
+    sqes_len: usize,
+    params: sys::io_uring_params,
+}
+
+impl Ring {
+    fn new(entries: u32) -> Result<Self, ()> {
+        let mut params = unsafe { core::mem::zeroed::<sys::io_uring_params>() };
+        let fd = sys::io_uring_setup(entries, &mut params).map_err(|_| ())?;
+
+        let mut ring = Ring { fd, rings: ptr::null_mut(), rings_len: 0, sqes: ptr::null_mut(), sqes_len: 0, params };
+
+        // Kernels without a single mmap for both rings (< 5.4) don't support statx operations anyway
+        if params.features & sys::IORING_FEAT_SINGLE_MMAP == 0 {
+            return Err(())
+        }
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * size_of::<u32>();
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<sys::io_uring_cqe>();
+        let rings_len = core::cmp::max(sq_len, cq_len);
+
+        let prot = sys::PROT_READ | sys::PROT_WRITE;
+        let flags = sys::MAP_SHARED | sys::MAP_POPULATE;
+
+        ring.rings = sys::mmap(ptr::null_mut(), rings_len, prot, flags, fd, sys::IORING_OFF_SQ_RING as u64).map_err(|_| ())? as *mut u8;
+        ring.rings_len = rings_len;
+
+        let sqes_len = params.sq_entries as usize * size_of::<StatxSqe>();
+        ring.sqes = sys::mmap(ptr::null_mut(), sqes_len, prot, flags, fd, sys::IORING_OFF_SQES as u64).map_err(|_| ())? as *mut StatxSqe;
+        ring.sqes_len = sqes_len;
+
+        Ok(ring)
+    }
+
+    unsafe fn field(&self, offset: u32) -> &AtomicU32 {
+        &*(self.rings.add(offset as usize) as *const AtomicU32)
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        if !self.sqes.is_null() {
+            let _ = sys::munmap(self.sqes as *mut _, self.sqes_len);
+        }
+        if !self.rings.is_null() {
+            let _ = sys::munmap(self.rings as *mut _, self.rings_len);
+        }
+        let _ = sys::close(self.fd);
+    }
+}
+
+// Writes the null-terminated candidate path for a feature level and variant into buffer.
+// Returns its length.
+fn format_candidate(buffer: &mut [u8], feature_level: u32, suffix: &[u8], bin_path: &[u8]) -> Result<usize, ()> {
+    let directory = capabilities::FEATURE_LEVELS[feature_level as usize].directory;
+
+    let directory_index = HWCAPS_PATH.len();
+    let suffix_index = directory_index + directory.len();
+    let bin_index = suffix_index + suffix.len();
+    let len = bin_index + bin_path.len();
+    if len > buffer.len() {
+        return Err(())
+    }
+
+    buffer[..directory_index].copy_from_slice(HWCAPS_PATH);
+    buffer[directory_index..suffix_index].copy_from_slice(directory);
+    buffer[suffix_index..bin_index].copy_from_slice(suffix);
+    buffer[bin_index..len].copy_from_slice(bin_path);
+
+    Ok(len)
+}
+
+// Returns the highest feature level (up to max_level) which has a candidate for bin_path in any
+// of the variants, None if no candidates exist, or an error if io_uring can't be used on this system.
+// bin_path must be null-terminated and relative to /usr (e.g. "/bin/foo").
+pub fn find_best_level(max_level: u32, suffixes: &[&[u8]], bin_path: &[u8]) -> Result<Option<u32>, ()> {
+    let mut buffer = [0u8; CANDIDATES_MAX];
+    let mut candidates = [(0u32, 0usize); MAX_CANDIDATES];
+    let mut count = 0;
+    let mut offset = 0;
+
+    for level in 0..=max_level {
+        for suffix in suffixes {
+            let len = format_candidate(&mut buffer[offset..], level, suffix, bin_path)?;
+            candidates[count] = (level, offset);
+            count += 1;
+            offset += len;
+        }
+    }
+
+    let ring = Ring::new(MAX_CANDIDATES as u32)?;
+    let params = &ring.params;
+
+    // We don't care about the results, so every operation can share the same buffer
+    let mut statx_buffer = [0u64; 32];
+
+    unsafe {
+        let sq_array = ring.rings.add(params.sq_off.array as usize) as *mut u32;
+        let sq_mask = ring.field(params.sq_off.ring_mask).load(Ordering::Relaxed);
+        let sq_tail = ring.field(params.sq_off.tail).load(Ordering::Relaxed);
+
+        for (i, (level, offset)) in candidates[..count].iter().enumerate() {
+            let index = (sq_tail + i as u32) & sq_mask;
+
+            ring.sqes.add(index as usize).write(StatxSqe {
+                opcode: sys::io_uring_op_IORING_OP_STATX as u8,
+                flags: 0,
+                ioprio: 0,
+                dirfd: sys::AT_FDCWD,
+                statx_buffer: statx_buffer.as_mut_ptr() as u64,
+                path: buffer[*offset..].as_ptr() as u64,
+                mask: 0,
+                statx_flags: 0,
+                user_data: *level as u64,
+                _pad: [0; 3],
+            });
+            sq_array.add(index as usize).write(index);
+        }
+
+        ring.field(params.sq_off.tail).store(sq_tail + count as u32, Ordering::Release);
+    }
+
+    let submitted = sys::io_uring_enter(ring.fd, count as u32, count as u32, sys::IORING_ENTER_GETEVENTS).map_err(|_| ())?;
+    if submitted != count {
+        return Err(())
+    }
+
+    let mut best_level = None;
+
+    unsafe {
+        let cqes = ring.rings.add(params.cq_off.cqes as usize) as *const sys::io_uring_cqe;
+        let cq_mask = ring.field(params.cq_off.ring_mask).load(Ordering::Relaxed);
+        let mut cq_head = ring.field(params.cq_off.head).load(Ordering::Relaxed);
+        let cq_tail = ring.field(params.cq_off.tail).load(Ordering::Acquire);
+
+        while cq_head != cq_tail {
+            let cqe = &*cqes.add((cq_head & cq_mask) as usize);
+
+            match -cqe.res as u32 {
+                0 => best_level = core::cmp::max(best_level, Some(cqe.user_data as u32)),
+                sys::ENOENT | sys::ENOTDIR => (),
+                // Kernels without IORING_OP_STATX (< 5.6) and other unexpected errors
+                _ => return Err(())
+            }
+            cq_head += 1;
+        }
+
+        ring.field(params.cq_off.head).store(cq_head, Ordering::Release);
+    }
+
+    Ok(best_level)
+}
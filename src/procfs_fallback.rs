@@ -0,0 +1,122 @@
+/*
+   Path resolution without procfs.
+   Minimal containers and initramfs images may not have /proc mounted, which breaks
+   /proc/self/exe and /dev/fd. When that happens:
+   - Directories are resolved by changing into them and asking the kernel for the
+     current working directory (getcwd() doesn't depend on procfs).
+   - The loader's path is found by following the symlinks of the path we were executed
+     through (AT_EXECFN) until we reach the loader binary itself.
+*/
+
+use core::ffi::CStr;
+use crate::sys::Errno;
+
+use crate::sys;
+use crate::make_uninit_array;
+
+// Same as the kernel's MAXSYMLINKS
+const MAX_SYMLINKS: usize = 40;
+
+fn find_last_slash(path: &[u8]) -> Option<usize> {
+    path.iter().rposition(|c| *c == b'/')
+}
+
+fn getcwd_of(dirfd: i32, dir: &CStr, buffer: &mut [u8]) -> Result<usize, Errno> {
+    let old_cwd = sys::openat(sys::AT_FDCWD, c".", sys::O_PATH | sys::O_DIRECTORY)?;
+
+    let result = sys::openat(dirfd, dir, sys::O_PATH | sys::O_DIRECTORY).and_then(|dir_fd| {
+        let result = sys::fchdir(dir_fd).and_then(|_| sys::getcwd(buffer));
+        let _ = sys::close(dir_fd);
+        result
+    });
+
+    // Our working directory is inherited by the target, so it must always be restored
+    let restored = sys::fchdir(old_cwd);
+    let _ = sys::close(old_cwd);
+
+    match restored {
+        Ok(_) => result,
+        Err(_) => Err(Errno::EIO)
+    }
+}
+
+// Resolves the absolute path of path (relative to dirfd), without following its last component.
+// path must be null-terminated. Returns the length of the resolved path, without a terminator.
+pub fn resolve_path(dirfd: i32, path: &[u8], buffer: &mut [u8]) -> Result<usize, Errno> {
+    let path = &path[..path.len()-1];
+
+    let mut dir_buffer = make_uninit_array!(sys::PATH_MAX as usize);
+    let (dir, name): (&CStr, &[u8]) = match find_last_slash(path) {
+        None => (c".", path),
+        Some(0) => (c"/", &path[1..]),
+        Some(i) => {
+            if i >= dir_buffer.len() {
+                return Err(Errno::ENAMETOOLONG)
+            }
+            dir_buffer[..i].copy_from_slice(&path[..i]);
+            dir_buffer[i] = b'\0';
+            (unsafe { CStr::from_bytes_with_nul_unchecked(&dir_buffer[..i+1]) }, &path[i+1..])
+        }
+    };
+
+    let mut len = getcwd_of(dirfd, dir, buffer)? - 1;
+
+    if buffer[..len] != *b"/" {
+        if len >= buffer.len() {
+            return Err(Errno::ENAMETOOLONG)
+        }
+        buffer[len] = b'/';
+        len += 1;
+    }
+
+    if len + name.len() > buffer.len() {
+        return Err(Errno::ENAMETOOLONG)
+    }
+    buffer[len..len + name.len()].copy_from_slice(name);
+
+    Ok(len + name.len())
+}
+
+// Finds the loader's absolute path by following the symlinks of the path we were executed through.
+// Returns the length of the path, without a terminator.
+pub fn get_loader_path(buffer: &mut [u8]) -> Result<usize, Errno> {
+    let execfn = match sys::execfn() {
+        Some(p) => p.to_bytes_with_nul(),
+        None => return Err(Errno::ENOENT)
+    };
+
+    let mut path = make_uninit_array!(sys::PATH_MAX as usize);
+    let mut target = make_uninit_array!(sys::PATH_MAX as usize);
+
+    if execfn.len() > path.len() {
+        return Err(Errno::ENAMETOOLONG)
+    }
+    path[..execfn.len()].copy_from_slice(execfn);
+    let mut len = execfn.len() - 1;
+
+    for _ in 0..MAX_SYMLINKS {
+        let path_cstr = unsafe { CStr::from_bytes_with_nul_unchecked(&path[..len+1]) };
+
+        let target_len = match sys::readlink(path_cstr, &mut target) {
+            Ok(l) => l,
+            // Not a symlink, so this must be the loader binary
+            Err(e) if e.into_raw() as u32 == sys::EINVAL => return resolve_path(sys::AT_FDCWD, &path[..len+1], buffer),
+            Err(e) => return Err(e)
+        };
+
+        // Relative targets replace the last component of the link's path
+        let target_index = match target[0] {
+            b'/' => 0,
+            _ => find_last_slash(&path[..len]).map_or(0, |i| i + 1)
+        };
+
+        if target_index + target_len >= path.len() {
+            return Err(Errno::ENAMETOOLONG)
+        }
+        path[target_index..target_index + target_len].copy_from_slice(&target[..target_len]);
+        len = target_index + target_len;
+        path[len] = b'\0';
+    }
+
+    Err(Errno::ELOOP)
+}
@@ -0,0 +1,111 @@
+/*
+   Resilience mode.
+   For production servers, a service running slower is often better than one failing to start.
+   With "on_error = baseline" in the configuration, errors which only concern finding the best
+   candidate make the loader execute the program's baseline build (e.g.
+   /usr/hwcaps/x86-64-v1/bin/foo) instead of aborting:
+   - PathResolutionIOError -> The alias' path couldn't be resolved
+   - TargetPathTooLarge    -> A candidate's path doesn't fit in PATH_MAX
+   - TargetExecutionError  -> A candidate exists, but couldn't be executed (e.g. EIO)
+   The error is still reported as usual, followed by a note about the baseline build being
+   executed. Everything else still aborts, since it could be a security problem (e.g. invalid
+   paths) or the baseline build wouldn't fare any better, and so does every error before the
+   configuration is read. If the baseline build can't be executed either, the loader exits with
+   the original error's code.
+   Candidates checked by interp_check, signatures or build_ids can't be recovered from, since the
+   baseline build would be executed without those checks.
+*/
+
+use core::ffi::{c_char, CStr};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::capabilities::{FEATURE_LEVELS, BASELINE_FEATURE_LEVEL};
+use crate::{HWCAPS_PATH, BIN_PATH, USR_PATH};
+
+// Everything needed to execute the baseline build from an abort. Only set up by the loader,
+// whose buffers outlive every abort, since main() never returns.
+static ARMED: AtomicBool = AtomicBool::new(false);
+static ARGV: AtomicPtr<*const c_char> = AtomicPtr::new(core::ptr::null_mut());
+static ENVP: AtomicPtr<*const c_char> = AtomicPtr::new(core::ptr::null_mut());
+// The program's path under /usr (e.g. "/bin/foo"), or just its name if it's an alias in /usr/bin
+static PROGRAM: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+static PROGRAM_LEN: AtomicUsize = AtomicUsize::new(0);
+static PROGRAM_IS_NAME: AtomicBool = AtomicBool::new(false);
+
+fn is_recoverable(code: ExitCode) -> bool {
+    !cfg!(any(feature = "interp_check", feature = "signatures", feature = "build_ids"))
+        && matches!(code, ExitCode::PathResolutionIOError | ExitCode::TargetPathTooLarge | ExitCode::TargetExecutionError)
+}
+
+// Lets later aborts execute the baseline build of the program argv0 (normalized) names.
+// Does nothing if the program can't be told from argv0 (e.g. "./foo"), or in secure-execution
+// mode, since the environment hasn't been filtered yet (secure_env).
+//
+// Safety: argv0 must stay valid until the process executes something or exits.
+pub unsafe fn arm(argv: *const *const c_char, envp: *const *const c_char, argv0: &[u8]) {
+    if sys::is_secure() {
+        return
+    }
+
+    let argv0 = argv0.strip_suffix(b"\0").unwrap_or(argv0);
+    // Paths which could leave the hwcaps directory are only trusted once they're resolved
+    if argv0.windows(3).any(|w| w == b"/..") {
+        return
+    }
+
+    match argv0.strip_prefix(USR_PATH) {
+        _ if !argv0.contains(&b'/') => set(argv, envp, argv0, true),
+        Some(program) if program.starts_with(b"/") => set(argv, envp, program, false),
+        _ => ()
+    }
+}
+
+// Like arm(), once the program's path under /usr (e.g. "/bin/foo") and the environment its
+// candidates get are known.
+//
+// Safety: bin_path and envp must stay valid until the process executes something or exits.
+pub unsafe fn rearm(argv: *const *const c_char, envp: *const *const c_char, bin_path: &[u8]) {
+    set(argv, envp, bin_path, false);
+}
+
+fn set(argv: *const *const c_char, envp: *const *const c_char, program: &[u8], is_name: bool) {
+    // Some callers pass paths along with their terminator
+    let program = program.strip_suffix(b"\0").unwrap_or(program);
+
+    ARGV.store(argv as *mut _, Ordering::Relaxed);
+    ENVP.store(envp as *mut _, Ordering::Relaxed);
+    PROGRAM.store(program.as_ptr() as *mut u8, Ordering::Relaxed);
+    PROGRAM_LEN.store(program.len(), Ordering::Relaxed);
+    PROGRAM_IS_NAME.store(is_name, Ordering::Relaxed);
+    ARMED.store(true, Ordering::Relaxed);
+}
+
+// Executes the baseline build instead of failing with code, if it's armed and the error can be
+// recovered from. Returns if it isn't, or the baseline build couldn't be executed.
+pub fn recover(code: ExitCode) {
+    // Failing to execute the baseline build mustn't lead back here
+    if !ARMED.swap(false, Ordering::Relaxed) || !is_recoverable(code) {
+        return
+    }
+
+    let program = unsafe { core::slice::from_raw_parts(PROGRAM.load(Ordering::Relaxed), PROGRAM_LEN.load(Ordering::Relaxed)) };
+    let bin_dir: &[u8] = match PROGRAM_IS_NAME.load(Ordering::Relaxed) {
+        true => &BIN_PATH[USR_PATH.len()..],
+        false => b""
+    };
+
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let directory = FEATURE_LEVELS[BASELINE_FEATURE_LEVEL as usize].directory;
+    let len = match path::join(&mut buffer, &[HWCAPS_PATH, directory, bin_dir, program]) {
+        Some(l) => l,
+        None => return
+    };
+    let baseline = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+
+    #[cfg(feature = "error_output")]
+    crate::output::hint("Executing the baseline build instead.", Some(baseline.to_bytes()));
+
+    let _ = sys::execve(baseline, ARGV.load(Ordering::Relaxed), ENVP.load(Ordering::Relaxed));
+}
@@ -0,0 +1,260 @@
+/*
+   Library resolution.
+   Applications which dlopen() optimized plugins can defer the choice of build to us, following
+   the same policy as programs:
+   /usr/hwcaps/<level>[+variant]/<libdir>/<name>
+   With generic_fallback, /usr/hwcaps/generic/<libdir>/<name> is tried after every level.
+   Available through resolve_library(), and as "hwcaps-loader --resolve-lib <name>".
+
+   Tools which need to know where the loader looks for a program's binaries can use candidates(),
+   which lists them in the same order the loader tries them, without probing or executing anything,
+   or resolve_program(), which finds the one the loader would execute.
+   Failures are reported through Error, instead of the loader's exit codes.
+*/
+
+use core::fmt;
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::path;
+use crate::capabilities::FEATURE_LEVELS;
+use crate::variants::Variants;
+use crate::{HWCAPS_PATH, USR_PATH, BIN_PATH};
+#[cfg(feature = "generic_fallback")]
+use crate::GENERIC_DIRECTORY;
+#[cfg(feature = "ignore_markers")]
+use crate::ignore;
+#[cfg(feature = "resolve_lib")]
+use crate::sys::ExitCode;
+#[cfg(feature = "resolve_lib")]
+use crate::output::{self, abort, Fatal};
+
+// Why a program or library couldn't be resolved. More cases may be added in the future.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Error {
+    // The program isn't in /usr, or the name is empty or contains invalid characters
+    InvalidPrefix,
+    // A path doesn't fit in PATH_MAX (or the buffer it was supposed to be written to)
+    TooLong,
+    // The program isn't an alias of hwcaps-loader
+    NotManaged,
+    // None of the candidates exist. searched is how many of them were looked for.
+    NoCandidates { searched: u32 },
+    // A syscall failed with errno
+    Io { errno: u32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPrefix => f.write_str("invalid path or name"),
+            Error::TooLong => f.write_str("path too large"),
+            Error::NotManaged => f.write_str("not an alias of hwcaps-loader"),
+            Error::NoCandidates { searched } => write!(f, "no supported builds available ({searched} candidates searched)"),
+            Error::Io { errno } => write!(f, "IO error (errno {errno})")
+        }
+    }
+}
+
+// What aliases point to
+const LOADER_NAME: &[u8] = b"hwcaps-loader";
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "riscv64", target_arch = "powerpc64", target_arch = "s390x", target_arch = "loongarch64", target_arch = "mips64"))]
+pub const LIBRARY_DIR: &'static [u8] = b"/lib64";
+#[cfg(any(target_arch = "x86", target_arch = "arm", target_arch = "mips"))]
+pub const LIBRARY_DIR: &'static [u8] = b"/lib";
+
+// Writes the null-terminated path of the best build (up to max_level) of the shared library called
+// name (e.g. "libfoo.so.1") into buffer. Returns its length without the terminator.
+pub fn resolve_library(name: &[u8], max_level: u32, buffer: &mut [u8]) -> Result<usize, Error> {
+    if name.is_empty() || name.contains(&b'/') || name.contains(&b'\0') {
+        return Err(Error::InvalidPrefix)
+    }
+
+    let max_level = core::cmp::min(max_level, FEATURE_LEVELS.len() as u32 - 1);
+    let variants = Variants::detect();
+
+    let mut searched = 0;
+    for (level, suffix) in variants.candidates(max_level) {
+        let directory = FEATURE_LEVELS[level as usize].directory;
+        let len = path::join(buffer, &[HWCAPS_PATH, directory, suffix, LIBRARY_DIR, b"/", name]).ok_or(Error::TooLong)?;
+
+        let path = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+        if sys::faccessat(sys::AT_FDCWD, path, sys::R_OK).is_ok() {
+            return Ok(len)
+        }
+        searched += 1;
+    }
+
+    #[cfg(feature = "generic_fallback")]
+    {
+        let len = path::join(buffer, &[HWCAPS_PATH, GENERIC_DIRECTORY, LIBRARY_DIR, b"/", name]).ok_or(Error::TooLong)?;
+
+        let path = unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) };
+        if sys::faccessat(sys::AT_FDCWD, path, sys::R_OK).is_ok() {
+            return Ok(len)
+        }
+        searched += 1;
+    }
+
+    Err(Error::NoCandidates { searched })
+}
+
+// Path of one of a program's candidates, as returned by candidates()
+pub struct CandidatePath {
+    buffer: [u8; sys::PATH_MAX as usize],
+    len: usize,
+    // Feature level and variant suffix the candidate was built for
+    pub level: u32,
+    pub suffix: &'static [u8],
+    // Whether it's in the generic directory (generic_fallback), which isn't a level. If it
+    // is, level is 0 and suffix is empty.
+    pub generic: bool,
+}
+
+impl CandidatePath {
+    // Without the terminator
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    pub fn as_c_str(&self) -> &CStr {
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.buffer[..self.len+1]) }
+    }
+}
+
+// Serialized as { path, level, suffix, generic }
+#[cfg(feature = "serde")]
+impl serde::Serialize for CandidatePath {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("CandidatePath", 4)?;
+        // Paths aren't guaranteed to be UTF-8
+        match core::str::from_utf8(self.as_bytes()) {
+            Ok(path) => state.serialize_field("path", path)?,
+            Err(_) => state.serialize_field("path", self.as_bytes())?
+        }
+        state.serialize_field("level", &self.level)?;
+        state.serialize_field("suffix", core::str::from_utf8(self.suffix).unwrap_or_default())?;
+        state.serialize_field("generic", &self.generic)?;
+        state.end()
+    }
+}
+
+pub struct Candidates {
+    variants: Variants,
+    // Part of the program's path after /usr (e.g. "/bin/foo")
+    program: [u8; sys::PATH_MAX as usize],
+    program_len: usize,
+    max_level: u32,
+    next: usize,
+    #[cfg(feature = "generic_fallback")]
+    generic_tried: bool,
+}
+
+impl Iterator for Candidates {
+    type Item = CandidatePath;
+
+    fn next(&mut self) -> Option<CandidatePath> {
+        // Always walking the same sequence as the loader is more important than the cost of nth()
+        let (directory, level, suffix, generic) = match self.variants.candidates(self.max_level).nth(self.next) {
+            Some((level, suffix)) => (FEATURE_LEVELS[level as usize].directory, level, suffix, false),
+            // Once every level was tried
+            #[cfg(feature = "generic_fallback")]
+            None if !self.generic_tried => {
+                self.generic_tried = true;
+                (GENERIC_DIRECTORY, 0, &b""[..], true)
+            },
+            None => return None
+        };
+        self.next += 1;
+
+        let mut candidate = CandidatePath { buffer: [0; sys::PATH_MAX as usize], len: 0, level, suffix, generic };
+        candidate.len = path::join(&mut candidate.buffer, &[HWCAPS_PATH, directory, suffix, &self.program[..self.program_len]])?;
+
+        Some(candidate)
+    }
+}
+
+// Returns the paths the loader would try (up to max_level) when executing program, in order.
+// program is the alias' path (e.g. "/usr/sbin/foo"), or just its name for aliases in /usr/bin.
+// The iterator stops early if a path is too large.
+pub fn candidates(program: &[u8], max_level: u32) -> Result<Candidates, Error> {
+    if program.is_empty() || program.contains(&b'\0') {
+        return Err(Error::InvalidPrefix)
+    }
+
+    let mut candidates = Candidates {
+        variants: Variants::detect(),
+        program: [0; sys::PATH_MAX as usize],
+        program_len: 0,
+        max_level: core::cmp::min(max_level, FEATURE_LEVELS.len() as u32 - 1),
+        next: 0,
+        #[cfg(feature = "generic_fallback")]
+        generic_tried: false
+    };
+
+    let parts: [&[u8]; 2] = match program.strip_prefix(USR_PATH) {
+        Some(rest) if rest.first() == Some(&b'/') => [rest, b""],
+        _ if program.contains(&b'/') => return Err(Error::InvalidPrefix),
+        _ => [&BIN_PATH[USR_PATH.len()..], program]
+    };
+    candidates.program_len = path::join(&mut candidates.program, &parts).ok_or(Error::TooLong)?;
+
+    Ok(candidates)
+}
+
+// Returns the candidate the loader would execute (up to max_level) for program, which is
+// interpreted like in candidates(). Unlike the loader, nothing is executed.
+pub fn resolve_program(program: &[u8], max_level: u32) -> Result<CandidatePath, Error> {
+    let candidates = candidates(program, max_level)?;
+
+    // Aliases are links to the loader, usually relative ones (foo -> hwcaps-loader)
+    let mut alias_buffer = [0u8; sys::PATH_MAX as usize];
+    let mut target = [0u8; sys::PATH_MAX as usize];
+    let alias_len = path::join(&mut alias_buffer, &[USR_PATH, &candidates.program[..candidates.program_len]]).ok_or(Error::TooLong)?;
+    let alias = unsafe { CStr::from_bytes_with_nul_unchecked(&alias_buffer[..alias_len+1]) };
+
+    match sys::readlinkat(sys::AT_FDCWD, alias, &mut target) {
+        Ok(len) if path::file_name(&target[..len]) == LOADER_NAME => (),
+        Ok(_) => return Err(Error::NotManaged),
+        Err(e) if matches!(e.into_raw() as u32, sys::ENOENT | sys::EINVAL) => return Err(Error::NotManaged),
+        Err(e) => return Err(Error::Io { errno: e.into_raw() as u32 })
+    }
+
+    let mut searched = 0;
+    for candidate in candidates {
+        // Skipped candidates are handled like missing ones
+        #[cfg(feature = "ignore_markers")]
+        if ignore::is_ignored(HWCAPS_PATH, &candidate.as_c_str().to_bytes()[HWCAPS_PATH.len()..]) {
+            searched += 1;
+            continue
+        }
+
+        match sys::faccessat(sys::AT_FDCWD, candidate.as_c_str(), sys::X_OK) {
+            Ok(_) => return Ok(candidate),
+            // The loader only moves on to the next candidate if this one doesn't exist
+            Err(e) if e.into_raw() as u32 == sys::ENOENT => searched += 1,
+            Err(e) => return Err(Error::Io { errno: e.into_raw() as u32 })
+        }
+    }
+
+    Err(Error::NoCandidates { searched })
+}
+
+// Prints the best build of name, for "hwcaps-loader --resolve-lib <name>"
+#[cfg(feature = "resolve_lib")]
+pub fn run(name: &[u8], max_level: u32) -> ! {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    match resolve_library(name, max_level, &mut buffer) {
+        Ok(len) => {
+            output::print_line(&buffer[..len]);
+            sys::exit(0)
+        },
+        Err(Error::TooLong) => abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, Some(name)),
+        Err(_) => abort(&Fatal(ExitCode::TargetNoViableBinaries, "Library has no supported builds available."), 0, Some(name))
+    }
+}
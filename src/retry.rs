@@ -0,0 +1,36 @@
+/*
+   Retrying transient execution failures.
+   Some execve() failures say nothing about the candidate, only about the moment it was tried:
+   - ETXTBSY -> The binary is open for writing, usually because a package upgrade is replacing it
+   - ENOMEM  -> The kernel was briefly short on memory
+   - EAGAIN  -> A resource limit was hit, which may clear up as other processes exit
+   Instead of aborting right away, these are retried a few times with short sleeps in between
+   (1ms, doubling each time), so package upgrades don't break programs started in the meantime.
+   Anything else, or a failure which outlasts the retries, is handled as usual.
+*/
+
+use crate::sys::{self, Errno};
+
+const MAX_RETRIES: u32 = 5;
+const FIRST_DELAY_NS: i64 = 1_000_000;
+
+fn is_transient(errno: Errno) -> bool {
+    matches!(errno.into_raw() as u32, sys::ETXTBSY | sys::ENOMEM | sys::EAGAIN)
+}
+
+// Calls exec until it fails with a permanent error, or it's been retried MAX_RETRIES times.
+// Returns the last error.
+pub fn with_retry(mut exec: impl FnMut() -> Errno) -> Errno {
+    let mut delay = sys::timespec { tv_sec: 0, tv_nsec: FIRST_DELAY_NS as _ };
+
+    for _ in 0..MAX_RETRIES {
+        let errno = exec();
+        if !is_transient(errno) {
+            return errno
+        }
+
+        let _ = sys::clock_nanosleep(sys::CLOCK_MONOTONIC, &delay);
+        delay.tv_nsec *= 2;
+    }
+    exec()
+}
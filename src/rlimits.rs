@@ -0,0 +1,139 @@
+/*
+   Resource limits.
+   Some builds need different limits than the rest (e.g. a larger stack for AVX-512 builds with
+   big spill frames, or no core dumps from optimized builds), which used to take wrapper scripts.
+   Configuration can set the soft limit of a resource for every candidate, or only for those of
+   one feature level, with "rlimit = [<level>:]<resource>:<limit>":
+   - <level> is a feature level's name (e.g. x86-64-v4), whose variants are included
+   - <resource> is one of core, cpu, data, fsize, memlock, nofile, nproc, stack or as
+   - <limit> is a number, optionally with a k, m or g suffix (powers of 1024), or "unlimited"
+   Rules for a level win over the ones for every level, and later rules override earlier ones.
+   Combined with [program:<name>] sections, limits can be set per program and level.
+   Hard limits are left alone, so soft limits are capped to them. Since limits are inherited by
+   whatever we execute, the original ones are restored before a candidate they don't apply to is
+   tried. Failing to set a limit never prevents the target from being executed.
+*/
+
+use core::ffi::c_uint;
+
+use crate::sys;
+use crate::path;
+use crate::capabilities;
+
+const MAX_RULES: usize = 16;
+
+const RESOURCES: &[(&[u8], c_uint)] = &[
+    (b"core", sys::RLIMIT_CORE),
+    (b"cpu", sys::RLIMIT_CPU),
+    (b"data", sys::RLIMIT_DATA),
+    (b"fsize", sys::RLIMIT_FSIZE),
+    (b"memlock", sys::RLIMIT_MEMLOCK),
+    (b"nofile", sys::RLIMIT_NOFILE),
+    (b"nproc", sys::RLIMIT_NPROC),
+    (b"stack", sys::RLIMIT_STACK),
+    (b"as", sys::RLIMIT_AS),
+];
+
+#[derive(Clone, Copy)]
+struct Rule {
+    // None for every level
+    level: Option<u32>,
+    resource: c_uint,
+    limit: u64,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rules {
+    rules: [Option<Rule>; MAX_RULES],
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules { rules: [None; MAX_RULES] }
+    }
+}
+
+fn parse_limit(value: &[u8]) -> Option<u64> {
+    if value == b"unlimited" {
+        return Some(sys::RLIM_INFINITY)
+    }
+
+    let (digits, shift) = match value.last()? {
+        b'k' | b'K' => (&value[..value.len()-1], 10),
+        b'm' | b'M' => (&value[..value.len()-1], 20),
+        b'g' | b'G' => (&value[..value.len()-1], 30),
+        _ => (value, 0)
+    };
+    Some((path::atoi(digits)? as u64) << shift)
+}
+
+impl Rules {
+    // Parses "[<level>:]<resource>:<limit>" (e.g. "x86-64-v4:stack:64m"). Returns None if it's
+    // invalid, or there are too many rules.
+    pub fn push(&mut self, value: &[u8]) -> Option<()> {
+        let mut fields = value.rsplitn(3, |c| *c == b':');
+        let (limit, resource) = (fields.next()?, fields.next()?);
+        let level = match fields.next() {
+            Some(name) => Some(capabilities::find_feature_level(name)?),
+            None => None
+        };
+
+        let (_, resource) = RESOURCES.iter().find(|(name, _)| *name == resource)?;
+        let rule = Rule { level, resource: *resource, limit: parse_limit(limit)? };
+
+        // A later rule for the same level and resource replaces the earlier one
+        let slot = match self.rules.iter().position(|r| r.is_some_and(|r| r.level == level && r.resource == rule.resource)) {
+            Some(i) => &mut self.rules[i],
+            None => self.rules.iter_mut().find(|r| r.is_none())?
+        };
+        *slot = Some(rule);
+        Some(())
+    }
+
+    // The limit for resource at level (None for candidates which aren't built for a level)
+    fn find(&self, resource: c_uint, level: Option<u32>) -> Option<u64> {
+        let rules = || self.rules.iter().flatten().filter(|r| r.resource == resource);
+        match rules().find(|r| r.level.is_some() && r.level == level) {
+            Some(rule) => Some(rule.limit),
+            None => rules().find(|r| r.level.is_none()).map(|r| r.limit)
+        }
+    }
+}
+
+// The limits we were executed with, for every resource rules mention
+pub struct Limits<'a> {
+    rules: &'a Rules,
+    original: [Option<sys::rlimit64>; RESOURCES.len()],
+}
+
+impl<'a> Limits<'a> {
+    pub fn new(rules: &'a Rules) -> Self {
+        let mut original = [None; RESOURCES.len()];
+        for (i, (_, resource)) in RESOURCES.iter().enumerate() {
+            if rules.rules.iter().flatten().any(|r| r.resource == *resource) {
+                original[i] = sys::prlimit(*resource, None).ok();
+            }
+        }
+
+        Limits { rules, original }
+    }
+
+    // Sets the limits for a candidate in directory (e.g. "x86-64-v4+bigmem", or "generic")
+    pub fn apply(&self, directory: &[u8]) {
+        let level = capabilities::find_directory_level(directory);
+
+        for (i, (_, resource)) in RESOURCES.iter().enumerate() {
+            let original = match self.original[i] {
+                Some(o) => o,
+                None => continue
+            };
+
+            let soft = match self.rules.find(*resource, level) {
+                Some(limit) if original.rlim_max != sys::RLIM_INFINITY => core::cmp::min(limit, original.rlim_max),
+                Some(limit) => limit,
+                None => original.rlim_cur
+            };
+            let _ = sys::prlimit(*resource, Some(&sys::rlimit64 { rlim_cur: soft, rlim_max: original.rlim_max }));
+        }
+    }
+}
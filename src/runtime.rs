@@ -0,0 +1,78 @@
+/*
+   The loader binary's runtime.
+   The library is a regular no_std crate, so the binary provides everything else.
+*/
+
+#[allow(unused_imports)]
+use hwcaps_loader::sys::{exit, write, ExitCode, STDOUT};
+
+/*
+   LINKING
+   To have a functional program, we must provide the following members to
+   the compiler and the linker:
+   - entry point (_start) or external libc
+   - memory routines (memcpy, memcmp...) or external libc
+   - panic_handler
+   - rust_eh_personality
+*/
+
+/* For targets with no OS/ABI, link a minimal entry point (_start) function.*/
+#[cfg(target_os="none")]
+#[cfg_attr(target_arch = "x86", path = "entry_point/arch_x86.rs")]
+#[cfg_attr(target_arch = "x86_64", path = "entry_point/arch_x86.rs")]
+#[cfg_attr(target_arch = "aarch64", path = "entry_point/arch_aarch64.rs")]
+#[cfg_attr(target_arch = "riscv64", path = "entry_point/arch_riscv64.rs")]
+mod entry_point;
+
+/* Without libc, provide small memory routines instead of the compiler's large generic ones.*/
+#[cfg(target_os="none")]
+#[cfg_attr(target_arch = "x86", path = "mem/arch_x86.rs")]
+#[cfg_attr(target_arch = "x86_64", path = "mem/arch_x86.rs")]
+#[cfg_attr(target_arch = "aarch64", path = "mem/arch_aarch64.rs")]
+#[cfg_attr(target_arch = "riscv64", path = "mem/arch_riscv64.rs")]
+mod mem;
+
+/* For targets with an OS/ABI, link libc */
+#[cfg(not(target_os="none"))]
+#[link(name = "c")]
+extern "C" {}
+
+//TODO: use when https://doc.rust-lang.org/unstable-book/language-features/lang-items.html stabilizes
+//#[lang = "eh_personality"]
+//extern "C" fn eh_personality() {}
+
+//Workarounds for https://github.com/rust-lang/rust/issues/106864
+#[no_mangle]
+extern "C" fn rust_eh_personality() {}
+
+// Debug panic handler. Test builds (e.g. clippy's) get std's instead.
+#[cfg(all(debug_assertions, not(test)))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    use core::fmt;
+    use fmt::Write;
+
+    use hwcaps_loader::output::debug::PrintBuff;
+
+    let message = _info.message();
+    let location = _info.location().unwrap();
+
+    let mut buffer = [0; 1024];
+    let mut writer = PrintBuff::new(&mut buffer);
+
+    let _ = write!(&mut writer, "Error: {message}\nAt: {location}\n");
+
+    _ = write(STDOUT, &buffer);
+    exit(ExitCode::RustPanic as u8)
+}
+
+
+// Production panic handler
+/* We can't do panic on production...
+   core::fmt increases binary size by an obscene amount
+   Just exist with a special error code if that happens */
+#[cfg(all(not(debug_assertions), not(test)))]
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    exit(ExitCode::RustPanic as u8)
+}
@@ -0,0 +1,95 @@
+/*
+   Search path report.
+   When executed as "hwcaps-loader --print-search-paths", print where this build looks for
+   things, one "<key> <value>" line each, so packagers can check a loader matches their
+   distribution's filesystem policy before shipping it:
+   - arch       -> Architecture the loader was built for (e.g. x86_64)
+   - loader     -> Where the running loader is
+   - prefix     -> Aliases must be under this directory
+   - aliases    -> Where aliases given by name are looked up
+   - hwcaps     -> Root of the level directories
+   - layout     -> How candidates' paths are made
+   - level      -> Name and directory of every feature level, lowest first
+   - variant    -> Every suffix a level directory may have, besides none
+   - max_level  -> Highest level this machine would use, after configured limits
+   - config     -> Configuration files and drop-in directories, in decreasing priority (config)
+   - index      -> Index of level directories (level_index)
+   - daemon     -> Resolution daemon's socket (resolve_daemon)
+   - last_error -> Where errors which couldn't be printed are written (error_file)
+   - generic    -> Directory tried after every level (generic_fallback)
+   - fallbacks  -> Per-program counters of dispatches below max_level (fallback_counters)
+*/
+
+use crate::sys;
+use crate::path;
+use crate::output;
+use crate::capabilities::{self, FEATURE_LEVELS};
+use crate::variants;
+use crate::{HWCAPS_PATH, USR_PATH, BIN_PATH};
+#[cfg(feature = "generic_fallback")]
+use crate::GENERIC_DIRECTORY;
+#[cfg(feature = "config")]
+use crate::config;
+#[cfg(feature = "level_index")]
+use crate::level_index;
+#[cfg(feature = "resolve_daemon")]
+use crate::daemon;
+#[cfg(feature = "error_file")]
+use crate::last_error;
+#[cfg(feature = "fallback_counters")]
+use crate::fallbacks;
+
+// Directories without their trailing slash
+fn trim(directory: &[u8]) -> &[u8] {
+    directory.strip_suffix(b"/").unwrap_or(directory)
+}
+
+fn print(key: &[u8], value: &[&[u8]]) {
+    let mut line = [0u8; sys::PATH_MAX as usize];
+    let mut parts: [&[u8]; 6] = [b""; 6];
+    parts[0] = key;
+    parts[1] = b" ";
+    for (part, value) in parts[2..].iter_mut().zip(value) {
+        *part = value;
+    }
+
+    if let Some(len) = path::join(&mut line, &parts) {
+        output::print_line(&line[..len]);
+    }
+}
+
+// Prints the report, for "hwcaps-loader --print-search-paths"
+pub fn run(loader_path: &[u8], max_level: u32) -> ! {
+    print(b"arch", &[capabilities::ARCH_NAME]);
+    print(b"loader", &[loader_path]);
+    print(b"prefix", &[USR_PATH]);
+    print(b"aliases", &[trim(BIN_PATH)]);
+    print(b"hwcaps", &[trim(HWCAPS_PATH)]);
+    print(b"layout", &[HWCAPS_PATH, b"<level>[<variant>]/<path under ", USR_PATH, b">"]);
+
+    for level in FEATURE_LEVELS {
+        print(b"level", &[level.name, b" ", HWCAPS_PATH, level.directory]);
+    }
+    for suffix in variants::SUFFIXES {
+        print(b"variant", &[suffix]);
+    }
+    print(b"max_level", &[FEATURE_LEVELS[max_level as usize].name]);
+
+    #[cfg(feature = "config")]
+    for dir in config::CONFIG_DIRS {
+        print(b"config", &[dir, config::CONFIG_NAME]);
+        print(b"config", &[dir, config::DROPIN_DIR, b"/"]);
+    }
+    #[cfg(feature = "level_index")]
+    print(b"index", &[level_index::INDEX_PATH.to_bytes()]);
+    #[cfg(feature = "resolve_daemon")]
+    print(b"daemon", &[daemon::SOCKET_PATH.to_bytes()]);
+    #[cfg(feature = "error_file")]
+    print(b"last_error", &[last_error::LAST_ERROR_PATH.to_bytes()]);
+    #[cfg(feature = "generic_fallback")]
+    print(b"generic", &[HWCAPS_PATH, GENERIC_DIRECTORY]);
+    #[cfg(feature = "fallback_counters")]
+    print(b"fallbacks", &[fallbacks::FALLBACKS_PATH.to_bytes(), b"/"]);
+
+    sys::exit(0)
+}
@@ -0,0 +1,64 @@
+/*
+   Secure-execution environment filtering.
+   In secure-execution mode (AT_SECURE: setuid/setgid programs or file capabilities), the
+   dynamic loader ignores variables which would let unprivileged users alter how a privileged
+   program runs. The target inherits our exec directly though, so we remove them ourselves:
+   LD_PRELOAD, LD_AUDIT, LD_LIBRARY_PATH and every HWCAPS_* variable.
+   The original envp is left untouched, since the auxiliary vector is found right after it.
+*/
+
+use core::ffi::{c_char, CStr};
+use core::mem::MaybeUninit;
+use core::ptr;
+
+use crate::sys::{self, ExitCode};
+use crate::output::{abort, Fatal};
+
+const MAX_VARIABLES: usize = 4096;
+
+const REMOVED_VARIABLES: &[&[u8]] = &[b"LD_PRELOAD=", b"LD_AUDIT=", b"LD_LIBRARY_PATH="];
+const REMOVED_PREFIX: &[u8] = b"HWCAPS_";
+
+// Room for the filtered envp, including its terminator
+pub type Buffer = [MaybeUninit<*const c_char>; MAX_VARIABLES + 1];
+
+pub fn new_buffer() -> Buffer {
+    [const { MaybeUninit::uninit() }; MAX_VARIABLES + 1]
+}
+
+fn is_removed(variable: &[u8]) -> bool {
+    variable.starts_with(REMOVED_PREFIX) || REMOVED_VARIABLES.iter().any(|prefix| variable.starts_with(prefix))
+}
+
+// Returns the envp to execute the target with: envp itself, or a filtered copy of it
+// (stored in buffer) in secure-execution mode.
+//
+// Safety: envp must be a list of C strings ending with a null pointer, like main()'s.
+pub unsafe fn filter(envp: *const *const c_char, buffer: &mut Buffer) -> *const *const c_char {
+    if !sys::is_secure() {
+        return envp
+    }
+
+    let mut count = 0;
+    let mut i = 0;
+    loop {
+        let variable = unsafe { *envp.add(i) };
+        if variable.is_null() {
+            break
+        }
+        i += 1;
+
+        if is_removed(unsafe { CStr::from_ptr(variable) }.to_bytes()) {
+            continue
+        }
+
+        if count == MAX_VARIABLES {
+            abort(&Fatal(ExitCode::TargetExecutionError, "Too many environment variables!"), sys::E2BIG, None)
+        }
+        buffer[count].write(variable);
+        count += 1;
+    }
+    buffer[count].write(ptr::null());
+
+    buffer.as_ptr() as *const *const c_char
+}
@@ -0,0 +1,137 @@
+/*
+   Self-test.
+   "hwcaps-loader --selftest" probes the syscalls and kernel features which optional code paths
+   rely on, and reports which features enabled in this build will fall back on this kernel.
+   Every probe is free of side effects. Useful for distribution QA on old kernels.
+   With kernel_cmdline, a kernel command line too long to be fully honored is reported on stderr.
+*/
+
+use crate::sys::{Errno, Newer};
+
+use crate::sys;
+use crate::path;
+use crate::output;
+
+struct Probe {
+    name: &'static [u8],
+    run: fn() -> Result<(), Errno>,
+    // Features enabled in this build which rely on the probe, and what happens without it
+    fallbacks: &'static [(bool, &'static [u8])],
+}
+
+const PROBES: &[Probe] = &[
+    Probe {
+        name: b"/proc/self/exe",
+        run: probe_proc_exe,
+        fallbacks: &[
+            (cfg!(feature = "procfs_fallback"), b"procfs_fallback: the loader's path is found through AT_EXECFN"),
+            (!cfg!(feature = "procfs_fallback"), b"hwcaps-loader can't find its own path (see procfs_fallback)"),
+        ]
+    },
+    Probe {
+        name: b"/proc/self/auxv",
+        run: probe_proc_auxv,
+        fallbacks: &[(!cfg!(target_os = "none"), b"auxiliary vector entries are read from the initial stack")]
+    },
+    Probe { name: b"statx", run: probe_statx, fallbacks: &[(true, b"fstatat() is used instead")] },
+    Probe {
+        name: b"io_uring",
+        run: probe_io_uring,
+        fallbacks: &[(cfg!(feature = "io_uring"), b"io_uring: candidates are tried one by one")]
+    },
+    Probe { name: b"openat2", run: probe_openat2, fallbacks: &[(true, b"openat() is used instead, without resolve restrictions")] },
+    Probe { name: b"execveat", run: probe_execveat, fallbacks: &[(true, b"execve() through /proc/self/fd is used instead")] },
+    Probe { name: b"faccessat2", run: probe_faccessat2, fallbacks: &[(true, b"faccessat() is used instead, outside of setuid programs")] },
+    Probe { name: b"landlock", run: probe_landlock, fallbacks: &[] },
+];
+
+fn probe_proc_exe() -> Result<(), Errno> {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    sys::readlink(c"/proc/self/exe", &mut buffer).map(|_| ())
+}
+
+fn probe_proc_auxv() -> Result<(), Errno> {
+    let mut buffer = [0u8; 16];
+    sys::read_file(c"/proc/self/auxv", &mut buffer).map(|_| ())
+}
+
+// Wrappers of newer syscalls fall back on their own, so they're checked for having done so
+fn newer(syscall: Newer) -> Result<(), Errno> {
+    match sys::is_missing(syscall) {
+        true => Err(Errno::ENOSYS),
+        false => Ok(())
+    }
+}
+
+fn probe_statx() -> Result<(), Errno> {
+    let mut buffer = unsafe { core::mem::zeroed::<sys::statx>() };
+    sys::statx(sys::AT_FDCWD, c"/", 0, sys::STATX_BASIC_STATS, &mut buffer)?;
+    newer(Newer::Statx)
+}
+
+fn probe_io_uring() -> Result<(), Errno> {
+    let mut params = unsafe { core::mem::zeroed::<sys::io_uring_params>() };
+    let fd = sys::io_uring_setup(1, &mut params)?;
+    let _ = sys::close(fd);
+    Ok(())
+}
+
+fn probe_openat2() -> Result<(), Errno> {
+    let how = sys::open_how { flags: (sys::O_PATH | sys::O_CLOEXEC) as u64, mode: 0, resolve: sys::RESOLVE_NO_SYMLINKS as u64 };
+    let fd = sys::openat2(sys::AT_FDCWD, c"/", &how);
+    if let Ok(fd) = fd {
+        let _ = sys::close(fd);
+    }
+    newer(Newer::Openat2).and(fd.map(|_| ()))
+}
+
+fn probe_execveat() -> Result<(), Errno> {
+    // An invalid descriptor is rejected with EBADF by kernels which support execveat()
+    match sys::execveat(-1, c"", core::ptr::null(), core::ptr::null(), sys::AT_EMPTY_PATH) {
+        Errno::EBADF => newer(Newer::Execveat),
+        e => Err(e)
+    }
+}
+
+fn probe_faccessat2() -> Result<(), Errno> {
+    sys::faccessat2(sys::AT_FDCWD, c"/", sys::F_OK, 0)?;
+    newer(Newer::Faccessat2)
+}
+
+fn probe_landlock() -> Result<(), Errno> {
+    sys::landlock_create_ruleset(core::ptr::null(), 0, sys::LANDLOCK_CREATE_RULESET_VERSION).map(|_| ())
+}
+
+// Prints what this system supports, for "hwcaps-loader --selftest"
+pub fn run() -> ! {
+    for probe in PROBES {
+        let mut buffer = [0u8; 256];
+        let mut errno_buffer = [0u8; 16];
+
+        let result = (probe.run)();
+        let len = match result {
+            Ok(()) => path::join(&mut buffer, &[probe.name, b": available"]),
+            Err(e) => {
+                let errno_len = path::itoa(e.into_raw() as u32, &mut errno_buffer);
+                path::join(&mut buffer, &[probe.name, b": unavailable (errno ", &errno_buffer[..errno_len], b")"])
+            }
+        };
+        output::print_line(&buffer[..len.unwrap_or(0)]);
+
+        if result.is_ok() {
+            continue
+        }
+
+        for (_, fallback) in probe.fallbacks.iter().filter(|(enabled, _)| *enabled) {
+            let len = path::join(&mut buffer, &[b"  -> ", fallback]).unwrap_or(0);
+            output::print_line(&buffer[..len]);
+        }
+    }
+
+    #[cfg(feature = "kernel_cmdline")]
+    if crate::cmdline::is_truncated() {
+        let _ = output::print_to(sys::STDERR, None, "Warning: kernel command line is too long, parameters at its end are ignored.", 0, None);
+    }
+
+    sys::exit(0)
+}
@@ -0,0 +1,148 @@
+/*
+   Interpreter rewriting for scripts.
+   A script whose interpreter is itself an alias of hwcaps-loader (e.g. "#!/usr/bin/python3")
+   would go through the loader twice: once for the script, and once more when the kernel executes
+   its interpreter. Instead, the interpreter's best candidate is found right away and executed
+   directly, with the arguments the kernel would have given it:
+   <interpreter> [argument] <script> [args...]
+   Its argv[0] is the interpreter's path as written in the script, as the kernel would do.
+   When none of the interpreter's candidates can be executed, the error names the interpreter
+   rather than the script.
+   The interpreter's candidate is chosen with the script's level limits, skipping those marked
+   with .hwcaps-ignore. Scripts are checked (interp_check, signatures) before being read, and so
+   are interpreters before being executed. Interpreters found through the PATH (such as
+   "#!/usr/bin/env python3") or outside /usr are executed by the kernel, as usual.
+*/
+
+use core::ffi::{c_char, CStr};
+use core::ptr;
+
+use crate::sys::{self, Errno, ExitCode};
+use crate::resolve::{self, CandidatePath};
+use crate::arg_size;
+use crate::output::{abort, Fatal};
+use crate::USR_PATH;
+
+// How much of a script the kernel reads to find its interpreter (BINPRM_BUF_SIZE)
+const HEADER_MAX: usize = 256;
+// Arguments we add before the original ones (except argv0): interpreter, argument, script
+const EXTRA_ARGS: usize = 3;
+
+// A script whose interpreter is an alias
+pub struct Script {
+    // The script's first line, with terminators after the interpreter and its argument
+    header: [u8; HEADER_MAX],
+    interpreter_len: usize,
+    // Where the argument starts, if there's one
+    argument: Option<usize>,
+    candidate: CandidatePath,
+}
+
+fn is_blank(c: u8) -> bool {
+    c == b' ' || c == b'\t'
+}
+
+// Returns the script at path, if it's one whose interpreter is an alias of hwcaps-loader.
+// Aborts if none of the interpreter's candidates (up to max_level) can be executed.
+pub fn find(path: &CStr, max_level: u32) -> Option<Script> {
+    let mut header = [0u8; HEADER_MAX];
+    let len = match sys::openat(sys::AT_FDCWD, path, sys::O_RDONLY) {
+        Ok(fd) => {
+            let result = sys::read(fd, &mut header);
+            let _ = sys::close(fd);
+            result.ok()?
+        },
+        Err(_) => return None
+    };
+
+    // Lines which don't fit are left to the kernel, which rejects them
+    let header_slice = header[..len].strip_prefix(b"#!")?;
+    let end = 2 + header_slice.iter().position(|c| *c == b'\n')?;
+
+    // "#! /usr/bin/foo  -x  " has "/usr/bin/foo" as its interpreter, and "-x" as its argument
+    let start = 2 + header[2..end].iter().position(|c| !is_blank(*c))?;
+    let interpreter_end = start + header[start..end].iter().position(|c| is_blank(*c)).unwrap_or(end - start);
+    let argument_start = interpreter_end + header[interpreter_end..end].iter().position(|c| !is_blank(*c)).unwrap_or(end - interpreter_end);
+    let argument_end = argument_start + header[argument_start..end].iter().rposition(|c| !is_blank(*c)).map_or(0, |i| i + 1);
+
+    let interpreter = &header[start..interpreter_end];
+    if interpreter.first() != Some(&b'/') || interpreter.contains(&b'\0') || header[argument_start..argument_end].contains(&b'\0') {
+        return None
+    }
+
+    let candidate = match resolve::resolve_program(interpreter, max_level) {
+        Ok(c) => c,
+        Err(resolve::Error::NoCandidates { .. }) => {
+            abort(&Fatal(ExitCode::TargetNoViableBinaries, "Script interpreter has no supported binaries available."), 0, Some(interpreter))
+        },
+        // Not ours, or something the kernel will report better
+        Err(_) => return None
+    };
+
+    // The interpreter moves to the start, so both can be terminated in place
+    header.copy_within(start..end, 0);
+    let interpreter_len = interpreter_end - start;
+    header[interpreter_len] = b'\0';
+    let argument = match argument_end > argument_start {
+        true => {
+            header[argument_end - start] = b'\0';
+            Some(argument_start - start)
+        },
+        false => None
+    };
+
+    Some(Script { header, interpreter_len, argument, candidate })
+}
+
+impl Script {
+    // The interpreter's candidate
+    pub fn candidate(&self) -> &CStr {
+        self.candidate.as_c_str()
+    }
+
+    // Path of the candidate up to the interpreter's (e.g. "/usr/hwcaps/x86-64-v3")
+    pub fn directory(&self) -> &[u8] {
+        let program_len = self.interpreter_len - USR_PATH.len();
+        &self.candidate.as_bytes()[..self.candidate.as_bytes().len() - program_len]
+    }
+
+    // Returns the interpreter's argc and argv for executing the script at path with argv's
+    // arguments. The vector is never freed, since it's only needed until exec.
+    //
+    // Safety: argv must hold argc arguments followed by a terminator, like main()'s.
+    pub unsafe fn argv(&self, path: &CStr, argc: i32, argv: *const *const c_char) -> Result<(i32, *const *const c_char), Errno> {
+        let extra_args = EXTRA_ARGS - self.argument.is_none() as usize;
+        let new_argc = argc as usize - 1 + extra_args;
+        let size = (new_argc + 1) * size_of::<*const c_char>();
+        let prot = sys::PROT_READ | sys::PROT_WRITE;
+        let flags = sys::MAP_PRIVATE | sys::MAP_ANONYMOUS;
+
+        let new_argv = sys::mmap(ptr::null_mut(), size, prot, flags, -1, 0)? as *mut *const c_char;
+
+        unsafe {
+            let mut i = 0;
+            new_argv.add(i).write(self.header.as_ptr() as *const c_char);
+            i += 1;
+            if let Some(argument) = self.argument {
+                new_argv.add(i).write(self.header[argument..].as_ptr() as *const c_char);
+                i += 1;
+            }
+            new_argv.add(i).write(path.as_ptr());
+
+            // Copy the original arguments (except argv0), along with the terminator
+            ptr::copy_nonoverlapping(argv.add(1), new_argv.add(extra_args), argc as usize);
+        }
+
+        Ok((new_argc as i32, new_argv))
+    }
+
+    // Reports why executing the interpreter failed, then aborts
+    #[cold]
+    pub fn failed(&self, errno: Errno, argv: *const *const c_char, envp: *const *const c_char) -> ! {
+        let candidate = self.candidate.as_bytes();
+        match errno.into_raw() as u32 {
+            sys::E2BIG => arg_size::abort_too_large(argv, envp, candidate),
+            other => abort(&Fatal(ExitCode::TargetExecutionError, "Failed to execute script interpreter!"), other, Some(candidate))
+        }
+    }
+}
@@ -0,0 +1,99 @@
+/*
+   Candidate signatures.
+   Every candidate must come with a detached Ed25519 signature of its contents, in a file next
+   to it with a ".sig" suffix (e.g. /usr/hwcaps/x86-64-v3/bin/foo.sig). Signatures are checked
+   against a public key compiled into hwcaps-loader (through the HWCAPS_SIGNING_KEY environment
+   variable at build time) or, if there's none, the one set in the configuration. This way,
+   appliances don't have to trust the integrity of the filesystem alone.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys::{self, ExitCode};
+use crate::path;
+use crate::output::{abort, Fatal};
+use crate::ed25519::Verifier;
+
+const SIGNATURE_SUFFIX: &[u8] = b".sig";
+const SIGNATURE_LEN: usize = 64;
+const KEY_LEN: usize = 32;
+
+// Hex encoded
+const BUILTIN_KEY: Option<&str> = option_env!("HWCAPS_SIGNING_KEY");
+
+const CHUNK_SIZE: usize = 16384;
+
+#[derive(Clone, Copy)]
+pub struct PublicKey([u8; KEY_LEN]);
+
+impl PublicKey {
+    pub fn from_hex(hex: &[u8]) -> Option<Self> {
+        if hex.len() != KEY_LEN * 2 {
+            return None
+        }
+
+        let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+
+        let mut key = [0u8; KEY_LEN];
+        for (byte, pair) in key.iter_mut().zip(hex.chunks_exact(2)) {
+            *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+        }
+        Some(PublicKey(key))
+    }
+}
+
+// Returns the key compiled into hwcaps-loader, or the configured one if there's none
+pub fn get_key(configured: Option<&PublicKey>) -> PublicKey {
+    let builtin = match BUILTIN_KEY {
+        Some(hex) => match PublicKey::from_hex(hex.as_bytes()) {
+            Some(key) => Some(key),
+            None => abort(&Fatal(ExitCode::TargetSignatureInvalid, "Built-in signing key is malformed!"), 0, None)
+        },
+        None => None
+    };
+
+    match builtin.or(configured.copied()) {
+        Some(key) => key,
+        None => abort(&Fatal(ExitCode::TargetSignatureInvalid, "No signing key is set!"), 0, None)
+    }
+}
+
+// Aborts if the candidate (open as fd) doesn't have a valid signature
+pub fn check(fd: i32, path: &CStr, key: &PublicKey) {
+    let mut buffer = [0u8; sys::PATH_MAX as usize];
+    let signature_path = match path::join(&mut buffer, &[path.to_bytes(), SIGNATURE_SUFFIX]) {
+        Some(len) => unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..len+1]) },
+        None => abort(&Fatal(ExitCode::TargetPathTooLarge, "Target path too large!"), 0, None)
+    };
+
+    // One more byte than needed, to catch files which are too long
+    let mut signature = [0u8; SIGNATURE_LEN + 1];
+    match sys::read_file(signature_path, &mut signature) {
+        Ok(SIGNATURE_LEN) => (),
+        Ok(_) => abort(&Fatal(ExitCode::TargetSignatureInvalid, "Target binary's signature is malformed!"), 0, Some(signature_path.to_bytes())),
+        Err(e) => abort(&Fatal(ExitCode::TargetSignatureInvalid, "Failed to read target binary's signature!"), e.into_raw() as u32, Some(signature_path.to_bytes()))
+    }
+    let signature: &[u8; SIGNATURE_LEN] = signature[..SIGNATURE_LEN].try_into().unwrap();
+
+    let mut verifier = match Verifier::new(&key.0, signature) {
+        Some(v) => v,
+        None => abort(&Fatal(ExitCode::TargetSignatureInvalid, "Target binary's signature is malformed!"), 0, Some(signature_path.to_bytes()))
+    };
+
+    let mut chunk = [0u8; CHUNK_SIZE];
+    let mut offset = 0;
+    loop {
+        match sys::pread64(fd, &mut chunk, offset) {
+            Ok(0) => break,
+            Ok(n) => {
+                verifier.update(&chunk[..n]);
+                offset += n as u64;
+            },
+            Err(e) => abort(&Fatal(ExitCode::TargetSignatureInvalid, "Failed to read target binary!"), e.into_raw() as u32, Some(path.to_bytes()))
+        }
+    }
+
+    if !verifier.verify() {
+        abort(&Fatal(ExitCode::TargetSignatureInvalid, "Target binary's signature is invalid!"), 0, Some(path.to_bytes()))
+    }
+}
@@ -0,0 +1,87 @@
+/*
+   Per-program speculation controls.
+   Configuration can ask for speculative execution misfeatures to be disabled (or enabled) for
+   specific programs, through prctl(PR_SET_SPECULATION_CTRL). Settings are inherited across
+   execve(), so they're applied to the loader itself right before the target is executed.
+   The misfeatures and their modes mirror the kernel's ones:
+   - store_bypass    -> Speculative Store Bypass (SSBD)
+   - indirect_branch -> Indirect branch speculation (STIBP/IBPB)
+   - l1d_flush       -> Flushing the L1D cache on context switches (enable only)
+   Modes are "enable", "disable" and "force-disable" (which can't be undone by the target).
+*/
+
+use crate::sys::{self, ExitCode};
+use crate::output::{abort, Fatal};
+
+const MISFEATURES: [(&[u8], u32); 3] = [
+    (b"store_bypass", sys::PR_SPEC_STORE_BYPASS),
+    (b"indirect_branch", sys::PR_SPEC_INDIRECT_BRANCH),
+    (b"l1d_flush", sys::PR_SPEC_L1D_FLUSH),
+];
+
+const MODES: [(&[u8], u32); 3] = [
+    (b"enable", sys::PR_SPEC_ENABLE),
+    (b"disable", sys::PR_SPEC_DISABLE),
+    (b"force-disable", sys::PR_SPEC_FORCE_DISABLE),
+];
+
+// Requested mode of each misfeature, 0 if unchanged
+#[derive(Clone, Copy, Default)]
+pub struct Settings([u32; MISFEATURES.len()]);
+
+impl Settings {
+    // Parses "<misfeature>:<mode>" (e.g. "store_bypass:force-disable"), overriding earlier settings
+    // for the same misfeature. Returns None if it's invalid.
+    pub fn set(&mut self, value: &[u8]) -> Option<()> {
+        let separator = value.iter().position(|c| *c == b':')?;
+        let (name, mode) = (&value[..separator], &value[separator+1..]);
+
+        let index = MISFEATURES.iter().position(|(n, _)| *n == name)?;
+        let (_, control) = MODES.iter().find(|(m, _)| *m == mode)?;
+
+        // L1D flushing can only be turned on
+        if MISFEATURES[index].1 == sys::PR_SPEC_L1D_FLUSH && *control != sys::PR_SPEC_ENABLE {
+            return None
+        }
+
+        self.0[index] = *control;
+        Some(())
+    }
+}
+
+// Whether the misfeature is already in the requested state, even if we can't control it
+fn satisfied(which: u32, control: u32) -> bool {
+    let state = match sys::prctl(sys::PR_GET_SPECULATION_CTRL, which as usize, 0) {
+        Ok(s) => s as u32,
+        Err(_) => return false
+    };
+
+    if state == sys::PR_SPEC_NOT_AFFECTED {
+        return true
+    }
+
+    match control {
+        sys::PR_SPEC_ENABLE => state & sys::PR_SPEC_ENABLE != 0,
+        // Mitigations which are always on (e.g. spec_store_bypass_disable=on) count as disabled
+        _ => state & (sys::PR_SPEC_DISABLE | sys::PR_SPEC_FORCE_DISABLE) != 0
+    }
+}
+
+// Applies settings to the current process. Aborts if any of them can't be honored.
+pub fn apply(settings: &Settings) {
+    for (i, control) in settings.0.iter().enumerate() {
+        if *control == 0 {
+            continue
+        }
+
+        let (name, which) = MISFEATURES[i];
+        if let Err(e) = sys::prctl(sys::PR_SET_SPECULATION_CTRL, which as usize, *control as usize) {
+            // ENXIO is returned when the kernel doesn't allow per-process control, which is fine
+            // if the CPU isn't affected or the mitigation is enforced system-wide
+            if e.into_raw() as u32 == sys::ENXIO && satisfied(which, *control) {
+                continue
+            }
+            abort(&Fatal(ExitCode::SpeculationCtrlFailed, "Failed to apply speculation control!"), e.into_raw() as u32, Some(name))
+        }
+    }
+}
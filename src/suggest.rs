@@ -0,0 +1,159 @@
+/*
+   "Did you mean" suggestions.
+   When no candidates exist for a program, look for programs with similar names in the
+   hwcaps directories, since typos in alias names are a common packaging mistake.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::output;
+use crate::capabilities::FEATURE_LEVELS;
+use crate::HWCAPS_PATH;
+
+// Names further away than this are unlikely to be typos
+const MAX_DISTANCE: usize = 2;
+const NAME_MAX: usize = 255;
+
+// Levenshtein distance, or None if it's larger than MAX_DISTANCE
+fn distance(a: &[u8], b: &[u8]) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > MAX_DISTANCE || a.len() > NAME_MAX || b.len() > NAME_MAX {
+        return None
+    }
+
+    let mut previous = [0u8; NAME_MAX + 1];
+    let mut current = [0u8; NAME_MAX + 1];
+
+    for j in 0..=b.len() {
+        previous[j] = j as u8;
+    }
+
+    for i in 1..=a.len() {
+        current[0] = i as u8;
+
+        for j in 1..=b.len() {
+            // Rows of NAME_MAX long names can reach 255, which is already too far
+            let substitution = previous[j-1].saturating_add((a[i-1] != b[j-1]) as u8);
+            let deletion = previous[j].saturating_add(1);
+            let insertion = current[j-1].saturating_add(1);
+            current[j] = core::cmp::min(substitution, core::cmp::min(deletion, insertion));
+        }
+
+        core::mem::swap(&mut previous, &mut current);
+    }
+
+    let distance = previous[b.len()] as usize;
+    if distance > MAX_DISTANCE {
+        return None
+    }
+    Some(distance)
+}
+
+// Finds the most similar entry of the directory at path. Returns its distance and length.
+fn find_similar(path: &CStr, name: &[u8], best: &mut [u8; NAME_MAX]) -> Option<(usize, usize)> {
+    let fd = sys::openat(sys::AT_FDCWD, path, sys::O_RDONLY | sys::O_DIRECTORY).ok()?;
+
+    let mut result: Option<(usize, usize)> = None;
+
+    let _ = sys::read_dir(fd, |entry_name| {
+        if let Some(d) = distance(name, entry_name) {
+            // An exact match would have been executed, unless it's broken
+            if d != 0 && result.map_or(true, |(best_d, _)| d < best_d) {
+                best[..entry_name.len()].copy_from_slice(entry_name);
+                result = Some((d, entry_name.len()));
+            }
+        }
+    });
+
+    let _ = sys::close(fd);
+    result
+}
+
+// Prints the path of the program most similar to bin_path (e.g. "/bin/foo"), if there's one.
+pub fn print_suggestion(max_level: u32, bin_path: &[u8]) {
+    let bin_path = &bin_path[..bin_path.len()-1];
+    let separator = match bin_path.iter().rposition(|c| *c == b'/') {
+        Some(s) => s,
+        None => return
+    };
+    let (bin_dir, name) = (&bin_path[..separator], &bin_path[separator+1..]);
+
+    let mut best = [0u8; NAME_MAX];
+    let mut best_distance = MAX_DISTANCE + 1;
+    let mut best_path = [0u8; sys::PATH_MAX as usize];
+    let mut best_path_len = 0;
+
+    for level in FEATURE_LEVELS[..=max_level as usize].iter().rev() {
+        let mut path = [0u8; sys::PATH_MAX as usize];
+
+        let directory_index = HWCAPS_PATH.len();
+        let bin_dir_index = directory_index + level.directory.len();
+        let dir_len = bin_dir_index + bin_dir.len();
+        // The directory, a separator, the name and a terminator must fit
+        if dir_len + 1 + NAME_MAX + 1 > path.len() {
+            return
+        }
+
+        path[..directory_index].copy_from_slice(HWCAPS_PATH);
+        path[directory_index..bin_dir_index].copy_from_slice(level.directory);
+        path[bin_dir_index..dir_len].copy_from_slice(bin_dir);
+
+        let path_cstr = unsafe { CStr::from_bytes_with_nul_unchecked(&path[..dir_len+1]) };
+
+        if let Some((distance, len)) = find_similar(path_cstr, name, &mut best) {
+            if distance < best_distance {
+                path[dir_len] = b'/';
+                path[dir_len+1..dir_len+1+len].copy_from_slice(&best[..len]);
+
+                best_path = path;
+                best_path_len = dir_len + 1 + len;
+                best_distance = distance;
+            }
+        }
+    }
+
+    if best_path_len != 0 {
+        output::hint("Did you mean this program?", Some(&best_path[..best_path_len]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance, NAME_MAX};
+
+    #[test]
+    fn distances() {
+        let cases: &[(&[u8], &[u8], Option<usize>)] = &[
+            (b"foo", b"foo", Some(0)),
+            (b"", b"", Some(0)),
+            (b"foo", b"fo", Some(1)),
+            (b"fo", b"foo", Some(1)),
+            (b"foo", b"fob", Some(1)),
+            (b"gcc-13", b"gcc-12", Some(1)),
+            // Transpositions are two edits
+            (b"python3", b"pyhton3", Some(2)),
+            (b"", b"ab", Some(2)),
+            (b"foo", b"bar", None),
+            (b"", b"abc", None),
+            (b"foo", b"foobar", None),
+            (b"clang", b"gnalc", None),
+        ];
+
+        for (a, b, expected) in cases {
+            assert!(distance(a, b) == *expected, "{:?} {:?}", core::str::from_utf8(a), core::str::from_utf8(b));
+        }
+    }
+
+    #[test]
+    fn long_names() {
+        let a = [b'a'; NAME_MAX + 1];
+        let mut b = [b'a'; NAME_MAX];
+        b[0] = b'b';
+
+        assert_eq!(distance(&a[..NAME_MAX], &a[..NAME_MAX]), Some(0));
+        assert_eq!(distance(&a[..NAME_MAX], &b), Some(1));
+        assert_eq!(distance(&a[..NAME_MAX - 1], &b), Some(1));
+        // Longer names can't be file names
+        assert_eq!(distance(&a, &a), None);
+    }
+}
@@ -19,8 +19,11 @@
  */
 
 /*
-   This module contains all of the nasty low-level OS and linking stuff.
+   This module contains all of the nasty low-level OS stuff.
    The rest of hwcaps-loader should be (somewhat) OS agnostic.
+   Linking stuff only concerns the loader binary, so it lives in runtime.rs.
+   Linux is the main target. FreeBSD builds (14.0 or newer) get the same wrappers where FreeBSD
+   has an equivalent, and leave out the rest, which only Linux-only features use.
 */
 
 #[allow(unused_imports)]
@@ -32,8 +35,10 @@ mod bindings {
 }
 pub use bindings::*;
 
-use core::ffi::{c_int, c_uint, c_void, /*c_size_t, c_ssize_t,*/ c_char, CStr};
-use syscalls::{Sysno, syscall, Errno};
+use core::ffi::{c_int, c_uint, c_ulong, c_void, /*c_size_t, c_ssize_t,*/ c_char, CStr};
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+use crate::syscall::{Sysno, syscall};
+pub use crate::syscall::Errno;
 
 //TODO: remove this when https://github.com/rust-lang/rust/issues/88345 is stabilized
 #[allow(non_camel_case_types)]
@@ -41,66 +46,73 @@ type c_size_t  = usize;
 #[allow(non_camel_case_types)]
 type c_ssize_t = isize;
 
+pub const STDIN: c_int = 0;
 pub const STDOUT: c_int = 1;
+pub const STDERR: c_int = 2;
 
-/*
-   LINKING
-   To have a functional program, we must provide the following members to
-   the compiler and the linker:
-   - entry point (_start) or external libc
-   - panic_handler
-   - rust_eh_personality
-*/
-
-/* For targets with no OS/ABI, link a minimal entry point (_start) function.*/
-#[cfg(target_os="none")]
-#[cfg_attr(target_arch = "x86", path = "entry_point/arch_x86.rs")]
-#[cfg_attr(target_arch = "x86_64", path = "entry_point/arch_x86.rs")]
-mod entry_point;
-
-/* For targets with an OS/ABI, link libc */
-#[cfg(not(target_os="none"))]
-#[link(name = "c")]
-extern "C" {}
-
-//TODO: use when https://doc.rust-lang.org/unstable-book/language-features/lang-items.html stabilizes
-//#[lang = "eh_personality"]
-//extern "C" fn eh_personality() {}
-
-//Workarounds for https://github.com/rust-lang/rust/issues/106864
-#[no_mangle]
-extern "C" fn rust_eh_personality() {}
-
-// Debug panic handler
-#[cfg(debug_assertions)]
-#[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
-    use core::fmt;
-    use fmt::Write;
-
-    use crate::output::debug::PrintBuff;
-
-    let message = _info.message();
-    let location = _info.location().unwrap();
-
-    let mut buffer = [0; 1024];
-    let mut writer = PrintBuff::new(&mut buffer);
-
-    let _ = write!(&mut writer, "Error: {message}\nAt: {location}\n");
-
-    _ = write(STDOUT, &buffer);
-    exit(ExitCode::RustPanic as u8)
-}
+// glibc defines these as enum members, which bindgen doesn't expose under their usual names
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const SOCK_DGRAM: c_uint = 2;
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const SOCK_SEQPACKET: c_uint = 5;
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const SOCK_CLOEXEC: c_uint = O_CLOEXEC;
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const SOCK_NONBLOCK: c_uint = O_NONBLOCK;
 
+// Resource limits. Only some of them are shared between Linux and FreeBSD.
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const RLIMIT_NPROC: c_uint = 6;
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const RLIMIT_NOFILE: c_uint = 7;
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const RLIMIT_MEMLOCK: c_uint = 8;
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const RLIMIT_AS: c_uint = 9;
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)]
+pub const RLIM_INFINITY: u64 = u64::MAX;
+#[cfg(target_os = "freebsd")]
+#[allow(unused)]
+pub const RLIMIT_MEMLOCK: c_uint = 6;
+#[cfg(target_os = "freebsd")]
+#[allow(unused)]
+pub const RLIMIT_NPROC: c_uint = 7;
+#[cfg(target_os = "freebsd")]
+#[allow(unused)]
+pub const RLIMIT_NOFILE: c_uint = 8;
+#[cfg(target_os = "freebsd")]
+#[allow(unused)]
+pub const RLIMIT_AS: c_uint = 10;
+#[cfg(target_os = "freebsd")]
+#[allow(unused)]
+pub const RLIM_INFINITY: u64 = i64::MAX as u64;
+#[allow(unused)]
+pub const RLIMIT_CPU: c_uint = 0;
+#[allow(unused)]
+pub const RLIMIT_FSIZE: c_uint = 1;
+#[allow(unused)]
+pub const RLIMIT_DATA: c_uint = 2;
+#[allow(unused)]
+pub const RLIMIT_STACK: c_uint = 3;
+#[allow(unused)]
+pub const RLIMIT_CORE: c_uint = 4;
 
-// Production panic handler
-/* We can't do panic on production...
-   core::fmt increases binary size by an obscene amount
-   Just exist with a special error code if that happens */
-#[cfg(not(debug_assertions))]
-#[panic_handler]
-fn panic(_: &core::panic::PanicInfo) -> ! {
-    exit(ExitCode::RustPanic as u8)
+// Like Linux's struct rlimit64, which has the same layout as FreeBSD's struct rlimit
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+pub struct rlimit64 {
+    pub rlim_cur: u64,
+    pub rlim_max: u64,
 }
 
 /*
@@ -109,9 +121,15 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
    directly with the kernel (rather than using libc)
 */
 
-#[derive(Debug, PartialEq, Eq)]
+#[allow(dead_code)] // Some codes are only used by optional features
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ExitCode {
     RustPanic = 100,
+    MaintenanceInvalid = 190,
+    MaintenanceIOError = 191,
+    VerifyFailed = 192,
+    DaemonIOError = 193,
+    FailureRecordIOError = 194,
     SelfExecution = 200,
     CommandPathInvalid = 210,
     ProcPathIOError = 220,
@@ -120,7 +138,17 @@ pub enum ExitCode {
     TargetPathInvalid = 240,
     TargetPathTooLarge = 241,
     TargetExecutionError = 242,
-    TargetNoViableBinaries = 243
+    TargetNoViableBinaries = 243,
+    TargetInterpreterRejected = 244,
+    TargetSignatureInvalid = 245,
+    HwcapsDeviceMismatch = 246,
+    SpeculationCtrlFailed = 247,
+    TargetArgumentsTooLarge = 248,
+    PreExecHookFailed = 249,
+    ConfigInvalid = 250,
+    ConfigIOError = 251,
+    CandidateUnusable = 252,
+    TargetBuildIdMismatch = 253
 }
 
 impl iovec {
@@ -132,6 +160,48 @@ impl iovec {
     }
 }
 
+/*
+   NEWER SYSCALLS
+   statx(), execveat(), openat2() and faccessat2() are missing from older kernels (before 4.11,
+   3.19, 5.6 and 5.8, respectively). The first ENOSYS from one of them is remembered, and from
+   then on its wrapper goes straight to the older equivalent, so the same binary works on both.
+   Requests the older syscalls can't honor (like openat2()'s resolve restrictions) still fail
+   with ENOSYS.
+*/
+
+#[derive(Clone, Copy)]
+pub enum Newer {
+    Statx = 1,
+    Execveat = 2,
+    Openat2 = 4,
+    Faccessat2 = 8,
+}
+
+static MISSING: AtomicU8 = AtomicU8::new(0);
+
+// Whether the kernel was found to lack the syscall
+#[allow(unused)] // Only used by optional features
+pub fn is_missing(syscall: Newer) -> bool {
+    MISSING.load(Ordering::Relaxed) & syscall as u8 != 0
+}
+
+// Calls the newer syscall, unless it's known to be missing.
+// Returns None if it is, so the caller can fall back.
+#[cfg_attr(target_os = "freebsd", allow(unused))] // FreeBSD has none of them
+fn try_newer<T>(syscall: Newer, call: impl FnOnce() -> Result<T, Errno>) -> Option<Result<T, Errno>> {
+    if is_missing(syscall) {
+        return None
+    }
+
+    match call() {
+        Err(Errno::ENOSYS) => {
+            MISSING.fetch_or(syscall as u8, Ordering::Relaxed);
+            None
+        },
+        result => Some(result)
+    }
+}
+
 #[macro_export] macro_rules! make_uninit_array {
     ($size:expr) => {{
         use core::mem::{transmute, MaybeUninit};
@@ -145,7 +215,9 @@ impl iovec {
 #[inline]
 pub fn exit(code: u8) -> ! {
     unsafe {
-        _ = syscall!(Sysno::exit, code);
+        // exit() only terminates the calling thread. Use exit_group() so the exit code is
+        // preserved even if the kernel spawned threads on our behalf (io_uring workers, for example)
+        _ = syscall!(Sysno::exit_group, code);
         core::hint::unreachable_unchecked()
     }
 }
@@ -164,17 +236,87 @@ pub fn write(fd: i32, buffer: &[u8]) -> Result<usize, Errno> {
 #[inline]
 pub fn readlink(path: &CStr, buffer: &mut [u8]) -> Result<usize, Errno> {
     unsafe {
+        #[cfg(not(any(target_arch = "aarch64", target_arch = "riscv64", target_arch = "loongarch64")))]
         let ret = syscall!(Sysno::readlink, path.as_ptr(), buffer.as_mut_ptr(), buffer.len());
+        // Newer architectures only have readlinkat()
+        #[cfg(any(target_arch = "aarch64", target_arch = "riscv64", target_arch = "loongarch64"))]
+        let ret = syscall!(Sysno::readlinkat, AT_FDCWD, path.as_ptr(), buffer.as_mut_ptr(), buffer.len());
         /* man "readlink(2)":
            readlink()  places the contents of the symbolic link pathname in the buffer buf, which has size bufsiz.  read‐
            link() does not append a terminating null byte to buf.  It will (silently) truncate the contents (to a  length
            of bufsiz characters), in case the buffer is too small to hold all of the contents.
         */
-        core::hint::assert_unchecked(ret.unwrap_unchecked() <= buffer.len());
+        if let Ok(len) = ret {
+            core::hint::assert_unchecked(len <= buffer.len());
+        }
         ret
     }
 }
 
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn read(fd: i32, buffer: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::read, fd, buffer.as_mut_ptr(), buffer.len()) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn pread64(fd: i32, buffer: &mut [u8], offset: u64) -> Result<usize, Errno> {
+    #[cfg(target_pointer_width = "64")]
+    unsafe { syscall!(Sysno::pread64, fd, buffer.as_mut_ptr(), buffer.len(), offset) }
+
+    // 32-bit architectures take the offset as a pair of registers. ARM EABI and mips o32 align
+    // the pair to an even register, so the one before it is padding.
+    #[cfg(all(target_pointer_width = "32", any(target_arch = "arm", target_arch = "mips")))]
+    unsafe {
+        let (first, second) = split_u64(offset);
+        syscall!(Sysno::pread64, fd, buffer.as_mut_ptr(), buffer.len(), 0, first, second)
+    }
+    #[cfg(all(target_pointer_width = "32", not(any(target_arch = "arm", target_arch = "mips"))))]
+    unsafe {
+        let (first, second) = split_u64(offset);
+        syscall!(Sysno::pread64, fd, buffer.as_mut_ptr(), buffer.len(), first, second)
+    }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn pwrite64(fd: i32, buffer: &[u8], offset: u64) -> Result<usize, Errno> {
+    #[cfg(target_pointer_width = "64")]
+    unsafe { syscall!(Sysno::pwrite64, fd, buffer.as_ptr(), buffer.len(), offset) }
+
+    // Like pread64()
+    #[cfg(all(target_pointer_width = "32", any(target_arch = "arm", target_arch = "mips")))]
+    unsafe {
+        let (first, second) = split_u64(offset);
+        syscall!(Sysno::pwrite64, fd, buffer.as_ptr(), buffer.len(), 0, first, second)
+    }
+    #[cfg(all(target_pointer_width = "32", not(any(target_arch = "arm", target_arch = "mips"))))]
+    unsafe {
+        let (first, second) = split_u64(offset);
+        syscall!(Sysno::pwrite64, fd, buffer.as_ptr(), buffer.len(), first, second)
+    }
+}
+
+// Halves of a 64-bit syscall argument on 32-bit architectures, in the order the kernel merges
+// them: like the value's words in memory, so the low half comes first on little endian
+#[cfg(target_pointer_width = "32")]
+#[allow(unused)] // Only used by optional features
+#[inline]
+fn split_u64(value: u64) -> (usize, usize) {
+    let (low, high) = (value as u32 as usize, (value >> 32) as u32 as usize);
+
+    #[cfg(target_endian = "little")]
+    return (low, high);
+    #[cfg(target_endian = "big")]
+    return (high, low);
+}
+
+#[inline]
+pub fn close(fd: i32) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::close, fd) }
+}
+
 #[inline]
 pub fn openat(dirfd: i32, path: &CStr, flags: c_uint) -> Result<i32, Errno> {
     let result = unsafe { syscall!(Sysno::openat, dirfd, path.as_ptr(), O_CLOEXEC | flags) };
@@ -184,6 +326,834 @@ pub fn openat(dirfd: i32, path: &CStr, flags: c_uint) -> Result<i32, Errno> {
     }
 }
 
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn uname(buffer: &mut utsname) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::uname, buffer as *mut utsname) }
+}
+
+// FreeBSD's uname() is a libc function, which reads each field with sysctl()
+#[cfg(target_os = "freebsd")]
+#[allow(unused)] // Only used by optional features
+pub fn uname(buffer: &mut utsname) -> Result<usize, Errno> {
+    let fields = [
+        ([CTL_KERN as c_int, KERN_OSTYPE as c_int], &mut buffer.sysname),
+        ([CTL_KERN as c_int, KERN_HOSTNAME as c_int], &mut buffer.nodename),
+        ([CTL_KERN as c_int, KERN_OSRELEASE as c_int], &mut buffer.release),
+        ([CTL_KERN as c_int, KERN_VERSION as c_int], &mut buffer.version),
+        ([CTL_HW as c_int, HW_MACHINE as c_int], &mut buffer.machine),
+    ];
+
+    for (name, field) in fields {
+        // Leave room for the terminator, in case the value is truncated
+        let len = field.len() - 1;
+        let field = unsafe { core::slice::from_raw_parts_mut(field.as_mut_ptr() as *mut u8, len) };
+        sysctl(&name, field)?;
+    }
+    Ok(0)
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn sysinfo(info: &mut sysinfo) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::sysinfo, info as *mut sysinfo) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn mmap(addr: *mut c_void, length: usize, prot: c_uint, flags: c_uint, fd: i32, offset: u64) -> Result<*mut c_void, Errno> {
+    // x86, arm and mips only have mmap2(), which takes the offset in pages (always 4096 bytes on
+    // arm and mips)
+    #[cfg(any(target_arch = "x86", target_arch = "arm", target_arch = "mips"))]
+    let offset = offset / 4096;
+
+    #[cfg(not(target_arch = "s390x"))]
+    let ret = unsafe { syscall!(Sysno::mmap, addr, length, prot, flags, fd, offset)? };
+    // s390x only has old_mmap(), which takes its arguments in memory
+    #[cfg(target_arch = "s390x")]
+    let ret = unsafe {
+        let args = [addr as usize, length, prot as usize, flags as usize, fd as usize, offset as usize];
+        syscall!(Sysno::mmap, args.as_ptr())?
+    };
+    Ok(ret as *mut c_void)
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn munmap(addr: *mut c_void, length: usize) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::munmap, addr, length) }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn io_uring_setup(entries: u32, params: &mut io_uring_params) -> Result<i32, Errno> {
+    let result = unsafe { syscall!(Sysno::io_uring_setup, entries, params as *mut io_uring_params) };
+    result.map(|fd| fd as i32)
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn io_uring_enter(fd: i32, to_submit: u32, min_complete: u32, flags: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::io_uring_enter, fd, to_submit, min_complete, flags, 0, 0) }
+}
+
+// Like openat, but with the mode used for new files
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn openat_mode(dirfd: i32, path: &CStr, flags: c_uint, mode: c_uint) -> Result<i32, Errno> {
+    let result = unsafe { syscall!(Sysno::openat, dirfd, path.as_ptr(), O_CLOEXEC | flags, mode) };
+    result.map(|fd| fd as i32)
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn flock(fd: i32, operation: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::flock, fd, operation) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn renameat(olddirfd: i32, oldpath: &CStr, newdirfd: i32, newpath: &CStr) -> Result<usize, Errno> {
+    #[cfg(not(any(target_arch = "riscv64", target_arch = "loongarch64")))]
+    let result = unsafe { syscall!(Sysno::renameat, olddirfd, oldpath.as_ptr(), newdirfd, newpath.as_ptr()) };
+    // riscv64 and loongarch64 only have renameat2(), which is the same without flags
+    #[cfg(any(target_arch = "riscv64", target_arch = "loongarch64"))]
+    let result = unsafe { syscall!(Sysno::renameat2, olddirfd, oldpath.as_ptr(), newdirfd, newpath.as_ptr(), 0) };
+    result
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn unlinkat(dirfd: i32, path: &CStr, flags: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::unlinkat, dirfd, path.as_ptr(), flags) }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getdents64(fd: i32, buffer: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::getdents64, fd, buffer.as_mut_ptr(), buffer.len()) }
+}
+
+// getdirentries(), without the offset it'd write to basep
+#[cfg(target_os = "freebsd")]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getdents64(fd: i32, buffer: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::getdirentries, fd, buffer.as_mut_ptr(), buffer.len(), 0) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn faccessat(dirfd: i32, path: &CStr, mode: c_uint) -> Result<usize, Errno> {
+    // FreeBSD's takes flags as well
+    unsafe { syscall!(Sysno::faccessat, dirfd, path.as_ptr(), mode, 0) }
+}
+
+#[cfg(target_os = "freebsd")]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn faccessat2(dirfd: i32, path: &CStr, mode: c_uint, flags: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::faccessat, dirfd, path.as_ptr(), mode, flags) }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn faccessat2(dirfd: i32, path: &CStr, mode: c_uint, flags: c_uint) -> Result<usize, Errno> {
+    let call = || unsafe { syscall!(Sysno::faccessat2, dirfd, path.as_ptr(), mode, flags) };
+    if let Some(result) = try_newer(Newer::Faccessat2, call) {
+        return result
+    }
+
+    // faccessat() checks with the real IDs, which are the effective ones outside of setuid/setgid programs
+    let same_ids = flags & AT_EACCESS == 0 || (getuid() == geteuid() && getgid() == getegid());
+    match flags & !AT_EACCESS == 0 && same_ids {
+        true => faccessat(dirfd, path, mode),
+        false => Err(Errno::ENOSYS)
+    }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getpid() -> u32 {
+    unsafe { syscall!(Sysno::getpid).unwrap_unchecked() as u32 }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getuid() -> u32 {
+    unsafe { syscall!(Sysno::getuid).unwrap_unchecked() as u32 }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn geteuid() -> u32 {
+    unsafe { syscall!(Sysno::geteuid).unwrap_unchecked() as u32 }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getgid() -> u32 {
+    unsafe { syscall!(Sysno::getgid).unwrap_unchecked() as u32 }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getegid() -> u32 {
+    unsafe { syscall!(Sysno::getegid).unwrap_unchecked() as u32 }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn fchdir(fd: i32) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::fchdir, fd) }
+}
+
+// Returns the length of the current working directory's path, including its terminator
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getcwd(buffer: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::getcwd, buffer.as_mut_ptr(), buffer.len()) }
+}
+
+#[cfg(target_os = "freebsd")]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn getcwd(buffer: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::getcwd, buffer.as_mut_ptr(), buffer.len())? };
+    Ok(buffer.iter().position(|c| *c == 0).map_or(buffer.len(), |len| len + 1))
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn statx(dirfd: i32, path: &CStr, flags: c_uint, mask: c_uint, buffer: &mut statx) -> Result<usize, Errno> {
+    let call = || unsafe { syscall!(Sysno::statx, dirfd, path.as_ptr(), flags, mask, &mut *buffer as *mut statx) };
+    if let Some(result) = try_newer(Newer::Statx, call) {
+        return result
+    }
+    fstatat_statx(dirfd, path, flags, buffer)
+}
+
+// statx() through fstatat(), for kernels older than 4.11
+#[cfg(not(any(target_os = "freebsd", target_arch = "mips", target_arch = "mips64")))]
+fn fstatat_statx(dirfd: i32, path: &CStr, flags: c_uint, buffer: &mut statx) -> Result<usize, Errno> {
+    // fstatat() always fills in the basic fields, and doesn't know the AT_STATX_* flags
+    let mut stat = unsafe { core::mem::zeroed::<stat64>() };
+    let flags = flags & (AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH);
+    unsafe { syscall!(Sysno::fstatat, dirfd, path.as_ptr(), &mut stat as *mut stat64, flags)? };
+
+    // dev_t keeps the low bits of the minor number in the middle, for compatibility
+    let split = |dev: u64| (((dev >> 8) & 0xfff) as u32 | ((dev >> 32) & !0xfff) as u32, (dev & 0xff) as u32 | ((dev >> 12) & !0xff) as u32);
+    #[allow(clippy::unnecessary_cast)] // 32-bit on x86
+    let timestamp = |time: timespec| statx_timestamp { tv_sec: time.tv_sec as i64, tv_nsec: time.tv_nsec as u32, __statx_timestamp_pad1: [0] };
+    let (dev_major, dev_minor) = split(stat.st_dev);
+    let (rdev_major, rdev_minor) = split(stat.st_rdev);
+
+    *buffer = unsafe { core::mem::zeroed::<statx>() };
+    buffer.stx_mask = STATX_BASIC_STATS;
+    buffer.stx_blksize = stat.st_blksize as u32;
+    buffer.stx_nlink = stat.st_nlink as u32;
+    buffer.stx_uid = stat.st_uid;
+    buffer.stx_gid = stat.st_gid;
+    buffer.stx_mode = stat.st_mode as u16;
+    buffer.stx_ino = stat.st_ino;
+    buffer.stx_size = stat.st_size as u64;
+    buffer.stx_blocks = stat.st_blocks as u64;
+    buffer.stx_atime = timestamp(stat.st_atim);
+    buffer.stx_ctime = timestamp(stat.st_ctim);
+    buffer.stx_mtime = timestamp(stat.st_mtim);
+    buffer.stx_dev_major = dev_major;
+    buffer.stx_dev_minor = dev_minor;
+    buffer.stx_rdev_major = rdev_major;
+    buffer.stx_rdev_minor = rdev_minor;
+    Ok(0)
+}
+
+// mips' fstatat() fills the kernel's struct stat, which isn't the C library's, so these kernels
+// aren't supported there
+#[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+fn fstatat_statx(_dirfd: i32, _path: &CStr, _flags: c_uint, _buffer: &mut statx) -> Result<usize, Errno> {
+    Err(Errno::ENOSYS)
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn readlinkat(dirfd: i32, path: &CStr, buffer: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::readlinkat, dirfd, path.as_ptr(), buffer.as_mut_ptr(), buffer.len()) }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn fgetxattr(fd: i32, name: &CStr, buffer: &mut [u8]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::fgetxattr, fd, name.as_ptr(), buffer.as_mut_ptr(), buffer.len()) }
+}
+
+// Extended attributes in FreeBSD's user namespace, named like Linux's (e.g. "user.foo").
+// Other namespaces aren't supported.
+#[cfg(target_os = "freebsd")]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn fgetxattr(fd: i32, name: &CStr, buffer: &mut [u8]) -> Result<usize, Errno> {
+    let name = match name.to_bytes_with_nul().strip_prefix(b"user.") {
+        Some(n) => n,
+        None => return Err(Errno::ENOSYS)
+    };
+    unsafe {
+        syscall!(Sysno::extattr_get_fd, fd, EXTATTR_NAMESPACE_USER, name.as_ptr(), buffer.as_mut_ptr(), buffer.len())
+    }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn symlinkat(target: &CStr, newdirfd: i32, linkpath: &CStr) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::symlinkat, target.as_ptr(), newdirfd, linkpath.as_ptr()) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn linkat(olddirfd: i32, oldpath: &CStr, newdirfd: i32, newpath: &CStr, flags: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::linkat, olddirfd, oldpath.as_ptr(), newdirfd, newpath.as_ptr(), flags) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn mkdirat(dirfd: i32, path: &CStr, mode: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::mkdirat, dirfd, path.as_ptr(), mode) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn fchmodat(dirfd: i32, path: &CStr, mode: c_uint) -> Result<usize, Errno> {
+    // FreeBSD's takes flags as well
+    unsafe { syscall!(Sysno::fchmodat, dirfd, path.as_ptr(), mode, 0) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn clock_gettime(clock: c_uint, time: &mut timespec) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::clock_gettime, clock, time as *mut timespec) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn clock_nanosleep(clock: c_uint, time: &timespec) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::clock_nanosleep, clock, 0, time as *const timespec, 0) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn socket(domain: c_uint, kind: c_uint, protocol: c_uint) -> Result<i32, Errno> {
+    let result = unsafe { syscall!(Sysno::socket, domain, kind, protocol) };
+    result.map(|fd| fd as i32)
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn sendto(fd: i32, buffer: &[u8], flags: c_uint, address: &sockaddr_un) -> Result<usize, Errno> {
+    unsafe {
+        syscall!(Sysno::sendto, fd, buffer.as_ptr(), buffer.len(), flags, address as *const sockaddr_un, size_of::<sockaddr_un>())
+    }
+}
+
+// sendto() on a connected socket
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn send(fd: i32, buffer: &[u8], flags: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::sendto, fd, buffer.as_ptr(), buffer.len(), flags, 0, 0) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn connect(fd: i32, address: &sockaddr_un) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::connect, fd, address as *const sockaddr_un, size_of::<sockaddr_un>()) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn bind(fd: i32, address: &sockaddr_un) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::bind, fd, address as *const sockaddr_un, size_of::<sockaddr_un>()) }
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn listen(fd: i32, backlog: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::listen, fd, backlog) }
+}
+
+// The peer's address isn't needed
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn accept4(fd: i32, flags: c_uint) -> Result<i32, Errno> {
+    let result = unsafe { syscall!(Sysno::accept4, fd, 0, 0, flags) };
+    result.map(|fd| fd as i32)
+}
+
+// The user of the process on the other end of a connected Unix socket
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn peer_uid(fd: i32) -> Result<u32, Errno> {
+    let mut credentials = ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = size_of::<ucred>() as c_uint;
+    unsafe { syscall!(Sysno::getsockopt, fd, SOL_SOCKET, SO_PEERCRED, &mut credentials as *mut ucred, &mut len as *mut c_uint) }?;
+    Ok(credentials.uid)
+}
+
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn poll(fds: &mut [pollfd], timeout_ms: c_int) -> Result<usize, Errno> {
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "riscv64", target_arch = "loongarch64")))]
+    let result = unsafe { syscall!(Sysno::poll, fds.as_mut_ptr(), fds.len(), timeout_ms) };
+
+    // Newer architectures only have ppoll(), which takes a timespec (or nothing, to wait forever)
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64", target_arch = "loongarch64"))]
+    let timeout = timespec { tv_sec: (timeout_ms / 1000) as _, tv_nsec: (timeout_ms % 1000 * 1_000_000) as _ };
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64", target_arch = "loongarch64"))]
+    let result = match timeout_ms < 0 {
+        true => unsafe { syscall!(Sysno::ppoll, fds.as_mut_ptr(), fds.len(), 0, 0, 0) },
+        false => unsafe { syscall!(Sysno::ppoll, fds.as_mut_ptr(), fds.len(), &timeout as *const timespec, 0, 0) }
+    };
+    result
+}
+
+// fork(), through clone(), which every architecture has. Returns 0 in the child.
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn fork() -> Result<u32, Errno> {
+    #[cfg(not(target_arch = "s390x"))]
+    let result = unsafe { syscall!(Sysno::clone, SIGCHLD, 0, 0, 0, 0) };
+    // s390x's clone() takes the stack first
+    #[cfg(target_arch = "s390x")]
+    let result = unsafe { syscall!(Sysno::clone, 0, SIGCHLD, 0, 0, 0) };
+    result.map(|pid| pid as u32)
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn wait4(pid: u32, status: &mut c_int, options: c_uint) -> Result<u32, Errno> {
+    let result = unsafe { syscall!(Sysno::wait4, pid, status as *mut c_int, options, 0) };
+    result.map(|pid| pid as u32)
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn kill(pid: u32, signal: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::kill, pid, signal) }
+}
+
+// Sets (if new isn't None) and returns the calling process' limit for resource
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn prlimit(resource: c_uint, new: Option<&rlimit64>) -> Result<rlimit64, Errno> {
+    let mut old = rlimit64::default();
+    let new = new.map_or(core::ptr::null(), |limit| limit as *const rlimit64);
+    unsafe { syscall!(Sysno::prlimit64, 0, resource, new, &mut old as *mut rlimit64)? };
+    Ok(old)
+}
+
+#[cfg(target_os = "freebsd")]
+#[allow(unused)] // Only used by optional features
+pub fn prlimit(resource: c_uint, new: Option<&rlimit64>) -> Result<rlimit64, Errno> {
+    let mut old = rlimit64::default();
+    unsafe { syscall!(Sysno::getrlimit, resource, &mut old as *mut rlimit64)? };
+    if let Some(new) = new {
+        unsafe { syscall!(Sysno::setrlimit, resource, new as *const rlimit64)? };
+    }
+    Ok(old)
+}
+
+// Missing from kernels before 5.3
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn pidfd_open(pid: u32) -> Result<i32, Errno> {
+    let result = unsafe { syscall!(Sysno::pidfd_open, pid, 0) };
+    result.map(|fd| fd as i32)
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn openat2(dirfd: i32, path: &CStr, how: &open_how) -> Result<i32, Errno> {
+    let call = || unsafe { syscall!(Sysno::openat2, dirfd, path.as_ptr(), how as *const open_how, size_of::<open_how>()) };
+    if let Some(result) = try_newer(Newer::Openat2, call) {
+        return result.map(|fd| fd as i32)
+    }
+
+    // Without resolve restrictions, it's just openat()
+    match how.resolve {
+        0 => openat_mode(dirfd, path, how.flags as c_uint, how.mode as c_uint),
+        _ => Err(Errno::ENOSYS)
+    }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn execveat(dirfd: i32, path: &CStr, argv: *const *const c_char, envp: *const *const c_char, flags: c_uint) -> Errno {
+    let call = || unsafe { syscall!(Sysno::execveat, dirfd, path.as_ptr(), argv, envp, flags) };
+    //Execveat doesn't return, so it's safe to assume an error occured
+    if let Some(result) = try_newer(Newer::Execveat, call) {
+        return unsafe { result.unwrap_err_unchecked() }
+    }
+
+    let path_bytes = path.to_bytes();
+    if flags & !AT_EMPTY_PATH != 0 {
+        return Errno::ENOSYS
+    }
+    if dirfd == AT_FDCWD || path_bytes.first() == Some(&b'/') {
+        return execve(path, argv, envp)
+    }
+    if dirfd < 0 {
+        return Errno::EBADF
+    }
+    if path_bytes.is_empty() && flags & AT_EMPTY_PATH == 0 {
+        return Errno::ENOENT
+    }
+
+    // The same file, reached through /proc/self/fd/<dirfd>[/<path>]
+    let mut buffer = [0u8; PATH_MAX as usize];
+    let prefix = b"/proc/self/fd/";
+    buffer[..prefix.len()].copy_from_slice(prefix);
+    let mut len = prefix.len();
+
+    let mut digits = [0u8; 10];
+    let mut value = dirfd as u32;
+    let mut count = 0;
+    loop {
+        digits[count] = b'0' + (value % 10) as u8;
+        value /= 10;
+        count += 1;
+        if value == 0 {
+            break
+        }
+    }
+    for digit in digits[..count].iter().rev() {
+        buffer[len] = *digit;
+        len += 1;
+    }
+
+    if !path_bytes.is_empty() {
+        if len + 1 + path_bytes.len() >= buffer.len() {
+            return Errno::ENAMETOOLONG
+        }
+        buffer[len] = b'/';
+        buffer[len + 1..len + 1 + path_bytes.len()].copy_from_slice(path_bytes);
+        len += 1 + path_bytes.len();
+    }
+
+    match CStr::from_bytes_with_nul(&buffer[..len + 1]) {
+        Ok(proc_path) => execve(proc_path, argv, envp),
+        Err(_) => Errno::ENOENT
+    }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn landlock_create_ruleset(attr: *const landlock_ruleset_attr, size: usize, flags: c_uint) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::landlock_create_ruleset, attr, size, flags) }
+}
+
+// A key, and the value riscv_hwprobe() fills in for it (as in the kernel's asm/hwprobe.h)
+#[cfg(target_arch = "riscv64")]
+#[allow(non_camel_case_types)]
+#[repr(C)]
+pub struct riscv_hwprobe {
+    pub key: i64,
+    pub value: u64,
+}
+
+// Fills in the value of each pair's key, as supported by every CPU, or sets the key to -1 if the
+// kernel doesn't know it. Linux 6.4 or newer.
+#[cfg(target_arch = "riscv64")]
+#[inline]
+pub fn riscv_hwprobe(pairs: &mut [riscv_hwprobe]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::riscv_hwprobe, pairs.as_mut_ptr(), pairs.len(), 0, 0, 0) }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn sched_getaffinity(pid: i32, mask: &mut [u64]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::sched_getaffinity, pid, mask.len() * size_of::<u64>(), mask.as_mut_ptr()) }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn sched_setaffinity(pid: i32, mask: &[u64]) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::sched_setaffinity, pid, mask.len() * size_of::<u64>(), mask.as_ptr()) }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+#[inline]
+pub fn prctl(option: u32, arg2: usize, arg3: usize) -> Result<usize, Errno> {
+    unsafe { syscall!(Sysno::prctl, option, arg2, arg3, 0, 0) }
+}
+
+// linux_dirent64 is a variable length struct, so we read it by offsets
+#[cfg(not(target_os = "freebsd"))]
+const DIRENT_RECLEN_OFFSET: usize = 16;
+#[cfg(not(target_os = "freebsd"))]
+const DIRENT_NAME_OFFSET: usize = 19;
+// Same for FreeBSD's struct dirent, whose name comes after d_type and d_namlen
+#[cfg(target_os = "freebsd")]
+const DIRENT_RECLEN_OFFSET: usize = 16;
+#[cfg(target_os = "freebsd")]
+const DIRENT_NAME_OFFSET: usize = 24;
+
+// Calls f with the name of every entry of the directory fd (including "." and "..")
+#[allow(unused)] // Only used by optional features
+pub fn read_dir(fd: i32, mut f: impl FnMut(&[u8])) -> Result<(), Errno> {
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        let len = getdents64(fd, &mut buffer)?;
+        if len == 0 {
+            return Ok(())
+        }
+
+        let mut offset = 0;
+        while offset < len {
+            let entry = &buffer[offset..len];
+            let reclen = u16::from_ne_bytes([entry[DIRENT_RECLEN_OFFSET], entry[DIRENT_RECLEN_OFFSET+1]]) as usize;
+            let name = &entry[DIRENT_NAME_OFFSET..reclen];
+
+            f(&name[..name.iter().position(|c| *c == 0).unwrap_or(name.len())]);
+            offset += reclen;
+        }
+    }
+}
+
+// Reads up to buffer.len() bytes from the file at path, returning how many were read
+#[allow(unused)] // Only used by optional features
+pub fn read_file(path: &CStr, buffer: &mut [u8]) -> Result<usize, Errno> {
+    read_file_at(AT_FDCWD, path, 0, buffer)
+}
+
+// Like read_file, but relative to dirfd and with additional open flags
+#[allow(unused)] // Only used by optional features
+pub fn read_file_at(dirfd: i32, path: &CStr, flags: c_uint, buffer: &mut [u8]) -> Result<usize, Errno> {
+    let fd = openat(dirfd, path, O_RDONLY | flags)?;
+
+    let mut len = 0;
+    let result = loop {
+        if len == buffer.len() {
+            break Ok(len)
+        }
+
+        match read(fd, &mut buffer[len..]) {
+            Ok(0) => break Ok(len),
+            Ok(n) => len += n,
+            Err(e) => break Err(e)
+        }
+    };
+
+    let _ = close(fd);
+    result
+}
+
+// The arguments main() got, which can be read without unsafe code once they're wrapped
+#[derive(Clone, Copy)]
+pub struct Args<'a> {
+    argv: &'a [*const c_char],
+}
+
+impl<'a> Args<'a> {
+    // Safety: argv must hold argc pointers to C strings, which stay valid for 'a
+    pub unsafe fn new(argc: i32, argv: *const *const c_char) -> Self {
+        Args { argv: core::slice::from_raw_parts(argv, argc.max(0) as usize) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.argv.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.argv.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&'a [u8]> {
+        self.argv.get(i).map(|arg| unsafe { CStr::from_ptr(*arg) }.to_bytes())
+    }
+
+    // The arguments after the first n
+    pub fn skip(&self, n: usize) -> Self {
+        Args { argv: self.argv.get(n..).unwrap_or(&[]) }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.argv.iter().map(|arg| unsafe { CStr::from_ptr(*arg) }.to_bytes())
+    }
+}
+
+// Returns the value of the environment variable called name
+#[allow(unused)] // Only used by optional features
+pub fn getenv(envp: *const *const c_char, name: &[u8]) -> Option<&'static [u8]> {
+    let mut ptr = envp;
+
+    unsafe {
+        while !(*ptr).is_null() {
+            let var = CStr::from_ptr(*ptr).to_bytes();
+
+            if var.len() > name.len() && var[name.len()] == b'=' && &var[..name.len()] == name {
+                return Some(&var[name.len()+1..])
+            }
+            ptr = ptr.add(1);
+        }
+    }
+    None
+}
+
+// Returns the auxiliary vector following envp's terminator.
+//
+// Safety: envp must be the environment the kernel placed on the stack.
+unsafe fn find_auxv(envp: *const *const c_char) -> *const c_ulong {
+    let mut ptr = envp;
+    while !(*ptr).is_null() {
+        ptr = ptr.add(1);
+    }
+    ptr.add(1) as *const c_ulong
+}
+
+// Returns the value of key's entry in auxv, which is a list of key/value pairs ending with AT_NULL
+//
+// Safety: auxv must be the auxiliary vector the kernel placed on the stack.
+unsafe fn read_auxv(mut auxv: *const c_ulong, key: c_ulong) -> Option<c_ulong> {
+    while *auxv != AT_NULL as c_ulong {
+        if *auxv == key {
+            return Some(*auxv.add(1))
+        }
+        auxv = auxv.add(2);
+    }
+    None
+}
+
+// The auxiliary vector the kernel placed on the stack, for auxval()
+static AUXV: AtomicPtr<c_ulong> = AtomicPtr::new(core::ptr::null_mut());
+
+// Finds the auxiliary vector after envp, so auxval() can read it without walking the environment
+// every time, and without procfs in freestanding builds.
+// The kernel places it on the stack, right after the environment's terminator. Freestanding builds
+// get envp straight from the stack, but libc-linked builds get whatever libc passes to main(), so
+// they read /proc/self/auxv instead, only falling back to the stack if procfs is unavailable.
+// FreeBSD's libc passes on the stack's envp, and there's no /proc/self/auxv there anyway.
+//
+// Safety: envp must be the environment main() got, and stay valid until the process executes
+// something or exits.
+pub unsafe fn init_auxv(envp: *const *const c_char) {
+    AUXV.store(find_auxv(envp) as *mut _, Ordering::Relaxed);
+}
+
+// Returns the value of an entry of the auxiliary vector. Only reads /proc/self/auxv until
+// init_auxv() is called, so it returns None in other programs if procfs is unavailable.
+#[allow(unused)] // Only used by some architectures and optional features
+pub fn auxval(key: c_ulong) -> Option<c_ulong> {
+    #[cfg(not(any(target_os="none", target_os="freebsd")))]
+    if let Some(value) = getauxval_procfs(key) {
+        return value
+    }
+
+    let auxv = AUXV.load(Ordering::Relaxed);
+    if auxv.is_null() {
+        return None
+    }
+    unsafe { read_auxv(auxv, key) }
+}
+
+// The path we were executed through (AT_EXECFN), as given to execve()
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+pub fn execfn() -> Option<&'static CStr> {
+    auxval(AT_EXECFN as _).map(|p| unsafe { CStr::from_ptr(p as *const c_char) })
+}
+
+// Returns None if /proc/self/auxv can't be read, or the entry's value (if there's one) otherwise
+#[cfg(not(any(target_os="none", target_os="freebsd")))]
+fn getauxval_procfs(key: c_ulong) -> Option<Option<c_ulong>> {
+    // The kernel doesn't provide more than a few dozen entries
+    let mut auxv = [0 as c_ulong; 128];
+    let buffer = unsafe { core::slice::from_raw_parts_mut(auxv.as_mut_ptr() as *mut u8, size_of_val(&auxv)) };
+
+    let len = read_file(c"/proc/self/auxv", buffer).ok()?;
+
+    for entry in auxv[..len / size_of::<c_ulong>()].chunks_exact(2) {
+        if entry[0] == AT_NULL as c_ulong {
+            break
+        }
+        if entry[0] == key {
+            return Some(Some(entry[1]))
+        }
+    }
+    Some(None)
+}
+
+// Whether we're in secure-execution mode (setuid/setgid programs or file capabilities), where
+// nothing from the environment should be trusted
+#[cfg(not(target_os = "freebsd"))]
+#[allow(unused)] // Only used by optional features
+pub fn is_secure() -> bool {
+    auxval(AT_SECURE as _).is_some_and(|secure| secure != 0)
+}
+
+// FreeBSD has no AT_SECURE, but it remembers the same thing for issetugid()
+#[cfg(target_os = "freebsd")]
+#[allow(unused)] // Only used by optional features
+pub fn is_secure() -> bool {
+    unsafe { syscall!(Sysno::issetugid).unwrap_unchecked() != 0 }
+}
+
+// Reads the sysctl called by name (e.g. { CTL_KERN, KERN_OSTYPE }), returning its length
+#[cfg(target_os = "freebsd")]
+pub fn sysctl(name: &[c_int], buffer: &mut [u8]) -> Result<usize, Errno> {
+    let mut len = buffer.len();
+    unsafe {
+        syscall!(Sysno::__sysctl, name.as_ptr(), name.len(), buffer.as_mut_ptr(), &mut len as *mut usize, 0, 0)?
+    };
+    Ok(len)
+}
+
+// Writes the running executable's path to buffer, like readlink("/proc/self/exe") on Linux
+#[cfg(target_os = "freebsd")]
+pub fn executable_path(buffer: &mut [u8]) -> Result<usize, Errno> {
+    let name = [CTL_KERN as c_int, KERN_PROC as c_int, KERN_PROC_PATHNAME as c_int, -1];
+    // The length includes the terminator, which readlink() doesn't write
+    sysctl(&name, buffer).map(|len| len.saturating_sub(1))
+}
+
+// Writes the path of what fd refers to to buffer, like readlink("/dev/fd/<fd>") on Linux
+#[cfg(target_os = "freebsd")]
+pub fn fd_path(fd: i32, buffer: &mut [u8]) -> Result<usize, Errno> {
+    let mut info = unsafe { core::mem::zeroed::<kinfo_file>() };
+    info.kf_structsize = KINFO_FILE_SIZE as c_int;
+    unsafe { syscall!(Sysno::fcntl, fd, F_KINFO, &mut info as *mut kinfo_file)? };
+
+    let path = unsafe { CStr::from_ptr(info.kf_path.as_ptr()) }.to_bytes();
+    // The kernel leaves it empty when the name cache no longer knows it
+    if path.is_empty() {
+        return Err(Errno::ENOENT)
+    }
+    if path.len() > buffer.len() {
+        return Err(Errno::ENAMETOOLONG)
+    }
+    buffer[..path.len()].copy_from_slice(path);
+    Ok(path.len())
+}
+
 #[inline]
 pub fn execve(path: &CStr, argv: *const *const c_char, envp: *const *const c_char) -> Errno {
      unsafe {
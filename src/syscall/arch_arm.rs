@@ -0,0 +1,119 @@
+use core::arch::asm;
+
+// The EABI syscall table
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+#[repr(usize)]
+pub enum Sysno {
+    read = 3,
+    write = 4,
+    close = 6,
+    execve = 11,
+    getpid = 20,
+    kill = 37,
+    readlink = 85,
+    munmap = 91,
+    wait4 = 114,
+    sysinfo = 116,
+    clone = 120,
+    uname = 122,
+    fchdir = 133,
+    flock = 143,
+    writev = 146,
+    poll = 168,
+    prctl = 172,
+    pread64 = 180,
+    pwrite64 = 181,
+    getcwd = 183,
+    // Takes its offset in 4096-byte units
+    mmap = 192,
+    // The 32-bit ID versions
+    getuid = 199,
+    getgid = 200,
+    geteuid = 201,
+    getegid = 202,
+    getdents64 = 217,
+    fgetxattr = 231,
+    sched_setaffinity = 241,
+    sched_getaffinity = 242,
+    exit_group = 248,
+    clock_gettime = 263,
+    clock_nanosleep = 265,
+    socket = 281,
+    bind = 282,
+    connect = 283,
+    listen = 284,
+    sendto = 290,
+    getsockopt = 295,
+    openat = 322,
+    mkdirat = 323,
+    // fstatat64(), which fills struct stat64
+    fstatat = 327,
+    unlinkat = 328,
+    renameat = 329,
+    linkat = 330,
+    symlinkat = 331,
+    readlinkat = 332,
+    fchmodat = 333,
+    faccessat = 334,
+    accept4 = 366,
+    prlimit64 = 369,
+    execveat = 387,
+    statx = 397,
+    io_uring_setup = 425,
+    io_uring_enter = 426,
+    pidfd_open = 434,
+    openat2 = 437,
+    faccessat2 = 439,
+    landlock_create_ruleset = 444,
+}
+
+// The syscall number goes in r7, which LLVM reserves as the frame pointer in Thumb code, so it's
+// swapped in and restored afterwards. Every call goes through here, the unused arguments are just
+// zero.
+#[inline]
+pub unsafe fn syscall6(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize, a6: usize) -> usize {
+    let ret;
+    asm!(
+        "mov {saved}, r7",
+        "mov r7, {nr}",
+        "svc 0",
+        "mov r7, {saved}",
+        nr = in(reg) nr as usize,
+        saved = out(reg) _,
+        inlateout("r0") a1 => ret,
+        in("r1") a2, in("r2") a3, in("r3") a4, in("r4") a5, in("r5") a6,
+        options(nostack),
+    );
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall0(nr: Sysno) -> usize {
+    syscall6(nr, 0, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall1(nr: Sysno, a1: usize) -> usize {
+    syscall6(nr, a1, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall2(nr: Sysno, a1: usize, a2: usize) -> usize {
+    syscall6(nr, a1, a2, 0, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall3(nr: Sysno, a1: usize, a2: usize, a3: usize) -> usize {
+    syscall6(nr, a1, a2, a3, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall4(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    syscall6(nr, a1, a2, a3, a4, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall5(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    syscall6(nr, a1, a2, a3, a4, a5, 0)
+}
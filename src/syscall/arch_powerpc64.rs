@@ -0,0 +1,140 @@
+use core::arch::asm;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+#[repr(usize)]
+pub enum Sysno {
+    read = 3,
+    write = 4,
+    close = 6,
+    execve = 11,
+    getpid = 20,
+    getuid = 24,
+    kill = 37,
+    getgid = 47,
+    geteuid = 49,
+    getegid = 50,
+    readlink = 85,
+    mmap = 90,
+    munmap = 91,
+    wait4 = 114,
+    sysinfo = 116,
+    clone = 120,
+    uname = 122,
+    fchdir = 133,
+    flock = 143,
+    writev = 146,
+    poll = 167,
+    prctl = 171,
+    pread64 = 179,
+    pwrite64 = 180,
+    getcwd = 182,
+    getdents64 = 202,
+    fgetxattr = 214,
+    sched_setaffinity = 222,
+    sched_getaffinity = 223,
+    exit_group = 234,
+    clock_gettime = 246,
+    clock_nanosleep = 248,
+    openat = 286,
+    mkdirat = 287,
+    // newfstatat(), whose struct stat is the same as struct stat64
+    fstatat = 291,
+    unlinkat = 292,
+    renameat = 293,
+    linkat = 294,
+    symlinkat = 295,
+    readlinkat = 296,
+    fchmodat = 297,
+    faccessat = 298,
+    prlimit64 = 325,
+    socket = 326,
+    bind = 327,
+    connect = 328,
+    listen = 329,
+    sendto = 335,
+    getsockopt = 340,
+    accept4 = 344,
+    execveat = 362,
+    statx = 383,
+    io_uring_setup = 425,
+    io_uring_enter = 426,
+    pidfd_open = 434,
+    openat2 = 437,
+    faccessat2 = 439,
+    landlock_create_ruleset = 444,
+}
+
+// The number goes in r0, and the result comes back in r3. Errors set the summary overflow bit of
+// cr0 and return a positive error number, which is negated to look like other architectures'.
+// The kernel clobbers the other volatile registers.
+
+#[inline]
+pub unsafe fn syscall0(nr: Sysno) -> usize {
+    let ret;
+    asm!("sc", "bns+ 2f", "neg 3, 3", "2:", inlateout("r0") nr as usize => _, lateout("r3") ret,
+         lateout("r4") _, lateout("r5") _, lateout("r6") _, lateout("r7") _, lateout("r8") _, lateout("r9") _,
+         lateout("r10") _, lateout("r11") _, lateout("r12") _, lateout("cr0") _, lateout("ctr") _,
+         lateout("xer") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall1(nr: Sysno, a1: usize) -> usize {
+    let ret;
+    asm!("sc", "bns+ 2f", "neg 3, 3", "2:", inlateout("r0") nr as usize => _, inlateout("r3") a1 => ret,
+         lateout("r4") _, lateout("r5") _, lateout("r6") _, lateout("r7") _, lateout("r8") _, lateout("r9") _,
+         lateout("r10") _, lateout("r11") _, lateout("r12") _, lateout("cr0") _, lateout("ctr") _,
+         lateout("xer") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall2(nr: Sysno, a1: usize, a2: usize) -> usize {
+    let ret;
+    asm!("sc", "bns+ 2f", "neg 3, 3", "2:", inlateout("r0") nr as usize => _, inlateout("r3") a1 => ret,
+         inlateout("r4") a2 => _, lateout("r5") _, lateout("r6") _, lateout("r7") _, lateout("r8") _,
+         lateout("r9") _, lateout("r10") _, lateout("r11") _, lateout("r12") _, lateout("cr0") _,
+         lateout("ctr") _, lateout("xer") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall3(nr: Sysno, a1: usize, a2: usize, a3: usize) -> usize {
+    let ret;
+    asm!("sc", "bns+ 2f", "neg 3, 3", "2:", inlateout("r0") nr as usize => _, inlateout("r3") a1 => ret,
+         inlateout("r4") a2 => _, inlateout("r5") a3 => _, lateout("r6") _, lateout("r7") _, lateout("r8") _,
+         lateout("r9") _, lateout("r10") _, lateout("r11") _, lateout("r12") _, lateout("cr0") _,
+         lateout("ctr") _, lateout("xer") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall4(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let ret;
+    asm!("sc", "bns+ 2f", "neg 3, 3", "2:", inlateout("r0") nr as usize => _, inlateout("r3") a1 => ret,
+         inlateout("r4") a2 => _, inlateout("r5") a3 => _, inlateout("r6") a4 => _, lateout("r7") _,
+         lateout("r8") _, lateout("r9") _, lateout("r10") _, lateout("r11") _, lateout("r12") _,
+         lateout("cr0") _, lateout("ctr") _, lateout("xer") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall5(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let ret;
+    asm!("sc", "bns+ 2f", "neg 3, 3", "2:", inlateout("r0") nr as usize => _, inlateout("r3") a1 => ret,
+         inlateout("r4") a2 => _, inlateout("r5") a3 => _, inlateout("r6") a4 => _, inlateout("r7") a5 => _,
+         lateout("r8") _, lateout("r9") _, lateout("r10") _, lateout("r11") _, lateout("r12") _,
+         lateout("cr0") _, lateout("ctr") _, lateout("xer") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall6(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize, a6: usize) -> usize {
+    let ret;
+    asm!("sc", "bns+ 2f", "neg 3, 3", "2:", inlateout("r0") nr as usize => _, inlateout("r3") a1 => ret,
+         inlateout("r4") a2 => _, inlateout("r5") a3 => _, inlateout("r6") a4 => _, inlateout("r7") a5 => _,
+         inlateout("r8") a6 => _, lateout("r9") _, lateout("r10") _, lateout("r11") _, lateout("r12") _,
+         lateout("cr0") _, lateout("ctr") _, lateout("xer") _, options(nostack));
+    ret
+}
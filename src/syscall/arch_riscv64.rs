@@ -0,0 +1,122 @@
+use core::arch::asm;
+
+// The generic syscall table, which has no poll(), readlink() or even renameat(), so sys.rs uses
+// ppoll(), readlinkat() and renameat2() instead
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+#[repr(usize)]
+pub enum Sysno {
+    fgetxattr = 10,
+    getcwd = 17,
+    flock = 32,
+    mkdirat = 34,
+    unlinkat = 35,
+    symlinkat = 36,
+    linkat = 37,
+    faccessat = 48,
+    fchdir = 50,
+    fchmodat = 53,
+    openat = 56,
+    close = 57,
+    getdents64 = 61,
+    read = 63,
+    write = 64,
+    writev = 66,
+    pread64 = 67,
+    pwrite64 = 68,
+    ppoll = 73,
+    readlinkat = 78,
+    // newfstatat(), whose struct stat is the same as struct stat64
+    fstatat = 79,
+    exit_group = 94,
+    clock_gettime = 113,
+    clock_nanosleep = 115,
+    sched_setaffinity = 122,
+    sched_getaffinity = 123,
+    kill = 129,
+    uname = 160,
+    prctl = 167,
+    getpid = 172,
+    getuid = 174,
+    geteuid = 175,
+    getgid = 176,
+    getegid = 177,
+    sysinfo = 179,
+    socket = 198,
+    bind = 200,
+    listen = 201,
+    connect = 203,
+    sendto = 206,
+    getsockopt = 209,
+    munmap = 215,
+    clone = 220,
+    execve = 221,
+    mmap = 222,
+    accept4 = 242,
+    riscv_hwprobe = 258,
+    wait4 = 260,
+    prlimit64 = 261,
+    renameat2 = 276,
+    execveat = 281,
+    statx = 291,
+    io_uring_setup = 425,
+    io_uring_enter = 426,
+    pidfd_open = 434,
+    openat2 = 437,
+    faccessat2 = 439,
+    landlock_create_ruleset = 444,
+}
+
+// The number goes in a7, and the result comes back in a0
+
+#[inline]
+pub unsafe fn syscall0(nr: Sysno) -> usize {
+    let ret;
+    asm!("ecall", in("a7") nr as usize, inlateout("a0") 0usize => ret, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall1(nr: Sysno, a1: usize) -> usize {
+    let ret;
+    asm!("ecall", in("a7") nr as usize, inlateout("a0") a1 => ret, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall2(nr: Sysno, a1: usize, a2: usize) -> usize {
+    let ret;
+    asm!("ecall", in("a7") nr as usize, inlateout("a0") a1 => ret, in("a1") a2, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall3(nr: Sysno, a1: usize, a2: usize, a3: usize) -> usize {
+    let ret;
+    asm!("ecall", in("a7") nr as usize, inlateout("a0") a1 => ret, in("a1") a2, in("a2") a3, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall4(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let ret;
+    asm!("ecall", in("a7") nr as usize, inlateout("a0") a1 => ret, in("a1") a2, in("a2") a3,
+         in("a3") a4, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall5(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let ret;
+    asm!("ecall", in("a7") nr as usize, inlateout("a0") a1 => ret, in("a1") a2, in("a2") a3,
+         in("a3") a4, in("a4") a5, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall6(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize, a6: usize) -> usize {
+    let ret;
+    asm!("ecall", in("a7") nr as usize, inlateout("a0") a1 => ret, in("a1") a2, in("a2") a3,
+         in("a3") a4, in("a4") a5, in("a5") a6, options(nostack));
+    ret
+}
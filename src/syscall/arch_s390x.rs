@@ -0,0 +1,123 @@
+use core::arch::asm;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+#[repr(usize)]
+pub enum Sysno {
+    read = 3,
+    write = 4,
+    close = 6,
+    execve = 11,
+    getpid = 20,
+    kill = 37,
+    readlink = 85,
+    // old_mmap(), which takes its arguments in memory (see sys::mmap)
+    mmap = 90,
+    munmap = 91,
+    wait4 = 114,
+    sysinfo = 116,
+    // Takes the stack before the flags (see sys::fork)
+    clone = 120,
+    uname = 122,
+    fchdir = 133,
+    flock = 143,
+    writev = 146,
+    poll = 168,
+    prctl = 172,
+    pread64 = 180,
+    pwrite64 = 181,
+    getcwd = 183,
+    getuid = 199,
+    getgid = 200,
+    geteuid = 201,
+    getegid = 202,
+    getdents64 = 220,
+    fgetxattr = 229,
+    sched_setaffinity = 239,
+    sched_getaffinity = 240,
+    exit_group = 248,
+    clock_gettime = 260,
+    clock_nanosleep = 262,
+    openat = 288,
+    mkdirat = 289,
+    // newfstatat(), whose struct stat is the same as struct stat64
+    fstatat = 293,
+    unlinkat = 294,
+    renameat = 295,
+    linkat = 296,
+    symlinkat = 297,
+    readlinkat = 298,
+    fchmodat = 299,
+    faccessat = 300,
+    prlimit64 = 334,
+    execveat = 354,
+    socket = 359,
+    bind = 361,
+    connect = 362,
+    listen = 363,
+    accept4 = 364,
+    getsockopt = 365,
+    sendto = 369,
+    statx = 379,
+    io_uring_setup = 425,
+    io_uring_enter = 426,
+    pidfd_open = 434,
+    openat2 = 437,
+    faccessat2 = 439,
+    landlock_create_ruleset = 444,
+}
+
+// The number goes in r1 (so that it isn't limited to svc's 8-bit immediate), and the result comes
+// back in r2. The kernel preserves every other register.
+
+#[inline]
+pub unsafe fn syscall0(nr: Sysno) -> usize {
+    let ret;
+    asm!("svc 0", in("r1") nr as usize, lateout("r2") ret, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall1(nr: Sysno, a1: usize) -> usize {
+    let ret;
+    asm!("svc 0", in("r1") nr as usize, inlateout("r2") a1 => ret, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall2(nr: Sysno, a1: usize, a2: usize) -> usize {
+    let ret;
+    asm!("svc 0", in("r1") nr as usize, inlateout("r2") a1 => ret, in("r3") a2, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall3(nr: Sysno, a1: usize, a2: usize, a3: usize) -> usize {
+    let ret;
+    asm!("svc 0", in("r1") nr as usize, inlateout("r2") a1 => ret, in("r3") a2, in("r4") a3, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall4(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let ret;
+    asm!("svc 0", in("r1") nr as usize, inlateout("r2") a1 => ret, in("r3") a2, in("r4") a3, in("r5") a4,
+         options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall5(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let ret;
+    asm!("svc 0", in("r1") nr as usize, inlateout("r2") a1 => ret, in("r3") a2, in("r4") a3, in("r5") a4,
+         in("r6") a5, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall6(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize, a6: usize) -> usize {
+    let ret;
+    asm!("svc 0", in("r1") nr as usize, inlateout("r2") a1 => ret, in("r3") a2, in("r4") a3, in("r5") a4,
+         in("r6") a5, in("r7") a6, options(nostack));
+    ret
+}
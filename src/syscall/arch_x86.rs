@@ -0,0 +1,119 @@
+use core::arch::asm;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+#[repr(usize)]
+pub enum Sysno {
+    read = 3,
+    write = 4,
+    close = 6,
+    execve = 11,
+    getpid = 20,
+    kill = 37,
+    readlink = 85,
+    munmap = 91,
+    wait4 = 114,
+    sysinfo = 116,
+    clone = 120,
+    uname = 122,
+    fchdir = 133,
+    flock = 143,
+    writev = 146,
+    prctl = 172,
+    poll = 168,
+    pread64 = 180,
+    pwrite64 = 181,
+    getcwd = 183,
+    // Takes its offset in pages
+    mmap = 192,
+    // The 32-bit ID versions
+    getuid = 199,
+    getgid = 200,
+    geteuid = 201,
+    getegid = 202,
+    getdents64 = 220,
+    fgetxattr = 231,
+    sched_setaffinity = 241,
+    sched_getaffinity = 242,
+    exit_group = 252,
+    clock_gettime = 265,
+    clock_nanosleep = 267,
+    openat = 295,
+    mkdirat = 296,
+    // fstatat64(), which fills struct stat64
+    fstatat = 300,
+    unlinkat = 301,
+    renameat = 302,
+    linkat = 303,
+    symlinkat = 304,
+    readlinkat = 305,
+    fchmodat = 306,
+    faccessat = 307,
+    prlimit64 = 340,
+    execveat = 358,
+    socket = 359,
+    bind = 361,
+    connect = 362,
+    listen = 363,
+    accept4 = 364,
+    getsockopt = 365,
+    sendto = 369,
+    statx = 383,
+    io_uring_setup = 425,
+    io_uring_enter = 426,
+    pidfd_open = 434,
+    openat2 = 437,
+    faccessat2 = 439,
+    landlock_create_ruleset = 444,
+}
+
+// LLVM reserves esi and ebp, so they're loaded from memory (along with the syscall number)
+// and restored afterwards. Every call goes through here, the unused arguments are just zero.
+#[inline]
+pub unsafe fn syscall6(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize, a6: usize) -> usize {
+    let args = [a4, a6, nr as usize];
+    let ret;
+    asm!(
+        "push ebp",
+        "push esi",
+        "mov esi, [eax]",
+        "mov ebp, [eax + 4]",
+        "mov eax, [eax + 8]",
+        "int 0x80",
+        "pop esi",
+        "pop ebp",
+        inout("eax") args.as_ptr() => ret,
+        in("ebx") a1, in("ecx") a2, in("edx") a3, in("edi") a5,
+    );
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall0(nr: Sysno) -> usize {
+    syscall6(nr, 0, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall1(nr: Sysno, a1: usize) -> usize {
+    syscall6(nr, a1, 0, 0, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall2(nr: Sysno, a1: usize, a2: usize) -> usize {
+    syscall6(nr, a1, a2, 0, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall3(nr: Sysno, a1: usize, a2: usize, a3: usize) -> usize {
+    syscall6(nr, a1, a2, a3, 0, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall4(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    syscall6(nr, a1, a2, a3, a4, 0, 0)
+}
+
+#[inline]
+pub unsafe fn syscall5(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    syscall6(nr, a1, a2, a3, a4, a5, 0)
+}
@@ -0,0 +1,123 @@
+use core::arch::asm;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+#[repr(usize)]
+pub enum Sysno {
+    read = 0,
+    write = 1,
+    close = 3,
+    poll = 7,
+    mmap = 9,
+    munmap = 11,
+    pread64 = 17,
+    pwrite64 = 18,
+    writev = 20,
+    getpid = 39,
+    socket = 41,
+    connect = 42,
+    sendto = 44,
+    bind = 49,
+    listen = 50,
+    getsockopt = 55,
+    clone = 56,
+    execve = 59,
+    wait4 = 61,
+    kill = 62,
+    uname = 63,
+    flock = 73,
+    getcwd = 79,
+    fchdir = 81,
+    readlink = 89,
+    sysinfo = 99,
+    getuid = 102,
+    getgid = 104,
+    geteuid = 107,
+    getegid = 108,
+    prctl = 157,
+    fgetxattr = 193,
+    sched_setaffinity = 203,
+    sched_getaffinity = 204,
+    getdents64 = 217,
+    clock_gettime = 228,
+    clock_nanosleep = 230,
+    exit_group = 231,
+    openat = 257,
+    mkdirat = 258,
+    // newfstatat(), whose struct stat is the same as x86's struct stat64
+    fstatat = 262,
+    unlinkat = 263,
+    renameat = 264,
+    linkat = 265,
+    symlinkat = 266,
+    readlinkat = 267,
+    fchmodat = 268,
+    faccessat = 269,
+    accept4 = 288,
+    prlimit64 = 302,
+    execveat = 322,
+    statx = 332,
+    io_uring_setup = 425,
+    io_uring_enter = 426,
+    pidfd_open = 434,
+    openat2 = 437,
+    faccessat2 = 439,
+    landlock_create_ruleset = 444,
+}
+
+// The kernel clobbers rcx (return address) and r11 (flags)
+
+#[inline]
+pub unsafe fn syscall0(nr: Sysno) -> usize {
+    let ret;
+    asm!("syscall", inlateout("rax") nr as usize => ret, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall1(nr: Sysno, a1: usize) -> usize {
+    let ret;
+    asm!("syscall", inlateout("rax") nr as usize => ret, in("rdi") a1,
+         lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall2(nr: Sysno, a1: usize, a2: usize) -> usize {
+    let ret;
+    asm!("syscall", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2,
+         lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall3(nr: Sysno, a1: usize, a2: usize, a3: usize) -> usize {
+    let ret;
+    asm!("syscall", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2, in("rdx") a3,
+         lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall4(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let ret;
+    asm!("syscall", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2, in("rdx") a3,
+         in("r10") a4, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall5(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let ret;
+    asm!("syscall", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2, in("rdx") a3,
+         in("r10") a4, in("r8") a5, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall6(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize, a6: usize) -> usize {
+    let ret;
+    asm!("syscall", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2, in("rdx") a3,
+         in("r10") a4, in("r8") a5, in("r9") a6, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
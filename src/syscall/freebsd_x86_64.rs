@@ -0,0 +1,118 @@
+use core::arch::asm;
+
+// Where FreeBSD's syscall does the same job under another name, it takes the Linux one, so
+// sys.rs doesn't need to tell them apart
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+#[repr(usize)]
+pub enum Sysno {
+    // exit(), which ends every thread, like Linux's exit_group()
+    exit_group = 1,
+    read = 3,
+    write = 4,
+    close = 6,
+    fchdir = 13,
+    getpid = 20,
+    getuid = 24,
+    geteuid = 25,
+    getegid = 43,
+    getgid = 47,
+    readlink = 58,
+    execve = 59,
+    munmap = 73,
+    fcntl = 92,
+    socket = 97,
+    connect = 98,
+    bind = 104,
+    listen = 106,
+    writev = 121,
+    flock = 131,
+    sendto = 133,
+    getrlimit = 194,
+    setrlimit = 195,
+    __sysctl = 202,
+    poll = 209,
+    clock_gettime = 232,
+    clock_nanosleep = 244,
+    issetugid = 253,
+    // __getcwd(), which returns 0 rather than the path's length
+    getcwd = 326,
+    extattr_get_fd = 372,
+    // pread()
+    pread64 = 475,
+    // pwrite()
+    pwrite64 = 476,
+    mmap = 477,
+    // Takes flags, like Linux's faccessat2()
+    faccessat = 489,
+    fchmodat = 490,
+    linkat = 495,
+    mkdirat = 496,
+    openat = 499,
+    readlinkat = 500,
+    renameat = 501,
+    symlinkat = 502,
+    unlinkat = 503,
+    accept4 = 541,
+    getdirentries = 554,
+}
+
+// The kernel clobbers rcx (return address), r11 (flags) and rdx (second return value). Errors
+// set the carry flag and return a positive error number, which is negated to look like Linux's.
+
+#[inline]
+pub unsafe fn syscall0(nr: Sysno) -> usize {
+    let ret;
+    asm!("syscall", "jnc 2f", "neg rax", "2:", inlateout("rax") nr as usize => ret,
+         lateout("rdx") _, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall1(nr: Sysno, a1: usize) -> usize {
+    let ret;
+    asm!("syscall", "jnc 2f", "neg rax", "2:", inlateout("rax") nr as usize => ret, in("rdi") a1,
+         lateout("rdx") _, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall2(nr: Sysno, a1: usize, a2: usize) -> usize {
+    let ret;
+    asm!("syscall", "jnc 2f", "neg rax", "2:", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2,
+         lateout("rdx") _, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall3(nr: Sysno, a1: usize, a2: usize, a3: usize) -> usize {
+    let ret;
+    asm!("syscall", "jnc 2f", "neg rax", "2:", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2,
+         inlateout("rdx") a3 => _, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall4(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let ret;
+    asm!("syscall", "jnc 2f", "neg rax", "2:", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2,
+         inlateout("rdx") a3 => _, in("r10") a4, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall5(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize) -> usize {
+    let ret;
+    asm!("syscall", "jnc 2f", "neg rax", "2:", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2,
+         inlateout("rdx") a3 => _, in("r10") a4, in("r8") a5, lateout("rcx") _, lateout("r11") _, options(nostack));
+    ret
+}
+
+#[inline]
+pub unsafe fn syscall6(nr: Sysno, a1: usize, a2: usize, a3: usize, a4: usize, a5: usize, a6: usize) -> usize {
+    let ret;
+    asm!("syscall", "jnc 2f", "neg rax", "2:", inlateout("rax") nr as usize => ret, in("rdi") a1, in("rsi") a2,
+         inlateout("rdx") a3 => _, in("r10") a4, in("r8") a5, in("r9") a6, lateout("rcx") _, lateout("r11") _,
+         options(nostack));
+    ret
+}
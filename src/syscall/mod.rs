@@ -0,0 +1,92 @@
+/*
+   Raw syscalls.
+   The few syscalls we need are issued directly through per-architecture assembly stubs, so
+   supporting a new architecture or a newer syscall only takes adding its number here.
+   syscall!(Sysno::<name>, args...) casts every argument to usize and returns
+   Result<usize, Errno>, where errors are the kernel's negated return values.
+   FreeBSD reports errors through the carry flag instead, so its stubs negate them to match.
+*/
+
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "x86"), path = "arch_x86.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "x86_64"), path = "arch_x86_64.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "aarch64"), path = "arch_aarch64.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "arm"), path = "arch_arm.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "mips"), path = "arch_mips.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "mips64"), path = "arch_mips64.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "riscv64"), path = "arch_riscv64.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "powerpc64"), path = "arch_powerpc64.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "s390x"), path = "arch_s390x.rs")]
+#[cfg_attr(all(not(target_os = "freebsd"), target_arch = "loongarch64"), path = "arch_loongarch64.rs")]
+#[cfg_attr(all(target_os = "freebsd", target_arch = "x86_64"), path = "freebsd_x86_64.rs")]
+mod arch;
+
+pub use arch::Sysno;
+#[doc(hidden)]
+pub use arch::{syscall0, syscall1, syscall2, syscall3, syscall4, syscall5, syscall6};
+
+// Kernel error number (e.g. ENOENT)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Errno(i32);
+
+#[cfg(not(target_os = "freebsd"))]
+impl Errno {
+    pub const ENOENT: Errno = Errno(2);
+    pub const EIO: Errno = Errno(5);
+    pub const EBADF: Errno = Errno(9);
+    pub const ENAMETOOLONG: Errno = Errno(36);
+    pub const ENOSYS: Errno = Errno(38);
+    pub const ELOOP: Errno = Errno(40);
+}
+
+#[cfg(target_os = "freebsd")]
+impl Errno {
+    pub const ENOENT: Errno = Errno(2);
+    pub const EIO: Errno = Errno(5);
+    pub const EBADF: Errno = Errno(9);
+    pub const ELOOP: Errno = Errno(62);
+    pub const ENAMETOOLONG: Errno = Errno(63);
+    pub const ENOSYS: Errno = Errno(78);
+}
+
+impl Errno {
+
+    #[inline]
+    pub fn into_raw(self) -> i32 {
+        self.0
+    }
+
+    // Return values between -4095 and -1 are errors
+    #[inline]
+    pub fn from_ret(ret: usize) -> Result<usize, Errno> {
+        if ret > -4096isize as usize {
+            Err(Errno(-(ret as isize) as i32))
+        } else {
+            Ok(ret)
+        }
+    }
+}
+
+macro_rules! syscall {
+    ($nr:expr) => {
+        $crate::syscall::Errno::from_ret($crate::syscall::syscall0($nr))
+    };
+    ($nr:expr, $a1:expr) => {
+        $crate::syscall::Errno::from_ret($crate::syscall::syscall1($nr, $a1 as usize))
+    };
+    ($nr:expr, $a1:expr, $a2:expr) => {
+        $crate::syscall::Errno::from_ret($crate::syscall::syscall2($nr, $a1 as usize, $a2 as usize))
+    };
+    ($nr:expr, $a1:expr, $a2:expr, $a3:expr) => {
+        $crate::syscall::Errno::from_ret($crate::syscall::syscall3($nr, $a1 as usize, $a2 as usize, $a3 as usize))
+    };
+    ($nr:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr) => {
+        $crate::syscall::Errno::from_ret($crate::syscall::syscall4($nr, $a1 as usize, $a2 as usize, $a3 as usize, $a4 as usize))
+    };
+    ($nr:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr, $a5:expr) => {
+        $crate::syscall::Errno::from_ret($crate::syscall::syscall5($nr, $a1 as usize, $a2 as usize, $a3 as usize, $a4 as usize, $a5 as usize))
+    };
+    ($nr:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr, $a5:expr, $a6:expr) => {
+        $crate::syscall::Errno::from_ret($crate::syscall::syscall6($nr, $a1 as usize, $a2 as usize, $a3 as usize, $a4 as usize, $a5 as usize, $a6 as usize))
+    };
+}
+pub(crate) use syscall;
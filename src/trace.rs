@@ -0,0 +1,75 @@
+/*
+   HWCAPS_TRACE_FILE exec trace.
+   Debugging sessions and CI harnesses can check which candidates were tried, and what came of
+   them, by pointing HWCAPS_TRACE_FILE at a file. A line is appended for every attempt:
+   <pid> <candidate> exec         -> The candidate exists, and is about to be executed
+   <pid> <candidate> errno=<n>    -> Executing it failed (2 is ENOENT: it doesn't exist)
+   The file is opened with O_APPEND, so lines from concurrent processes don't interleave, and the
+   trail outlives the loader once the target is executed. Control characters in paths are
+   escaped like in error messages ("\xNN"), so they can't fake lines.
+   The variable is ignored in secure-execution mode (setuid/setgid programs, for example), since
+   it would let unprivileged users append to files as another user. Failing to write the trace
+   never prevents the target from being executed.
+*/
+
+use core::ffi::{c_char, CStr};
+
+use crate::sys::{self, Errno};
+use crate::path::itoa;
+use crate::output;
+
+const TRACE_FILE_VAR: &[u8] = b"HWCAPS_TRACE_FILE";
+const LINE_MAX: usize = sys::PATH_MAX as usize + 32;
+
+pub struct Trace {
+    fd: i32,
+    pid: u32,
+}
+
+// Opens the trace file HWCAPS_TRACE_FILE names, if there's one
+pub fn open(envp: *const *const c_char) -> Option<Trace> {
+    let path = sys::getenv(envp, TRACE_FILE_VAR)?;
+
+    if sys::is_secure() {
+        return None
+    }
+
+    // Environment variables are null-terminated
+    let path = unsafe { CStr::from_ptr(path.as_ptr() as *const c_char) };
+    let flags = sys::O_WRONLY | sys::O_APPEND | sys::O_CREAT;
+    let fd = sys::openat_mode(sys::AT_FDCWD, path, flags, 0o644).ok()?;
+
+    Some(Trace { fd, pid: sys::getpid() })
+}
+
+impl Trace {
+    fn record(&self, candidate: &CStr, outcome: &[u8], errno: Option<u32>) {
+        let mut line = [0u8; LINE_MAX];
+        let mut len = itoa(self.pid, &mut line);
+        line[len] = b' ';
+        len += 1;
+
+        len += output::escape(candidate.to_bytes(), &mut line[len..len + sys::PATH_MAX as usize]);
+
+        line[len] = b' ';
+        line[len+1..len+1+outcome.len()].copy_from_slice(outcome);
+        len += 1 + outcome.len();
+        if let Some(errno) = errno {
+            len += itoa(errno, &mut line[len..]);
+        }
+        line[len] = b'\n';
+
+        // A single write, so the line is appended as a whole
+        let _ = sys::write(self.fd, &line[..len+1]);
+    }
+
+    // Records that candidate is about to be executed
+    pub fn exec(&self, candidate: &CStr) {
+        self.record(candidate, b"exec", None);
+    }
+
+    // Records why executing candidate failed
+    pub fn failed(&self, candidate: &CStr, errno: Errno) {
+        self.record(candidate, b"errno=", Some(errno.into_raw() as u32));
+    }
+}
@@ -0,0 +1,263 @@
+/*
+   Post-transaction validation.
+   Package managers can check the hwcaps entries a transaction touched right after it, instead of
+   users finding out the next time a program fails to start. Every touched path is mapped to the
+   program it belongs to, which is checked once, however many of its files were touched:
+   /usr/hwcaps/<level>[+variant]/<path>  -> /usr/<path>
+   /usr/<path>                           -> /usr/<path>
+   Paths may also be relative to / (e.g. "usr/bin/foo", as pacman hooks get them), and removed
+   paths are welcome too, since removals can break programs just as well. Directories, dotfiles
+   (e.g. ignore markers) and paths outside /usr are skipped.
+   Unlike the loader, every feature level and variant is considered, since the tree may be shared
+   with other machines. Available through Validator, and as "hwcaps-loader validate [path...]"
+   (maintenance feature).
+*/
+
+use core::fmt;
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::path;
+use crate::capabilities::{self, FEATURE_LEVELS, BASELINE_FEATURE_LEVEL};
+use crate::variants::{self, Variants};
+use crate::{HWCAPS_PATH, USR_PATH};
+#[cfg(feature = "generic_fallback")]
+use crate::GENERIC_DIRECTORY;
+#[cfg(feature = "ignore_markers")]
+use crate::ignore;
+
+// What aliases point to
+const LOADER_NAME: &[u8] = b"hwcaps-loader";
+// Programs remembered as checked. Once full, programs may be reported more than once.
+const MAX_PROGRAMS: usize = 1024;
+
+// What's wrong with a program. More cases may be added in the future.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Problem {
+    // The program has builds, but its path isn't an alias of hwcaps-loader (or doesn't exist)
+    NotManaged,
+    // The program's alias was kept, but every one of its builds is gone
+    NoCandidates,
+    // There's no baseline (or generic) build, so older machines can't run the program
+    NoBaseline,
+    // A build exists, but can't be executed
+    NotExecutable,
+    // A path is in a directory which isn't a feature level or variant of this architecture
+    UnknownDirectory,
+}
+
+impl Problem {
+    // Whether the program can't be executed because of this, rather than in some cases
+    pub fn is_error(&self) -> bool {
+        matches!(self, Problem::NoCandidates | Problem::NotExecutable)
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NotManaged => f.write_str("program has builds, but isn't an alias of hwcaps-loader"),
+            Problem::NoCandidates => f.write_str("alias has no builds left"),
+            Problem::NoBaseline => f.write_str("program has no baseline build"),
+            Problem::NotExecutable => f.write_str("build isn't executable"),
+            Problem::UnknownDirectory => f.write_str("unknown feature level directory")
+        }
+    }
+}
+
+// A problem, and the path it was found at: the alias' for programs, the build's for builds
+pub struct Issue<'a> {
+    pub problem: Problem,
+    pub path: &'a [u8],
+}
+
+pub struct Validator<'a> {
+    // Prepended to every path, without a trailing slash. Empty for the live tree.
+    root: &'a [u8],
+    // Hashes of the programs already checked
+    seen: [u64; MAX_PROGRAMS],
+    seen_count: usize,
+}
+
+// FNV-1a
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, c| (hash ^ *c as u64).wrapping_mul(0x100000001b3))
+}
+
+fn is_directory(path: &CStr) -> bool {
+    match sys::openat(sys::AT_FDCWD, path, sys::O_RDONLY | sys::O_DIRECTORY) {
+        Ok(fd) => {
+            let _ = sys::close(fd);
+            true
+        },
+        Err(_) => false
+    }
+}
+
+// Whether directory is a feature level (with a known variant suffix, if any) or the generic one
+fn is_known_directory(directory: &[u8]) -> bool {
+    #[cfg(feature = "generic_fallback")]
+    if directory == GENERIC_DIRECTORY {
+        return true
+    }
+
+    let suffix = &directory[directory.iter().position(|c| *c == b'+').unwrap_or(directory.len())..];
+    capabilities::find_directory_level(directory).is_some()
+        && (suffix.is_empty() || variants::SUFFIXES.contains(&suffix))
+}
+
+impl<'a> Validator<'a> {
+    // Validates the tree staged in root (e.g. a package's), or the live one if it's empty
+    pub fn new(root: &'a [u8]) -> Self {
+        let root = root.strip_suffix(b"/").unwrap_or(root);
+        Validator { root, seen: [0; MAX_PROGRAMS], seen_count: 0 }
+    }
+
+    // Joins parts inside the tree. None if the path doesn't fit.
+    fn join<'b>(&self, buffer: &'b mut [u8], parts: &[&[u8]]) -> Option<&'b CStr> {
+        if self.root.len() >= buffer.len() {
+            return None
+        }
+        buffer[..self.root.len()].copy_from_slice(self.root);
+
+        let len = path::join(&mut buffer[self.root.len()..], parts)?;
+        Some(unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..self.root.len()+len+1]) })
+    }
+
+    // Whether program was already checked. Remembers it otherwise.
+    fn seen(&mut self, program: &[u8]) -> bool {
+        let hash = hash(program);
+        if self.seen[..self.seen_count].contains(&hash) {
+            return true
+        }
+
+        if self.seen_count < MAX_PROGRAMS {
+            self.seen[self.seen_count] = hash;
+            self.seen_count += 1;
+        }
+        false
+    }
+
+    // Checks the program path (inside the tree) belongs to, passing every problem found to
+    // report. Returns how many of them were errors.
+    pub fn check(&mut self, path: &[u8], mut report: impl FnMut(&Issue)) -> u32 {
+        let mut errors = 0;
+        let mut report = |problem: Problem, path: &[u8]| {
+            errors += problem.is_error() as u32;
+            report(&Issue { problem, path });
+        };
+
+        // Targets of pacman hooks are relative to /
+        let mut absolute_buffer = [0u8; sys::PATH_MAX as usize];
+        let path = match path.first() {
+            Some(b'/') => path,
+            _ => match path::join(&mut absolute_buffer, &[b"/", path]) {
+                Some(len) => &absolute_buffer[..len],
+                None => return 0
+            }
+        };
+
+        // Paths which could leave /usr are nobody's
+        if path.contains(&b'\0') || path.windows(3).any(|w| w == b"/..") || path::file_name(path).starts_with(b".") {
+            return 0
+        }
+
+        let mut buffer = [0u8; sys::PATH_MAX as usize];
+        let touched = match self.join(&mut buffer, &[path]) {
+            Some(touched) if !is_directory(touched) => touched,
+            _ => return 0
+        };
+
+        // Part of the program's path after /usr (e.g. "/bin/foo")
+        let program = match path.strip_prefix(HWCAPS_PATH) {
+            Some(rest) => {
+                let end = match rest.iter().position(|c| *c == b'/') {
+                    Some(end) => end,
+                    None => return 0
+                };
+
+                // Removing files from one is fine, it's what should happen to them
+                if !is_known_directory(&rest[..end]) {
+                    if sys::faccessat(sys::AT_FDCWD, touched, sys::F_OK).is_ok() {
+                        report(Problem::UnknownDirectory, touched.to_bytes());
+                    }
+                    return errors
+                }
+                &rest[end..]
+            },
+            None => match path.strip_prefix(USR_PATH) {
+                Some(program) if program.first() == Some(&b'/') => program,
+                _ => return 0
+            }
+        };
+
+        if self.seen(program) {
+            return 0
+        }
+        self.check_program(program, &mut report);
+        errors
+    }
+
+    fn check_program(&self, program: &[u8], report: &mut impl FnMut(Problem, &[u8])) {
+        let mut builds = 0;
+        let mut has_baseline = false;
+        let mut check_build = |directory: &[u8], suffix: &[u8], baseline: bool| {
+            let mut buffer = [0u8; sys::PATH_MAX as usize];
+            let build = match self.join(&mut buffer, &[HWCAPS_PATH, directory, suffix, program]) {
+                Some(b) => b,
+                None => return
+            };
+
+            // Skipped builds are handled like missing ones, as the loader does
+            #[cfg(feature = "ignore_markers")]
+            {
+                let (hwcaps_dir, candidate) = build.to_bytes().split_at(self.root.len() + HWCAPS_PATH.len());
+                if ignore::is_ignored(hwcaps_dir, candidate) {
+                    return
+                }
+            }
+
+            // Directories with the program's path are someone else's
+            if sys::faccessat(sys::AT_FDCWD, build, sys::F_OK).is_err() || is_directory(build) {
+                return
+            }
+            if sys::faccessat(sys::AT_FDCWD, build, sys::X_OK).is_err() {
+                report(Problem::NotExecutable, build.to_bytes());
+            }
+            builds += 1;
+            has_baseline |= baseline;
+        };
+
+        for (level, suffix) in Variants::all().candidates(FEATURE_LEVELS.len() as u32 - 1) {
+            check_build(FEATURE_LEVELS[level as usize].directory, suffix, level == BASELINE_FEATURE_LEVEL && suffix.is_empty());
+        }
+        #[cfg(feature = "generic_fallback")]
+        check_build(GENERIC_DIRECTORY, b"", true);
+
+        let mut buffer = [0u8; sys::PATH_MAX as usize];
+        let alias = match self.join(&mut buffer, &[USR_PATH, program]) {
+            Some(a) => a,
+            None => return
+        };
+
+        // Aliases are links to the loader, usually relative ones (foo -> hwcaps-loader)
+        let mut target = [0u8; sys::PATH_MAX as usize];
+        let is_alias = match sys::readlinkat(sys::AT_FDCWD, alias, &mut target) {
+            Ok(len) => path::file_name(&target[..len]) == LOADER_NAME,
+            Err(_) => false
+        };
+
+        match (is_alias, builds) {
+            // Neither of them, so not ours (or removed as a whole)
+            (false, 0) => (),
+            (true, 0) => report(Problem::NoCandidates, alias.to_bytes()),
+            (false, _) => report(Problem::NotManaged, alias.to_bytes()),
+            (true, _) => ()
+        }
+        if builds != 0 && !has_baseline {
+            report(Problem::NoBaseline, alias.to_bytes());
+        }
+    }
+}
@@ -0,0 +1,262 @@
+/*
+   Build variants.
+   Besides feature levels, programs may provide variants built for specific properties of the
+   machine. These are placed in directories with a suffix (e.g. x86-64-v3+bigmem), which are
+   tried before the plain directory of every feature level.
+*/
+
+#[cfg(feature = "memory_variants")]
+use crate::sys;
+#[cfg(any(
+    all(any(feature = "amx_variants", feature = "crypto_variants"), target_arch = "x86_64"),
+    all(any(feature = "vector_variants", feature = "extension_variants"), target_arch = "riscv64"),
+    all(feature = "mma_variants", target_arch = "powerpc64"),
+    all(feature = "vector_variants", target_arch = "s390x")
+))]
+use crate::capabilities;
+#[cfg(any(
+    all(any(feature = "amx_variants", feature = "crypto_variants"), target_arch = "x86_64"),
+    all(feature = "extension_variants", target_arch = "riscv64"),
+    all(feature = "mma_variants", target_arch = "powerpc64"),
+    all(feature = "vector_variants", target_arch = "s390x")
+))]
+use crate::capabilities::Features;
+
+pub const MAX_VARIANTS: usize = 12;
+
+// Every suffix this build can prefer, besides the plain directory
+pub const SUFFIXES: &[&[u8]] = &[
+    #[cfg(all(feature = "amx_variants", target_arch = "x86_64"))]
+    b"+amx",
+    #[cfg(all(feature = "mma_variants", target_arch = "powerpc64"))]
+    b"+mma",
+    #[cfg(all(feature = "crypto_variants", target_arch = "x86_64"))]
+    b"+vaes",
+    #[cfg(all(feature = "crypto_variants", target_arch = "x86_64"))]
+    b"+aes",
+    #[cfg(all(feature = "crypto_variants", target_arch = "x86_64"))]
+    b"+sha",
+    #[cfg(all(feature = "vector_variants", target_arch = "riscv64"))]
+    b"+zvl512b",
+    #[cfg(all(feature = "vector_variants", target_arch = "riscv64"))]
+    b"+zvl256b",
+    #[cfg(all(feature = "vector_variants", target_arch = "riscv64"))]
+    b"+v",
+    #[cfg(all(feature = "vector_variants", target_arch = "s390x"))]
+    b"+vxe2",
+    #[cfg(all(feature = "vector_variants", target_arch = "s390x"))]
+    b"+vxe",
+    #[cfg(all(feature = "vector_variants", target_arch = "s390x"))]
+    b"+vx",
+    #[cfg(all(feature = "extension_variants", target_arch = "riscv64"))]
+    b"+zvbb",
+    #[cfg(all(feature = "extension_variants", target_arch = "riscv64"))]
+    b"+zicond",
+    #[cfg(all(feature = "extension_variants", target_arch = "riscv64"))]
+    b"+zbb",
+    #[cfg(all(feature = "extension_variants", target_arch = "riscv64"))]
+    b"+zba",
+    #[cfg(feature = "memory_variants")]
+    b"+smallmem",
+    #[cfg(feature = "memory_variants")]
+    b"+bigmem",
+];
+
+// Features machines need to prefer "+amx" variants, as in /proc/cpuinfo
+#[cfg(all(feature = "amx_variants", target_arch = "x86_64"))]
+const AMX_FEATURES: &[&[u8]] = &[b"amx_tile", b"amx_int8", b"amx_bf16"];
+
+// Features machines need to prefer "+mma" variants, as in AT_HWCAP2. The kernel only reports MMA
+// if it lets programs use the accumulators.
+#[cfg(all(feature = "mma_variants", target_arch = "powerpc64"))]
+const MMA_FEATURES: &[&[u8]] = &[b"arch_3_1", b"mma"];
+
+// Suffixes of crypto variants, and the features machines need to prefer them, most capable first.
+// VAES CPUs prefer "+vaes", then "+aes".
+#[cfg(all(feature = "crypto_variants", target_arch = "x86_64"))]
+const CRYPTO_VARIANTS: &[(&[u8], &[&[u8]])] = &[
+    (b"+vaes", &[b"vaes", b"vpclmulqdq", b"aes", b"pclmulqdq"]),
+    (b"+aes", &[b"aes", b"pclmulqdq"]),
+    (b"+sha", &[b"sha_ni"]),
+];
+
+// Suffixes of vector variants, and the VLEN machines need to prefer them, widest first. They're
+// named like -march's extensions (e.g. "rva22+zvl256b" for -march=rva22u64_v_zvl256b), and V
+// itself guarantees 128 bits.
+#[cfg(all(feature = "vector_variants", target_arch = "riscv64"))]
+const VECTOR_VARIANTS: &[(&[u8], u32)] = &[(b"+zvl512b", 512), (b"+zvl256b", 256), (b"+v", 128)];
+
+// On s390x, suffixes of vector variants, and the facilities (as in AT_HWCAP) machines need to prefer
+// them, most capable first. Levels require them too, but machines may be held below the level of
+// their vector facilities by others, or by configured limits.
+#[cfg(all(feature = "vector_variants", target_arch = "s390x"))]
+const VECTOR_VARIANTS: &[(&[u8], &[&[u8]])] = &[
+    (b"+vxe2", &[b"vx", b"vxe", b"vxe2"]),
+    (b"+vxe", &[b"vx", b"vxe"]),
+    (b"+vx", &[b"vx"]),
+];
+
+// Suffixes of extension variants, and the features machines need to prefer them, for silicon which
+// implements part of a profile beyond its level. Adding an extension only takes an entry (and its
+// suffix in SUFFIXES), as long as FEATURE_NAMES knows it.
+#[cfg(all(feature = "extension_variants", target_arch = "riscv64"))]
+const EXTENSION_VARIANTS: &[(&[u8], &[&[u8]])] = &[
+    (b"+zvbb", &[b"v", b"zvbb"]),
+    (b"+zicond", &[b"zicond"]),
+    (b"+zbb", &[b"zbb"]),
+    (b"+zba", &[b"zba"]),
+];
+
+// Machines with less memory than this prefer "+smallmem" variants
+#[cfg(feature = "memory_variants")]
+const SMALLMEM_MAX: u64 = 4 << 30;
+// Machines with at least this much memory prefer "+bigmem" variants
+#[cfg(feature = "memory_variants")]
+const BIGMEM_MIN: u64 = 64 << 30;
+
+pub struct Variants {
+    suffixes: [&'static [u8]; MAX_VARIANTS],
+    count: usize,
+}
+
+impl Variants {
+    #[allow(dead_code)]
+    fn push(&mut self, suffix: &'static [u8]) {
+        self.suffixes[self.count] = suffix;
+        self.count += 1;
+    }
+
+    pub fn detect() -> Self {
+        #[allow(unused_mut)]
+        let mut variants = Variants { suffixes: [b""; MAX_VARIANTS], count: 0 };
+
+        #[cfg(any(
+            all(any(feature = "amx_variants", feature = "crypto_variants"), target_arch = "x86_64"),
+            all(any(feature = "vector_variants", feature = "extension_variants"), target_arch = "riscv64"),
+            all(feature = "mma_variants", target_arch = "powerpc64"),
+            all(feature = "vector_variants", target_arch = "s390x")
+        ))]
+        let features = capabilities::detect().features;
+
+        // AMX builds only run on machines with AMX, so they come before anything else
+        #[cfg(all(feature = "amx_variants", target_arch = "x86_64"))]
+        if has_features(&features, AMX_FEATURES) {
+            variants.push(b"+amx");
+        }
+
+        // Likewise for MMA builds
+        #[cfg(all(feature = "mma_variants", target_arch = "powerpc64"))]
+        if has_features(&features, MMA_FEATURES) {
+            variants.push(b"+mma");
+        }
+
+        #[cfg(all(feature = "crypto_variants", target_arch = "x86_64"))]
+        for (suffix, names) in CRYPTO_VARIANTS {
+            if has_features(&features, names) {
+                variants.push(suffix);
+            }
+        }
+
+        #[cfg(all(feature = "vector_variants", target_arch = "riscv64"))]
+        if let Some(vlen) = capabilities::get_vlen(&features) {
+            for (suffix, min_vlen) in VECTOR_VARIANTS {
+                if vlen >= *min_vlen {
+                    variants.push(suffix);
+                }
+            }
+        }
+
+        #[cfg(all(feature = "vector_variants", target_arch = "s390x"))]
+        for (suffix, names) in VECTOR_VARIANTS {
+            if has_features(&features, names) {
+                variants.push(suffix);
+            }
+        }
+
+        #[cfg(all(feature = "extension_variants", target_arch = "riscv64"))]
+        for (suffix, names) in EXTENSION_VARIANTS {
+            if has_features(&features, names) {
+                variants.push(suffix);
+            }
+        }
+
+        #[cfg(feature = "memory_variants")]
+        if let Some(suffix) = get_memory_suffix() {
+            variants.push(suffix);
+        }
+
+        // The plain directory is always the last resort
+        variants.push(b"");
+        variants
+    }
+
+    // Suffixes to try for every feature level, in order of preference
+    // Every variant, for machines which can't be detected (e.g. with another architecture)
+    #[allow(dead_code)]
+    pub fn all() -> Self {
+        let mut variants = Variants { suffixes: [b""; MAX_VARIANTS], count: 0 };
+        for suffix in SUFFIXES {
+            variants.push(suffix);
+        }
+
+        variants.push(b"");
+        variants
+    }
+
+    pub fn suffixes(&self) -> &[&'static [u8]] {
+        &self.suffixes[..self.count]
+    }
+
+    // Every (feature level, suffix) pair up to max_level, in order of preference
+    #[cfg(feature = "variants")]
+    pub fn candidates(&self, max_level: u32) -> impl Iterator<Item = (u32, &'static [u8])> + '_ {
+        (0..=max_level).rev().flat_map(move |level| self.suffixes().iter().map(move |suffix| (level, *suffix)))
+    }
+
+    // Without variant features, there's only the plain directory of every level
+    #[cfg(not(feature = "variants"))]
+    pub fn candidates(&self, max_level: u32) -> impl Iterator<Item = (u32, &'static [u8])> + '_ {
+        (0..=max_level).rev().map(|level| (level, b"".as_slice()))
+    }
+}
+
+#[cfg(all(feature = "memory_variants", not(target_os = "freebsd")))]
+fn get_total_memory() -> Option<u64> {
+    let mut info = unsafe { core::mem::zeroed::<sys::sysinfo>() };
+    sys::sysinfo(&mut info).ok()?;
+
+    Some(info.totalram as u64 * core::cmp::max(info.mem_unit, 1) as u64)
+}
+
+#[cfg(all(feature = "memory_variants", target_os = "freebsd"))]
+fn get_total_memory() -> Option<u64> {
+    let mut physmem = [0u8; size_of::<u64>()];
+    sys::sysctl(&[sys::CTL_HW as _, sys::HW_PHYSMEM as _], &mut physmem).ok()?;
+
+    Some(u64::from_ne_bytes(physmem))
+}
+
+#[cfg(feature = "memory_variants")]
+fn get_memory_suffix() -> Option<&'static [u8]> {
+    let total = get_total_memory()?;
+
+    if total < SMALLMEM_MAX {
+        Some(b"+smallmem")
+    } else if total >= BIGMEM_MIN {
+        Some(b"+bigmem")
+    } else {
+        None
+    }
+}
+
+// Whether features include every feature in names. For AMX, that also means the kernel enabled its
+// registers, but programs still have to request them (ARCH_REQ_XCOMP_PERM) before using them.
+#[cfg(any(
+    all(any(feature = "amx_variants", feature = "crypto_variants"), target_arch = "x86_64"),
+    all(feature = "extension_variants", target_arch = "riscv64"),
+    all(feature = "mma_variants", target_arch = "powerpc64"),
+    all(feature = "vector_variants", target_arch = "s390x")
+))]
+fn has_features(features: &Features, names: &[&[u8]]) -> bool {
+    names.iter().all(|name| capabilities::find_feature(name).is_some_and(|f| features.contains(&f)))
+}
@@ -0,0 +1,76 @@
+/*
+   Candidate verification.
+   Features which inspect candidates before they're executed (interp_check, signatures,
+   build_ids) open them, check them and execute them through the same descriptor (with
+   execveat()), so they can't be swapped in between. Scripts can't be executed through a descriptor which is closed
+   on exec, so they're executed by path after being checked.
+*/
+
+use core::ffi::{c_char, CStr};
+#[cfg(not(any(feature = "interp_check", feature = "signatures")))]
+use core::marker::PhantomData;
+use crate::sys::Errno;
+
+use crate::sys;
+#[cfg(feature = "interp_check")]
+use crate::interp;
+#[cfg(feature = "signatures")]
+use crate::signature;
+#[cfg(feature = "build_ids")]
+use crate::build_id;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+pub struct Policy<'a> {
+    #[cfg(feature = "interp_check")]
+    pub interpreters: Option<&'a interp::Interpreters>,
+    #[cfg(feature = "signatures")]
+    pub signing_key: &'a signature::PublicKey,
+    #[cfg(feature = "build_ids")]
+    pub build_id_mismatch_warn: bool,
+    // Only build_ids doesn't borrow anything
+    #[cfg(not(any(feature = "interp_check", feature = "signatures")))]
+    pub lifetime: PhantomData<&'a ()>,
+}
+
+// Aborts if the candidate (open as fd) doesn't pass every check
+fn check_fd(fd: i32, path: &CStr, policy: &Policy) {
+    #[cfg(feature = "interp_check")]
+    interp::check(fd, path, policy.interpreters);
+    #[cfg(feature = "signatures")]
+    signature::check(fd, path, policy.signing_key);
+    #[cfg(feature = "build_ids")]
+    build_id::check(fd, path, policy.build_id_mismatch_warn);
+}
+
+// Aborts if the candidate doesn't pass every check.
+// Returns an error if it can't be opened (ENOENT if it doesn't exist).
+#[allow(dead_code)] // Only used with ld_invocation and shebang_rewrite
+pub fn check(path: &CStr, policy: &Policy) -> Result<(), Errno> {
+    let fd = sys::openat(sys::AT_FDCWD, path, sys::O_RDONLY)?;
+    check_fd(fd, path, policy);
+    let _ = sys::close(fd);
+    Ok(())
+}
+
+// Like sys::execve, but aborts if the candidate doesn't pass every check. before_exec is called
+// once it has passed them, right before it's executed.
+pub fn execve(path: &CStr, policy: &Policy, argv: *const *const c_char, envp: *const *const c_char,
+              before_exec: impl FnOnce()) -> Errno {
+    let fd = match sys::openat(sys::AT_FDCWD, path, sys::O_RDONLY) {
+        Ok(fd) => fd,
+        Err(e) => return e
+    };
+
+    check_fd(fd, path, policy);
+    before_exec();
+
+    let mut magic = [0u8; 4];
+    let errno = match sys::pread64(fd, &mut magic, 0) {
+        Ok(4) if magic == *ELF_MAGIC => sys::execveat(fd, c"", argv, envp, sys::AT_EMPTY_PATH),
+        _ => sys::execve(path, argv, envp)
+    };
+
+    let _ = sys::close(fd);
+    errno
+}
@@ -3,5 +3,23 @@
 #include <limits.h>
 #include <fcntl.h>
 #include <errno.h>
+#include <unistd.h>
+#include <poll.h>
+#include <signal.h>
+#include <time.h>
 
+#include <sys/auxv.h>
+#include <sys/file.h>
+#include <sys/mman.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
+#include <sys/sysinfo.h>
 #include <sys/uio.h>
+#include <sys/un.h>
+#include <sys/utsname.h>
+#include <sys/wait.h>
+
+#include <linux/io_uring.h>
+#include <linux/landlock.h>
+#include <linux/openat2.h>
+#include <linux/prctl.h>
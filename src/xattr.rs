@@ -0,0 +1,50 @@
+/*
+   Per-binary overrides in extended attributes.
+   Admins can change how a single program is dispatched without configuration files or
+   rebuilding its package, by setting attributes on its baseline build (e.g.
+   /usr/hwcaps/x86-64-v1/bin/foo for /usr/bin/foo):
+   - user.hwcaps.max = <name> -> Don't consider feature levels above <name> (e.g. x86-64-v2)
+   - user.hwcaps.skip = 1     -> Don't consider feature levels above the baseline
+   For example, "setfattr -n user.hwcaps.max -v x86-64-v2 /usr/hwcaps/x86-64-v1/bin/foo".
+   Aliases are symlinks to the loader, which can't have user attributes of their own, so the
+   baseline build stands in for the program. Like every other limit, overrides can only lower the
+   level, and invalid values are ignored.
+*/
+
+use core::ffi::CStr;
+
+use crate::sys;
+use crate::capabilities::{self, BASELINE_FEATURE_LEVEL};
+
+const MAX_ATTRIBUTE: &CStr = c"user.hwcaps.max";
+const SKIP_ATTRIBUTE: &CStr = c"user.hwcaps.skip";
+// Longer than any feature level's name
+const VALUE_MAX: usize = 32;
+
+fn get<'a>(fd: i32, name: &CStr, buffer: &'a mut [u8]) -> Option<&'a [u8]> {
+    let len = sys::fgetxattr(fd, name, buffer).ok()?;
+    // Values written with a trailing newline still count
+    Some(buffer[..len].trim_ascii())
+}
+
+// Returns feature_level, lowered by the overrides of the baseline build at path, if any
+pub fn apply(path: &CStr, feature_level: u32) -> u32 {
+    // Programs without a baseline build have nothing to override
+    let fd = match sys::openat(sys::AT_FDCWD, path, sys::O_RDONLY) {
+        Ok(fd) => fd,
+        Err(_) => return feature_level
+    };
+
+    let mut buffer = [0u8; VALUE_MAX];
+    let mut level = feature_level;
+
+    if let Some(max) = get(fd, MAX_ATTRIBUTE, &mut buffer).and_then(capabilities::find_feature_level) {
+        level = core::cmp::min(level, max);
+    }
+    if get(fd, SKIP_ATTRIBUTE, &mut buffer) == Some(b"1") {
+        level = core::cmp::min(level, BASELINE_FEATURE_LEVEL);
+    }
+
+    let _ = sys::close(fd);
+    level
+}